@@ -981,7 +981,7 @@ pub fn run() -> Result<(), Error> {
                     );
                 }
                 let path = info.file.path.clone();
-                if let Some(r) = Reader::new(context.fb.rect(), *info, &tx, &mut context) {
+                if let Some(r) = Reader::new(context.fb.rect(), *info, &tx, &mut rq, &mut context) {
                     let mut next_view = Box::new(r) as Box<dyn View>;
                     transfer_notifications(
                         view.as_mut(),
@@ -10,6 +10,7 @@ use crate::library::Library;
 use crate::lightsensor::LightSensor;
 use crate::rtc::Rtc;
 use crate::settings::Settings;
+use crate::view::home::thumbnail_size;
 use crate::view::keyboard::Layout;
 use crate::view::ViewId;
 use chrono::Local;
@@ -89,6 +90,9 @@ impl Context {
 
     pub fn batch_import(&mut self) {
         self.library.import(&self.settings.import);
+        let (width, height) = thumbnail_size(CURRENT_DEVICE.dpi);
+        self.library
+            .pregenerate_thumbnails(&self.settings.import, width, height);
         let selected_library = self.settings.selected_library;
         for (index, library_settings) in self.settings.libraries.iter().enumerate() {
             if index == selected_library {
@@ -0,0 +1,648 @@
+use super::html::dom::XmlTree;
+use super::html::xml::XmlParser;
+use super::pdf::PdfOpener;
+use super::{chapter, chapter_relative};
+use crate::document::{BoundedText, Document, Location, TocEntry};
+use crate::framebuffer::Pixmap;
+use crate::geom::{Boundary, CycleDir};
+use crate::helpers::decode_entities;
+use crate::metadata::TextAlign;
+use anyhow::{format_err, Error};
+use fxhash::FxHashMap;
+use sevenz_rust::{Password, SevenZReader};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use walkdir::WalkDir;
+use zip::ZipArchive;
+
+const COMIC_INFO: &str = "ComicInfo.xml";
+const IMAGE_KINDS: [&str; 7] = ["jpg", "jpeg", "png", "gif", "bmp", "tif", "tiff"];
+
+// The number of bytes read from the beginning of an image to find its dimensions.
+const HEADER_SIZE: u64 = 64 * 1024;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Half {
+    Left,
+    Right,
+}
+
+// A page of the comic: an image, or one half of a double-page spread.
+#[derive(Debug, Copy, Clone)]
+struct ComicPage {
+    entry: usize,
+    half: Option<Half>,
+}
+
+enum Archive {
+    Zip(ZipArchive<File>),
+    // The entries of a solid block can only be decoded sequentially, hence the whole archive is
+    // decoded once, when it's opened.
+    SevenZip(FxHashMap<String, Vec<u8>>),
+    Directory(PathBuf),
+}
+
+impl Archive {
+    fn open(path: &Path) -> Result<Archive, Error> {
+        if path.is_dir() {
+            return Ok(Archive::Directory(path.to_path_buf()));
+        }
+
+        let file = File::open(path)?;
+        let mut magic = [0; 4];
+        file.read_exact_at(&mut magic, 0)?;
+
+        match &magic {
+            b"PK\x03\x04" => Ok(Archive::Zip(ZipArchive::new(file)?)),
+            b"7z\xBC\xAF" => {
+                let mut entries = FxHashMap::default();
+                SevenZReader::open(path, Password::empty())?.for_each_entries(
+                    |entry, reader| {
+                        if !entry.is_directory() {
+                            let mut buf = Vec::with_capacity(entry.size() as usize);
+                            reader.read_to_end(&mut buf)?;
+                            entries.insert(entry.name().to_string(), buf);
+                        }
+                        Ok(true)
+                    },
+                )?;
+                Ok(Archive::SevenZip(entries))
+            }
+            _ => Err(format_err!("unknown archive format")),
+        }
+    }
+
+    fn names(&mut self) -> Result<Vec<String>, Error> {
+        match self {
+            Archive::Zip(archive) => Ok(archive
+                .file_names()
+                .filter(|name| !name.ends_with('/'))
+                .map(String::from)
+                .collect()),
+            Archive::SevenZip(entries) => Ok(entries.keys().cloned().collect()),
+            Archive::Directory(dir) => Ok(WalkDir::new(&dir)
+                .min_depth(1)
+                .into_iter()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_file())
+                .filter_map(|entry| {
+                    entry
+                        .path()
+                        .strip_prefix(&dir)
+                        .ok()
+                        .and_then(Path::to_str)
+                        .map(String::from)
+                })
+                .collect()),
+        }
+    }
+
+    fn fetch(&mut self, name: &str) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+        match self {
+            Archive::Zip(archive) => {
+                archive.by_name(name)?.read_to_end(&mut buf)?;
+            }
+            Archive::SevenZip(entries) => {
+                buf = entries
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| format_err!("can't find {} in the archive", name))?;
+            }
+            Archive::Directory(dir) => {
+                buf = fs::read(dir.join(name))?;
+            }
+        }
+        Ok(buf)
+    }
+
+    // Reads the beginning of the given entry.
+    fn head(&mut self, name: &str) -> Option<Vec<u8>> {
+        let mut buf = Vec::new();
+        match self {
+            Archive::Zip(archive) => {
+                let file = archive.by_name(name).ok()?;
+                file.take(HEADER_SIZE).read_to_end(&mut buf).ok()?;
+            }
+            Archive::SevenZip(entries) => {
+                let data = entries.get(name)?;
+                buf.extend_from_slice(&data[..data.len().min(HEADER_SIZE as usize)]);
+            }
+            Archive::Directory(dir) => {
+                let file = File::open(dir.join(name)).ok()?;
+                file.take(HEADER_SIZE).read_to_end(&mut buf).ok()?;
+            }
+        }
+        Some(buf)
+    }
+}
+
+pub struct ComicDocument {
+    archive: Mutex<Archive>,
+    opener: PdfOpener,
+    // The names of the images, in reading order.
+    entries: Vec<String>,
+    // The entry of the front cover.
+    cover: usize,
+    pages: Vec<ComicPage>,
+    info: XmlTree,
+    toc: Vec<TocEntry>,
+    rtl: bool,
+    dims: Mutex<FxHashMap<usize, (f32, f32)>>,
+}
+
+unsafe impl Send for ComicDocument {}
+unsafe impl Sync for ComicDocument {}
+
+impl ComicDocument {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<ComicDocument, Error> {
+        let mut archive = Archive::open(path.as_ref())?;
+        let opener = PdfOpener::new().ok_or_else(|| format_err!("can't create a context"))?;
+        let names = archive.names()?;
+
+        let info = names
+            .iter()
+            .find(|name| {
+                Path::new(name)
+                    .file_name()
+                    .is_some_and(|file_name| file_name.eq_ignore_ascii_case(COMIC_INFO))
+            })
+            .and_then(|name| archive.fetch(name).ok())
+            .map(|buf| XmlParser::new(&String::from_utf8_lossy(&buf)).parse())
+            .unwrap_or_else(XmlTree::new);
+
+        let mut entries: Vec<String> = names.into_iter().filter(|name| is_image(name)).collect();
+        entries.sort_by(|a, b| natural_cmp(a, b));
+
+        if entries.is_empty() {
+            return Err(format_err!("no images found"));
+        }
+
+        let rtl = is_right_to_left(&info);
+        let (spreads, bookmarks) = page_infos(&info);
+        let cover = front_cover(&info)
+            .filter(|&entry| entry < entries.len())
+            .unwrap_or(0);
+
+        let mut pages = Vec::with_capacity(entries.len());
+        let mut toc = Vec::new();
+
+        for (entry, name) in entries.iter().enumerate() {
+            if let Some(title) = bookmarks.get(&entry) {
+                toc.push(TocEntry {
+                    title: title.clone(),
+                    location: Location::Exact(pages.len()),
+                    index: toc.len(),
+                    children: Vec::new(),
+                });
+            }
+
+            // The first image is usually a cover, possibly wrapping around the book.
+            let spread = entry > 0
+                && (spreads.contains(&entry)
+                    || archive
+                        .head(name)
+                        .and_then(|buf| image_size(&buf))
+                        .is_some_and(|(width, height)| is_spread(width, height)));
+
+            if spread {
+                let (first, second) = if rtl {
+                    (Half::Right, Half::Left)
+                } else {
+                    (Half::Left, Half::Right)
+                };
+                pages.push(ComicPage {
+                    entry,
+                    half: Some(first),
+                });
+                pages.push(ComicPage {
+                    entry,
+                    half: Some(second),
+                });
+            } else {
+                pages.push(ComicPage { entry, half: None });
+            }
+        }
+
+        Ok(ComicDocument {
+            archive: Mutex::new(archive),
+            opener,
+            entries,
+            cover,
+            pages,
+            info,
+            toc,
+            rtl,
+            dims: Mutex::new(FxHashMap::default()),
+        })
+    }
+
+    fn comic_info(&self) -> Option<super::html::dom::NodeRef<'_>> {
+        self.info.root().find("ComicInfo")
+    }
+
+    // The dimensions of the given image.
+    fn entry_dims(&self, entry: usize) -> Option<(f32, f32)> {
+        if let Some(dims) = self.dims.lock().ok()?.get(&entry) {
+            return Some(*dims);
+        }
+
+        let name = &self.entries[entry];
+        let buf = self.archive.lock().ok()?.fetch(name).ok()?;
+        let dims = self.opener.open_memory(name, &buf)?.dims(0)?;
+        self.dims.lock().ok()?.insert(entry, dims);
+        Some(dims)
+    }
+
+    pub fn series(&self) -> Option<(String, String)> {
+        self.metadata("Series").map(|series| {
+            let number = self.metadata("Number").unwrap_or_default();
+            (series, number)
+        })
+    }
+
+    pub fn year(&self) -> Option<String> {
+        self.metadata("Year")
+    }
+
+    pub fn publisher(&self) -> Option<String> {
+        self.metadata("Publisher")
+    }
+
+    pub fn language(&self) -> Option<String> {
+        self.metadata("LanguageISO")
+    }
+
+    pub fn description(&self) -> Option<String> {
+        self.metadata("Summary")
+    }
+
+    pub fn categories(&self) -> BTreeSet<String> {
+        ["Genre", "Tags"]
+            .iter()
+            .filter_map(|key| self.metadata(key))
+            .flat_map(|value| {
+                value
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<String>>()
+            })
+            .collect()
+    }
+
+    pub fn is_manga(&self) -> bool {
+        self.rtl
+    }
+}
+
+impl Document for ComicDocument {
+    fn dims(&self, index: usize) -> Option<(f32, f32)> {
+        let page = self.pages.get(index)?;
+        self.entry_dims(page.entry).map(|(width, height)| {
+            if page.half.is_some() {
+                (width / 2.0, height)
+            } else {
+                (width, height)
+            }
+        })
+    }
+
+    fn pages_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    fn toc(&mut self) -> Option<Vec<TocEntry>> {
+        if self.toc.is_empty() {
+            None
+        } else {
+            Some(self.toc.clone())
+        }
+    }
+
+    fn chapter<'a>(&mut self, offset: usize, toc: &'a [TocEntry]) -> Option<(&'a TocEntry, f32)> {
+        chapter(offset, self.pages_count(), toc)
+    }
+
+    fn chapter_relative<'a>(
+        &mut self,
+        offset: usize,
+        dir: CycleDir,
+        toc: &'a [TocEntry],
+    ) -> Option<&'a TocEntry> {
+        chapter_relative(offset, dir, toc)
+    }
+
+    fn words(&mut self, loc: Location) -> Option<(Vec<BoundedText>, usize)> {
+        self.resolve_location(loc).map(|index| (Vec::new(), index))
+    }
+
+    fn lines(&mut self, loc: Location) -> Option<(Vec<BoundedText>, usize)> {
+        self.resolve_location(loc).map(|index| (Vec::new(), index))
+    }
+
+    fn links(&mut self, loc: Location) -> Option<(Vec<BoundedText>, usize)> {
+        self.resolve_location(loc).map(|index| (Vec::new(), index))
+    }
+
+    fn images(&mut self, _loc: Location) -> Option<(Vec<Boundary>, usize)> {
+        None
+    }
+
+    fn preview_pixmap(&mut self, width: f32, height: f32, samples: usize) -> Option<Pixmap> {
+        let name = &self.entries[self.cover];
+        let buf = self.archive.lock().ok()?.fetch(name).ok()?;
+        let mut doc = self.opener.open_memory(name, &buf)?;
+        doc.dims(0)
+            .and_then(|dims| {
+                let scale = (width / dims.0).min(height / dims.1);
+                doc.pixmap(Location::Exact(0), scale, samples)
+            })
+            .map(|(pixmap, _)| pixmap)
+    }
+
+    fn pixmap(&mut self, loc: Location, scale: f32, samples: usize) -> Option<(Pixmap, usize)> {
+        let index = self.resolve_location(loc)?;
+        let page = self.pages[index];
+        let name = &self.entries[page.entry];
+        let buf = self.archive.lock().ok()?.fetch(name).ok()?;
+        let doc = self.opener.open_memory(name, &buf)?;
+        let pixmap = doc.page(0).and_then(|p| p.pixmap(scale, samples))?;
+        let pixmap = match page.half {
+            Some(half) => crop_half(&pixmap, half)?,
+            None => pixmap,
+        };
+        Some((pixmap, index))
+    }
+
+    fn layout(&mut self, _width: u32, _height: u32, _font_size: f32, _dpi: u16) {}
+
+    fn set_font_family(&mut self, _family_name: &str, _search_path: &str) {}
+
+    fn set_margin_width(&mut self, _width: i32) {}
+
+    fn set_text_align(&mut self, _text_align: TextAlign) {}
+
+    fn set_line_height(&mut self, _line_height: f32) {}
+
+    fn set_hyphen_penalty(&mut self, _hyphen_penalty: i32) {}
+
+    fn set_stretch_tolerance(&mut self, _stretch_tolerance: f32) {}
+
+    fn set_ignore_document_css(&mut self, _ignore: bool) {}
+
+    fn title(&self) -> Option<String> {
+        self.metadata("Title").or_else(|| {
+            self.series().map(|(series, number)| {
+                if number.is_empty() {
+                    series
+                } else {
+                    format!("{} #{}", series, number)
+                }
+            })
+        })
+    }
+
+    fn author(&self) -> Option<String> {
+        self.metadata("Writer")
+    }
+
+    fn metadata(&self, key: &str) -> Option<String> {
+        self.comic_info()
+            .and_then(|comic_info| comic_info.find(key))
+            .map(|node| decode_entities(node.text().trim()).into_owned())
+            .filter(|text| !text.is_empty())
+    }
+
+    fn is_reflowable(&self) -> bool {
+        false
+    }
+
+    fn is_rtl(&self) -> bool {
+        self.rtl
+    }
+}
+
+pub fn is_image(name: &str) -> bool {
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    !file_name.starts_with('.')
+        && Path::new(file_name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| IMAGE_KINDS.contains(&ext.to_lowercase().as_str()))
+}
+
+// *Yes* only marks the book as a manga, whose pages might still be read from left to right.
+fn is_right_to_left(info: &XmlTree) -> bool {
+    info.root()
+        .find("Manga")
+        .is_some_and(|node| node.text().trim() == "YesAndRightToLeft")
+}
+
+fn is_spread(width: u32, height: u32) -> bool {
+    width > height
+}
+
+// The indices of the images flagged as double pages and the bookmarks, as given
+// by the *Pages* element.
+fn page_infos(info: &XmlTree) -> (BTreeSet<usize>, BTreeMap<usize, String>) {
+    let mut spreads = BTreeSet::new();
+    let mut bookmarks = BTreeMap::new();
+
+    if let Some(pages) = info.root().find("Pages") {
+        for page in pages
+            .children()
+            .filter(|child| child.tag_name() == Some("Page"))
+        {
+            if let Some(index) = page
+                .attribute("Image")
+                .and_then(|image| image.parse::<usize>().ok())
+            {
+                if page
+                    .attribute("DoublePage")
+                    .is_some_and(|value| value.eq_ignore_ascii_case("true"))
+                {
+                    spreads.insert(index);
+                }
+                if let Some(bookmark) = page.attribute("Bookmark").filter(|b| !b.is_empty()) {
+                    bookmarks.insert(index, decode_entities(bookmark).into_owned());
+                }
+            }
+        }
+    }
+
+    (spreads, bookmarks)
+}
+
+// The index of the image marked as the front cover in ComicInfo.xml.
+fn front_cover(info: &XmlTree) -> Option<usize> {
+    info.root()
+        .find("Pages")?
+        .children()
+        .filter(|child| child.tag_name() == Some("Page"))
+        .find(|page| page.attribute("Type") == Some("FrontCover"))
+        .and_then(|page| page.attribute("Image"))
+        .and_then(|image| image.parse::<usize>().ok())
+}
+
+// Compares file names, treating sequences of digits as numbers.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut m = String::new();
+                while let Some(c) = a.peek().copied().filter(char::is_ascii_digit) {
+                    m.push(c);
+                    a.next();
+                }
+                let mut n = String::new();
+                while let Some(c) = b.peek().copied().filter(char::is_ascii_digit) {
+                    n.push(c);
+                    b.next();
+                }
+                let m = m.trim_start_matches('0');
+                let n = n.trim_start_matches('0');
+                let ord = m.len().cmp(&n.len()).then_with(|| m.cmp(n));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ord = x.to_lowercase().cmp(y.to_lowercase());
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+// Reads the dimensions of a PNG, GIF, BMP or JPEG image from its header.
+fn image_size(buf: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |i: usize| -> Option<u32> {
+        buf.get(i..i + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as u32)
+    };
+    let le16 = |i: usize| -> Option<u32> {
+        buf.get(i..i + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as u32)
+    };
+    let be32 = |i: usize| -> Option<u32> {
+        buf.get(i..i + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let le32 = |i: usize| -> Option<u32> {
+        buf.get(i..i + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+
+    if buf.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return be32(16).zip(be32(20));
+    }
+
+    if buf.starts_with(b"GIF8") {
+        return le16(6).zip(le16(8));
+    }
+
+    if buf.starts_with(b"BM") {
+        return le32(18).zip(le32(22).map(|h| (h as i32).unsigned_abs()));
+    }
+
+    if buf.starts_with(b"\xFF\xD8") {
+        let mut i = 2;
+        while i + 4 <= buf.len() {
+            if buf[i] != 0xFF {
+                return None;
+            }
+            let marker = buf[i + 1];
+            // Fill bytes and standalone markers.
+            if marker == 0xFF {
+                i += 1;
+                continue;
+            }
+            if marker == 0x01 || (0xD0..=0xD8).contains(&marker) {
+                i += 2;
+                continue;
+            }
+            let len = be16(i + 2)? as usize;
+            // Start of frame markers, except DHT, JPG and DAC.
+            if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+                return be16(i + 7).zip(be16(i + 5));
+            }
+            i += 2 + len;
+        }
+    }
+
+    None
+}
+
+fn crop_half(pixmap: &Pixmap, half: Half) -> Option<Pixmap> {
+    let width = (pixmap.width / 2) as i32;
+    let x0 = match half {
+        Half::Left => 0,
+        Half::Right => pixmap.width as i32 - width,
+    };
+    pixmap.crop(&rect![x0, 0, x0 + width, pixmap.height as i32])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_natural_order() {
+        let mut names = vec!["p10.jpg", "p2.jpg", "P1.jpg", "p02b.jpg", "cover.png"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec!["cover.png", "P1.jpg", "p2.jpg", "p02b.jpg", "p10.jpg"]
+        );
+    }
+
+    #[test]
+    fn test_comic_info() {
+        let text = r#"<?xml version="1.0"?>
+<ComicInfo>
+  <Series>Akira</Series>
+  <Number>3</Number>
+  <Manga>YesAndRightToLeft</Manga>
+  <Pages>
+    <Page Image="0" Type="FrontCover"/>
+    <Page Image="4" DoublePage="True" Bookmark="Chapter 2"/>
+  </Pages>
+</ComicInfo>"#;
+        let info = XmlParser::new(text).parse();
+        assert!(is_right_to_left(&info));
+        let info = XmlParser::new(&text.replace("YesAndRightToLeft", "Yes")).parse();
+        assert!(!is_right_to_left(&info));
+        assert_eq!(front_cover(&info), Some(0));
+        assert_eq!(front_cover(&XmlTree::new()), None);
+        let (spreads, bookmarks) = page_infos(&info);
+        assert_eq!(spreads.into_iter().collect::<Vec<_>>(), vec![4]);
+        assert_eq!(bookmarks.get(&4).map(String::as_str), Some("Chapter 2"));
+    }
+
+    #[test]
+    fn test_image_size() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&1600u32.to_be_bytes());
+        png.extend_from_slice(&1200u32.to_be_bytes());
+        assert_eq!(image_size(&png), Some((1600, 1200)));
+        let jpeg = b"\xFF\xD8\xFF\xE0\x00\x04\x00\x00\xFF\xC0\x00\x11\x08\x04\xB0\x03\x20";
+        assert_eq!(image_size(jpeg), Some((800, 1200)));
+        assert!(!is_spread(800, 1200));
+    }
+}
@@ -1,16 +1,20 @@
 use super::html::css::CssParser;
 use super::html::dom::{NodeRef, XmlTree};
-use super::html::engine::{Engine, Page, ResourceFetcher};
-use super::html::layout::TextAlign;
+use super::html::engine::{root_writing_mode, Engine, Page, ResourceFetcher};
+use super::html::layout::{Direction, TextAlign, WritingMode};
 use super::html::layout::{DrawCommand, DrawState, ImageCommand, RootData, TextCommand};
 use super::html::layout::{LoopContext, StyleData};
+use super::html::parse::parse_direction;
 use super::html::style::StyleSheet;
 use super::html::xml::XmlParser;
-use super::pdf::PdfOpener;
-use crate::document::{chapter_from_uri, BoundedText, Document, Location, TextLocation, TocEntry};
+use super::html::HtmlDocument;
+use crate::document::{
+    chapter_from_uri, cover_pixmap, BoundedText, Document, Location, TextLocation, TocEntry,
+};
 use crate::framebuffer::Pixmap;
-use crate::geom::{Boundary, CycleDir};
+use crate::geom::{Boundary, CycleDir, Edge};
 use crate::helpers::{decode_entities, Normalize};
+use crate::settings::DEFAULT_LINE_HEIGHT;
 use crate::unit::pt_to_px;
 use anyhow::{format_err, Error};
 use fxhash::FxHashMap;
@@ -23,6 +27,12 @@ use zip::ZipArchive;
 
 const VIEWER_STYLESHEET: &str = "css/epub.css";
 const USER_STYLESHEET: &str = "css/epub-user.css";
+// One CSS pixel per device pixel.
+const FIXED_LAYOUT_DPI: u16 = 96;
+// The initial font size of CSS: 16px.
+const FIXED_LAYOUT_FONT_SIZE: f32 = 12.0;
+// The number of parsed chapters kept for the lookup of notes.
+const PARSED_CHAPTERS_CAPACITY: usize = 4;
 
 type UriCache = FxHashMap<String, usize>;
 
@@ -42,13 +52,21 @@ pub struct EpubDocument {
     engine: Engine,
     spine: Vec<Chunk>,
     cache: FxHashMap<usize, Vec<Page>>,
+    // The parsed chapters in which notes were looked up, by path.
+    parsed_chapters: FxHashMap<String, XmlTree>,
     ignore_document_css: bool,
+    // The dimensions of the pages of the fixed-layout chunks that don't declare any.
+    default_viewport: (u32, u32),
 }
 
 #[derive(Debug)]
 struct Chunk {
     path: String,
     size: usize,
+    fixed_layout: bool,
+    // The dimensions of the page, in CSS pixels, of a fixed-layout chunk, known once the
+    // chunk is laid out.
+    viewport: Option<(u32, u32)>,
 }
 
 unsafe impl Send for EpubDocument {}
@@ -96,6 +114,16 @@ impl EpubDocument {
                 .find("spine")
                 .ok_or_else(|| format_err!("the spine is missing"))?;
 
+            let global_layout = info.root().find("metadata").and_then(|md| {
+                md.children()
+                    .find(|child| {
+                        child.tag_name() == Some("meta")
+                            && child.attribute("property") == Some("rendition:layout")
+                    })
+                    .map(|child| child.text())
+            });
+            let pre_paginated = global_layout.as_deref().map(str::trim) == Some("pre-paginated");
+
             for child in spn.children() {
                 let vertebra_opt = child
                     .attribute("idref")
@@ -121,7 +149,20 @@ impl EpubDocument {
                     });
 
                 if let Some((size, path)) = vertebra_opt {
-                    spine.push(Chunk { path, size });
+                    let properties = child.attribute("properties").unwrap_or_default();
+                    let fixed_layout = if properties.contains("rendition:layout-pre-paginated") {
+                        true
+                    } else if properties.contains("rendition:layout-reflowable") {
+                        false
+                    } else {
+                        pre_paginated
+                    };
+                    spine.push(Chunk {
+                        path,
+                        size,
+                        fixed_layout,
+                        viewport: None,
+                    });
                 }
             }
         }
@@ -130,14 +171,30 @@ impl EpubDocument {
             return Err(format_err!("the spine is empty"));
         }
 
+        let engine = Engine::new();
+
+        let default_viewport = info
+            .root()
+            .find("metadata")
+            .and_then(|md| {
+                md.children().find(|child| {
+                    child.tag_name() == Some("meta")
+                        && child.attribute("property") == Some("rendition:viewport")
+                })
+            })
+            .and_then(|child| parse_viewport(&child.text()))
+            .unwrap_or(engine.dims);
+
         Ok(EpubDocument {
             archive,
             info,
             parent: parent.to_path_buf(),
-            engine: Engine::new(),
+            engine,
             spine,
             cache: FxHashMap::default(),
+            parsed_chapters: FxHashMap::default(),
             ignore_document_css: false,
+            default_viewport,
         })
     }
 
@@ -347,6 +404,33 @@ impl EpubDocument {
         }
     }
 
+    fn normalize_local_uri(&self, offset: usize, uri: &str) -> Option<String> {
+        let (index, _) = self.vertebra_coordinates(offset)?;
+        let path = &self.spine[index].path;
+        if uri.starts_with('#') {
+            Some(format!("{}{}", path, uri))
+        } else {
+            let parent = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+            Some(parent.join(uri).normalize().to_string_lossy().into_owned())
+        }
+    }
+
+    // Parses the given chapter, unless it was already.
+    fn parse_chapter(&mut self, name: &str) -> Option<()> {
+        if self.parsed_chapters.contains_key(name) {
+            return Some(());
+        }
+        let mut text = String::new();
+        let mut zf = self.archive.by_name(name).ok()?;
+        zf.read_to_string(&mut text).ok()?;
+        if self.parsed_chapters.len() >= PARSED_CHAPTERS_CAPACITY {
+            self.parsed_chapters.clear();
+        }
+        self.parsed_chapters
+            .insert(name.to_string(), XmlParser::new(&text).parse());
+        Some(())
+    }
+
     fn cache_uris(&mut self, node: NodeRef, name: &str, start_offset: usize, cache: &mut UriCache) {
         if let Some(id) = node.attribute("id") {
             let location = start_offset + node.offset();
@@ -357,24 +441,68 @@ impl EpubDocument {
         }
     }
 
+    // Runs the given closure with the engine set up for the viewport of the given chunk,
+    // if it has a fixed layout: the user's settings don't apply to such chunks.
+    fn with_viewport<T, F>(&mut self, index: usize, f: F) -> T
+    where
+        F: FnOnce(&mut EpubDocument) -> T,
+    {
+        let viewport = match self.spine[index].viewport {
+            Some(viewport) => viewport,
+            None => return f(self),
+        };
+        let engine = &mut self.engine;
+        let settings = (
+            engine.dims,
+            engine.dpi,
+            engine.font_size,
+            engine.line_height,
+            engine.text_align,
+            engine.margin,
+        );
+        engine.dims = viewport;
+        engine.dpi = FIXED_LAYOUT_DPI;
+        engine.font_size = FIXED_LAYOUT_FONT_SIZE;
+        engine.line_height = DEFAULT_LINE_HEIGHT;
+        engine.text_align = TextAlign::Left;
+        engine.margin = Edge::default();
+        let result = f(self);
+        let engine = &mut self.engine;
+        (
+            engine.dims,
+            engine.dpi,
+            engine.font_size,
+            engine.line_height,
+            engine.text_align,
+            engine.margin,
+        ) = settings;
+        result
+    }
+
     fn build_display_list(&mut self, index: usize, start_offset: usize) -> Vec<Page> {
         let mut text = String::new();
-        let mut spine_dir = PathBuf::default();
-
-        {
-            let path = &self.spine[index].path;
-            if let Some(parent) = Path::new(path).parent() {
-                spine_dir = parent.to_path_buf();
-            }
-
-            if let Ok(mut zf) = self.archive.by_name(path) {
-                zf.read_to_string(&mut text).ok();
-            }
+        if let Ok(mut zf) = self.archive.by_name(&self.spine[index].path) {
+            zf.read_to_string(&mut text).ok();
         }
 
         let mut root = XmlParser::new(&text).parse();
         root.wrap_lost_inlines();
 
+        let chunk = &mut self.spine[index];
+        if chunk.fixed_layout && chunk.viewport.is_none() {
+            chunk.viewport = Some(chunk_viewport(root.root()).unwrap_or(self.default_viewport));
+        }
+
+        self.with_viewport(index, |doc| doc.layout_chunk(index, start_offset, root))
+    }
+
+    fn layout_chunk(&mut self, index: usize, start_offset: usize, root: XmlTree) -> Vec<Page> {
+        let fixed = self.spine[index].fixed_layout;
+        let spine_dir = Path::new(&self.spine[index].path)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
         let mut stylesheet = StyleSheet::new();
 
         if let Ok(text) = fs::read_to_string(VIEWER_STYLESHEET) {
@@ -400,6 +528,9 @@ impl EpubDocument {
                                 if let Ok(mut zf) = self.archive.by_name(name) {
                                     zf.read_to_string(&mut text).ok();
                                     let mut css = CssParser::new(&text).parse();
+                                    css.set_base_dir(
+                                        Path::new(name).parent().unwrap_or_else(|| Path::new("")),
+                                    );
                                     inner_css.append(&mut css, false);
                                 }
                             }
@@ -408,20 +539,40 @@ impl EpubDocument {
                         && child.attribute("type") == Some("text/css")
                     {
                         let mut css = CssParser::new(&child.text()).parse();
+                        css.set_base_dir(&spine_dir);
                         inner_css.append(&mut css, false);
                     }
                 }
             }
 
+            self.engine.load_font_faces(&inner_css, &mut self.archive);
             stylesheet.append(&mut inner_css, true);
         }
 
+        stylesheet.evaluate_media(&self.engine.media_context());
+
         let mut display_list = Vec::new();
 
         if let Some(body) = root.root().find("body") {
             let mut rect = self.engine.rect();
             rect.shrink(&self.engine.margin);
 
+            let page_rect = rect;
+
+            // Fixed-layout pages aren't paginated: what overflows the viewport is clipped.
+            if fixed {
+                rect.max.y = i32::MAX / 2;
+            }
+            let writing_mode = root_writing_mode(root.root(), &stylesheet);
+
+            // The columns of the vertical writing mode are laid out as lines in a rotated frame.
+            if writing_mode == WritingMode::VerticalRl {
+                rect = rect![
+                    rect.min,
+                    rect.min + pt!(rect.height() as i32, rect.width() as i32)
+                ];
+            }
+
             let language = self.language().or_else(|| {
                 root.root()
                     .find("html")
@@ -429,15 +580,31 @@ impl EpubDocument {
                     .map(String::from)
             });
 
+            let direction = root
+                .root()
+                .find("html")
+                .and_then(|html| html.attribute("dir"))
+                .and_then(parse_direction)
+                .or_else(|| self.page_progression_direction())
+                .unwrap_or(Direction::Ltr);
+
+            let text_align = if direction == Direction::Rtl {
+                self.engine.text_align.mirrored()
+            } else {
+                self.engine.text_align
+            };
+
             let style = StyleData {
                 language,
+                direction,
+                writing_mode,
                 font_size: self.engine.font_size,
                 line_height: pt_to_px(
                     self.engine.line_height * self.engine.font_size,
                     self.engine.dpi,
                 )
                 .round() as i32,
-                text_align: self.engine.text_align,
+                text_align,
                 start_x: rect.min.x,
                 end_x: rect.max.x,
                 width: rect.max.x - rect.min.x,
@@ -447,6 +614,7 @@ impl EpubDocument {
             let loop_context = LoopContext::default();
             let mut draw_state = DrawState {
                 position: rect.min,
+                containing_block: fixed.then_some(page_rect),
                 ..Default::default()
             };
 
@@ -469,6 +637,14 @@ impl EpubDocument {
                 &mut display_list,
             );
 
+            if writing_mode == WritingMode::VerticalRl {
+                self.engine.rotate_pages(&mut display_list, page_rect);
+            }
+
+            if fixed {
+                display_list = vec![display_list.into_iter().flatten().collect()];
+            }
+
             display_list.retain(|page| !page.is_empty());
 
             if display_list.is_empty() {
@@ -701,37 +877,34 @@ impl EpubDocument {
         self.metadata("dc:date")
             .map(|s| s.chars().take(4).collect())
     }
+
+    pub fn page_progression_direction(&self) -> Option<Direction> {
+        self.info
+            .root()
+            .find("spine")
+            .and_then(|spine| spine.attribute("page-progression-direction"))
+            .and_then(parse_direction)
+    }
 }
 
 impl Document for EpubDocument {
     fn preview_pixmap(&mut self, width: f32, height: f32, samples: usize) -> Option<Pixmap> {
-        let opener = PdfOpener::new()?;
-        self.cover_image()
+        let cover = self
+            .cover_image()
             .map(|path| self.parent.join(path).to_string_lossy().into_owned())
-            .and_then(|path| {
-                self.archive
-                    .fetch(&path)
-                    .ok()
-                    .and_then(|buf| opener.open_memory(&path, &buf))
-                    .and_then(|mut doc| {
-                        doc.dims(0).and_then(|dims| {
-                            let scale = (width / dims.0).min(height / dims.1);
-                            doc.pixmap(Location::Exact(0), scale, samples)
-                        })
-                    })
-            })
-            .or_else(|| {
-                self.dims(0).and_then(|dims| {
-                    let scale = (width / dims.0).min(height / dims.1);
-                    self.pixmap(Location::Exact(0), scale, samples)
-                })
-            })
-            .map(|(pixmap, _)| pixmap)
+            .and_then(|path| self.archive.fetch(&path).ok().map(|buf| (path, buf)));
+        cover_pixmap(self, cover, width, height, samples)
     }
 
     #[inline]
-    fn dims(&self, _index: usize) -> Option<(f32, f32)> {
-        Some((self.engine.dims.0 as f32, self.engine.dims.1 as f32))
+    fn dims(&self, index: usize) -> Option<(f32, f32)> {
+        let (width, height) = self
+            .vertebra_coordinates(index)
+            .map(|(index, _)| &self.spine[index])
+            .filter(|chunk| chunk.fixed_layout)
+            .map(|chunk| chunk.viewport.unwrap_or(self.default_viewport))
+            .unwrap_or(self.engine.dims);
+        Some((width as f32, height as f32))
     }
 
     fn pages_count(&self) -> usize {
@@ -936,16 +1109,7 @@ impl Document for EpubDocument {
             }
             Location::LocalUri(offset, ref uri) => {
                 let mut cache = FxHashMap::default();
-                let normalized_uri: String = {
-                    let (index, _) = self.vertebra_coordinates(offset)?;
-                    let path = &self.spine[index].path;
-                    if uri.starts_with('#') {
-                        format!("{}{}", path, uri)
-                    } else {
-                        let parent = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
-                        parent.join(uri).normalize().to_string_lossy().into_owned()
-                    }
-                };
+                let normalized_uri = self.normalize_local_uri(offset, uri)?;
                 self.resolve_link(&normalized_uri, &mut cache)
             }
             Location::Uri(ref uri) => {
@@ -1054,9 +1218,10 @@ impl Document for EpubDocument {
         let page_index = self.page_index(offset, index, start_offset)?;
         let page = self.cache.get(&index)?.get(page_index)?.clone();
 
-        let pixmap = self
-            .engine
-            .render_page(&page, scale, samples, &mut self.archive)?;
+        let pixmap = self.with_viewport(index, |doc| {
+            doc.engine
+                .render_page(&page, scale, samples, &mut doc.archive)
+        })?;
 
         Some((pixmap, offset))
     }
@@ -1121,11 +1286,272 @@ impl Document for EpubDocument {
             .map(|child| decode_entities(&child.text()).into_owned())
     }
 
+    // The documents that mix both layouts are reflowable.
     fn is_reflowable(&self) -> bool {
-        true
+        !self.spine.iter().all(|chunk| chunk.fixed_layout)
     }
 
     fn has_synthetic_page_numbers(&self) -> bool {
         true
     }
+
+    fn is_rtl(&self) -> bool {
+        self.page_progression_direction() == Some(Direction::Rtl)
+    }
+
+    fn footnote(&mut self, loc: Location) -> Option<HtmlDocument> {
+        let (offset, uri) = match loc {
+            Location::LocalUri(offset, uri) => (offset, uri),
+            _ => return None,
+        };
+
+        let normalized_uri = self.normalize_local_uri(offset, &uri)?;
+        let (name, id) = normalized_uri.split_once('#')?;
+        let (index, start_offset) = self.vertebra_coordinates(offset)?;
+        let source_name = self.spine[index].path.clone();
+
+        self.parse_chapter(&source_name)?;
+        self.parse_chapter(name)?;
+        let source = &self.parsed_chapters[&source_name];
+        // The link is the last anchor that starts before the tapped text.
+        let is_noteref = source
+            .root()
+            .descendants()
+            .filter(|n| n.tag_name() == Some("a") && n.offset() + start_offset <= offset)
+            .last()
+            .is_some_and(|anchor| {
+                has_semantic(anchor, &["noteref"])
+                    || anchor.attribute("role") == Some("doc-noteref")
+            });
+
+        let target_tree = &self.parsed_chapters[name];
+        let target = target_tree.root().find_by_id(id)?;
+
+        let note = std::iter::once(target)
+            .chain(target.ancestor_elements())
+            .find(|n| is_note(*n))
+            .or_else(|| {
+                if is_noteref {
+                    std::iter::once(target)
+                        .chain(target.ancestor_elements())
+                        .find(|n| n.is_block())
+                        .filter(|n| !matches!(n.tag_name(), Some("body" | "html")))
+                } else {
+                    None
+                }
+            })?;
+
+        // The note is laid out with the stylesheets of its chapter, and the resources
+        // it references are taken from the archive.
+        let chapter_dir = Path::new(name).parent().unwrap_or_else(|| Path::new(""));
+        let mut head = String::new();
+        let mut resources = Vec::new();
+
+        if let Some(head_node) = target_tree.root().find("head") {
+            for child in head_node.children() {
+                if child.tag_name() == Some("link") && child.attribute("rel") == Some("stylesheet")
+                {
+                    if let Some(href) = child.attribute("href") {
+                        if let Some(path) = chapter_dir.join(href).normalize().to_str() {
+                            resources.push(path.to_string());
+                        }
+                    }
+                    head.push_str(&child.markup());
+                } else if child.tag_name() == Some("style") {
+                    head.push_str(&child.markup());
+                }
+            }
+        }
+
+        for node in std::iter::once(note).chain(note.descendants()) {
+            let src = match node.tag_name() {
+                Some("img") => node.attribute("src"),
+                Some("image") => node.attribute("xlink:href"),
+                _ => None,
+            };
+            if let Some(path) = src.and_then(|src| {
+                chapter_dir.join(src).normalize().to_str().map(|path| {
+                    percent_decode_str(&decode_entities(path))
+                        .decode_utf8_lossy()
+                        .into_owned()
+                })
+            }) {
+                resources.push(path);
+            }
+        }
+
+        let mut doc = HtmlDocument::new_from_memory(&format!(
+            "<html><head>{}</head><body>{}</body></html>",
+            head,
+            note.markup()
+        ));
+        doc.set_base_dir(chapter_dir);
+        doc.set_viewer_stylesheet(VIEWER_STYLESHEET);
+        doc.set_user_stylesheet(USER_STYLESHEET);
+        doc.set_ignore_document_css(self.ignore_document_css);
+
+        for path in resources {
+            if let Ok(buf) = self.archive.fetch(&path) {
+                doc.add_resource(&path, buf);
+            }
+        }
+
+        Some(doc)
+    }
+}
+
+fn has_semantic(node: NodeRef, values: &[&str]) -> bool {
+    node.attribute("epub:type")
+        .is_some_and(|t| t.split_whitespace().any(|v| values.contains(&v)))
+}
+
+fn is_note(node: NodeRef) -> bool {
+    node.tag_name() == Some("aside")
+        || has_semantic(node, &["footnote", "endnote", "rearnote", "note"])
+        || matches!(node.attribute("role"), Some("doc-footnote" | "doc-endnote"))
+}
+
+// Returns the viewport declared by a fixed-layout chunk, or the size of its SVG root.
+fn chunk_viewport(root: NodeRef) -> Option<(u32, u32)> {
+    root.find("head")
+        .and_then(|head| {
+            head.children().find(|child| {
+                child.tag_name() == Some("meta") && child.attribute("name") == Some("viewport")
+            })
+        })
+        .and_then(|meta| meta.attribute("content"))
+        .and_then(parse_viewport)
+        .or_else(|| {
+            root.find("svg").and_then(|svg| {
+                svg.attribute("viewBox")
+                    .and_then(|view_box| {
+                        let values: Vec<f32> = view_box
+                            .split(|c: char| c.is_whitespace() || c == ',')
+                            .filter_map(|v| v.parse().ok())
+                            .collect();
+                        (values.len() == 4).then(|| (values[2], values[3]))
+                    })
+                    .or_else(|| {
+                        svg.attribute("width")
+                            .zip(svg.attribute("height"))
+                            .and_then(|(w, h)| {
+                                Some((
+                                    w.trim_end_matches("px").parse().ok()?,
+                                    h.trim_end_matches("px").parse().ok()?,
+                                ))
+                            })
+                    })
+                    .filter(|&(w, h): &(f32, f32)| w > 0.0 && h > 0.0)
+                    .map(|(w, h)| (w.round() as u32, h.round() as u32))
+            })
+        })
+}
+
+// Parses the content of a viewport declaration, e.g. `width=1200, height=1600`.
+fn parse_viewport(content: &str) -> Option<(u32, u32)> {
+    let mut width = None;
+    let mut height = None;
+    for pair in content.split([',', ';']) {
+        if let Some((key, value)) = pair.split_once('=') {
+            let value = value.trim().trim_end_matches("px").parse::<f32>().ok();
+            match key.trim() {
+                "width" => width = value,
+                "height" => height = value,
+                _ => (),
+            }
+        }
+    }
+    match (width, height) {
+        (Some(w), Some(h)) if w > 0.0 && h > 0.0 => Some((w.round() as u32, h.round() as u32)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn viewport_declarations() {
+        assert_eq!(
+            parse_viewport("width=1200, height=1600"),
+            Some((1200, 1600))
+        );
+        assert_eq!(
+            parse_viewport("height = 800px; width = 600px"),
+            Some((600, 800))
+        );
+        assert_eq!(parse_viewport("width=device-width, initial-scale=1"), None);
+    }
+
+    #[test]
+    fn mixed_layouts() {
+        use std::io::Write;
+        use zip::write::{SimpleFileOptions, ZipWriter};
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut zip = ZipWriter::new(file.reopen().unwrap());
+        let entries = [
+            (
+                "META-INF/container.xml",
+                "<?xml version=\"1.0\"?>\
+                 <container><rootfiles>\
+                 <rootfile full-path=\"content.opf\" media-type=\"application/oebps-package+xml\"/>\
+                 </rootfiles></container>",
+            ),
+            (
+                "content.opf",
+                "<?xml version=\"1.0\"?>\
+                 <package><metadata></metadata>\
+                 <manifest>\
+                 <item id=\"cover\" href=\"cover.xhtml\" media-type=\"application/xhtml+xml\"/>\
+                 <item id=\"text\" href=\"text.xhtml\" media-type=\"application/xhtml+xml\"/>\
+                 </manifest>\
+                 <spine>\
+                 <itemref idref=\"cover\" properties=\"rendition:layout-pre-paginated\"/>\
+                 <itemref idref=\"text\"/>\
+                 </spine></package>",
+            ),
+            (
+                "cover.xhtml",
+                "<html><head><meta name=\"viewport\" content=\"width=300, height=400\"/></head>\
+                 <body><p>Cover</p></body></html>",
+            ),
+            (
+                "text.xhtml",
+                "<html><head></head><body><p>Text</p></body></html>",
+            ),
+        ];
+        for (name, content) in entries {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let root_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let mut doc = EpubDocument::new(file.path()).unwrap();
+        doc.engine.load_fonts_from(&root_dir);
+        doc.layout(600, 800, 12.0, 300);
+
+        assert!(doc.is_reflowable());
+        assert!(doc.spine.iter().all(|chunk| chunk.viewport.is_none()));
+
+        let words = doc
+            .words(Location::Exact(0))
+            .map(|(words, _)| words)
+            .unwrap_or_default();
+        assert!(words.iter().any(|w| w.text == "Cover"));
+        assert_eq!(doc.spine[0].viewport, Some((300, 400)));
+        assert_eq!(doc.dims(0), Some((300.0, 400.0)));
+
+        let offset = doc.spine[0].size;
+        let words = doc
+            .words(Location::Exact(offset))
+            .map(|(words, _)| words)
+            .unwrap_or_default();
+        assert!(words.iter().any(|w| w.text == "Text"));
+        assert_eq!(doc.spine[1].viewport, None);
+        let (width, height) = doc.engine.dims;
+        assert_eq!(doc.dims(offset), Some((width as f32, height as f32)));
+    }
 }
@@ -0,0 +1,999 @@
+use super::html::css::CssParser;
+use super::html::dom::{NodeData, NodeRef, TextData, XmlTree};
+use super::html::engine::{Engine, Page, ResourceFetcher};
+use super::html::layout::{DrawCommand, DrawState, ImageCommand, RootData, TextCommand};
+use super::html::layout::{LoopContext, StyleData, TextAlign};
+use super::html::style::StyleSheet;
+use super::html::xml::XmlParser;
+use super::html::HtmlDocument;
+use super::text::decode_text;
+use super::{chapter, chapter_relative, cover_pixmap};
+use crate::document::{BoundedText, Document, Location, TextLocation, TocEntry};
+use crate::framebuffer::Pixmap;
+use crate::geom::{Boundary, CycleDir};
+use crate::helpers::decode_entities;
+use crate::unit::pt_to_px;
+use anyhow::{format_err, Error};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use fxhash::FxHashMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+const VIEWER_STYLESHEET: &str = "css/fb2.css";
+const USER_STYLESHEET: &str = "css/fb2-user.css";
+
+const CHUNK_HEADER: &str = "<html><body>";
+const CHUNK_FOOTER: &str = "</body></html>";
+
+// The elements whose images are displayed as blocks.
+const BLOCK_CONTAINERS: [&str; 7] = [
+    "body",
+    "section",
+    "epigraph",
+    "cite",
+    "annotation",
+    "coverpage",
+    "poem",
+];
+
+// The embedded binaries, encoded in base64, indexed by name.
+struct Binaries(FxHashMap<String, String>);
+
+impl ResourceFetcher for Binaries {
+    fn fetch(&mut self, name: &str) -> Result<Vec<u8>, Error> {
+        let data = self
+            .0
+            .get(name)
+            .ok_or_else(|| format_err!("can't find the binary named {}", name))?;
+        STANDARD.decode(data).map_err(Into::into)
+    }
+}
+
+pub struct Fb2Document {
+    // The description of the book.
+    info: XmlTree,
+    binaries: Binaries,
+    engine: Engine,
+    // The viewer and user stylesheets.
+    stylesheet: StyleSheet,
+    // The XHTML conversions of the bodies, split at their top-level sections.
+    chunks: Vec<String>,
+    ids: FxHashMap<String, usize>,
+    notes: FxHashMap<String, String>,
+    toc: Vec<TocEntry>,
+    cover: Option<String>,
+    cache: FxHashMap<usize, Vec<Page>>,
+}
+
+unsafe impl Send for Fb2Document {}
+unsafe impl Sync for Fb2Document {}
+
+impl Fb2Document {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Fb2Document, Error> {
+        let buf = fs::read(path)?;
+        Fb2Document::new_from_memory(&buf)
+    }
+
+    pub fn new_from_memory(buf: &[u8]) -> Result<Fb2Document, Error> {
+        let text = decode_text(buf);
+        let tree = XmlParser::new(&text).parse();
+        let root = tree
+            .root()
+            .find("FictionBook")
+            .ok_or_else(|| format_err!("the root element is missing"))?;
+
+        let info = XmlParser::new(
+            &root
+                .find("description")
+                .map(|desc| desc.markup())
+                .unwrap_or_default(),
+        )
+        .parse();
+
+        let mut binaries = FxHashMap::default();
+        let mut names = FxHashMap::default();
+        for child in root.children() {
+            if child.tag_name() != Some("binary") {
+                continue;
+            }
+            if let Some(id) = child.attribute("id") {
+                let content_type = child.attribute("content-type").unwrap_or_default();
+                let name = binary_name(id, content_type);
+                let data = child
+                    .text()
+                    .chars()
+                    .filter(|c| !c.is_ascii_whitespace())
+                    .collect::<String>();
+                names.insert(id.to_string(), name.clone());
+                binaries.insert(name, data);
+            }
+        }
+
+        let mut converter = Converter {
+            names,
+            ..Default::default()
+        };
+        let mut toc = Vec::new();
+
+        let cover = info
+            .root()
+            .find("title-info")
+            .and_then(|title_info| title_info.find("coverpage"))
+            .and_then(|coverpage| coverpage.find("image"))
+            .and_then(href)
+            .and_then(|href| converter.names.get(href.trim_start_matches('#')))
+            .cloned();
+
+        if let Some(name) = cover.as_ref() {
+            converter.open_chunk();
+            converter.buf.push_str(&format!(
+                "<div class=\"cover\"><img src=\"{}\"/></div>",
+                name
+            ));
+            converter.close_chunk();
+        }
+
+        for (index, body) in root
+            .children()
+            .filter(|child| child.tag_name() == Some("body"))
+            .enumerate()
+        {
+            // The bodies that follow the main one hold the notes.
+            converter.convert_body(body, index > 0, &mut toc);
+        }
+
+        if converter.chunks.is_empty() {
+            return Err(format_err!("the body is empty"));
+        }
+
+        let mut stylesheet = StyleSheet::new();
+
+        for path in [VIEWER_STYLESHEET, USER_STYLESHEET] {
+            if let Ok(text) = fs::read_to_string(path) {
+                let mut css = CssParser::new(&text).parse();
+                stylesheet.append(&mut css, true);
+            }
+        }
+
+        Ok(Fb2Document {
+            info,
+            binaries: Binaries(binaries),
+            engine: Engine::new(),
+            stylesheet,
+            chunks: converter.chunks,
+            ids: converter.ids,
+            notes: converter.notes,
+            toc,
+            cover,
+            cache: FxHashMap::default(),
+        })
+    }
+
+    fn size(&self) -> usize {
+        self.chunks.iter().map(String::len).sum()
+    }
+
+    fn chunk_coordinates(&self, offset: usize) -> Option<(usize, usize)> {
+        let mut start_offset = 0;
+
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            if offset < start_offset + chunk.len() {
+                return Some((index, start_offset));
+            }
+            start_offset += chunk.len();
+        }
+
+        None
+    }
+
+    #[inline]
+    fn page_index(&mut self, offset: usize, index: usize, start_offset: usize) -> Option<usize> {
+        self.display_list(index, start_offset).map(|display_list| {
+            display_list
+                .iter()
+                .rposition(|page| page.first().is_some_and(|dc| offset >= dc.offset()))
+                .unwrap_or(0)
+        })
+    }
+
+    fn display_list(&mut self, index: usize, start_offset: usize) -> Option<&Vec<Page>> {
+        if !self.cache.contains_key(&index) {
+            let display_list = self.build_display_list(index, start_offset);
+            self.cache.insert(index, display_list);
+        }
+        self.cache.get(&index)
+    }
+
+    fn build_display_list(&mut self, index: usize, start_offset: usize) -> Vec<Page> {
+        let mut root = XmlParser::new(&self.chunks[index]).parse();
+        root.wrap_lost_inlines();
+
+        let mut stylesheet = self.stylesheet.clone();
+        stylesheet.evaluate_media(&self.engine.media_context());
+
+        let mut display_list = Vec::new();
+
+        if let Some(body) = root.root().find("body") {
+            let mut rect = self.engine.rect();
+            rect.shrink(&self.engine.margin);
+
+            let style = StyleData {
+                language: self.language(),
+                font_size: self.engine.font_size,
+                line_height: pt_to_px(
+                    self.engine.line_height * self.engine.font_size,
+                    self.engine.dpi,
+                )
+                .round() as i32,
+                text_align: self.engine.text_align,
+                start_x: rect.min.x,
+                end_x: rect.max.x,
+                width: rect.max.x - rect.min.x,
+                ..Default::default()
+            };
+
+            let loop_context = LoopContext::default();
+            let mut draw_state = DrawState {
+                position: rect.min,
+                ..Default::default()
+            };
+
+            let root_data = RootData {
+                start_offset,
+                spine_dir: PathBuf::default(),
+                rect,
+            };
+
+            display_list.push(Vec::new());
+
+            self.engine.build_display_list(
+                body,
+                &style,
+                &loop_context,
+                &stylesheet,
+                &root_data,
+                &mut self.binaries,
+                &mut draw_state,
+                &mut display_list,
+            );
+
+            display_list.retain(|page| !page.is_empty());
+
+            if display_list.is_empty() {
+                display_list.push(vec![DrawCommand::Marker(start_offset + body.offset())]);
+            }
+        } else {
+            display_list.push(vec![DrawCommand::Marker(start_offset)]);
+        }
+
+        display_list
+    }
+
+    fn title_info(&self) -> Option<NodeRef<'_>> {
+        self.info.root().find("title-info")
+    }
+
+    fn publish_info(&self) -> Option<NodeRef<'_>> {
+        self.info.root().find("publish-info")
+    }
+
+    pub fn categories(&self) -> BTreeSet<String> {
+        self.title_info()
+            .map(|title_info| {
+                title_info
+                    .children()
+                    .filter(|child| child.tag_name() == Some("genre"))
+                    .map(|child| decode_entities(child.text().trim()).into_owned())
+                    .filter(|genre| !genre.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn series(&self) -> Option<(String, String)> {
+        self.title_info()
+            .and_then(|title_info| title_info.find("sequence"))
+            .or_else(|| {
+                self.publish_info()
+                    .and_then(|publish_info| publish_info.find("sequence"))
+            })
+            .and_then(|sequence| {
+                sequence.attribute("name").map(|name| {
+                    (
+                        decode_entities(name).into_owned(),
+                        sequence.attribute("number").unwrap_or_default().to_string(),
+                    )
+                })
+            })
+    }
+
+    pub fn description(&self) -> Option<String> {
+        self.metadata("annotation")
+    }
+
+    pub fn publisher(&self) -> Option<String> {
+        self.publish_info()
+            .and_then(|publish_info| publish_info.find("publisher"))
+            .map(node_text)
+    }
+
+    pub fn language(&self) -> Option<String> {
+        self.metadata("lang")
+    }
+
+    pub fn year(&self) -> Option<String> {
+        self.title_info()
+            .and_then(|title_info| title_info.find("date"))
+            .and_then(|date| {
+                date.attribute("value")
+                    .map(String::from)
+                    .or_else(|| Some(node_text(date)))
+            })
+            .or_else(|| {
+                self.publish_info()
+                    .and_then(|publish_info| publish_info.find("year"))
+                    .map(node_text)
+            })
+            .map(|s| s.chars().take(4).collect())
+            .filter(|s: &String| !s.is_empty())
+    }
+}
+
+impl Document for Fb2Document {
+    fn preview_pixmap(&mut self, width: f32, height: f32, samples: usize) -> Option<Pixmap> {
+        let cover = self
+            .cover
+            .clone()
+            .and_then(|name| self.binaries.fetch(&name).ok().map(|buf| (name, buf)));
+        cover_pixmap(self, cover, width, height, samples)
+    }
+
+    #[inline]
+    fn dims(&self, _index: usize) -> Option<(f32, f32)> {
+        Some((self.engine.dims.0 as f32, self.engine.dims.1 as f32))
+    }
+
+    fn pages_count(&self) -> usize {
+        self.size()
+    }
+
+    fn toc(&mut self) -> Option<Vec<TocEntry>> {
+        if self.toc.is_empty() {
+            None
+        } else {
+            Some(self.toc.clone())
+        }
+    }
+
+    fn chapter<'a>(&mut self, offset: usize, toc: &'a [TocEntry]) -> Option<(&'a TocEntry, f32)> {
+        chapter(offset, self.size(), toc)
+    }
+
+    fn chapter_relative<'a>(
+        &mut self,
+        offset: usize,
+        dir: CycleDir,
+        toc: &'a [TocEntry],
+    ) -> Option<&'a TocEntry> {
+        chapter_relative(offset, dir, toc)
+    }
+
+    fn resolve_location(&mut self, loc: Location) -> Option<usize> {
+        self.engine.load_fonts();
+
+        match loc {
+            Location::Exact(offset) => {
+                let (index, start_offset) = self.chunk_coordinates(offset)?;
+                let page_index = self.page_index(offset, index, start_offset)?;
+                self.cache
+                    .get(&index)
+                    .and_then(|display_list| display_list[page_index].first())
+                    .map(DrawCommand::offset)
+            }
+            Location::Previous(offset) => {
+                let (index, start_offset) = self.chunk_coordinates(offset)?;
+                let page_index = self.page_index(offset, index, start_offset)?;
+                if page_index > 0 {
+                    self.cache.get(&index).and_then(|display_list| {
+                        display_list[page_index - 1]
+                            .first()
+                            .map(DrawCommand::offset)
+                    })
+                } else {
+                    if index == 0 {
+                        return None;
+                    }
+                    let start_offset = start_offset - self.chunks[index - 1].len();
+                    self.display_list(index - 1, start_offset)
+                        .and_then(|display_list| display_list.last())
+                        .and_then(|page| page.first())
+                        .map(DrawCommand::offset)
+                }
+            }
+            Location::Next(offset) => {
+                let (index, start_offset) = self.chunk_coordinates(offset)?;
+                let page_index = self.page_index(offset, index, start_offset)?;
+                if page_index < self.cache.get(&index).map(Vec::len)? - 1 {
+                    self.cache.get(&index).and_then(|display_list| {
+                        display_list[page_index + 1]
+                            .first()
+                            .map(DrawCommand::offset)
+                    })
+                } else {
+                    if index == self.chunks.len() - 1 {
+                        return None;
+                    }
+                    let start_offset = start_offset + self.chunks[index].len();
+                    self.display_list(index + 1, start_offset)
+                        .and_then(|display_list| display_list.first())
+                        .and_then(|page| page.first())
+                        .map(DrawCommand::offset)
+                }
+            }
+            Location::LocalUri(_, ref uri) | Location::Uri(ref uri) => {
+                let (_, id) = uri.split_once('#')?;
+                self.ids.get(id).cloned()
+            }
+        }
+    }
+
+    fn words(&mut self, loc: Location) -> Option<(Vec<BoundedText>, usize)> {
+        let offset = self.resolve_location(loc)?;
+        let (index, start_offset) = self.chunk_coordinates(offset)?;
+        let page_index = self.page_index(offset, index, start_offset)?;
+
+        self.cache.get(&index).map(|display_list| {
+            (
+                display_list[page_index]
+                    .iter()
+                    .filter_map(|dc| match dc {
+                        DrawCommand::Text(TextCommand {
+                            text, rect, offset, ..
+                        }) => Some(BoundedText {
+                            text: text.clone(),
+                            rect: (*rect).into(),
+                            location: TextLocation::Dynamic(*offset),
+                        }),
+                        _ => None,
+                    })
+                    .collect(),
+                offset,
+            )
+        })
+    }
+
+    fn lines(&mut self, _loc: Location) -> Option<(Vec<BoundedText>, usize)> {
+        None
+    }
+
+    fn links(&mut self, loc: Location) -> Option<(Vec<BoundedText>, usize)> {
+        let offset = self.resolve_location(loc)?;
+        let (index, start_offset) = self.chunk_coordinates(offset)?;
+        let page_index = self.page_index(offset, index, start_offset)?;
+
+        self.cache.get(&index).map(|display_list| {
+            (
+                display_list[page_index]
+                    .iter()
+                    .filter_map(|dc| match dc {
+                        DrawCommand::Text(TextCommand {
+                            uri, rect, offset, ..
+                        })
+                        | DrawCommand::Image(ImageCommand {
+                            uri, rect, offset, ..
+                        }) if uri.is_some() => Some(BoundedText {
+                            text: uri.clone().unwrap(),
+                            rect: (*rect).into(),
+                            location: TextLocation::Dynamic(*offset),
+                        }),
+                        _ => None,
+                    })
+                    .collect(),
+                offset,
+            )
+        })
+    }
+
+    fn images(&mut self, loc: Location) -> Option<(Vec<Boundary>, usize)> {
+        let offset = self.resolve_location(loc)?;
+        let (index, start_offset) = self.chunk_coordinates(offset)?;
+        let page_index = self.page_index(offset, index, start_offset)?;
+
+        self.cache.get(&index).map(|display_list| {
+            (
+                display_list[page_index]
+                    .iter()
+                    .filter_map(|dc| match dc {
+                        DrawCommand::Image(ImageCommand { rect, .. }) => Some((*rect).into()),
+                        _ => None,
+                    })
+                    .collect(),
+                offset,
+            )
+        })
+    }
+
+    fn pixmap(&mut self, loc: Location, scale: f32, samples: usize) -> Option<(Pixmap, usize)> {
+        let offset = self.resolve_location(loc)?;
+        let (index, start_offset) = self.chunk_coordinates(offset)?;
+        let page_index = self.page_index(offset, index, start_offset)?;
+        let page = self.cache.get(&index)?.get(page_index)?.clone();
+
+        let pixmap = self
+            .engine
+            .render_page(&page, scale, samples, &mut self.binaries)?;
+
+        Some((pixmap, offset))
+    }
+
+    fn layout(&mut self, width: u32, height: u32, font_size: f32, dpi: u16) {
+        self.engine.layout(width, height, font_size, dpi);
+        self.cache.clear();
+    }
+
+    fn set_text_align(&mut self, text_align: TextAlign) {
+        self.engine.set_text_align(text_align);
+        self.cache.clear();
+    }
+
+    fn set_font_family(&mut self, family_name: &str, search_path: &str) {
+        self.engine.set_font_family(family_name, search_path);
+        self.cache.clear();
+    }
+
+    fn set_margin_width(&mut self, width: i32) {
+        self.engine.set_margin_width(width);
+        self.cache.clear();
+    }
+
+    fn set_line_height(&mut self, line_height: f32) {
+        self.engine.set_line_height(line_height);
+        self.cache.clear();
+    }
+
+    fn set_hyphen_penalty(&mut self, hyphen_penalty: i32) {
+        self.engine.set_hyphen_penalty(hyphen_penalty);
+        self.cache.clear();
+    }
+
+    fn set_stretch_tolerance(&mut self, stretch_tolerance: f32) {
+        self.engine.set_stretch_tolerance(stretch_tolerance);
+        self.cache.clear();
+    }
+
+    // The style sheets of FictionBook target its own elements, not their conversions.
+    fn set_ignore_document_css(&mut self, _ignore: bool) {}
+
+    fn title(&self) -> Option<String> {
+        self.metadata("book-title")
+    }
+
+    fn author(&self) -> Option<String> {
+        let authors = self
+            .title_info()?
+            .children()
+            .filter(|child| child.tag_name() == Some("author"))
+            .filter_map(author_name)
+            .collect::<Vec<String>>();
+
+        if authors.is_empty() {
+            None
+        } else {
+            Some(authors.join(", "))
+        }
+    }
+
+    fn metadata(&self, key: &str) -> Option<String> {
+        self.title_info()
+            .and_then(|title_info| title_info.find(key))
+            .map(node_text)
+            .filter(|text| !text.is_empty())
+    }
+
+    fn is_reflowable(&self) -> bool {
+        true
+    }
+
+    fn has_synthetic_page_numbers(&self) -> bool {
+        true
+    }
+
+    fn footnote(&mut self, loc: Location) -> Option<HtmlDocument> {
+        let uri = match loc {
+            Location::LocalUri(_, uri) => uri,
+            _ => return None,
+        };
+        let (_, id) = uri.split_once('#')?;
+        let note = self.notes.get(id)?;
+        let mut doc =
+            HtmlDocument::new_from_memory(&format!("{}{}{}", CHUNK_HEADER, note, CHUNK_FOOTER));
+        doc.set_viewer_stylesheet(VIEWER_STYLESHEET);
+        doc.set_user_stylesheet(USER_STYLESHEET);
+
+        let tree = XmlParser::new(note).parse();
+        for node in tree.root().descendants() {
+            if let Some(name) = node
+                .attribute("src")
+                .filter(|_| node.tag_name() == Some("img"))
+            {
+                if let Ok(buf) = self.binaries.fetch(name) {
+                    doc.add_resource(name, buf);
+                }
+            }
+        }
+
+        Some(doc)
+    }
+}
+
+#[derive(Default)]
+struct Converter {
+    // The names of the binaries, indexed by identifier.
+    names: FxHashMap<String, String>,
+    chunks: Vec<String>,
+    buf: String,
+    start_offset: usize,
+    ids: FxHashMap<String, usize>,
+    notes: FxHashMap<String, String>,
+    index: usize,
+}
+
+impl Converter {
+    fn offset(&self) -> usize {
+        self.start_offset + self.buf.len()
+    }
+
+    fn open_chunk(&mut self) {
+        if self.buf.is_empty() {
+            self.buf.push_str(CHUNK_HEADER);
+        }
+    }
+
+    fn close_chunk(&mut self) {
+        if self.buf.len() > CHUNK_HEADER.len() {
+            self.buf.push_str(CHUNK_FOOTER);
+            let chunk = mem::take(&mut self.buf);
+            self.start_offset += chunk.len();
+            self.chunks.push(chunk);
+        } else {
+            self.buf.clear();
+        }
+    }
+
+    fn convert_body(&mut self, body: NodeRef, is_notes: bool, toc: &mut Vec<TocEntry>) {
+        self.open_chunk();
+
+        if is_notes {
+            if let Some(title) = body
+                .children()
+                .find(|child| child.tag_name() == Some("title"))
+            {
+                toc.push(TocEntry {
+                    title: title_text(title),
+                    location: Location::Exact(self.offset()),
+                    index: self.index,
+                    children: Vec::new(),
+                });
+                self.index += 1;
+            }
+        }
+
+        for child in body.children().filter(NodeRef::is_element) {
+            if child.tag_name() == Some("section") {
+                // Each top-level section of the main body is laid out on its own.
+                if !is_notes {
+                    self.close_chunk();
+                    self.open_chunk();
+                }
+                let mut entries = Vec::new();
+                self.convert_section(child, 1, is_notes, &mut entries);
+                if !is_notes {
+                    toc.append(&mut entries);
+                    self.close_chunk();
+                    self.open_chunk();
+                }
+            } else {
+                self.convert(child, 0);
+            }
+        }
+
+        self.close_chunk();
+    }
+
+    fn convert_section(
+        &mut self,
+        node: NodeRef,
+        depth: usize,
+        is_notes: bool,
+        entries: &mut Vec<TocEntry>,
+    ) {
+        let offset = self.offset();
+        let title = node
+            .children()
+            .find(|child| child.tag_name() == Some("title"))
+            .map(title_text)
+            .filter(|title| !title.is_empty());
+        let index = self.index;
+        if title.is_some() {
+            self.index += 1;
+        }
+
+        let mut children = Vec::new();
+        self.open_tag("div", Some("section"), node);
+        for child in node.children() {
+            if child.tag_name() == Some("section") {
+                self.convert_section(child, depth + 1, is_notes, &mut children);
+            } else {
+                self.convert(child, depth);
+            }
+        }
+        self.buf.push_str("</div>");
+
+        if is_notes {
+            if let Some(id) = node.attribute("id") {
+                let start = offset - self.start_offset;
+                self.notes
+                    .insert(id.to_string(), self.buf[start..].to_string());
+            }
+        }
+
+        if let Some(title) = title {
+            entries.push(TocEntry {
+                title,
+                location: Location::Exact(offset),
+                index,
+                children,
+            });
+        } else {
+            entries.append(&mut children);
+        }
+    }
+
+    fn open_tag(&mut self, name: &str, class: Option<&str>, node: NodeRef) {
+        if let Some(id) = node.attribute("id") {
+            self.ids.insert(id.to_string(), self.offset());
+        }
+        self.buf.push('<');
+        self.buf.push_str(name);
+        if let Some(class) = class {
+            self.buf.push_str(&format!(" class=\"{}\"", class));
+        }
+        if let Some(attributes) = node.attributes() {
+            for (key, value) in attributes {
+                if matches!(
+                    key.as_str(),
+                    "id" | "colspan" | "rowspan" | "align" | "valign"
+                ) {
+                    self.buf
+                        .push_str(&format!(" {}=\"{}\"", key, value.replace('"', "&quot;")));
+                }
+            }
+        }
+        self.buf.push('>');
+    }
+
+    fn wrap(&mut self, name: &str, class: Option<&str>, node: NodeRef, depth: usize) {
+        self.open_tag(name, class, node);
+        for child in node.children() {
+            self.convert(child, depth);
+        }
+        self.buf.push_str(&format!("</{}>", name));
+    }
+
+    fn convert(&mut self, node: NodeRef, depth: usize) {
+        match node.data() {
+            NodeData::Text(TextData { text, .. }) | NodeData::Whitespace(TextData { text, .. }) => {
+                self.buf.push_str(text);
+            }
+            NodeData::Element(..) => match node.tag_name().unwrap_or_default() {
+                "p" => self.wrap("p", None, node, depth),
+                "title" => {
+                    let heading = format!("h{}", (depth + 1).min(6));
+                    self.open_tag("div", Some("title"), node);
+                    for child in node.children() {
+                        if child.tag_name() == Some("p") {
+                            self.wrap(&heading, None, child, depth);
+                        } else if child.tag_name() != Some("empty-line") {
+                            self.convert(child, depth);
+                        }
+                    }
+                    self.buf.push_str("</div>");
+                }
+                "subtitle" => self.wrap("p", Some("subtitle"), node, depth),
+                "epigraph" => self.wrap("blockquote", Some("epigraph"), node, depth),
+                "cite" => self.wrap("blockquote", Some("cite"), node, depth),
+                "annotation" => self.wrap("div", Some("annotation"), node, depth),
+                "poem" => self.wrap("div", Some("poem"), node, depth),
+                "stanza" => self.wrap("div", Some("stanza"), node, depth),
+                "v" => self.wrap("p", Some("v"), node, depth),
+                "text-author" => self.wrap("p", Some("text-author"), node, depth),
+                "date" => self.wrap("p", Some("date"), node, depth),
+                "empty-line" => self.buf.push_str("<p class=\"empty-line\">&#160;</p>"),
+                "strong" => self.wrap("strong", None, node, depth),
+                "emphasis" => self.wrap("em", None, node, depth),
+                "style" => self.wrap("span", None, node, depth),
+                "strikethrough" => self.wrap("span", Some("strikethrough"), node, depth),
+                "sub" | "sup" | "code" | "table" | "tr" | "td" | "th" => {
+                    let name = node.tag_name().unwrap_or_default();
+                    self.wrap(name, None, node, depth);
+                }
+                "a" => {
+                    let class = (node.attribute("type") == Some("note")).then_some("note");
+                    self.open_tag("a", class, node);
+                    self.buf.pop();
+                    if let Some(href) = href(node) {
+                        self.buf
+                            .push_str(&format!(" href=\"{}\"", href.replace('"', "&quot;")));
+                    }
+                    self.buf.push('>');
+                    for child in node.children() {
+                        self.convert(child, depth);
+                    }
+                    self.buf.push_str("</a>");
+                }
+                "image" => {
+                    let name = href(node)
+                        .and_then(|href| self.names.get(href.trim_start_matches('#')))
+                        .cloned();
+                    if let Some(name) = name {
+                        let is_block = node
+                            .parent_element()
+                            .and_then(|parent| parent.tag_name())
+                            .is_some_and(|tag| BLOCK_CONTAINERS.contains(&tag));
+                        if is_block {
+                            self.open_tag("div", Some("image"), node);
+                            self.buf.push_str(&format!("<img src=\"{}\"/></div>", name));
+                        } else {
+                            self.buf.push_str(&format!("<img src=\"{}\"/>", name));
+                        }
+                    }
+                }
+                _ => {
+                    for child in node.children() {
+                        self.convert(child, depth);
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+// The name under which a binary is fetched: its extension reveals its type.
+fn binary_name(id: &str, content_type: &str) -> String {
+    let extension = match content_type {
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/svg+xml" => "svg",
+        _ => return id.to_string(),
+    };
+    let has_extension = Path::new(id)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension));
+    if has_extension {
+        id.to_string()
+    } else {
+        format!("{}.{}", id, extension)
+    }
+}
+
+// The namespace prefix of the links varies between books.
+fn href<'a>(node: NodeRef<'a>) -> Option<&'a str> {
+    node.attributes()?
+        .iter()
+        .find(|(key, _)| key.as_str() == "href" || key.ends_with(":href"))
+        .map(|(_, value)| value.as_str())
+}
+
+fn node_text(node: NodeRef) -> String {
+    decode_entities(&node.text())
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+fn title_text(node: NodeRef) -> String {
+    node.children()
+        .filter(|child| child.tag_name() == Some("p"))
+        .map(node_text)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn author_name(node: NodeRef) -> Option<String> {
+    let name = ["first-name", "middle-name", "last-name"]
+        .iter()
+        .filter_map(|key| node.find(key).map(node_text))
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    if name.is_empty() {
+        node.find("nickname")
+            .map(node_text)
+            .filter(|nickname| !nickname.is_empty())
+    } else {
+        Some(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOK: &str = r##"<?xml version="1.0" encoding="utf-8"?>
+<FictionBook xmlns="http://www.gribuser.ru/xml/fictionbook/2.0" xmlns:l="http://www.w3.org/1999/xlink">
+<description><title-info>
+<genre>sf_space</genre><genre>adventure</genre>
+<author><first-name>Arkady</first-name><last-name>Strugatsky</last-name></author>
+<author><first-name>Boris</first-name><last-name>Strugatsky</last-name></author>
+<book-title>Noon: 22nd Century</book-title>
+<date value="1962-01-01">1962</date><lang>en</lang>
+<coverpage><image l:href="#cover"/></coverpage>
+<sequence name="Noon Universe" number="1"/>
+</title-info></description>
+<body><title><p>Noon</p></title>
+<section id="s1"><title><p>Part One</p></title>
+<epigraph><p>An epigraph.</p><text-author>Someone</text-author></epigraph>
+<section><title><p>Chapter 1</p></title><p>Text<a l:href="#n1" type="note">1</a>.</p></section>
+<section><title><p>Chapter 2</p></title><poem><stanza><v>A verse.</v></stanza></poem></section>
+</section>
+</body>
+<body name="notes"><title><p>Notes</p></title>
+<section id="n1"><title><p>1</p></title><p>A note.</p></section>
+</body>
+<binary id="cover" content-type="image/png">iVBORw0K
+Gg==</binary>
+</FictionBook>"##;
+
+    #[test]
+    fn test_metadata() {
+        let doc = Fb2Document::new_from_memory(BOOK.as_bytes()).unwrap();
+        assert_eq!(doc.title().as_deref(), Some("Noon: 22nd Century"));
+        assert_eq!(
+            doc.author().as_deref(),
+            Some("Arkady Strugatsky, Boris Strugatsky")
+        );
+        assert_eq!(doc.year().as_deref(), Some("1962"));
+        assert_eq!(doc.language().as_deref(), Some("en"));
+        assert_eq!(
+            doc.series(),
+            Some(("Noon Universe".to_string(), "1".to_string()))
+        );
+        assert_eq!(
+            doc.categories().into_iter().collect::<Vec<String>>(),
+            vec!["adventure".to_string(), "sf_space".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_conversion() {
+        let mut doc = Fb2Document::new_from_memory(BOOK.as_bytes()).unwrap();
+        // The cover, the title of the main body, its section and the notes.
+        assert_eq!(doc.chunks.len(), 4);
+        assert!(doc.chunks[0].contains("<img src=\"cover.png\"/>"));
+        assert!(doc.chunks[2].contains("<blockquote class=\"epigraph\"><p>An epigraph.</p>"));
+        assert!(doc.chunks[2].contains("<a class=\"note\" href=\"#n1\">1</a>"));
+        assert!(doc.chunks[2].contains("<p class=\"v\">A verse.</p>"));
+        assert_eq!(doc.binaries.fetch("cover.png").unwrap()[..4], *b"\x89PNG");
+
+        let toc = doc.toc().unwrap();
+        assert_eq!(toc.len(), 2);
+        assert_eq!(toc[0].title, "Part One");
+        assert_eq!(toc[0].children[1].title, "Chapter 2");
+        assert_eq!(toc[1].title, "Notes");
+
+        let offset = doc.ids["s1"];
+        assert!(
+            doc.chunks[2][offset - doc.chunks[0].len() - doc.chunks[1].len()..]
+                .starts_with("<div class=\"section\" id=\"s1\">")
+        );
+        assert!(doc
+            .footnote(Location::LocalUri(0, "#n1".to_string()))
+            .is_some_and(|note| note.text().contains("<p>A note.</p>")));
+    }
+}
@@ -1,10 +1,12 @@
 use super::style::StyleSheet;
 use fxhash::FxHashSet;
+use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
 pub struct Selector {
     pub simple_selectors: Vec<SimpleSelector>,
     pub combinators: Vec<Combinator>,
+    pub pseudo_element: Option<PseudoElement>,
 }
 
 #[derive(Debug, Clone)]
@@ -20,12 +22,33 @@ pub struct SimpleSelector {
 pub struct Attribute {
     pub name: String,
     pub operator: AttributeOperator,
+    // `[attr=value i]`: the value is stored in lowercase.
+    pub case_insensitive: bool,
 }
 
 #[derive(Debug, Clone)]
 pub enum PseudoClass {
     FirstChild,
     LastChild,
+    OnlyChild,
+    FirstOfType,
+    LastOfType,
+    OnlyOfType,
+    // `:nth-child(an+b)`
+    NthChild(i32, i32),
+    NthLastChild(i32, i32),
+    NthOfType(i32, i32),
+    NthLastOfType(i32, i32),
+    Empty,
+    Root,
+    // `:not(a, b)`
+    Not(Vec<SimpleSelector>),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PseudoElement {
+    FirstLetter,
+    FirstLine,
 }
 
 #[derive(Debug, Clone)]
@@ -38,6 +61,12 @@ pub enum AttributeOperator {
     Contains(String),
     // `[attr|=value]`
     StartsWith(String),
+    // `[attr^=value]`
+    Prefix(String),
+    // `[attr$=value]`
+    Suffix(String),
+    // `[attr*=value]`
+    Substring(String),
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -58,6 +87,7 @@ impl Default for Selector {
         Selector {
             simple_selectors: Vec::new(),
             combinators: Vec::new(),
+            pseudo_element: None,
         }
     }
 }
@@ -80,16 +110,70 @@ impl Selector {
     pub fn specificity(&self) -> Specificity {
         let mut spec = [0usize; 3];
         for sel in &self.simple_selectors {
-            spec[0] = spec[0].saturating_add(sel.id.iter().count());
-            spec[1] = spec[1].saturating_add(sel.classes.len());
-            spec[1] = spec[1].saturating_add(sel.pseudo_classes.len());
-            spec[1] = spec[1].saturating_add(sel.attributes.len());
-            spec[2] = spec[2].saturating_add(sel.tag_name.iter().count());
+            let sel_spec = sel.specificity();
+            for i in 0..3 {
+                spec[i] = spec[i].saturating_add(sel_spec[i]);
+            }
+        }
+        spec[2] = spec[2].saturating_add(self.pseudo_element.iter().count());
+        spec
+    }
+}
+
+impl SimpleSelector {
+    fn specificity(&self) -> Specificity {
+        let mut spec = [0usize; 3];
+        spec[0] = spec[0].saturating_add(self.id.iter().count());
+        spec[1] = spec[1].saturating_add(self.classes.len());
+        spec[1] = spec[1].saturating_add(self.attributes.len());
+        spec[2] = spec[2].saturating_add(self.tag_name.iter().count());
+        for pc in &self.pseudo_classes {
+            // The specificity of a negation is the one of its most specific argument.
+            if let PseudoClass::Not(selectors) = pc {
+                if let Some(arg_spec) = selectors.iter().map(SimpleSelector::specificity).max() {
+                    for i in 0..3 {
+                        spec[i] = spec[i].saturating_add(arg_spec[i]);
+                    }
+                }
+            } else {
+                spec[1] = spec[1].saturating_add(1);
+            }
         }
         spec
     }
 }
 
+// Parses the argument of the `:nth-*` pseudo-classes.
+fn parse_nth(text: &str) -> Option<(i32, i32)> {
+    let text = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    match text.as_str() {
+        "odd" => Some((2, 1)),
+        "even" => Some((2, 0)),
+        _ => {
+            if let Some(index) = text.find('n') {
+                let a = match &text[..index] {
+                    "" | "+" => 1,
+                    "-" => -1,
+                    a => a.parse().ok()?,
+                };
+                let b = if index + 1 < text.len() {
+                    text[index + 1..].parse().ok()?
+                } else {
+                    0
+                };
+                Some((a, b))
+            } else {
+                text.parse().ok().map(|b| (0, b))
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Declaration {
     pub name: String,
@@ -111,6 +195,139 @@ impl Default for Declaration {
 pub struct Rule {
     pub selector: Selector,
     pub declarations: Vec<Declaration>,
+    // Index, in the style sheet, of the query list of the enclosing `@media` rule.
+    pub media: Option<usize>,
+}
+
+// `[only|not] type and (feature: value) and …`
+#[derive(Debug, Clone)]
+pub struct MediaQuery {
+    pub negated: bool,
+    // `None` stands for `all`.
+    pub media_type: Option<String>,
+    pub features: Vec<MediaFeature>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MediaFeature {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+pub type MediaQueryList = Vec<MediaQuery>;
+
+impl MediaQuery {
+    // Queries that can't be parsed are equivalent to `not all`.
+    fn never() -> MediaQuery {
+        MediaQuery {
+            negated: true,
+            media_type: None,
+            features: Vec::new(),
+        }
+    }
+}
+
+// Parses the argument of `:not()`: a list of compound selectors.
+fn parse_negation(text: &str) -> Option<Vec<SimpleSelector>> {
+    let count = text.split(',').count();
+    let selectors = CssParser::new(&format!("{} {{", text.trim())).parse_selectors();
+
+    if selectors.len() != count
+        || selectors
+            .iter()
+            .any(|s| s.simple_selectors.len() != 1 || s.pseudo_element.is_some())
+    {
+        return None;
+    }
+
+    Some(
+        selectors
+            .into_iter()
+            .filter_map(|mut s| s.simple_selectors.pop())
+            .collect(),
+    )
+}
+
+pub fn parse_media_query_list(text: &str) -> MediaQueryList {
+    let mut queries = Vec::new();
+
+    for query in text.split(',') {
+        let query = query.trim();
+        if query.is_empty() {
+            continue;
+        }
+
+        let mut media_query = MediaQuery {
+            negated: false,
+            media_type: None,
+            features: Vec::new(),
+        };
+        let mut valid = true;
+        let mut rest = query;
+
+        while !rest.is_empty() {
+            if let Some(inner) = rest.strip_prefix('(') {
+                if let Some(end) = inner.find(')') {
+                    let feature = &inner[..end];
+                    let (name, value) = match feature.find(':') {
+                        Some(index) => (&feature[..index], Some(feature[index + 1..].trim())),
+                        None => (feature, None),
+                    };
+                    media_query.features.push(MediaFeature {
+                        name: name.trim().to_lowercase(),
+                        value: value.map(String::from),
+                    });
+                    rest = inner[end + 1..].trim_start();
+                } else {
+                    valid = false;
+                    break;
+                }
+            } else {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '(')
+                    .unwrap_or(rest.len());
+                let word = rest[..end].to_lowercase();
+                match word.as_str() {
+                    "not" => media_query.negated = true,
+                    "only" | "and" => (),
+                    _ if media_query.media_type.is_none() && media_query.features.is_empty() => {
+                        media_query.media_type = Some(word);
+                    }
+                    _ => {
+                        valid = false;
+                        break;
+                    }
+                }
+                rest = rest[end..].trim_start();
+            }
+        }
+
+        queries.push(if valid {
+            media_query
+        } else {
+            MediaQuery::never()
+        });
+    }
+
+    queries
+}
+
+// An `@font-face` at-rule.
+#[derive(Debug, Clone)]
+pub struct FontFace {
+    pub declarations: Vec<Declaration>,
+    // The directory against which the `src` URLs are resolved.
+    pub base_dir: PathBuf,
+}
+
+impl FontFace {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.declarations
+            .iter()
+            .rev()
+            .find(|d| d.name == name)
+            .map(|d| d.value.as_str())
+    }
 }
 
 #[derive(Debug)]
@@ -226,6 +443,94 @@ impl<'a> CssParser<'a> {
         }
     }
 
+    // Returns the text up to the closing parenthesis, which is skipped.
+    fn parenthesized(&mut self) -> String {
+        let offset = self.offset;
+        let mut depth = 0u8;
+
+        while let Some(c) = self.next() {
+            match c {
+                '(' => depth = depth.saturating_add(1),
+                ')' if depth == 0 => break,
+                ')' => depth -= 1,
+                _ => (),
+            }
+            self.advance(1);
+        }
+
+        let text = self.input[offset..self.offset].to_string();
+        self.advance(1);
+        text
+    }
+
+    // Parses an attribute selector, the opening bracket excepted.
+    fn parse_attribute(&mut self) -> Option<Attribute> {
+        self.skip_spaces_and_comments();
+        let offset = self.offset;
+        self.skip_ident();
+        let mut name = self.input[offset..self.offset].to_string();
+        if self.next() == Some('|') && !self.starts_with("|=") {
+            self.advance(1);
+            name += ":";
+            let offset = self.offset;
+            self.skip_ident();
+            name += &self.input[offset..self.offset];
+        }
+        self.skip_spaces_and_comments();
+
+        let kind = match self.next() {
+            Some(']') => {
+                self.advance(1);
+                return Some(Attribute {
+                    name,
+                    operator: AttributeOperator::Exists,
+                    case_insensitive: false,
+                });
+            }
+            Some('=') => {
+                self.advance(1);
+                '='
+            }
+            Some(c @ ('~' | '|' | '^' | '$' | '*'))
+                if self.input[self.offset + 1..].starts_with('=') =>
+            {
+                self.advance(2);
+                c
+            }
+            _ => {
+                self.advance_while(|&c| c != ']');
+                self.advance(1);
+                return None;
+            }
+        };
+
+        self.skip_spaces_and_comments();
+        let mut value = self.attribute_value();
+        self.skip_spaces_and_comments();
+        let case_insensitive = matches!(self.next(), Some('i' | 'I'));
+        self.advance_while(|&c| c != ']');
+        self.advance(1);
+
+        if case_insensitive {
+            value = value.to_lowercase();
+        }
+
+        let operator = match kind {
+            '~' => AttributeOperator::Contains(value),
+            '|' => AttributeOperator::StartsWith(value),
+            '^' => AttributeOperator::Prefix(value),
+            '$' => AttributeOperator::Suffix(value),
+            '*' => AttributeOperator::Substring(value),
+            _ => AttributeOperator::Matches(value),
+        };
+
+        Some(Attribute {
+            name,
+            operator,
+            case_insensitive,
+        })
+    }
+
     fn parse_selectors(&mut self) -> Vec<Selector> {
         let mut supported = true;
         let mut selectors = Vec::new();
@@ -251,90 +556,65 @@ impl<'a> CssParser<'a> {
                 }
                 Some('[') => {
                     self.advance(1);
-                    self.skip_spaces_and_comments();
-                    let offset = self.offset;
-                    self.skip_ident();
-                    let mut name = self.input[offset..self.offset].to_string();
-                    if self.next() == Some('|') && !self.starts_with("|=") {
-                        self.advance(1);
-                        name += ":";
-                        let offset = self.offset;
-                        self.skip_ident();
-                        name += &self.input[offset..self.offset];
-                    }
-                    self.skip_spaces_and_comments();
-                    match self.next() {
-                        Some(']') => {
-                            self.advance(1);
-                            selec.attributes.push(Attribute {
-                                name,
-                                operator: AttributeOperator::Exists,
-                            });
-                        }
-                        Some('=') => {
-                            self.advance(1);
-                            self.skip_spaces_and_comments();
-                            let value = self.attribute_value();
-                            selec.attributes.push(Attribute {
-                                name,
-                                operator: AttributeOperator::Matches(value),
-                            });
-                            self.skip_spaces_and_comments();
-                            self.advance(1);
-                        }
-                        Some('~') => {
-                            self.advance(2);
-                            self.skip_spaces_and_comments();
-                            let value = self.attribute_value();
-                            selec.attributes.push(Attribute {
-                                name,
-                                operator: AttributeOperator::Contains(value),
-                            });
-                            self.skip_spaces_and_comments();
-                            self.advance(1);
-                        }
-                        Some('|') => {
-                            self.advance(2);
-                            self.skip_spaces_and_comments();
-                            let value = self.attribute_value();
-                            selec.attributes.push(Attribute {
-                                name,
-                                operator: AttributeOperator::StartsWith(value),
-                            });
-                            self.skip_spaces_and_comments();
-                            self.advance(1);
-                        }
-                        _ => {
-                            self.advance(2);
-                            self.skip_spaces_and_comments();
-                            self.advance(1);
-                            supported = false;
-                        }
+                    if let Some(attribute) = self.parse_attribute() {
+                        selec.attributes.push(attribute);
+                    } else {
+                        supported = false;
                     }
                 }
                 Some(':') => {
                     self.advance(1);
-                    if self.next() == Some(':') {
-                        supported = false;
+                    let is_element = self.next() == Some(':');
+                    if is_element {
                         self.advance(1);
                     }
                     let offset = self.offset;
                     self.skip_ident();
-                    match &self.input[offset..self.offset] {
-                        "first-child" => {
-                            selec.pseudo_classes.push(PseudoClass::FirstChild);
+                    let name = self.input[offset..self.offset].to_ascii_lowercase();
+                    let argument = if self.next() == Some('(') {
+                        self.advance(1);
+                        Some(self.parenthesized())
+                    } else {
+                        None
+                    };
+                    let pseudo_class = match (name.as_str(), argument.as_deref()) {
+                        _ if is_element => None,
+                        ("first-child", None) => Some(PseudoClass::FirstChild),
+                        ("last-child", None) => Some(PseudoClass::LastChild),
+                        ("only-child", None) => Some(PseudoClass::OnlyChild),
+                        ("first-of-type", None) => Some(PseudoClass::FirstOfType),
+                        ("last-of-type", None) => Some(PseudoClass::LastOfType),
+                        ("only-of-type", None) => Some(PseudoClass::OnlyOfType),
+                        ("empty", None) => Some(PseudoClass::Empty),
+                        ("root", None) => Some(PseudoClass::Root),
+                        ("nth-child", Some(arg)) => {
+                            parse_nth(arg).map(|(a, b)| PseudoClass::NthChild(a, b))
+                        }
+                        ("nth-last-child", Some(arg)) => {
+                            parse_nth(arg).map(|(a, b)| PseudoClass::NthLastChild(a, b))
+                        }
+                        ("nth-of-type", Some(arg)) => {
+                            parse_nth(arg).map(|(a, b)| PseudoClass::NthOfType(a, b))
+                        }
+                        ("nth-last-of-type", Some(arg)) => {
+                            parse_nth(arg).map(|(a, b)| PseudoClass::NthLastOfType(a, b))
                         }
-                        "last-child" => {
-                            selec.pseudo_classes.push(PseudoClass::LastChild);
+                        ("not", Some(arg)) => parse_negation(arg).map(PseudoClass::Not),
+                        _ => None,
+                    };
+                    match (pseudo_class, name.as_str()) {
+                        (Some(pc), _) => selec.pseudo_classes.push(pc),
+                        // The single colon syntax of CSS 2 is also accepted.
+                        (None, "first-letter") if argument.is_none() => {
+                            s.pseudo_element = Some(PseudoElement::FirstLetter);
+                        }
+                        (None, "first-line") if argument.is_none() => {
+                            s.pseudo_element = Some(PseudoElement::FirstLine);
                         }
                         _ => {
                             supported = false;
                         }
                     }
-                    if self.next() == Some('(') {
-                        self.advance_while(|&c| c != ')');
-                        self.advance(1);
-                    }
                 }
                 Some('*') => {
                     self.advance(1);
@@ -416,13 +696,20 @@ impl<'a> CssParser<'a> {
                     let offset = self.offset;
 
                     while !self.eof() {
-                        self.advance_while(|&c| c != '"' && c != ';' && c != '}' && c != '!');
+                        self.advance_while(|&c| {
+                            c != '"' && c != '(' && c != ';' && c != '}' && c != '!'
+                        });
                         match self.next() {
                             Some('"') => {
                                 self.advance(1);
                                 self.advance_while(|&c| c != '"');
                                 self.advance(1);
                             }
+                            Some('(') => {
+                                self.advance(1);
+                                self.advance_while(|&c| c != ')');
+                                self.advance(1);
+                            }
                             Some('!') => {
                                 d.important = true;
                                 break;
@@ -464,31 +751,87 @@ impl<'a> CssParser<'a> {
         declarations
     }
 
-    fn parse_rules(&mut self, rules: &mut Vec<Rule>) {
+    fn parse_rules(&mut self, rules: &mut Vec<Rule>, media: Option<usize>) {
         let selectors = self.parse_selectors();
         let declarations = self.parse_declarations();
         for selector in selectors.into_iter() {
             rules.push(Rule {
                 selector,
                 declarations: declarations.clone(),
+                media,
+            });
+        }
+    }
+
+    fn parse_media(&mut self, rules: &mut Vec<Rule>, media: &mut Vec<MediaQueryList>) {
+        self.advance("@media".len());
+        let offset = self.offset;
+        self.advance_while(|&c| c != '{' && c != ';');
+
+        if self.next() != Some('{') {
+            self.advance(1);
+            return;
+        }
+
+        media.push(parse_media_query_list(&self.input[offset..self.offset]));
+        let index = media.len() - 1;
+        self.advance(1);
+
+        while !self.eof() {
+            self.skip_spaces_and_comments();
+
+            match self.next() {
+                None => break,
+                Some('}') => {
+                    self.advance(1);
+                    break;
+                }
+                Some('@') => self.skip_at_rule(),
+                _ => self.parse_rules(rules, Some(index)),
+            }
+        }
+    }
+
+    fn parse_font_face(&mut self, font_faces: &mut Vec<FontFace>) {
+        self.advance_while(|&c| c != '{');
+        self.advance(1);
+        let declarations = self.parse_declarations();
+        if !declarations.is_empty() {
+            font_faces.push(FontFace {
+                declarations,
+                base_dir: PathBuf::default(),
             });
         }
     }
 
     pub fn parse(&mut self) -> StyleSheet {
         let mut rules = Vec::new();
+        let mut font_faces = Vec::new();
+        let mut media = Vec::new();
 
         while !self.eof() {
             self.skip_spaces_and_comments();
 
             match self.next() {
                 None => break,
-                Some('@') => self.skip_at_rule(),
-                _ => self.parse_rules(&mut rules),
+                Some('@') => {
+                    if self.starts_with("@font-face") {
+                        self.parse_font_face(&mut font_faces);
+                    } else if self.starts_with("@media") {
+                        self.parse_media(&mut rules, &mut media);
+                    } else {
+                        self.skip_at_rule();
+                    }
+                }
+                _ => self.parse_rules(&mut rules, None),
             }
         }
 
-        StyleSheet { rules }
+        StyleSheet {
+            rules,
+            font_faces,
+            media,
+        }
     }
 }
 
@@ -503,6 +846,106 @@ mod tests {
         println!("{:?}", css);
     }
 
+    #[test]
+    fn font_face_css() {
+        let text = "@font-face { font-family: \"Foo Bar\"; src: url(../fonts/foo.otf); }\
+                    @font-face { font-family: Foo Bar; font-weight: bold; \
+                                 src: url('../fonts/foo-bold.otf') format('opentype') }\
+                    p { font-family: \"Foo Bar\", serif }";
+        let css = CssParser::new(text).parse();
+        assert_eq!(css.font_faces.len(), 2);
+        assert_eq!(css.rules.len(), 1);
+        assert_eq!(css.font_faces[0].get("font-family"), Some("\"Foo Bar\""));
+        assert_eq!(css.font_faces[1].get("font-weight"), Some("bold"));
+    }
+
+    #[test]
+    fn media_css() {
+        let text = "p { a: b } \
+                    @media amzn-kf8 { p { c: d } h1, h2 { e: f } } \
+                    @media only screen and (min-width: 600px), print { q { g: h } } \
+                    @import url(foo.css); \
+                    r { i: j }";
+        let css = CssParser::new(text).parse();
+        assert_eq!(css.media.len(), 2);
+        assert_eq!(
+            css.rules.iter().map(|r| r.media).collect::<Vec<_>>(),
+            vec![None, Some(0), Some(0), Some(0), Some(1), None]
+        );
+        let queries = &css.media[1];
+        assert_eq!(queries.len(), 2);
+        assert_eq!(queries[0].media_type.as_deref(), Some("screen"));
+        assert_eq!(queries[0].features[0].name, "min-width");
+        assert_eq!(queries[0].features[0].value.as_deref(), Some("600px"));
+        assert_eq!(queries[1].media_type.as_deref(), Some("print"));
+    }
+
+    #[test]
+    fn pseudo_elements_css() {
+        let text = "p.first::first-letter { a: b } p:first-line, h1::before { c: d } \
+                    p:first-child { e: f }";
+        let css = CssParser::new(text).parse();
+        assert_eq!(css.rules.len(), 3);
+        assert_eq!(
+            css.rules[0].selector.pseudo_element,
+            Some(PseudoElement::FirstLetter)
+        );
+        assert_eq!(css.rules[0].selector.specificity(), [0, 1, 2]);
+        assert_eq!(
+            css.rules[1].selector.pseudo_element,
+            Some(PseudoElement::FirstLine)
+        );
+        assert_eq!(css.rules[2].selector.pseudo_element, None);
+    }
+
+    #[test]
+    fn publisher_selectors_css() {
+        let text = r#"
+            p.noindent:first-of-type { text-indent: 0 }
+            table.data tr:nth-child(odd) > td { background: #eee }
+            ol li:nth-child( 2n + 1 ), ul li:nth-last-child(-n+2) { a: b }
+            section > p:not(.first):not(.noindent, .centered) { text-indent: 1.2em }
+            a[href^="http"], img[src$='.svg'], span[class*=smallcap] { c: d }
+            aside[epub|type~="footnote"], [lang|=fr] { display: none }
+            div:empty, li:only-child, p:nth-of-type(3), p:last-of-type { e: f }
+            :root { g: h }
+            input[type="A" i] { i: j }
+            p:hover, a:not(:visited), p:nth-child(foo) { k: l }
+        "#;
+        let css = CssParser::new(text).parse();
+        assert_eq!(css.rules.len(), 16);
+        let selec = &css.rules[1].selector.simple_selectors[1];
+        assert!(matches!(
+            selec.pseudo_classes[0],
+            PseudoClass::NthChild(2, 1)
+        ));
+        let selec = &css.rules[3].selector.simple_selectors[1];
+        assert!(matches!(
+            selec.pseudo_classes[0],
+            PseudoClass::NthLastChild(-1, 2)
+        ));
+        assert_eq!(css.rules[4].selector.specificity(), [0, 2, 2]);
+        let selec = &css.rules[6].selector.simple_selectors[0];
+        assert!(
+            matches!(selec.attributes[0].operator, AttributeOperator::Suffix(ref v) if v == ".svg")
+        );
+        let selec = &css.rules[15].selector.simple_selectors[0];
+        assert!(selec.attributes[0].case_insensitive);
+        assert!(
+            matches!(selec.attributes[0].operator, AttributeOperator::Matches(ref v) if v == "a")
+        );
+    }
+
+    #[test]
+    fn nth_css() {
+        assert_eq!(parse_nth("even"), Some((2, 0)));
+        assert_eq!(parse_nth("-n + 3"), Some((-1, 3)));
+        assert_eq!(parse_nth("+5"), Some((0, 5)));
+        assert_eq!(parse_nth("n"), Some((1, 0)));
+        assert_eq!(parse_nth("3n-2"), Some((3, -2)));
+        assert_eq!(parse_nth("foo"), None);
+    }
+
     #[test]
     fn combinators_css() {
         let text = "a#i.j.k > b { b: c } a + .l { u: v } a { x: y }";
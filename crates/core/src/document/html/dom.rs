@@ -431,6 +431,46 @@ impl<'a> NodeRef<'a> {
     pub fn find_by_id(&self, id: &str) -> Option<Self> {
         self.descendants().find(|n| n.id() == Some(id))
     }
+
+    // Reconstructs the markup of this node and its descendants.
+    pub fn markup(&self) -> String {
+        let mut buf = String::new();
+        self.write_markup(&mut buf);
+        buf
+    }
+
+    fn write_markup(&self, buf: &mut String) {
+        match self.node.data {
+            NodeData::Element(ElementData {
+                ref name,
+                ref attributes,
+                ..
+            }) => {
+                buf.push('<');
+                buf.push_str(name);
+                for (key, value) in attributes {
+                    // The values of the attributes are stored as they appear in the source.
+                    buf.push_str(&format!(" {}=\"{}\"", key, value.replace('"', "&quot;")));
+                }
+                if self.has_children() {
+                    buf.push('>');
+                    for child in self.children() {
+                        child.write_markup(buf);
+                    }
+                    buf.push_str(&format!("</{}>", name));
+                } else {
+                    buf.push_str("/>");
+                }
+            }
+            NodeData::Text(TextData { ref text, .. })
+            | NodeData::Whitespace(TextData { ref text, .. }) => buf.push_str(text),
+            _ => {
+                for child in self.children() {
+                    child.write_markup(buf);
+                }
+            }
+        }
+    }
 }
 
 impl<'a> NodeMut<'a> {
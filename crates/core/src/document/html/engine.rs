@@ -1,23 +1,36 @@
+use super::css::PseudoElement;
 use super::dom::{ElementData, NodeData, NodeRef, TextData, WRAPPER_TAG_NAME};
 use super::layout::{collapse_margins, hyph_lang, DEFAULT_HYPH_LANG, HYPHENATION_PATTERNS};
 use super::layout::{ChildArtifact, GlueMaterial, LoopContext, PenaltyMaterial, SiblingStyle};
-use super::layout::{Display, Float, ImageElement, ParagraphElement, TextAlign, TextElement};
+use super::layout::{Clear, Direction, Display, Float, ImageElement, ParagraphElement, TextAlign};
 use super::layout::{DrawCommand, DrawState, FontKind, Fonts, ImageCommand, RootData, TextCommand};
+use super::layout::{DropCap, RubyElement, RubyMaterial, SpanningCell, VerticalAlign};
+use super::layout::{EmbeddedFont, EmbeddedFontFamily, FontStyle, FontWeight};
+use super::layout::{GlyphOrientation, TextOrientation, WritingMode};
 use super::layout::{ImageMaterial, InlineMaterial, StyleData, TextMaterial};
-use super::layout::{LineStats, ListStyleType, WordSpacing};
+use super::layout::{LineStats, ListStyleType, TextElement, WordSpacing};
 use super::layout::{EM_SPACE_RATIOS, FONT_SPACES, WORD_SPACE_RATIOS};
+use super::parse::{
+    parse_cell_vertical_align, parse_clear, parse_display, parse_edge, parse_float,
+};
 use super::parse::{parse_color, parse_line_height, parse_list_style_type, parse_vertical_align};
-use super::parse::{parse_display, parse_edge, parse_float, parse_text_align, parse_text_indent};
+use super::parse::{parse_direction, parse_font_face_src, parse_font_family_names};
 use super::parse::{parse_font_features, parse_font_size, parse_font_variant, parse_font_weight};
 use super::parse::{
     parse_font_kind, parse_font_style, parse_height, parse_inline_material, parse_width,
 };
 use super::parse::{parse_letter_spacing, parse_word_spacing};
-use super::style::{specified_values, StyleSheet};
+use super::parse::{parse_text_align, parse_text_indent};
+use super::parse::{parse_text_orientation, parse_writing_mode};
+use super::style::{
+    pseudo_element_values, specified_values, MediaContext, PropertyMap, StyleSheet,
+};
+use super::svg::{embed_images, inline_svg_markup, inline_svg_path, intrinsic_size, SVG_MIME_TYPE};
 use super::xml::XmlExt;
+use crate::device::CURRENT_DEVICE;
 use crate::document::pdf::PdfOpener;
 use crate::document::{Document, Location};
-use crate::font::{FontFamily, FontOpener};
+use crate::font::{Font, FontFamily, FontOpener, RenderPlan};
 use crate::framebuffer::{Framebuffer, Pixmap};
 use crate::geom::{Edge, Point, Rectangle, Vec2};
 use crate::helpers::{decode_entities, Normalize};
@@ -27,25 +40,48 @@ use crate::settings::{
 use crate::settings::{HYPHEN_PENALTY, STRETCH_TOLERANCE};
 use crate::unit::{mm_to_px, pt_to_px};
 use anyhow::Error;
+use fxhash::FxHashMap;
 use kl_hyphenate::{Hyphenator, Iter, Standard};
 use paragraph_breaker::{standard_fit, total_fit};
 use paragraph_breaker::{Breakpoint, Item as ParagraphItem, INFINITE_PENALTY};
 use percent_encoding::percent_decode_str;
 use septem::Roman;
 use std::convert::TryFrom;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use unicode_bidi::{BidiInfo, Level, ParagraphBidiInfo};
+use unicode_normalization::char::is_combining_mark;
 use xi_unicode::LineBreakIterator;
 
 const DEFAULT_DPI: u16 = 300;
 const DEFAULT_WIDTH: u32 = 1404;
 const DEFAULT_HEIGHT: u32 = 1872;
 
+const WRITING_MODE_PROPERTIES: [&str; 3] =
+    ["writing-mode", "-epub-writing-mode", "-webkit-writing-mode"];
+const TEXT_ORIENTATION_PROPERTIES: [&str; 3] = [
+    "text-orientation",
+    "-epub-text-orientation",
+    "-webkit-text-orientation",
+];
+
 pub type Page = Vec<DrawCommand>;
 
 pub trait ResourceFetcher {
     fn fetch(&mut self, name: &str) -> Result<Vec<u8>, Error>;
 }
 
+// The layout state of the children of a block, handed over to the elements laid out
+// outside of the normal flow.
+struct LayoutState<'a> {
+    parent_style: &'a StyleData,
+    loop_context: &'a LoopContext,
+    stylesheet: &'a StyleSheet,
+    root_data: &'a RootData,
+    resource_fetcher: &'a mut dyn ResourceFetcher,
+    draw_state: &'a mut DrawState,
+    display_list: &'a mut [Page],
+}
+
 // TODO: Add min_font_size.
 pub struct Engine {
     // The fonts used for each CSS font family.
@@ -66,6 +102,8 @@ pub struct Engine {
     pub dims: (u32, u32),
     // Device DPI.
     pub dpi: u16,
+    // The markup of the inline SVG elements, by image path.
+    inline_svgs: FxHashMap<String, String>,
 }
 
 impl Engine {
@@ -84,16 +122,128 @@ impl Engine {
             line_height,
             dims: (DEFAULT_WIDTH, DEFAULT_HEIGHT),
             dpi: DEFAULT_DPI,
+            inline_svgs: FxHashMap::default(),
         }
     }
 
     #[inline]
     pub fn load_fonts(&mut self) {
+        self.load_fonts_from("");
+    }
+
+    // Loads the default fonts from the `fonts` directory of the given root directory.
+    pub fn load_fonts_from<P: AsRef<Path>>(&mut self, root_dir: P) {
         if self.fonts.is_none() {
-            self.fonts = default_fonts().ok();
+            self.fonts = default_fonts(&root_dir.as_ref().join("fonts")).ok();
+        }
+    }
+
+    pub fn load_font_faces(
+        &mut self,
+        stylesheet: &StyleSheet,
+        resource_fetcher: &mut dyn ResourceFetcher,
+    ) {
+        if stylesheet.font_faces.is_empty() {
+            return;
+        }
+
+        self.load_fonts();
+
+        let (fonts, opener) = match (self.fonts.as_mut(), FontOpener::new()) {
+            (Some(fonts), Ok(opener)) => (fonts, opener),
+            _ => return,
+        };
+
+        for font_face in &stylesheet.font_faces {
+            let name = match font_face
+                .get("font-family")
+                .and_then(|value| parse_font_family_names(value).into_iter().next())
+            {
+                Some(name) => name,
+                None => continue,
+            };
+
+            let font_style = font_face
+                .get("font-style")
+                .and_then(parse_font_style)
+                .unwrap_or(FontStyle::Normal);
+            let font_weight = font_face
+                .get("font-weight")
+                .and_then(parse_font_weight)
+                .unwrap_or(FontWeight::Normal);
+
+            let index = fonts.embedded_family(&name).unwrap_or_else(|| {
+                fonts.embedded.push(EmbeddedFontFamily {
+                    name,
+                    faces: Vec::new(),
+                });
+                fonts.embedded.len() - 1
+            });
+
+            let family = &mut fonts.embedded[index];
+
+            if family
+                .faces
+                .iter()
+                .any(|f| f.font_style == font_style && f.font_weight == font_weight)
+            {
+                continue;
+            }
+
+            let sources = font_face
+                .get("src")
+                .map(parse_font_face_src)
+                .unwrap_or_default();
+
+            for src in sources {
+                let path = font_face
+                    .base_dir
+                    .join(&src)
+                    .normalize()
+                    .to_str()
+                    .map(|uri| {
+                        percent_decode_str(&decode_entities(uri))
+                            .decode_utf8_lossy()
+                            .into_owned()
+                    })
+                    .unwrap_or_default();
+
+                if let Ok(data) = resource_fetcher.fetch(&path) {
+                    match opener.open_memory(&data) {
+                        Ok(font) => {
+                            family.faces.push(EmbeddedFont {
+                                font,
+                                font_style,
+                                font_weight,
+                                data,
+                            });
+                            break;
+                        }
+                        Err(e) => eprintln!("Can't open font '{}': {:#}.", path, e),
+                    }
+                }
+            }
         }
     }
 
+    // Returns the first embedded family listed in `value`, or its generic family.
+    fn resolve_font_kind(&self, value: &str) -> Option<FontKind> {
+        self.fonts
+            .as_ref()
+            .filter(|fonts| !fonts.embedded.is_empty())
+            .and_then(|fonts| {
+                parse_font_family_names(value)
+                    .iter()
+                    .find_map(|name| {
+                        fonts
+                            .embedded_family(name)
+                            .filter(|&index| !fonts.embedded[index].faces.is_empty())
+                    })
+                    .map(FontKind::Embedded)
+            })
+            .or_else(|| parse_font_kind(value))
+    }
+
     pub fn set_hyphen_penalty(&mut self, hyphen_penalty: i32) {
         self.hyphen_penalty = hyphen_penalty;
     }
@@ -138,6 +288,16 @@ impl Engine {
         self.line_height = line_height;
     }
 
+    pub fn media_context(&self) -> MediaContext {
+        MediaContext {
+            width: self.dims.0 as i32,
+            height: self.dims.1 as i32,
+            dpi: self.dpi,
+            font_size: self.font_size,
+            color: CURRENT_DEVICE.color_samples() > 1,
+        }
+    }
+
     #[inline]
     pub fn rect(&self) -> Rectangle {
         let (width, height) = self.dims;
@@ -176,9 +336,25 @@ impl Engine {
             };
         }
 
+        if !loop_context.is_absolute && node.parent().is_some() && is_out_of_flow(&props) {
+            if let Some(containing_block) = draw_state.containing_block {
+                let state = LayoutState {
+                    parent_style,
+                    loop_context,
+                    stylesheet,
+                    root_data,
+                    resource_fetcher,
+                    draw_state,
+                    display_list,
+                };
+                return self.place_absolute(node, containing_block, state);
+            }
+        }
+
         style.font_style = parent_style.font_style;
         style.line_height = parent_style.line_height;
         style.retain_whitespace = parent_style.retain_whitespace;
+        style.writing_mode = parent_style.writing_mode;
 
         match node.tag_name() {
             Some("pre") => style.retain_whitespace = true,
@@ -192,6 +368,7 @@ impl Engine {
                 draw_state.max_column_widths.clear();
                 draw_state.center_table = style.display == Display::InlineTable
                     && parent_style.text_align == TextAlign::Center;
+                draw_state.rowspans.clear();
                 self.compute_column_widths(
                     node,
                     parent_style,
@@ -201,6 +378,8 @@ impl Engine {
                     resource_fetcher,
                     draw_state,
                 );
+                draw_state.rowspans.clear();
+                draw_state.spanning_cells.clear();
                 draw_state.position = position;
             }
             _ => (),
@@ -211,6 +390,19 @@ impl Engine {
             .cloned()
             .or_else(|| parent_style.language.clone());
 
+        style.direction = props
+            .get("direction")
+            .map(String::as_str)
+            .or_else(|| node.attribute("dir"))
+            .and_then(parse_direction)
+            .unwrap_or(parent_style.direction);
+
+        style.text_orientation = TEXT_ORIENTATION_PROPERTIES
+            .iter()
+            .find_map(|name| props.get(*name))
+            .and_then(|value| parse_text_orientation(value))
+            .unwrap_or(parent_style.text_orientation);
+
         style.font_size = props
             .get("font-size")
             .and_then(|value| parse_font_size(value, parent_style.font_size, self.font_size))
@@ -251,7 +443,7 @@ impl Engine {
 
         style.font_kind = props
             .get("font-family")
-            .and_then(|value| parse_font_kind(value))
+            .and_then(|value| self.resolve_font_kind(value))
             .unwrap_or(parent_style.font_kind);
 
         style.font_style = props
@@ -286,8 +478,15 @@ impl Engine {
             .get("text-align")
             .map(String::as_str)
             .or_else(|| node.attribute("align"))
-            .and_then(|value| parse_text_align(value))
-            .unwrap_or(parent_style.text_align);
+            .and_then(|value| parse_text_align(value, style.direction))
+            .unwrap_or_else(|| {
+                // The inherited alignment is relative to the direction of the parent.
+                if style.direction != parent_style.direction {
+                    parent_style.text_align.mirrored()
+                } else {
+                    parent_style.text_align
+                }
+            });
 
         style.font_features = props
             .get("font-feature-settings")
@@ -354,6 +553,8 @@ impl Engine {
             })
             .unwrap_or(0);
 
+        let specified_width = style.width;
+
         style.height = props
             .get("height")
             .and_then(|value| {
@@ -400,11 +601,63 @@ impl Engine {
 
         style.width = width;
 
+        if !loop_context.is_float && node.parent().is_some() && style.display == Display::Block {
+            if let Some(float) = props.get("float").and_then(|value| parse_float(value)) {
+                let state = LayoutState {
+                    parent_style,
+                    loop_context,
+                    stylesheet,
+                    root_data,
+                    resource_fetcher: &mut *resource_fetcher,
+                    draw_state: &mut *draw_state,
+                    display_list: &mut display_list[..],
+                };
+                if let Some(artifact) =
+                    self.place_float(node, float, specified_width, &style, state)
+                {
+                    return artifact;
+                }
+            }
+        }
+
         if props.get("page-break-before").map(String::as_str) == Some("always") {
             display_list.push(Vec::new());
             draw_state.position.y = root_data.rect.min.y;
         }
 
+        if let Some(clear) = props.get("clear").and_then(|value| parse_clear(value)) {
+            let page_index = display_list.len() - 1;
+            if let Some(floating_rects) = draw_state.floats.get(&page_index) {
+                // Move the top border edge below the matching floats.
+                let max_y = floating_rects
+                    .iter()
+                    .filter(|frect| {
+                        let side = float_side(frect, parent_style);
+                        clear == Clear::Both
+                            || (clear == Clear::Left && side == Float::Left)
+                            || (clear == Clear::Right && side == Float::Right)
+                    })
+                    .map(|frect| frect.max.y - style.margin.top)
+                    .max();
+                if let Some(max_y) = max_y {
+                    draw_state.position.y = draw_state.position.y.max(max_y);
+                }
+            }
+        }
+
+        // The pseudo-elements apply to the first paragraph of the block.
+        let first_letter = pseudo_element_values(node, stylesheet, PseudoElement::FirstLetter);
+        let has_first_letter = !first_letter.is_empty();
+        if has_first_letter {
+            draw_state.first_letter = Some(first_letter);
+        }
+
+        let first_line = pseudo_element_values(node, stylesheet, PseudoElement::FirstLine);
+        let has_first_line = !first_line.is_empty();
+        if has_first_line {
+            draw_state.first_line = Some(first_line);
+        }
+
         draw_state.position.y += style.padding.top;
 
         let has_blocks = node.children().any(|n| n.is_block());
@@ -469,8 +722,19 @@ impl Engine {
                     let page_index = display_list.len() - 1;
                     let mut index = 0;
 
-                    // TODO: rowspan, vertical-align
+                    let row_align = cell_vertical_align(node, stylesheet);
+                    // Cells that end with this row, with the range of their commands.
+                    let mut cells = Vec::new();
+
                     for child in node.children().filter(|child| child.is_element()) {
+                        // Skip the columns covered by the cells of the previous rows.
+                        while index < draw_state.column_widths.len()
+                            && draw_state.rowspans.get(index).is_some_and(|n| *n > 0)
+                        {
+                            cur_x += draw_state.column_widths[index];
+                            index += 1;
+                        }
+
                         if index >= draw_state.column_widths.len() {
                             break;
                         }
@@ -480,6 +744,10 @@ impl Engine {
                             .and_then(|v| v.parse().ok())
                             .unwrap_or(1)
                             .min(draw_state.column_widths.len() - index);
+                        let rowspan = cell_rowspan(child, node);
+                        let vertical_align = cell_vertical_align(child, stylesheet)
+                            .or(row_align)
+                            .unwrap_or(VerticalAlign::Top);
                         let column_width = draw_state.column_widths[index..index + colspan]
                             .iter()
                             .sum::<i32>();
@@ -498,11 +766,33 @@ impl Engine {
                             &mut child_display_list,
                         );
                         let pages_count = child_display_list.len();
-                        if pages_count > final_page.0
-                            || (pages_count == final_page.0
-                                && draw_state.position.y > final_page.1.y)
-                        {
-                            final_page = (pages_count, draw_state.position);
+                        let commands_start = display_list[page_index].len();
+                        let commands = if pages_count == 1 {
+                            Some(commands_start..commands_start + child_display_list[0].len())
+                        } else {
+                            None
+                        };
+
+                        // A cell that spans several rows doesn't stretch its first row.
+                        if rowspan > 1 {
+                            occupy_columns(&mut draw_state.rowspans, index, colspan, rowspan);
+                            draw_state.spanning_cells.push(SpanningCell {
+                                remaining: rowspan,
+                                page_index: page_index + pages_count - 1,
+                                bottom: draw_state.position.y,
+                                vertical_align,
+                                commands,
+                            });
+                        } else {
+                            if pages_count > final_page.0
+                                || (pages_count == final_page.0
+                                    && draw_state.position.y > final_page.1.y)
+                            {
+                                final_page = (pages_count, draw_state.position);
+                            }
+                            if let Some(range) = commands {
+                                cells.push((range, draw_state.position.y, vertical_align));
+                            }
                         }
 
                         for (i, mut pg) in child_display_list.into_iter().enumerate() {
@@ -540,6 +830,48 @@ impl Engine {
                         cur_x += column_width;
                     }
 
+                    for n in draw_state.rowspans.iter_mut() {
+                        *n = n.saturating_sub(1);
+                    }
+
+                    // The last row covered by a spanning cell is stretched to its bottom.
+                    for cell in draw_state.spanning_cells.iter_mut() {
+                        cell.remaining -= 1;
+                        if cell.remaining == 0 && cell.page_index >= page_index {
+                            let pages_count = cell.page_index - page_index + 1;
+                            if pages_count > final_page.0
+                                || (pages_count == final_page.0 && cell.bottom > final_page.1.y)
+                            {
+                                final_page = (pages_count, pt!(position.x, cell.bottom));
+                            }
+                        }
+                    }
+
+                    let last_page_index = page_index + final_page.0.max(1) - 1;
+                    for cell in draw_state.spanning_cells.iter() {
+                        if cell.remaining == 0 && cell.page_index == last_page_index {
+                            if let Some(range) = cell.commands.clone() {
+                                cells.push((range, cell.bottom, cell.vertical_align));
+                            }
+                        }
+                    }
+                    draw_state.spanning_cells.retain(|cell| cell.remaining > 0);
+
+                    if final_page.0 == 1 {
+                        for (range, bottom, vertical_align) in cells {
+                            let dy = match vertical_align {
+                                VerticalAlign::Top => 0,
+                                VerticalAlign::Middle => (final_page.1.y - bottom) / 2,
+                                VerticalAlign::Bottom => final_page.1.y - bottom,
+                            };
+                            if dy > 0 {
+                                for dc in &mut display_list[last_page_index][range] {
+                                    dc.shift_y(dy);
+                                }
+                            }
+                        }
+                    }
+
                     style.start_x = start_x;
                     style.end_x = end_x;
                     draw_state.position = final_page.1;
@@ -607,10 +939,18 @@ impl Engine {
             if node.has_children() {
                 let mut inlines = Vec::new();
                 let mut markers = Vec::new();
+                let mut positioned = Vec::new();
                 if node.id().is_some() {
                     markers.push(node.offset());
                 }
                 for child in node.children() {
+                    if draw_state.containing_block.is_some()
+                        && child.is_element()
+                        && is_out_of_flow(&specified_values(child, stylesheet))
+                    {
+                        positioned.push(child);
+                        continue;
+                    }
                     self.gather_inline_material(
                         child,
                         stylesheet,
@@ -638,6 +978,9 @@ impl Engine {
                         }
                         Some(kind) => format_list_prefix(kind, loop_context.index),
                     };
+                    if let Some(props) = draw_state.first_letter.take() {
+                        draw_state.drop_cap = self.split_first_letter(&props, &mut inlines);
+                    }
                     self.place_paragraphs(
                         &inlines,
                         &style,
@@ -649,6 +992,20 @@ impl Engine {
                         display_list,
                     );
                 }
+                if let Some(containing_block) = draw_state.containing_block {
+                    for child in positioned {
+                        let state = LayoutState {
+                            parent_style: &style,
+                            loop_context: &LoopContext::default(),
+                            stylesheet,
+                            root_data,
+                            resource_fetcher: &mut *resource_fetcher,
+                            draw_state: &mut *draw_state,
+                            display_list: &mut display_list[..],
+                        };
+                        self.place_absolute(child, containing_block, state);
+                    }
+                }
             } else {
                 if node.id().is_some() {
                     display_list
@@ -673,6 +1030,14 @@ impl Engine {
             style.margin.top = 0;
         }
 
+        if has_first_letter {
+            draw_state.first_letter = None;
+        }
+
+        if has_first_line {
+            draw_state.first_line = None;
+        }
+
         draw_state.position.y += style.padding.bottom;
 
         if props.get("page-break-after").map(String::as_str) == Some("always") {
@@ -689,6 +1054,298 @@ impl Engine {
         }
     }
 
+    // Lays out a floated block on the side of the current page.
+    // Returns `None` if the block doesn't fit in the remaining space of the page.
+    fn place_float(
+        &mut self,
+        node: NodeRef,
+        float: Float,
+        specified_width: i32,
+        style: &StyleData,
+        state: LayoutState,
+    ) -> Option<ChildArtifact> {
+        let LayoutState {
+            parent_style,
+            loop_context,
+            stylesheet,
+            root_data,
+            resource_fetcher,
+            draw_state,
+            display_list,
+        } = state;
+        let page_index = display_list.len() - 1;
+        let horiz_space =
+            style.margin.left + style.padding.left + style.margin.right + style.padding.right;
+        let max_width = parent_style.end_x - parent_style.start_x - horiz_space;
+
+        if max_width <= 0 {
+            return None;
+        }
+
+        let mut y_min = draw_state.position.y + loop_context.sibling_style.margin.bottom;
+
+        // Stack the floats of the same side vertically.
+        if let Some(floating_rects) = draw_state.floats.get(&page_index) {
+            for frect in floating_rects {
+                if frect.max.y > y_min && float_side(frect, parent_style) == float {
+                    y_min = frect.max.y;
+                }
+            }
+        }
+
+        let float_context = LoopContext {
+            is_float: true,
+            ..Default::default()
+        };
+
+        // Blocks without a specified width shrink to fit their content.
+        let mut content_width = if specified_width > 0 {
+            specified_width.min(max_width)
+        } else {
+            max_width / 2
+        };
+
+        let mut passes = if specified_width > 0 { 1 } else { 2 };
+
+        loop {
+            let mut container = parent_style.clone();
+            let box_width = content_width + horiz_space;
+            container.margin = Edge::default();
+            if float == Float::Left {
+                container.end_x = container.start_x + box_width;
+            } else {
+                container.start_x = container.end_x - box_width;
+            }
+            container.width = box_width;
+
+            let mut float_state = DrawState {
+                position: pt!(container.start_x, y_min),
+                containing_block: draw_state.containing_block,
+                ..Default::default()
+            };
+            let mut float_display_list = vec![Vec::new()];
+            let artifact = self.build_display_list(
+                node,
+                &container,
+                &float_context,
+                stylesheet,
+                root_data,
+                resource_fetcher,
+                &mut float_state,
+                &mut float_display_list,
+            );
+
+            if float_display_list.len() > 1 {
+                return None;
+            }
+
+            let width = artifact
+                .rects
+                .iter()
+                .flatten()
+                .map(|rect| rect.width() as i32)
+                .max()
+                .unwrap_or(0);
+
+            passes -= 1;
+
+            if passes > 0 && width > 0 && width < content_width {
+                content_width = width;
+                continue;
+            }
+
+            if artifact.rects.iter().all(Option::is_none) {
+                return Some(ChildArtifact {
+                    sibling_style: loop_context.sibling_style.clone(),
+                    rects: vec![None],
+                });
+            }
+
+            let rect = rect![
+                container.start_x,
+                y_min,
+                container.end_x,
+                float_state.position.y + artifact.sibling_style.margin.bottom
+            ];
+
+            draw_state.floats.entry(page_index).or_default().push(rect);
+
+            if let (Some(page), Some(mut commands)) =
+                (display_list.last_mut(), float_display_list.pop())
+            {
+                page.append(&mut commands);
+            }
+
+            // Floats are out of the normal flow: the margins of the surrounding siblings
+            // still collapse.
+            return Some(ChildArtifact {
+                sibling_style: loop_context.sibling_style.clone(),
+                rects: vec![Some(rect)],
+            });
+        }
+    }
+
+    // Lays out an absolutely positioned element within its containing block.
+    // Positioned elements are only taken out of the flow on fixed-layout pages, and
+    // the relatively positioned ones don't establish a new containing block.
+    fn place_absolute(
+        &mut self,
+        node: NodeRef,
+        containing_block: Rectangle,
+        state: LayoutState,
+    ) -> ChildArtifact {
+        let LayoutState {
+            parent_style,
+            loop_context,
+            stylesheet,
+            root_data,
+            resource_fetcher,
+            draw_state,
+            display_list,
+        } = state;
+        let props = specified_values(node, stylesheet);
+        let font_size = props
+            .get("font-size")
+            .and_then(|value| parse_font_size(value, parent_style.font_size, self.font_size))
+            .unwrap_or(parent_style.font_size);
+        let cb_width = containing_block.width() as i32;
+        let cb_height = containing_block.height() as i32;
+        let horizontal = |name: &str| {
+            props
+                .get(name)
+                .filter(|value| value.as_str() != "auto")
+                .and_then(|value| parse_width(value, font_size, self.font_size, cb_width, self.dpi))
+        };
+        let vertical = |name: &str| {
+            props
+                .get(name)
+                .filter(|value| value.as_str() != "auto")
+                .and_then(|value| {
+                    parse_height(value, font_size, self.font_size, cb_height, self.dpi)
+                })
+        };
+        let (left, right, width) = (horizontal("left"), horizontal("right"), horizontal("width"));
+        let (top, bottom, height) = (vertical("top"), vertical("bottom"), vertical("height"));
+
+        let edge = |kind: &str| {
+            parse_edge(
+                props.get(&format!("{}-top", kind)).map(String::as_str),
+                props.get(&format!("{}-right", kind)).map(String::as_str),
+                props.get(&format!("{}-bottom", kind)).map(String::as_str),
+                props.get(&format!("{}-left", kind)).map(String::as_str),
+                font_size,
+                self.font_size,
+                cb_width,
+                self.dpi,
+            )
+        };
+        let (margin, padding) = (edge("margin"), edge("padding"));
+        let horiz_space = margin.left + padding.left + margin.right + padding.right;
+        let vert_space = margin.top + padding.top + margin.bottom + padding.bottom;
+
+        // Offsets that aren't specified leave the element at its static position.
+        let x = match (left, right, width) {
+            (Some(left), _, _) => containing_block.min.x + left,
+            (None, Some(right), Some(width)) => {
+                containing_block.max.x - right - width - horiz_space
+            }
+            _ => parent_style.start_x,
+        };
+        let box_width = match (width, left, right) {
+            (Some(width), _, _) => width + horiz_space,
+            (None, Some(left), Some(right)) => cb_width - left - right,
+            _ => containing_block.max.x - x,
+        };
+        let y = top.map_or(draw_state.position.y, |top| containing_block.min.y + top);
+
+        let neutral = ChildArtifact {
+            sibling_style: loop_context.sibling_style.clone(),
+            rects: vec![None],
+        };
+
+        if box_width <= 0 {
+            return neutral;
+        }
+
+        let mut container = parent_style.clone();
+        container.margin = Edge::default();
+        container.start_x = x;
+        container.end_x = x + box_width;
+        container.width = box_width;
+
+        let box_bottom = height.map_or(containing_block.max.y, |height| y + height + vert_space);
+        let mut abs_state = DrawState {
+            position: pt!(x, y),
+            containing_block: Some(rect![x, y, x + box_width, box_bottom]),
+            ..Default::default()
+        };
+        let mut abs_display_list = vec![Vec::new()];
+
+        if node.is_block() {
+            let abs_context = LoopContext {
+                is_absolute: true,
+                ..Default::default()
+            };
+            self.build_display_list(
+                node,
+                &container,
+                &abs_context,
+                stylesheet,
+                root_data,
+                resource_fetcher,
+                &mut abs_state,
+                &mut abs_display_list,
+            );
+        } else {
+            // Inline elements are laid out as the content of an anonymous block.
+            container.start_x += margin.left + padding.left;
+            container.end_x -= margin.right + padding.right;
+            container.width = container.end_x - container.start_x;
+            abs_state.position.y += margin.top + padding.top;
+            let mut inlines = Vec::new();
+            let mut markers = Vec::new();
+            self.gather_inline_material(
+                node,
+                stylesheet,
+                &container,
+                &root_data.spine_dir,
+                &mut markers,
+                &mut inlines,
+            );
+            if !inlines.is_empty() {
+                let mut rects = vec![None];
+                self.place_paragraphs(
+                    &inlines,
+                    &container,
+                    root_data,
+                    &markers,
+                    resource_fetcher,
+                    &mut abs_state,
+                    &mut rects,
+                    &mut abs_display_list,
+                );
+            }
+            abs_state.position.y += margin.bottom + padding.bottom;
+        }
+
+        // Elements anchored to the bottom of their containing block are moved once laid out.
+        if let (None, Some(bottom)) = (top, bottom) {
+            let end_y = height.map_or(abs_state.position.y, |height| y + height + vert_space);
+            let dy = containing_block.max.y - bottom - end_y;
+            for dc in abs_display_list.iter_mut().flatten() {
+                dc.shift_y(dy);
+            }
+        }
+
+        if let Some(page) = display_list.last_mut() {
+            for mut commands in abs_display_list {
+                page.append(&mut commands);
+            }
+        }
+
+        neutral
+    }
+
     fn compute_column_widths(
         &mut self,
         node: NodeRef,
@@ -702,10 +1359,14 @@ impl Engine {
         if node.tag_name() == Some("tr") {
             let mut index = 0;
             for child in node.children().filter(|c| c.is_element()) {
+                while draw_state.rowspans.get(index).is_some_and(|n| *n > 0) {
+                    index += 1;
+                }
                 let colspan = child
                     .attribute("colspan")
                     .and_then(|v| v.parse().ok())
                     .unwrap_or(1);
+                let rowspan = cell_rowspan(child, node);
                 let mut display_list = vec![Vec::new()];
                 let artifact = self.build_display_list(
                     child,
@@ -743,20 +1404,25 @@ impl Engine {
                     .max()
                     .unwrap_or(0);
                 if colspan == 1 {
-                    if let Some(cw) = draw_state.min_column_widths.get_mut(index) {
-                        *cw = (*cw).max(min_width);
-                    } else {
-                        draw_state.min_column_widths.push(min_width);
-                    }
-                    if let Some(cw) = draw_state.max_column_widths.get_mut(index) {
-                        *cw = (*cw).max(max_width);
-                    } else {
-                        draw_state.max_column_widths.push(max_width);
+                    if draw_state.min_column_widths.len() <= index {
+                        draw_state.min_column_widths.resize(index + 1, 0);
+                        draw_state.max_column_widths.resize(index + 1, 0);
                     }
+                    let cw = &mut draw_state.min_column_widths[index];
+                    *cw = (*cw).max(min_width);
+                    let cw = &mut draw_state.max_column_widths[index];
+                    *cw = (*cw).max(max_width);
+                }
+
+                if rowspan > 1 {
+                    occupy_columns(&mut draw_state.rowspans, index, colspan, rowspan);
                 }
 
                 index += colspan;
             }
+            for n in draw_state.rowspans.iter_mut() {
+                *n = n.saturating_sub(1);
+            }
         } else {
             for child in node.children().filter(|c| c.is_element()) {
                 self.compute_column_widths(
@@ -773,7 +1439,7 @@ impl Engine {
     }
 
     fn gather_inline_material(
-        &self,
+        &mut self,
         node: NodeRef,
         stylesheet: &StyleSheet,
         parent_style: &StyleData,
@@ -797,6 +1463,7 @@ impl Engine {
                 style.retain_whitespace = parent_style.retain_whitespace;
                 style.language = parent_style.language.clone();
                 style.uri = parent_style.uri.clone();
+                style.writing_mode = parent_style.writing_mode;
 
                 style.display = props
                     .get("display")
@@ -807,6 +1474,19 @@ impl Engine {
                     return;
                 }
 
+                style.direction = props
+                    .get("direction")
+                    .map(String::as_str)
+                    .or_else(|| node.attribute("dir"))
+                    .and_then(parse_direction)
+                    .unwrap_or(parent_style.direction);
+
+                style.text_orientation = TEXT_ORIENTATION_PROPERTIES
+                    .iter()
+                    .find_map(|name| props.get(*name))
+                    .and_then(|value| parse_text_orientation(value))
+                    .unwrap_or(parent_style.text_orientation);
+
                 style.font_size = props
                     .get("font-size")
                     .and_then(|value| {
@@ -842,7 +1522,7 @@ impl Engine {
 
                 style.font_kind = props
                     .get("font-family")
-                    .and_then(|value| parse_font_kind(value))
+                    .and_then(|value| self.resolve_font_kind(value))
                     .unwrap_or(parent_style.font_kind);
 
                 style.color = props
@@ -904,19 +1584,31 @@ impl Engine {
                 }
 
                 match name.as_ref() {
-                    "img" | "image" => {
-                        let attr = if name == "img" { "src" } else { "xlink:href" };
-
-                        let path = attributes
-                            .get(attr)
-                            .and_then(|src| {
-                                spine_dir.join(src).normalize().to_str().map(|uri| {
-                                    percent_decode_str(&decode_entities(uri))
-                                        .decode_utf8_lossy()
-                                        .into_owned()
+                    "img" | "image" | "svg" => {
+                        let path = if name == "svg" {
+                            if style.width == 0 && style.height == 0 {
+                                if let Some((width, height)) = intrinsic_size(node) {
+                                    style.width = pt_to_px(0.75 * width, self.dpi).round() as i32;
+                                    style.height = pt_to_px(0.75 * height, self.dpi).round() as i32;
+                                }
+                            }
+                            let markup = inline_svg_markup(node, spine_dir);
+                            let path = inline_svg_path(&markup);
+                            self.inline_svgs.insert(path.clone(), markup);
+                            path
+                        } else {
+                            let attr = if name == "img" { "src" } else { "xlink:href" };
+                            attributes
+                                .get(attr)
+                                .and_then(|src| {
+                                    spine_dir.join(src).normalize().to_str().map(|uri| {
+                                        percent_decode_str(&decode_entities(uri))
+                                            .decode_utf8_lossy()
+                                            .into_owned()
+                                    })
                                 })
-                            })
-                            .unwrap_or_default();
+                                .unwrap_or_default()
+                        };
 
                         style.float = props.get("float").and_then(|value| parse_float(value));
 
@@ -957,43 +1649,288 @@ impl Engine {
                         inlines.push(InlineMaterial::LineBreak);
                         return;
                     }
+                    "ruby" => {
+                        self.gather_ruby_material(
+                            node, stylesheet, &style, spine_dir, markers, inlines,
+                        );
+                        return;
+                    }
                     _ => {}
                 }
 
-                if let Some(mut v) = props.get("-cadmus-insert-before").map(|value| {
-                    parse_inline_material(value, style.font_size, self.font_size, self.dpi)
-                }) {
-                    inlines.append(&mut v);
-                }
+                if let Some(mut v) = props.get("-cadmus-insert-before").map(|value| {
+                    parse_inline_material(value, style.font_size, self.font_size, self.dpi)
+                }) {
+                    inlines.append(&mut v);
+                }
+
+                for child in node.children() {
+                    self.gather_inline_material(
+                        child, stylesheet, &style, spine_dir, markers, inlines,
+                    );
+                }
+
+                if let Some(mut v) = props.get("-cadmus-insert-after").map(|value| {
+                    parse_inline_material(value, style.font_size, self.font_size, self.dpi)
+                }) {
+                    inlines.append(&mut v);
+                }
+            }
+            NodeData::Text(TextData { offset, text }) => {
+                inlines.push(InlineMaterial::Text(TextMaterial {
+                    offset: *offset,
+                    text: decode_entities(text).into_owned(),
+                    style: parent_style.clone(),
+                }));
+            }
+            NodeData::Whitespace(TextData { offset, text }) => {
+                inlines.push(InlineMaterial::Text(TextMaterial {
+                    offset: *offset,
+                    text: text.to_string(),
+                    style: parent_style.clone(),
+                }));
+            }
+            _ => (),
+        }
+    }
+
+    // Pairs each run of base text with the annotation that follows it.
+    // The fallback parentheses of `rp` elements are dropped.
+    fn gather_ruby_material(
+        &mut self,
+        node: NodeRef,
+        stylesheet: &StyleSheet,
+        style: &StyleData,
+        spine_dir: &PathBuf,
+        markers: &mut Vec<usize>,
+        inlines: &mut Vec<InlineMaterial>,
+    ) {
+        let mut bases = Vec::new();
+        for child in node.children() {
+            match child.tag_name() {
+                Some("rp") => (),
+                Some("rt") | Some("rtc") => {
+                    let mut annotations = Vec::new();
+                    self.gather_inline_material(
+                        child,
+                        stylesheet,
+                        style,
+                        spine_dir,
+                        markers,
+                        &mut annotations,
+                    );
+                    if let (Some(base), Some(annotation)) = (
+                        merge_text_materials(&bases),
+                        merge_text_materials(&annotations),
+                    ) {
+                        inlines.push(InlineMaterial::Ruby(Box::new(RubyMaterial {
+                            base,
+                            annotation,
+                        })));
+                        bases.clear();
+                    } else {
+                        inlines.append(&mut bases);
+                    }
+                }
+                _ => self.gather_inline_material(
+                    child, stylesheet, style, spine_dir, markers, &mut bases,
+                ),
+            }
+        }
+        inlines.append(&mut bases);
+    }
+
+    // The style of a pseudo-element: it inherits from the style of its originating element.
+    fn pseudo_element_style(&self, props: &PropertyMap, parent_style: &StyleData) -> StyleData {
+        let mut style = parent_style.clone();
+
+        style.font_size = props
+            .get("font-size")
+            .and_then(|value| parse_font_size(value, parent_style.font_size, self.font_size))
+            .unwrap_or(parent_style.font_size);
+
+        style.font_kind = props
+            .get("font-family")
+            .and_then(|value| self.resolve_font_kind(value))
+            .unwrap_or(parent_style.font_kind);
+
+        style.font_style = props
+            .get("font-style")
+            .and_then(|value| parse_font_style(value))
+            .unwrap_or(parent_style.font_style);
+
+        style.font_weight = props
+            .get("font-weight")
+            .and_then(|value| parse_font_weight(value))
+            .unwrap_or(parent_style.font_weight);
+
+        style.color = props
+            .get("color")
+            .and_then(|value| parse_color(value))
+            .unwrap_or(parent_style.color);
+
+        style.letter_spacing = props
+            .get("letter-spacing")
+            .and_then(|value| {
+                parse_letter_spacing(value, style.font_size, self.font_size, self.dpi)
+            })
+            .unwrap_or(parent_style.letter_spacing);
+
+        style.word_spacing = props
+            .get("word-spacing")
+            .and_then(|value| parse_word_spacing(value, style.font_size, self.font_size, self.dpi))
+            .unwrap_or(parent_style.word_spacing);
 
-                for child in node.children() {
-                    self.gather_inline_material(
-                        child, stylesheet, &style, spine_dir, markers, inlines,
-                    );
-                }
+        style.font_features = props
+            .get("font-feature-settings")
+            .map(|value| parse_font_features(value))
+            .or_else(|| parent_style.font_features.clone());
 
-                if let Some(mut v) = props.get("-cadmus-insert-after").map(|value| {
-                    parse_inline_material(value, style.font_size, self.font_size, self.dpi)
-                }) {
-                    inlines.append(&mut v);
+        if let Some(value) = props.get("font-variant") {
+            let mut features = parse_font_variant(value);
+            style
+                .font_features
+                .get_or_insert_with(Vec::new)
+                .append(&mut features);
+        }
+
+        style.float = props.get("float").and_then(|value| parse_float(value));
+
+        style.margin = parse_edge(
+            props.get("margin-top").map(String::as_str),
+            props.get("margin-right").map(String::as_str),
+            props.get("margin-bottom").map(String::as_str),
+            props.get("margin-left").map(String::as_str),
+            style.font_size,
+            self.font_size,
+            parent_style.width,
+            self.dpi,
+        );
+
+        style
+    }
+
+    // Applies the `::first-letter` values to the first letter of a paragraph, and its
+    // leading punctuation. Returns the letter if it's an initial around which the first
+    // lines wrap.
+    fn split_first_letter(
+        &self,
+        props: &PropertyMap,
+        inlines: &mut Vec<InlineMaterial>,
+    ) -> Option<DropCap> {
+        let index = inlines.iter().position(|inline| match inline {
+            InlineMaterial::Text(TextMaterial { text, .. }) => !text.trim().is_empty(),
+            InlineMaterial::Glue(..) | InlineMaterial::Penalty(..) => false,
+            _ => true,
+        })?;
+
+        let material = match inlines.get(index) {
+            Some(InlineMaterial::Text(material)) => material.clone(),
+            _ => return None,
+        };
+
+        let start = material.text.len() - material.text.trim_start().len();
+        let mut end = start;
+        let mut has_letter = false;
+
+        for c in material.text[start..].chars() {
+            if !has_letter {
+                if c.is_whitespace() {
+                    break;
                 }
+                has_letter = !is_punctuation(c);
+            } else if !is_combining_mark(c) {
+                break;
             }
-            NodeData::Text(TextData { offset, text }) => {
-                inlines.push(InlineMaterial::Text(TextMaterial {
-                    offset: *offset,
-                    text: decode_entities(text).into_owned(),
-                    style: parent_style.clone(),
+            end += c.len_utf8();
+        }
+
+        if !has_letter {
+            return None;
+        }
+
+        let style = self.pseudo_element_style(props, &material.style);
+        let lines = props
+            .get("initial-letter")
+            .or_else(|| props.get("-webkit-initial-letter"))
+            .and_then(|value| value.split_whitespace().next())
+            .and_then(|value| value.parse::<f32>().ok())
+            .filter(|value| *value >= 1.0)
+            .map(|value| value.round() as usize);
+
+        if lines.is_some() || style.float.is_some() {
+            let drop_cap = DropCap {
+                offset: material.offset + start,
+                text: material.text[start..end].to_string(),
+                style,
+                lines,
+            };
+            inlines[index] = InlineMaterial::Text(TextMaterial {
+                offset: material.offset + end,
+                text: material.text[end..].to_string(),
+                style: material.style,
+            });
+            Some(drop_cap)
+        } else {
+            let mut materials = Vec::new();
+            if start > 0 {
+                materials.push(InlineMaterial::Text(TextMaterial {
+                    offset: material.offset,
+                    text: material.text[..start].to_string(),
+                    style: material.style.clone(),
                 }));
             }
-            NodeData::Whitespace(TextData { offset, text }) => {
-                inlines.push(InlineMaterial::Text(TextMaterial {
-                    offset: *offset,
-                    text: text.to_string(),
-                    style: parent_style.clone(),
+            materials.push(InlineMaterial::Text(TextMaterial {
+                offset: material.offset + start,
+                text: material.text[start..end].to_string(),
+                style,
+            }));
+            if end < material.text.len() {
+                materials.push(InlineMaterial::Text(TextMaterial {
+                    offset: material.offset + end,
+                    text: material.text[end..].to_string(),
+                    style: material.style,
                 }));
             }
-            _ => (),
+            inlines.splice(index..=index, materials);
+            None
+        }
+    }
+
+    // Applies the `::first-line` values to the material that precedes the given offset.
+    fn restyle_first_line(
+        &self,
+        props: &PropertyMap,
+        inlines: &[InlineMaterial],
+        end_offset: usize,
+    ) -> Vec<InlineMaterial> {
+        let mut result = Vec::with_capacity(inlines.len() + 1);
+
+        for inline in inlines {
+            match inline {
+                InlineMaterial::Text(material) if material.offset < end_offset => {
+                    let mut index = (end_offset - material.offset).min(material.text.len());
+                    while !material.text.is_char_boundary(index) {
+                        index -= 1;
+                    }
+                    result.push(InlineMaterial::Text(TextMaterial {
+                        offset: material.offset,
+                        text: material.text[..index].to_string(),
+                        style: self.pseudo_element_style(props, &material.style),
+                    }));
+                    if index < material.text.len() {
+                        result.push(InlineMaterial::Text(TextMaterial {
+                            offset: material.offset + index,
+                            text: material.text[index..].to_string(),
+                            style: material.style.clone(),
+                        }));
+                    }
+                }
+                _ => result.push(inline.clone()),
+            }
         }
+
+        result
     }
 
     fn make_paragraph_items(
@@ -1005,6 +1942,7 @@ impl Engine {
     ) -> (Vec<ParagraphItem<ParagraphElement>>, Vec<ImageElement>) {
         let mut items = Vec::new();
         let mut floats = Vec::new();
+        let bidi_levels = inline_bidi_levels(inlines, parent_style.direction);
         let big_stretch = 3 * {
             let font_size = (parent_style.font_size * 64.0) as u32;
             let font = self.fonts.as_mut().unwrap().get_mut(
@@ -1039,9 +1977,9 @@ impl Engine {
                     let mut scale = 1.0;
                     let dpi = self.dpi;
 
-                    if let Ok(buf) = resource_fetcher.fetch(path) {
+                    if let Some((magic, buf)) = self.fetch_image(path, resource_fetcher) {
                         if let Some(doc) =
-                            PdfOpener::new().and_then(|opener| opener.open_memory(path, &buf))
+                            PdfOpener::new().and_then(|opener| opener.open_memory(magic, &buf))
                         {
                             if let Some((w, h)) = doc.dims(0) {
                                 if width == 0 && height == 0 {
@@ -1056,6 +1994,11 @@ impl Engine {
                             }
                         }
 
+                        // The lines of the vertical writing mode are laid out in a rotated frame.
+                        if parent_style.writing_mode == WritingMode::VerticalRl {
+                            std::mem::swap(&mut width, &mut height);
+                        }
+
                         if width * height > 0 {
                             let element = ImageElement {
                                 offset: *offset,
@@ -1105,6 +2048,12 @@ impl Engine {
                                 if j > 0 {
                                     let buf = &text[start_index..start_index + j];
                                     let local_offset = offset + start_index;
+                                    let bidi_level = bidi_levels
+                                        .as_ref()
+                                        .map_or(Level::ltr(), |(starts, levels)| {
+                                            levels[starts[index] + start_index]
+                                        });
+                                    let orientation = glyph_orientation(style, buf);
                                     let mut plan = {
                                         let font = self.fonts.as_mut().unwrap().get_mut(
                                             style.font_kind,
@@ -1112,7 +2061,13 @@ impl Engine {
                                             style.font_weight,
                                         );
                                         font.set_size(font_size, self.dpi);
-                                        font.plan(buf, None, style.font_features.as_deref())
+                                        plan_text(
+                                            font,
+                                            buf,
+                                            style.font_features.as_deref(),
+                                            bidi_level,
+                                            orientation,
+                                        )
                                     };
                                     plan.space_out(style.letter_spacing);
 
@@ -1129,6 +2084,8 @@ impl Engine {
                                             font_weight: style.font_weight,
                                             vertical_align: style.vertical_align,
                                             letter_spacing: style.letter_spacing,
+                                            bidi_level,
+                                            orientation,
                                             font_size,
                                             color: style.color,
                                             uri: style.uri.clone(),
@@ -1372,6 +2329,17 @@ impl Engine {
                         }
                     }
                 }
+                InlineMaterial::Ruby(ruby) => {
+                    let bidi_level = bidi_levels
+                        .as_ref()
+                        .map_or(Level::ltr(), |(starts, levels)| levels[starts[index]]);
+                    let base = self.ruby_text_element(&ruby.base, bidi_level);
+                    let annotation = self.ruby_text_element(&ruby.annotation, bidi_level);
+                    items.push(ParagraphItem::Box {
+                        width: base.plan.width.max(annotation.plan.width),
+                        data: ParagraphElement::Ruby(Box::new(RubyElement { base, annotation })),
+                    });
+                }
                 InlineMaterial::LineBreak => {
                     let stretch = if parent_style.text_align == TextAlign::Center {
                         big_stretch
@@ -1473,6 +2441,60 @@ impl Engine {
         (items, floats)
     }
 
+    fn ruby_text_element(&mut self, material: &TextMaterial, bidi_level: Level) -> TextElement {
+        let TextMaterial {
+            offset,
+            text,
+            style,
+        } = material;
+        let font_size = (style.font_size * 64.0) as u32;
+        let orientation = glyph_orientation(style, text);
+        let mut plan = {
+            let font = self.fonts.as_mut().unwrap().get_mut(
+                style.font_kind,
+                style.font_style,
+                style.font_weight,
+            );
+            font.set_size(font_size, self.dpi);
+            plan_text(
+                font,
+                text,
+                style.font_features.as_deref(),
+                bidi_level,
+                orientation,
+            )
+        };
+        plan.space_out(style.letter_spacing);
+        TextElement {
+            offset: *offset,
+            language: style.language.clone(),
+            text: text.clone(),
+            plan,
+            font_features: style.font_features.clone(),
+            font_kind: style.font_kind,
+            font_style: style.font_style,
+            font_weight: style.font_weight,
+            vertical_align: style.vertical_align,
+            letter_spacing: style.letter_spacing,
+            bidi_level,
+            orientation,
+            font_size,
+            color: style.color,
+            uri: style.uri.clone(),
+        }
+    }
+
+    // The ascender and descender of the font of the given element.
+    fn font_metrics(&mut self, element: &TextElement) -> (i32, i32) {
+        let font = self.fonts.as_mut().unwrap().get_mut(
+            element.font_kind,
+            element.font_style,
+            element.font_weight,
+        );
+        font.set_size(element.font_size, self.dpi);
+        (font.ascender(), font.descender())
+    }
+
     fn place_paragraphs(
         &mut self,
         inlines: &[InlineMaterial],
@@ -1492,30 +2514,72 @@ impl Engine {
             return;
         }
 
+        let drop_cap = draw_state.drop_cap.take();
+        let first_line = draw_state.first_line.take();
         let position = &mut draw_state.position;
 
-        let text_indent = if style.text_align == TextAlign::Center {
+        let text_indent = if style.text_align == TextAlign::Center || drop_cap.is_some() {
             0
         } else {
             style.text_indent
         };
 
-        let (ascender, descender) = {
+        let (ascender, descender, cap_height, space_width) = {
             let fonts = self.fonts.as_mut().unwrap();
             let font = fonts.get_mut(style.font_kind, style.font_style, style.font_weight);
             font.set_size((style.font_size * 64.0) as u32, self.dpi);
-            (font.ascender(), font.descender())
+            (
+                font.ascender(),
+                font.descender(),
+                font.height('H') as i32,
+                font.plan(" ", None, None).width,
+            )
         };
 
+        // The initial letter is scaled so that its capital height spans the given
+        // number of lines, otherwise the number of lines follows from its size.
+        let initial = drop_cap.map(|drop_cap| {
+            let font = self.fonts.as_mut().unwrap().get_mut(
+                drop_cap.style.font_kind,
+                drop_cap.style.font_style,
+                drop_cap.style.font_weight,
+            );
+            let mut font_size = (drop_cap.style.font_size * 64.0) as u32;
+            font.set_size(font_size, self.dpi);
+            let initial_cap_height = (font.height('H') as i32).max(1);
+            let lines = if let Some(lines) = drop_cap.lines {
+                let target = (lines as i32 - 1) * style.line_height + cap_height;
+                font_size =
+                    (font_size as f32 * target as f32 / initial_cap_height as f32).round() as u32;
+                font.set_size(font_size, self.dpi);
+                lines as i32
+            } else {
+                1 + ((initial_cap_height - cap_height).max(0) + style.line_height - 1)
+                    / style.line_height
+            };
+            let mut plan = font.plan(
+                &drop_cap.text,
+                None,
+                drop_cap.style.font_features.as_deref(),
+            );
+            plan.space_out(drop_cap.style.letter_spacing);
+            let metrics = (font.ascender(), font.descender());
+            (drop_cap, font_size, plan, lines, metrics)
+        });
+
         let ratio = ascender as f32 / (ascender - descender) as f32;
         let space_top = (style.line_height as f32 * ratio) as i32;
         let space_bottom = style.line_height - space_top;
 
         position.y += style.margin.top + space_top;
 
+        let initial_height = initial
+            .as_ref()
+            .map_or(0, |(_, _, _, lines, _)| (lines - 1) * style.line_height);
+
         let mut page = display_list.pop().unwrap();
         let mut page_rect = rects.pop().unwrap();
-        if position.y > root_data.rect.max.y - space_bottom {
+        if position.y + initial_height > root_data.rect.max.y - space_bottom {
             rects.push(page_rect.take());
             display_list.push(page);
             position.y = root_data.rect.min.y + space_top;
@@ -1593,6 +2657,59 @@ impl Engine {
             }
         }
 
+        if let Some((drop_cap, font_size, plan, lines, (initial_ascender, initial_descender))) =
+            initial
+        {
+            let y_min = position.y - space_top;
+            let y_max = y_min + lines * style.line_height;
+            let margin = drop_cap.style.margin;
+            let width = plan.width;
+            let (x, frect) = if style.direction == Direction::Rtl {
+                let gap = if margin.left > 0 {
+                    margin.left
+                } else {
+                    space_width
+                };
+                let x = style.end_x - margin.right - width;
+                (x, rect![x - gap, y_min, style.end_x, y_max])
+            } else {
+                let gap = if margin.right > 0 {
+                    margin.right
+                } else {
+                    space_width
+                };
+                let x = style.start_x + margin.left;
+                (x, rect![style.start_x, y_min, x + width + gap, y_max])
+            };
+
+            draw_state.floats.entry(page_index).or_default().push(frect);
+
+            let pt = pt!(x, position.y + (lines - 1) * style.line_height);
+            let rect = rect![
+                pt + pt!(0, -initial_ascender),
+                pt + pt!(width, -initial_descender)
+            ];
+            if let Some(pr) = page_rect.as_mut() {
+                pr.absorb(&rect);
+            } else {
+                page_rect = Some(rect);
+            }
+            page.push(DrawCommand::Text(TextCommand {
+                offset: drop_cap.offset + root_data.start_offset,
+                position: pt,
+                rect,
+                text: drop_cap.text,
+                plan,
+                uri: drop_cap.style.uri.clone(),
+                font_kind: drop_cap.style.font_kind,
+                font_style: drop_cap.style.font_style,
+                font_weight: drop_cap.style.font_weight,
+                font_size,
+                orientation: GlyphOrientation::Horizontal,
+                color: drop_cap.style.color,
+            }));
+        }
+
         let para_shape = if let Some(floating_rects) = draw_state.floats.get(&page_index) {
             let max_lines = (root_data.rect.max.y - position.y + space_top) / style.line_height;
             let mut para_shape = Vec::new();
@@ -1624,6 +2741,31 @@ impl Engine {
 
         let mut bps = total_fit(&items, &line_lengths, self.stretch_tolerance, 0);
 
+        // Restyle the material of the first line and break the paragraph anew.
+        if let Some(props) = first_line.as_ref() {
+            let estimate = if bps.is_empty() {
+                standard_fit(&items, &line_lengths, self.stretch_tolerance)
+            } else {
+                Vec::new()
+            };
+            let end_offset = bps.first().or(estimate.first()).and_then(|bp| {
+                items[..bp.index].iter().rev().find_map(|item| match item {
+                    ParagraphItem::Box {
+                        data: ParagraphElement::Text(element),
+                        ..
+                    } => Some(element.offset + element.text.len()),
+                    _ => None,
+                })
+            });
+            if let Some(end_offset) = end_offset {
+                let inlines = self.restyle_first_line(props, inlines, end_offset);
+                items = self
+                    .make_paragraph_items(&inlines, style, line_width, resource_fetcher)
+                    .0;
+                bps = total_fit(&items, &line_lengths, self.stretch_tolerance, 0);
+            }
+        }
+
         let mut hyph_indices = Vec::new();
         let mut glue_drifts = Vec::new();
 
@@ -1695,6 +2837,8 @@ impl Engine {
             items = self.cleanup_paragraph(items, &hyph_indices, &mut glue_drifts, &mut bps);
         }
 
+        let bidi_levels = item_bidi_levels(&items, style.direction);
+
         let mut last_index = 0;
         let mut markers_index = 0;
         let mut last_x_position = 0;
@@ -1711,8 +2855,12 @@ impl Engine {
                 font.set_size(font_size, self.dpi);
                 font.plan(prefix, None, style.font_features.as_deref())
             };
-            let (start_x, _) = para_shape[0];
-            let pt = pt!(start_x - prefix_plan.width, position.y);
+            let (start_x, end_x) = para_shape[0];
+            let pt = if style.direction == Direction::Rtl {
+                pt!(end_x, position.y)
+            } else {
+                pt!(start_x - prefix_plan.width, position.y)
+            };
             let rect = rect![
                 pt + pt!(0, -ascender),
                 pt + pt!(prefix_plan.width, -descender)
@@ -1729,6 +2877,7 @@ impl Engine {
                     font_style: style.font_style,
                     font_weight: style.font_weight,
                     font_size,
+                    orientation: GlyphOrientation::Horizontal,
                     color: style.color,
                 }));
             }
@@ -1753,6 +2902,8 @@ impl Engine {
 
             match style.text_align {
                 TextAlign::Right => position.x = end_x - width - current_text_indent,
+                // The indentation of right-to-left lines is on the right side.
+                _ if style.direction == Direction::Rtl => position.x = start_x,
                 _ => position.x = start_x + current_text_indent,
             }
 
@@ -1764,9 +2915,47 @@ impl Engine {
                 last_index += 1;
             }
 
+            // Make room for the annotations of the ruby text.
+            let ruby_height = items[last_index..index]
+                .iter()
+                .filter_map(|itm| match itm {
+                    ParagraphItem::Box {
+                        data: ParagraphElement::Ruby(element),
+                        ..
+                    } => Some(element),
+                    _ => None,
+                })
+                .map(|element| {
+                    let (ruby_ascender, ruby_descender) = self.font_metrics(&element.annotation);
+                    ruby_ascender - ruby_descender
+                })
+                .max();
+
+            if let Some(ruby_height) = ruby_height {
+                let excess = ruby_height - (style.line_height - ascender + descender);
+                if excess > 0 {
+                    position.y += excess;
+                    if position.y > root_data.rect.max.y - space_bottom {
+                        rects.push(page_rect.take());
+                        display_list.push(page);
+                        position.y = root_data.rect.min.y + space_top + excess;
+                        page = Vec::new();
+                    }
+                }
+            }
+
             let start_command_index = page.len();
 
-            for i in last_index..index {
+            let visual_order: Vec<usize> = if let Some(levels) = bidi_levels.as_ref() {
+                BidiInfo::reorder_visual(&levels[last_index..index])
+                    .into_iter()
+                    .map(|k| last_index + k)
+                    .collect()
+            } else {
+                (last_index..index).collect()
+            };
+
+            for i in visual_order {
                 match items[i] {
                     ParagraphItem::Box { ref data, width } => {
                         match data {
@@ -1791,19 +2980,62 @@ impl Engine {
                                         break;
                                     }
                                 }
-                                page.push(DrawCommand::Text(TextCommand {
-                                    offset: element.offset + root_data.start_offset,
-                                    position: pt,
+                                page.push(DrawCommand::Text(text_command(
+                                    element,
+                                    root_data.start_offset,
+                                    pt,
                                     rect,
-                                    text: element.text.clone(),
-                                    plan: element.plan.clone(),
-                                    uri: element.uri.clone(),
-                                    font_kind: element.font_kind,
-                                    font_style: element.font_style,
-                                    font_weight: element.font_weight,
-                                    font_size: element.font_size,
-                                    color: element.color,
-                                }));
+                                )));
+                            }
+                            ParagraphElement::Ruby(ruby) => {
+                                let RubyElement { base, annotation } = ruby.as_ref();
+                                let pt = pt!(
+                                    position.x + (width - base.plan.width) / 2,
+                                    position.y - base.vertical_align
+                                );
+                                let rect = rect![
+                                    pt + pt!(0, -ascender),
+                                    pt + pt!(base.plan.width, -descender)
+                                ];
+                                // The bottom of the annotation touches the top of the base.
+                                let (ruby_ascender, ruby_descender) = self.font_metrics(annotation);
+                                let ruby_pt = pt!(
+                                    position.x + (width - annotation.plan.width) / 2,
+                                    pt.y - ascender + ruby_descender
+                                );
+                                let ruby_rect = rect![
+                                    ruby_pt + pt!(0, -ruby_ascender),
+                                    ruby_pt + pt!(annotation.plan.width, -ruby_descender)
+                                ];
+                                for r in [rect, ruby_rect] {
+                                    if let Some(pr) = page_rect.as_mut() {
+                                        pr.absorb(&r);
+                                    } else {
+                                        page_rect = Some(r);
+                                    }
+                                }
+                                while let Some(offset) = markers.get(markers_index) {
+                                    if *offset < base.offset {
+                                        page.push(DrawCommand::Marker(
+                                            root_data.start_offset + *offset,
+                                        ));
+                                        markers_index += 1;
+                                    } else {
+                                        break;
+                                    }
+                                }
+                                page.push(DrawCommand::Text(text_command(
+                                    base,
+                                    root_data.start_offset,
+                                    pt,
+                                    rect,
+                                )));
+                                page.push(DrawCommand::Text(text_command(
+                                    annotation,
+                                    root_data.start_offset,
+                                    ruby_pt,
+                                    ruby_rect,
+                                )));
                             }
                             ParagraphElement::Image(element) => {
                                 while let Some(offset) = markers.get(markers_index) {
@@ -2067,6 +3299,8 @@ impl Engine {
                 font_size: element.font_size,
                 vertical_align: element.vertical_align,
                 letter_spacing: element.letter_spacing,
+                bidi_level: element.bidi_level,
+                orientation: element.orientation,
                 color: element.color,
                 uri: element.uri.clone(),
             }),
@@ -2084,10 +3318,11 @@ impl Engine {
 
         for itm in items {
             match itm {
+                // Right-to-left words aren't hyphenated.
                 ParagraphItem::Box {
                     data: ParagraphElement::Text(ref element),
                     ..
-                } => {
+                } if !element.bidi_level.is_rtl() => {
                     let text = &element.text;
                     let (hyphen_width, stretch) = {
                         let font = self.fonts.as_mut().unwrap().get_mut(
@@ -2329,6 +3564,77 @@ impl Engine {
         merged_items
     }
 
+    // Maps the pages laid out in the rotated frame of the vertical writing mode
+    // onto the given rectangle: the lines become columns, from right to left.
+    pub fn rotate_pages(&mut self, pages: &mut [Page], rect: Rectangle) {
+        let map_rect = |r: Rectangle| {
+            rect![
+                rect.max.x - r.max.y + rect.min.y,
+                rect.min.y + r.min.x - rect.min.x,
+                rect.max.x - r.min.y + rect.min.y,
+                rect.min.y + r.max.x - rect.min.x
+            ]
+        };
+
+        for page in pages.iter_mut() {
+            for dc in page.iter_mut() {
+                match dc {
+                    DrawCommand::Text(tc) | DrawCommand::ExtraText(tc) => {
+                        tc.position = pt!(
+                            rect.max.x - tc.position.y + rect.min.y,
+                            rect.min.y + tc.position.x - rect.min.x
+                        );
+                        tc.rect = map_rect(tc.rect);
+                        if tc.orientation == GlyphOrientation::Upright {
+                            // Center the glyphs on the column.
+                            let font = self.fonts.as_mut().unwrap().get_mut(
+                                tc.font_kind,
+                                tc.font_style,
+                                tc.font_weight,
+                            );
+                            font.set_size(tc.font_size, self.dpi);
+                            tc.position.x += (font.ascender() + font.descender()) / 2;
+                        } else {
+                            tc.orientation = GlyphOrientation::Sideways;
+                        }
+                    }
+                    DrawCommand::Image(ic) => {
+                        ic.rect = map_rect(ic.rect);
+                        ic.position = ic.rect.min;
+                    }
+                    DrawCommand::Marker(..) => (),
+                }
+            }
+        }
+    }
+
+    // Returns the magic and the data of an image. The external images referenced
+    // by SVG images are embedded, since MuPDF can't fetch them.
+    fn fetch_image<'a>(
+        &self,
+        path: &'a str,
+        resource_fetcher: &mut dyn ResourceFetcher,
+    ) -> Option<(&'a str, Vec<u8>)> {
+        if let Some(markup) = self.inline_svgs.get(path) {
+            return embed_images(markup, Path::new(""), resource_fetcher)
+                .map(|svg| (SVG_MIME_TYPE, svg.into_bytes()));
+        }
+
+        let buf = resource_fetcher.fetch(path).ok()?;
+
+        if path.to_lowercase().ends_with(".svg") {
+            let base_dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+            if let Some(svg) = std::str::from_utf8(&buf)
+                .ok()
+                .and_then(|text| embed_images(text, base_dir, resource_fetcher))
+            {
+                return Some((SVG_MIME_TYPE, svg.into_bytes()));
+            }
+        }
+
+        Some((path, buf))
+    }
+
     pub fn render_page(
         &mut self,
         page: &[DrawCommand],
@@ -2349,6 +3655,7 @@ impl Engine {
                     font_style,
                     font_weight,
                     font_size,
+                    orientation,
                     color,
                     ..
                 })
@@ -2359,6 +3666,7 @@ impl Engine {
                     font_style,
                     font_weight,
                     font_size,
+                    orientation,
                     color,
                     ..
                 }) => {
@@ -2371,7 +3679,11 @@ impl Engine {
                     let position = Point::from(scale_factor * Vec2::from(*position));
                     let plan = plan.scale(scale_factor);
                     font.set_size(font_size, self.dpi);
-                    font.render(&mut fb, *color, &plan, position);
+                    if *orientation == GlyphOrientation::Sideways {
+                        font.render_sideways(&mut fb, *color, &plan, position);
+                    } else {
+                        font.render(&mut fb, *color, &plan, position);
+                    }
                 }
                 DrawCommand::Image(ImageCommand {
                     position,
@@ -2379,9 +3691,9 @@ impl Engine {
                     scale,
                     ..
                 }) => {
-                    if let Ok(buf) = resource_fetcher.fetch(path) {
+                    if let Some((magic, buf)) = self.fetch_image(path, resource_fetcher) {
                         if let Some((pixmap, _)) = PdfOpener::new()
-                            .and_then(|opener| opener.open_memory(path, &buf))
+                            .and_then(|opener| opener.open_memory(magic, &buf))
                             .and_then(|mut doc| {
                                 doc.pixmap(Location::Exact(0), scale_factor * *scale, samples)
                             })
@@ -2399,6 +3711,75 @@ impl Engine {
     }
 }
 
+// The number of rows covered by a table cell, limited to the rows of its group.
+fn cell_rowspan(cell: NodeRef, row: NodeRef) -> usize {
+    let rows_left = 1 + row
+        .next_sibling_elements()
+        .filter(|n| n.tag_name() == Some("tr"))
+        .count();
+    match cell
+        .attribute("rowspan")
+        .and_then(|v| v.trim().parse::<usize>().ok())
+    {
+        Some(0) => rows_left,
+        Some(n) => n.min(rows_left),
+        None => 1,
+    }
+}
+
+fn cell_vertical_align(node: NodeRef, stylesheet: &StyleSheet) -> Option<VerticalAlign> {
+    specified_values(node, stylesheet)
+        .get("vertical-align")
+        .and_then(|value| parse_cell_vertical_align(value))
+        .or_else(|| node.attribute("valign").and_then(parse_cell_vertical_align))
+}
+
+fn occupy_columns(rowspans: &mut Vec<usize>, index: usize, colspan: usize, rowspan: usize) {
+    if rowspans.len() < index + colspan {
+        rowspans.resize(index + colspan, 0);
+    }
+    for n in &mut rowspans[index..index + colspan] {
+        *n = rowspan;
+    }
+}
+
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation()
+        || matches!(
+            c,
+            '«' | '»'
+                | '‹'
+                | '›'
+                | '“'
+                | '”'
+                | '„'
+                | '‘'
+                | '’'
+                | '‚'
+                | '¡'
+                | '¿'
+                | '–'
+                | '—'
+        )
+}
+
+// Whether the element is positioned outside of the normal flow.
+fn is_out_of_flow(props: &PropertyMap) -> bool {
+    matches!(
+        props.get("position").map(String::as_str),
+        Some("absolute" | "fixed")
+    )
+}
+
+// The side of the page on which a floating rectangle lies.
+fn float_side(rect: &Rectangle, style: &StyleData) -> Float {
+    if rect.min.x + rect.max.x <= style.start_x + style.end_x {
+        Float::Left
+    } else {
+        Float::Right
+    }
+}
+
 fn format_list_prefix(kind: ListStyleType, index: usize) -> Option<String> {
     match kind {
         ListStyleType::None => None,
@@ -2439,29 +3820,235 @@ fn format_list_prefix(kind: ListStyleType, index: usize) -> Option<String> {
     }
 }
 
-fn default_fonts() -> Result<Fonts, Error> {
+// Returns the offsets of the inline materials within the text of the paragraph,
+// and the embedding level of each byte of that text.
+fn inline_bidi_levels(
+    inlines: &[InlineMaterial],
+    direction: Direction,
+) -> Option<(Vec<usize>, Vec<Level>)> {
+    let mut text = String::new();
+    let mut starts = Vec::with_capacity(inlines.len());
+
+    for mater in inlines {
+        match mater {
+            InlineMaterial::Text(TextMaterial {
+                text: chunk, style, ..
+            }) => {
+                // Inline elements with a different direction are isolated.
+                let is_isolated = style.direction != direction;
+                if is_isolated {
+                    text.push(if style.direction == Direction::Rtl {
+                        '\u{2067}'
+                    } else {
+                        '\u{2066}'
+                    });
+                }
+                starts.push(text.len());
+                text.push_str(chunk);
+                if is_isolated {
+                    text.push('\u{2069}');
+                }
+            }
+            InlineMaterial::Image(..) | InlineMaterial::Ruby(..) => {
+                starts.push(text.len());
+                text.push('\u{FFFC}');
+            }
+            _ => starts.push(text.len()),
+        }
+    }
+
+    let info = ParagraphBidiInfo::new(&text, Some(direction.level()));
+
+    if direction == Direction::Ltr && !info.has_rtl() {
+        return None;
+    }
+
+    Some((starts, info.levels))
+}
+
+// Merges the given text materials into one, with collapsed white space.
+fn merge_text_materials(materials: &[InlineMaterial]) -> Option<TextMaterial> {
+    let mut text = String::new();
+    let mut first = None;
+    for mater in materials {
+        if let InlineMaterial::Text(material) = mater {
+            if first.is_none() && !material.text.trim().is_empty() {
+                first = Some(material);
+            }
+            text.push_str(&material.text);
+        } else {
+            return None;
+        }
+    }
+    first.map(|material| TextMaterial {
+        offset: material.offset + material.text.len() - material.text.trim_start().len(),
+        text: text.split_whitespace().collect::<Vec<&str>>().join(" "),
+        style: material.style.clone(),
+    })
+}
+
+fn text_command(
+    element: &TextElement,
+    start_offset: usize,
+    position: Point,
+    rect: Rectangle,
+) -> TextCommand {
+    TextCommand {
+        offset: element.offset + start_offset,
+        position,
+        rect,
+        text: element.text.clone(),
+        plan: element.plan.clone(),
+        uri: element.uri.clone(),
+        font_kind: element.font_kind,
+        font_style: element.font_style,
+        font_weight: element.font_weight,
+        font_size: element.font_size,
+        orientation: element.orientation,
+        color: element.color,
+    }
+}
+
+// The boxes of text carry their own embedding level, the level of the other items
+// is the lowest level of their closest neighbors.
+fn item_bidi_levels(
+    items: &[ParagraphItem<ParagraphElement>],
+    direction: Direction,
+) -> Option<Vec<Level>> {
+    let base_level = direction.level();
+    let levels: Vec<Option<Level>> = items
+        .iter()
+        .map(|itm| match itm {
+            ParagraphItem::Box {
+                data: ParagraphElement::Text(element),
+                ..
+            } => Some(element.bidi_level),
+            _ => None,
+        })
+        .collect();
+
+    if direction == Direction::Ltr && levels.iter().flatten().all(|level| *level == base_level) {
+        return None;
+    }
+
+    let mut previous_levels = Vec::with_capacity(levels.len());
+    let mut last_level = base_level;
+    for level in &levels {
+        if let Some(level) = level {
+            last_level = *level;
+        }
+        previous_levels.push(last_level);
+    }
+
+    let mut result = vec![base_level; levels.len()];
+    let mut next_level = base_level;
+    for (i, level) in levels.iter().enumerate().rev() {
+        result[i] = if let Some(level) = level {
+            next_level = *level;
+            *level
+        } else {
+            previous_levels[i].min(next_level)
+        };
+    }
+
+    Some(result)
+}
+
+#[inline]
+fn plan_text(
+    font: &mut Font,
+    text: &str,
+    features: Option<&[String]>,
+    bidi_level: Level,
+    orientation: GlyphOrientation,
+) -> RenderPlan {
+    if orientation == GlyphOrientation::Upright {
+        font.plan_vertical(text, None, features)
+    } else if bidi_level.is_rtl() {
+        font.plan_rtl(text, None, features)
+    } else {
+        font.plan(text, None, features)
+    }
+}
+
+fn glyph_orientation(style: &StyleData, text: &str) -> GlyphOrientation {
+    if style.writing_mode == WritingMode::HorizontalTb {
+        return GlyphOrientation::Horizontal;
+    }
+
+    match style.text_orientation {
+        TextOrientation::Upright => GlyphOrientation::Upright,
+        TextOrientation::Sideways => GlyphOrientation::Sideways,
+        TextOrientation::Mixed => {
+            if text.chars().next().is_some_and(is_upright) {
+                GlyphOrientation::Upright
+            } else {
+                GlyphOrientation::Sideways
+            }
+        }
+    }
+}
+
+// Characters of the scripts that are written upright in vertical text (cf. UAX #50).
+fn is_upright(c: char) -> bool {
+    matches!(u32::from(c),
+        0x1100..=0x11FF
+        | 0x2E80..=0x303F
+        | 0x3040..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA960..=0xA97F
+        | 0xAC00..=0xD7FF
+        | 0xF900..=0xFAFF
+        | 0xFE10..=0xFE1F
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFFEF
+        | 0x20000..=0x3FFFF)
+}
+
+// Returns the writing mode set on the root elements of the document.
+pub fn root_writing_mode(root: NodeRef, stylesheet: &StyleSheet) -> WritingMode {
+    let mut writing_mode = WritingMode::HorizontalTb;
+
+    for name in ["html", "body"] {
+        if let Some(value) = root.find(name).and_then(|node| {
+            let props = specified_values(node, stylesheet);
+            WRITING_MODE_PROPERTIES
+                .iter()
+                .find_map(|name| props.get(*name))
+                .and_then(|value| parse_writing_mode(value))
+        }) {
+            writing_mode = value;
+        }
+    }
+
+    writing_mode
+}
+
+fn default_fonts(search_path: &Path) -> Result<Fonts, Error> {
     let opener = FontOpener::new()?;
     let mut fonts = Fonts {
         serif: FontFamily {
-            regular: opener.open("fonts/LibertinusSerif-Regular.otf")?,
-            italic: opener.open("fonts/LibertinusSerif-Italic.otf")?,
-            bold: opener.open("fonts/LibertinusSerif-Bold.otf")?,
-            bold_italic: opener.open("fonts/LibertinusSerif-BoldItalic.otf")?,
+            regular: opener.open(search_path.join("LibertinusSerif-Regular.otf"))?,
+            italic: opener.open(search_path.join("LibertinusSerif-Italic.otf"))?,
+            bold: opener.open(search_path.join("LibertinusSerif-Bold.otf"))?,
+            bold_italic: opener.open(search_path.join("LibertinusSerif-BoldItalic.otf"))?,
         },
         sans_serif: FontFamily {
-            regular: opener.open("fonts/NotoSans-Regular.ttf")?,
-            italic: opener.open("fonts/NotoSans-Italic.ttf")?,
-            bold: opener.open("fonts/NotoSans-Bold.ttf")?,
-            bold_italic: opener.open("fonts/NotoSans-BoldItalic.ttf")?,
+            regular: opener.open(search_path.join("NotoSans-Regular.ttf"))?,
+            italic: opener.open(search_path.join("NotoSans-Italic.ttf"))?,
+            bold: opener.open(search_path.join("NotoSans-Bold.ttf"))?,
+            bold_italic: opener.open(search_path.join("NotoSans-BoldItalic.ttf"))?,
         },
         monospace: FontFamily {
-            regular: opener.open("fonts/SourceCodeVariable-Roman.otf")?,
-            italic: opener.open("fonts/SourceCodeVariable-Italic.otf")?,
-            bold: opener.open("fonts/SourceCodeVariable-Roman.otf")?,
-            bold_italic: opener.open("fonts/SourceCodeVariable-Italic.otf")?,
+            regular: opener.open(search_path.join("SourceCodeVariable-Roman.otf"))?,
+            italic: opener.open(search_path.join("SourceCodeVariable-Italic.otf"))?,
+            bold: opener.open(search_path.join("SourceCodeVariable-Roman.otf"))?,
+            bold_italic: opener.open(search_path.join("SourceCodeVariable-Italic.otf"))?,
         },
-        cursive: opener.open("fonts/Parisienne-Regular.ttf")?,
-        fantasy: opener.open("fonts/Delius-Regular.ttf")?,
+        cursive: opener.open(search_path.join("Parisienne-Regular.ttf"))?,
+        fantasy: opener.open(search_path.join("Delius-Regular.ttf"))?,
+        embedded: Vec::new(),
     };
     fonts.monospace.bold.set_variations(&["wght=600"]);
     fonts.monospace.bold_italic.set_variations(&["wght=600"]);
@@ -1,3 +1,4 @@
+use super::style::PropertyMap;
 use crate::color::Color;
 use crate::color::BLACK;
 use crate::font::{Font, FontFamily, RenderPlan};
@@ -8,7 +9,9 @@ use kl_hyphenate::{Language, Load, Standard};
 use lazy_static::lazy_static;
 use std::fmt::Debug;
 use std::fs;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use unicode_bidi::Level;
 
 pub const DEFAULT_HYPH_LANG: &str = "en";
 
@@ -28,6 +31,15 @@ pub struct DrawState {
     pub max_column_widths: Vec<i32>,
     pub column_widths: Vec<i32>,
     pub center_table: bool,
+    // Number of rows, after the current one, covered by each column's cell.
+    pub rowspans: Vec<usize>,
+    pub spanning_cells: Vec<SpanningCell>,
+    // Values of the pseudo-elements that apply to the next paragraph.
+    pub first_letter: Option<PropertyMap>,
+    pub first_line: Option<PropertyMap>,
+    pub drop_cap: Option<DropCap>,
+    // The box absolutely positioned elements are placed within, only set for fixed-layout pages.
+    pub containing_block: Option<Rectangle>,
 }
 
 impl Default for DrawState {
@@ -40,6 +52,12 @@ impl Default for DrawState {
             max_column_widths: Vec::new(),
             column_widths: Vec::new(),
             center_table: false,
+            rowspans: Vec::new(),
+            spanning_cells: Vec::new(),
+            first_letter: None,
+            first_line: None,
+            drop_cap: None,
+            containing_block: None,
         }
     }
 }
@@ -48,6 +66,9 @@ impl Default for DrawState {
 pub struct StyleData {
     pub display: Display,
     pub float: Option<Float>,
+    pub direction: Direction,
+    pub writing_mode: WritingMode,
+    pub text_orientation: TextOrientation,
     pub width: i32,
     pub height: i32,
     pub margin: Edge,
@@ -85,6 +106,71 @@ pub enum Float {
     Right,
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Clear {
+    Left,
+    Right,
+    Both,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VerticalAlign {
+    Top,
+    Middle,
+    Bottom,
+}
+
+// A table cell that spans several rows.
+#[derive(Debug, Clone)]
+pub struct SpanningCell {
+    // Number of rows left to cover.
+    pub remaining: usize,
+    // Index of the page and ordinate of the bottom of the cell.
+    pub page_index: usize,
+    pub bottom: i32,
+    pub vertical_align: VerticalAlign,
+    // Range of the cell's commands, when they're all on the same page.
+    pub commands: Option<Range<usize>>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Direction {
+    Ltr,
+    Rtl,
+}
+
+impl Direction {
+    pub fn level(self) -> Level {
+        match self {
+            Direction::Ltr => Level::ltr(),
+            Direction::Rtl => Level::rtl(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum WritingMode {
+    HorizontalTb,
+    VerticalRl,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TextOrientation {
+    Mixed,
+    Upright,
+    Sideways,
+}
+
+// How the glyphs of a run are drawn.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum GlyphOrientation {
+    Horizontal,
+    // Stacked from top to bottom.
+    Upright,
+    // Rotated by a quarter turn clockwise.
+    Sideways,
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Display {
     Block,
@@ -154,6 +240,8 @@ pub struct LoopContext {
     pub sibling_style: SiblingStyle,
     pub is_first: bool,
     pub is_last: bool,
+    pub is_float: bool,
+    pub is_absolute: bool,
 }
 
 impl Default for LoopContext {
@@ -163,6 +251,8 @@ impl Default for LoopContext {
             sibling_style: SiblingStyle::default(),
             is_first: false,
             is_last: false,
+            is_float: false,
+            is_absolute: false,
         }
     }
 }
@@ -172,6 +262,9 @@ impl Default for StyleData {
         StyleData {
             display: Display::Block,
             float: None,
+            direction: Direction::Ltr,
+            writing_mode: WritingMode::HorizontalTb,
+            text_orientation: TextOrientation::Mixed,
             width: 0,
             height: 0,
             margin: Edge::default(),
@@ -202,6 +295,7 @@ impl Default for StyleData {
 pub enum InlineMaterial {
     Text(TextMaterial),
     Image(ImageMaterial),
+    Ruby(Box<RubyMaterial>),
     Glue(GlueMaterial),
     Penalty(PenaltyMaterial),
     Box(i32),
@@ -213,6 +307,7 @@ impl InlineMaterial {
         match self {
             InlineMaterial::Text(TextMaterial { offset, .. })
             | InlineMaterial::Image(ImageMaterial { offset, .. }) => Some(*offset),
+            InlineMaterial::Ruby(ruby) => Some(ruby.base.offset),
             _ => None,
         }
     }
@@ -232,6 +327,23 @@ pub struct TextMaterial {
     pub style: StyleData,
 }
 
+// A ruby base and its annotation (e.g. furigana).
+#[derive(Debug, Clone)]
+pub struct RubyMaterial {
+    pub base: TextMaterial,
+    pub annotation: TextMaterial,
+}
+
+// An initial letter that the first lines of its paragraph wrap around.
+#[derive(Debug, Clone)]
+pub struct DropCap {
+    pub offset: usize,
+    pub text: String,
+    pub style: StyleData,
+    // The number of lines spanned, given by `initial-letter`.
+    pub lines: Option<usize>,
+}
+
 #[derive(Debug, Clone)]
 pub struct ImageMaterial {
     pub offset: usize,
@@ -260,15 +372,17 @@ pub enum FontKind {
     Monospace,
     Cursive,
     Fantasy,
+    // Index of a family declared through `@font-face`.
+    Embedded(usize),
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum FontStyle {
     Normal,
     Italic,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum FontWeight {
     Normal,
     Bold,
@@ -280,6 +394,31 @@ pub struct Fonts {
     pub monospace: FontFamily,
     pub cursive: Font,
     pub fantasy: Font,
+    pub embedded: Vec<EmbeddedFontFamily>,
+}
+
+pub struct EmbeddedFontFamily {
+    pub name: String,
+    pub faces: Vec<EmbeddedFont>,
+}
+
+pub struct EmbeddedFont {
+    pub font: Font,
+    pub font_style: FontStyle,
+    pub font_weight: FontWeight,
+    // FreeType reads the face from this buffer: it must outlive `font`.
+    pub data: Vec<u8>,
+}
+
+impl EmbeddedFontFamily {
+    fn face_index(&self, font_style: FontStyle, font_weight: FontWeight) -> Option<usize> {
+        self.faces
+            .iter()
+            .position(|f| f.font_style == font_style && f.font_weight == font_weight)
+            .or_else(|| self.faces.iter().position(|f| f.font_style == font_style))
+            .or_else(|| self.faces.iter().position(|f| f.font_weight == font_weight))
+            .or((!self.faces.is_empty()).then_some(0))
+    }
 }
 
 impl Fonts {
@@ -310,14 +449,32 @@ impl Fonts {
             },
             FontKind::Cursive => &mut self.cursive,
             FontKind::Fantasy => &mut self.fantasy,
+            FontKind::Embedded(index) => {
+                if let Some(i) = self
+                    .embedded
+                    .get(index)
+                    .and_then(|family| family.face_index(font_style, font_weight))
+                {
+                    &mut self.embedded[index].faces[i].font
+                } else {
+                    self.get_mut(FontKind::Serif, font_style, font_weight)
+                }
+            }
         }
     }
+
+    pub fn embedded_family(&self, name: &str) -> Option<usize> {
+        self.embedded
+            .iter()
+            .position(|family| family.name.eq_ignore_ascii_case(name))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum ParagraphElement {
     Text(TextElement),
     Image(ImageElement),
+    Ruby(Box<RubyElement>),
     Nothing,
 }
 
@@ -334,10 +491,19 @@ pub struct TextElement {
     pub font_size: u32,
     pub letter_spacing: i32,
     pub vertical_align: i32,
+    pub bidi_level: Level,
+    pub orientation: GlyphOrientation,
     pub color: Color,
     pub uri: Option<String>,
 }
 
+// The annotation is set above the base, or beside it in the vertical writing mode.
+#[derive(Debug, Clone)]
+pub struct RubyElement {
+    pub base: TextElement,
+    pub annotation: TextElement,
+}
+
 #[derive(Debug, Clone)]
 pub struct ImageElement {
     pub offset: usize,
@@ -370,6 +536,7 @@ pub struct TextCommand {
     pub font_style: FontStyle,
     pub font_weight: FontWeight,
     pub font_size: u32,
+    pub orientation: GlyphOrientation,
     pub color: Color,
     pub uri: Option<String>,
     pub rect: Rectangle,
@@ -404,6 +571,31 @@ impl DrawCommand {
         }
     }
 
+    pub fn shift_y(&mut self, dy: i32) {
+        match *self {
+            DrawCommand::Text(TextCommand {
+                ref mut position,
+                ref mut rect,
+                ..
+            })
+            | DrawCommand::ExtraText(TextCommand {
+                ref mut position,
+                ref mut rect,
+                ..
+            })
+            | DrawCommand::Image(ImageCommand {
+                ref mut position,
+                ref mut rect,
+                ..
+            }) => {
+                position.y += dy;
+                rect.min.y += dy;
+                rect.max.y += dy;
+            }
+            DrawCommand::Marker(..) => (),
+        }
+    }
+
     pub fn position_mut(&mut self) -> Option<&mut Point> {
         match *self {
             DrawCommand::Text(TextCommand {
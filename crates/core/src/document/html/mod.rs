@@ -4,13 +4,15 @@ pub mod engine;
 pub mod layout;
 pub mod parse;
 pub mod style;
+pub mod svg;
 pub mod xml;
 
 use self::css::CssParser;
 use self::dom::{NodeRef, XmlTree};
-use self::engine::{Engine, Page, ResourceFetcher};
-use self::layout::{DrawCommand, ImageCommand, TextAlign, TextCommand};
-use self::layout::{DrawState, LoopContext, RootData, StyleData};
+use self::engine::{root_writing_mode, Engine, Page, ResourceFetcher};
+use self::layout::{Direction, DrawCommand, ImageCommand, TextAlign, TextCommand};
+use self::layout::{DrawState, LoopContext, RootData, StyleData, WritingMode};
+use self::parse::parse_direction;
 use self::style::StyleSheet;
 use self::xml::XmlParser;
 use crate::document::{BoundedText, Document, Location, TextLocation, TocEntry};
@@ -34,11 +36,13 @@ pub struct HtmlDocument {
     content: XmlTree,
     engine: Engine,
     pages: Vec<Page>,
-    parent: PathBuf,
+    resources: Resources,
     size: usize,
     viewer_stylesheet: PathBuf,
     user_stylesheet: PathBuf,
     ignore_document_css: bool,
+    // The directory against which the references of the document are resolved.
+    base_dir: PathBuf,
 }
 
 impl ResourceFetcher for PathBuf {
@@ -50,6 +54,22 @@ impl ResourceFetcher for PathBuf {
     }
 }
 
+// The files of the document's directory, and the resources given in memory.
+#[derive(Default)]
+struct Resources {
+    parent: PathBuf,
+    memory: FxHashMap<String, Vec<u8>>,
+}
+
+impl ResourceFetcher for Resources {
+    fn fetch(&mut self, name: &str) -> Result<Vec<u8>, Error> {
+        if let Some(buf) = self.memory.get(name) {
+            return Ok(buf.clone());
+        }
+        self.parent.fetch(name)
+    }
+}
+
 unsafe impl Send for HtmlDocument {}
 unsafe impl Sync for HtmlDocument {}
 
@@ -68,11 +88,15 @@ impl HtmlDocument {
             content,
             engine: Engine::new(),
             pages: Vec::new(),
-            parent: parent.to_path_buf(),
+            resources: Resources {
+                parent: parent.to_path_buf(),
+                ..Default::default()
+            },
             size,
             viewer_stylesheet: PathBuf::from(VIEWER_STYLESHEET),
             user_stylesheet: PathBuf::from(USER_STYLESHEET),
             ignore_document_css: false,
+            base_dir: PathBuf::default(),
         })
     }
 
@@ -86,14 +110,19 @@ impl HtmlDocument {
             content,
             engine: Engine::new(),
             pages: Vec::new(),
-            parent: PathBuf::default(),
+            resources: Resources::default(),
             size,
             viewer_stylesheet: PathBuf::from(VIEWER_STYLESHEET),
             user_stylesheet: PathBuf::from(USER_STYLESHEET),
             ignore_document_css: false,
+            base_dir: PathBuf::default(),
         }
     }
 
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
     pub fn update(&mut self, text: &str) {
         self.size = text.len();
         self.content = XmlParser::new(text).parse();
@@ -102,6 +131,24 @@ impl HtmlDocument {
         self.pages.clear();
     }
 
+    // The directory from which the resources are fetched.
+    pub fn set_parent<P: AsRef<Path>>(&mut self, parent: P) {
+        self.resources.parent = parent.as_ref().to_path_buf();
+        self.pages.clear();
+    }
+
+    // The directory against which the stylesheets and the images are resolved.
+    pub fn set_base_dir<P: AsRef<Path>>(&mut self, base_dir: P) {
+        self.base_dir = base_dir.as_ref().to_path_buf();
+        self.pages.clear();
+    }
+
+    // Makes the given data available under the given name.
+    pub fn add_resource(&mut self, name: &str, data: Vec<u8>) {
+        self.resources.memory.insert(name.to_string(), data);
+        self.pages.clear();
+    }
+
     pub fn set_margin(&mut self, margin: &Edge) {
         self.engine.set_margin(margin);
         self.pages.clear();
@@ -168,7 +215,7 @@ impl HtmlDocument {
 
     fn build_pages(&mut self) -> Vec<Page> {
         let mut stylesheet = StyleSheet::new();
-        let spine_dir = PathBuf::default();
+        let spine_dir = self.base_dir.clone();
 
         if let Ok(text) = fs::read_to_string(&self.viewer_stylesheet) {
             let mut css = CssParser::new(&text).parse();
@@ -190,9 +237,14 @@ impl HtmlDocument {
                     {
                         if let Some(href) = child.attribute("href") {
                             if let Some(name) = spine_dir.join(href).normalize().to_str() {
-                                if let Ok(buf) = self.parent.fetch(name) {
+                                if let Ok(buf) = self.resources.fetch(name) {
                                     if let Ok(text) = String::from_utf8(buf) {
                                         let mut css = CssParser::new(&text).parse();
+                                        css.set_base_dir(
+                                            Path::new(name)
+                                                .parent()
+                                                .unwrap_or_else(|| Path::new("")),
+                                        );
                                         inner_css.append(&mut css, false);
                                     }
                                 }
@@ -207,14 +259,28 @@ impl HtmlDocument {
                 }
             }
 
+            self.engine.load_font_faces(&inner_css, &mut self.resources);
             stylesheet.append(&mut inner_css, true);
         }
 
+        stylesheet.evaluate_media(&self.engine.media_context());
+
         let mut pages = Vec::new();
 
         let mut rect = self.engine.rect();
         rect.shrink(&self.engine.margin);
 
+        let page_rect = rect;
+        let writing_mode = root_writing_mode(self.content.root(), &stylesheet);
+
+        // The columns of the vertical writing mode are laid out as lines in a rotated frame.
+        if writing_mode == WritingMode::VerticalRl {
+            rect = rect![
+                rect.min,
+                rect.min + pt!(rect.height() as i32, rect.width() as i32)
+            ];
+        }
+
         let language = self
             .content
             .root()
@@ -224,6 +290,7 @@ impl HtmlDocument {
 
         let style = StyleData {
             language,
+            writing_mode,
             font_size: self.engine.font_size,
             line_height: pt_to_px(
                 self.engine.line_height * self.engine.font_size,
@@ -257,11 +324,15 @@ impl HtmlDocument {
             &loop_context,
             &stylesheet,
             &root_data,
-            &mut self.parent,
+            &mut self.resources,
             &mut draw_state,
             &mut pages,
         );
 
+        if writing_mode == WritingMode::VerticalRl {
+            self.engine.rotate_pages(&mut pages, page_rect);
+        }
+
         pages.retain(|page| !page.is_empty());
 
         if pages.is_empty() {
@@ -290,6 +361,14 @@ impl HtmlDocument {
     pub fn year(&self) -> Option<String> {
         self.metadata("date").map(|s| s.chars().take(4).collect())
     }
+
+    pub fn direction(&self) -> Option<Direction> {
+        self.content
+            .root()
+            .find("html")
+            .and_then(|html| html.attribute("dir"))
+            .and_then(parse_direction)
+    }
 }
 
 impl Document for HtmlDocument {
@@ -423,7 +502,7 @@ impl Document for HtmlDocument {
         let page = self.pages[page_index].clone();
         let pixmap = self
             .engine
-            .render_page(&page, scale, samples, &mut self.parent)?;
+            .render_page(&page, scale, samples, &mut self.resources)?;
 
         Some((pixmap, offset))
     }
@@ -511,4 +590,183 @@ impl Document for HtmlDocument {
     fn has_synthetic_page_numbers(&self) -> bool {
         true
     }
+
+    fn is_rtl(&self) -> bool {
+        self.direction() == Some(Direction::Rtl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<BoundedText> {
+        let root_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let mut doc = HtmlDocument::new_from_memory(text);
+        doc.engine.load_fonts_from(&root_dir);
+        doc.set_viewer_stylesheet(root_dir.join(VIEWER_STYLESHEET));
+        doc.set_user_stylesheet(root_dir.join(USER_STYLESHEET));
+        doc.layout(600, 800, 12.0, 300);
+        doc.words(Location::Exact(0))
+            .map(|(words, _)| words)
+            .unwrap_or_default()
+    }
+
+    fn word<'a>(words: &'a [BoundedText], text: &str) -> &'a BoundedText {
+        words.iter().find(|w| w.text == text).unwrap()
+    }
+
+    #[test]
+    fn table_rowspan() {
+        let words = words(
+            "<table>\
+             <tr><td rowspan=\"2\">A</td><td>B</td></tr>\
+             <tr><td>C</td></tr>\
+             <tr><td>D</td><td>E</td></tr>\
+             </table>",
+        );
+        let (a, b, c) = (word(&words, "A"), word(&words, "B"), word(&words, "C"));
+        let (d, e) = (word(&words, "D"), word(&words, "E"));
+        // The second row starts in the second column.
+        assert_eq!(c.rect.min.x, b.rect.min.x);
+        assert!(c.rect.min.y > b.rect.max.y);
+        // The third row is placed below the spanning cell.
+        assert_eq!(d.rect.min.x, a.rect.min.x);
+        assert_eq!(e.rect.min.x, b.rect.min.x);
+        assert!(d.rect.min.y > c.rect.max.y);
+    }
+
+    #[test]
+    fn table_vertical_align() {
+        let words = words(
+            "<table><tr>\
+             <td valign=\"bottom\">A</td>\
+             <td style=\"vertical-align: middle\">B</td>\
+             <td>C</td>\
+             <td>D<br/>E<br/>F</td>\
+             </tr></table>",
+        );
+        let (a, b, c) = (word(&words, "A"), word(&words, "B"), word(&words, "C"));
+        let (d, e, f) = (word(&words, "D"), word(&words, "E"), word(&words, "F"));
+        assert_eq!(a.rect.max.y, f.rect.max.y);
+        assert_eq!(b.rect.max.y, e.rect.max.y);
+        assert_eq!(c.rect.max.y, d.rect.max.y);
+    }
+
+    #[test]
+    fn ruby_annotation() {
+        let words =
+            words("<p><ruby>Edo<rp>(</rp><rt>edo</rt><rp>)</rp>to<rt>to</rt></ruby> city</p>");
+        assert!(words.iter().all(|w| !w.text.contains('(')));
+        let (base, annotation) = (word(&words, "Edo"), word(&words, "edo"));
+        // The annotation is smaller and centered above its base.
+        assert!(annotation.rect.max.y <= base.rect.min.y);
+        assert!(annotation.rect.height() < base.rect.height());
+        assert!(annotation.rect.min.x >= base.rect.min.x);
+        assert!(annotation.rect.max.x <= base.rect.max.x);
+        assert!(word(&words, "city").rect.min.y >= base.rect.min.y);
+    }
+
+    #[test]
+    fn rtl_paragraph() {
+        let words = words(
+            "<p>abc</p>\
+             <p dir=\"rtl\">אחת שתיים</p>\
+             <p style=\"direction: rtl\">שלוש def ghi ארבע</p>",
+        );
+        let abc = word(&words, "abc");
+        let (one, two) = (word(&words, "אחת"), word(&words, "שתיים"));
+        // The words run from right to left, from the right edge of the page.
+        assert!(one.rect.min.x > two.rect.max.x);
+        assert_eq!(one.rect.max.x, 600.0 - abc.rect.min.x);
+        let (three, four) = (word(&words, "שלוש"), word(&words, "ארבע"));
+        let (def, ghi) = (word(&words, "def"), word(&words, "ghi"));
+        // The embedded left-to-right run keeps its order.
+        assert!(three.rect.min.x > ghi.rect.max.x);
+        assert!(ghi.rect.min.x > def.rect.max.x);
+        assert!(def.rect.min.x > four.rect.max.x);
+    }
+
+    #[test]
+    fn vertical_writing_mode() {
+        let words = words(
+            "<html style=\"writing-mode: vertical-rl\">\
+             <body><p>one two</p><p>three</p></body></html>",
+        );
+        let (one, two, three) = (
+            word(&words, "one"),
+            word(&words, "two"),
+            word(&words, "three"),
+        );
+        // The Latin runs are rotated and flow from top to bottom.
+        assert!(one.rect.height() > one.rect.width());
+        assert_eq!(one.rect.min.x, two.rect.min.x);
+        assert!(two.rect.min.y > one.rect.max.y);
+        // The lines are stacked from right to left.
+        assert_eq!(three.rect.min.y, one.rect.min.y);
+        assert!(three.rect.max.x < one.rect.min.x);
+    }
+
+    #[test]
+    fn float_left_and_clear() {
+        let words = words(
+            "<div style=\"float: left; width: 100px\">A</div>\
+             <p>B</p>\
+             <p style=\"clear: left\">C</p>",
+        );
+        let (a, b, c) = (word(&words, "A"), word(&words, "B"), word(&words, "C"));
+        // The lines beside the float are shortened.
+        assert!(b.rect.min.x > a.rect.max.x);
+        assert!(b.rect.min.y < a.rect.max.y);
+        // The cleared paragraph starts below the float, at the left margin.
+        assert_eq!(c.rect.min.x, a.rect.min.x);
+        assert!(c.rect.min.y > a.rect.max.y);
+    }
+
+    #[test]
+    fn float_right() {
+        let words = words("<div style=\"float: right; width: 100px\">A</div><p>B</p>");
+        let (a, b) = (word(&words, "A"), word(&words, "B"));
+        assert!(a.rect.min.x > b.rect.max.x);
+        assert!(b.rect.min.y < a.rect.max.y);
+    }
+
+    #[test]
+    fn drop_cap() {
+        let words = words(
+            "<html><head><style type=\"text/css\">\
+             p::first-letter { float: left; font-size: 3em }\
+             </style></head><body><p>Lorem ipsum dolor sit amet, consectetur adipiscing elit, \
+             sed do eiusmod tempor incididunt ut labore et dolore magna aliqua.</p></body></html>",
+        );
+        let (initial, orem) = (word(&words, "L"), word(&words, "orem"));
+        // The initial spans several lines, which wrap around it.
+        assert!(initial.rect.height() > 2.0 * orem.rect.height());
+        assert!(orem.rect.min.x > initial.rect.max.x);
+        let beside: Vec<&BoundedText> = words
+            .iter()
+            .filter(|w| w.rect.min.x == orem.rect.min.x)
+            .collect();
+        assert!(beside.len() > 1);
+        // The lines below the initial start at the left margin.
+        assert!(words
+            .iter()
+            .any(|w| w.rect.min.y > initial.rect.max.y && w.rect.min.x == initial.rect.min.x));
+    }
+
+    #[test]
+    fn first_line() {
+        let words = words(
+            "<html><head><style type=\"text/css\">\
+             p::first-line { font-weight: bold }\
+             </style></head><body><p>Lorem ipsum dolor sit amet, consectetur adipiscing elit, \
+             sed do eiusmod tempor incididunt ut labore et dolore magna ipsum aliqua.</p>\
+             </body></html>",
+        );
+        let ipsums: Vec<&BoundedText> = words.iter().filter(|w| w.text == "ipsum").collect();
+        let (first, last) = (ipsums[0], ipsums[ipsums.len() - 1]);
+        // Only the words of the first line are bold, hence wider.
+        assert!(last.rect.min.y > first.rect.max.y);
+        assert!(first.rect.width() > last.rect.width());
+    }
 }
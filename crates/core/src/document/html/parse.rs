@@ -1,11 +1,13 @@
-use super::layout::{Display, Float, ListStyleType, TextAlign};
+use super::layout::{Clear, Direction, Display, Float, ListStyleType, TextAlign};
 use super::layout::{FontKind, FontStyle, FontWeight, WordSpacing};
 use super::layout::{GlueMaterial, InlineMaterial, PenaltyMaterial};
+use super::layout::{TextOrientation, VerticalAlign, WritingMode};
 use crate::color::{Color, BLACK, WHITE};
 use crate::geom::Edge;
 use crate::unit::{in_to_px, mm_to_px, pc_to_px, pt_to_px};
 use crate::unit::{CENTIMETERS_PER_INCH, MILLIMETERS_PER_INCH, PICAS_PER_INCH, POINTS_PER_INCH};
 use fxhash::FxHashSet;
+use lazy_static::lazy_static;
 use regex::Regex;
 
 const SIZE_FACTOR: f32 = 1.26;
@@ -14,6 +16,10 @@ const ABSOLUTE_SIZE_KEYWORDS: [&str; 7] = [
 ];
 const RELATIVE_SIZE_KEYWORDS: [&str; 2] = ["smaller", "larger"];
 
+lazy_static! {
+    static ref FONT_FACE_URL: Regex = Regex::new(r#"url\(\s*["']?([^"')]+)["']?\s*\)"#).unwrap();
+}
+
 // TODO: vh, vw, vmin, vmax?
 pub fn parse_length(value: &str, em: f32, rem: f32, dpi: u16) -> Option<i32> {
     if let Some(index) = value.find(|c: char| c.is_ascii_alphabetic()) {
@@ -139,6 +145,23 @@ pub fn parse_font_kind(value: &str) -> Option<FontKind> {
         })
 }
 
+pub fn parse_font_family_names(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|name| name.trim().trim_matches(|c| c == '"' || c == '\'').trim())
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// Returns the URLs listed in the `src` descriptor of an `@font-face` rule.
+pub fn parse_font_face_src(value: &str) -> Vec<String> {
+    FONT_FACE_URL
+        .captures_iter(value)
+        .map(|cap| cap[1].trim().to_string())
+        .collect()
+}
+
 pub fn parse_letter_spacing(value: &str, em: f32, rem: f32, dpi: u16) -> Option<i32> {
     if value == "normal" {
         Some(0)
@@ -183,11 +206,27 @@ pub fn parse_vertical_align(
     }
 }
 
+// The vertical alignment of a table cell.
+pub fn parse_cell_vertical_align(value: &str) -> Option<VerticalAlign> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "top" | "baseline" => Some(VerticalAlign::Top),
+        "middle" | "center" => Some(VerticalAlign::Middle),
+        "bottom" => Some(VerticalAlign::Bottom),
+        _ => None,
+    }
+}
+
 pub fn parse_font_weight(value: &str) -> Option<FontWeight> {
     if value == "normal" {
         Some(FontWeight::Normal)
     } else if value == "bold" {
         Some(FontWeight::Bold)
+    } else if let Ok(weight) = value.parse::<u16>() {
+        if weight >= 600 {
+            Some(FontWeight::Bold)
+        } else {
+            Some(FontWeight::Normal)
+        }
     } else {
         None
     }
@@ -196,7 +235,7 @@ pub fn parse_font_weight(value: &str) -> Option<FontWeight> {
 pub fn parse_font_style(value: &str) -> Option<FontStyle> {
     if value == "normal" {
         Some(FontStyle::Normal)
-    } else if value == "italic" {
+    } else if value == "italic" || value.starts_with("oblique") {
         Some(FontStyle::Italic)
     } else {
         None
@@ -221,6 +260,40 @@ pub fn parse_float(value: &str) -> Option<Float> {
     }
 }
 
+pub fn parse_clear(value: &str) -> Option<Clear> {
+    match value {
+        "left" => Some(Clear::Left),
+        "right" => Some(Clear::Right),
+        "both" => Some(Clear::Both),
+        _ => None,
+    }
+}
+
+pub fn parse_direction(value: &str) -> Option<Direction> {
+    match value.to_ascii_lowercase().as_str() {
+        "ltr" => Some(Direction::Ltr),
+        "rtl" => Some(Direction::Rtl),
+        _ => None,
+    }
+}
+
+pub fn parse_writing_mode(value: &str) -> Option<WritingMode> {
+    match value {
+        "horizontal-tb" | "lr-tb" => Some(WritingMode::HorizontalTb),
+        "vertical-rl" | "tb-rl" => Some(WritingMode::VerticalRl),
+        _ => None,
+    }
+}
+
+pub fn parse_text_orientation(value: &str) -> Option<TextOrientation> {
+    match value {
+        "mixed" => Some(TextOrientation::Mixed),
+        "upright" => Some(TextOrientation::Upright),
+        "sideways" | "sideways-right" => Some(TextOrientation::Sideways),
+        _ => None,
+    }
+}
+
 pub fn parse_list_style_type(value: &str) -> Option<ListStyleType> {
     match value {
         "none" => Some(ListStyleType::None),
@@ -311,12 +384,16 @@ pub fn parse_edge(
     e
 }
 
-pub fn parse_text_align(value: &str) -> Option<TextAlign> {
+pub fn parse_text_align(value: &str, direction: Direction) -> Option<TextAlign> {
     match value {
         "justify" => Some(TextAlign::Justify),
         "left" => Some(TextAlign::Left),
         "right" => Some(TextAlign::Right),
         "center" => Some(TextAlign::Center),
+        "start" if direction == Direction::Rtl => Some(TextAlign::Right),
+        "start" => Some(TextAlign::Left),
+        "end" if direction == Direction::Rtl => Some(TextAlign::Left),
+        "end" => Some(TextAlign::Right),
         _ => None,
     }
 }
@@ -469,4 +546,61 @@ mod tests {
         assert_eq!(d, Some(Color::Rgb(0, 0, 255)));
         assert_eq!(e, Some(Color::Rgb(255, 255, 255)));
     }
+
+    #[test]
+    fn test_parse_font_face_src() {
+        let src = parse_font_face_src(
+            "local(Foo), url(\"fonts/foo.woff\") format(\"woff\"), url( fonts/foo.otf )",
+        );
+        assert_eq!(src, vec!["fonts/foo.woff", "fonts/foo.otf"]);
+        assert_eq!(
+            parse_font_family_names("'Foo Bar', \"Baz\", serif"),
+            vec!["Foo Bar", "Baz", "serif"]
+        );
+    }
+
+    #[test]
+    fn test_parse_text_align() {
+        assert_eq!(parse_direction("RTL"), Some(Direction::Rtl));
+        assert_eq!(parse_direction("auto"), None);
+        assert_eq!(
+            parse_text_align("start", Direction::Ltr),
+            Some(TextAlign::Left)
+        );
+        assert_eq!(
+            parse_text_align("start", Direction::Rtl),
+            Some(TextAlign::Right)
+        );
+        assert_eq!(
+            parse_text_align("end", Direction::Rtl),
+            Some(TextAlign::Left)
+        );
+        assert_eq!(
+            parse_text_align("right", Direction::Rtl),
+            Some(TextAlign::Right)
+        );
+    }
+
+    #[test]
+    fn test_parse_writing_mode() {
+        assert_eq!(
+            parse_writing_mode("vertical-rl"),
+            Some(WritingMode::VerticalRl)
+        );
+        assert_eq!(parse_writing_mode("tb-rl"), Some(WritingMode::VerticalRl));
+        assert_eq!(parse_writing_mode("sideways-lr"), None);
+        assert_eq!(
+            parse_text_orientation("sideways-right"),
+            Some(TextOrientation::Sideways)
+        );
+    }
+
+    #[test]
+    fn test_parse_float() {
+        assert_eq!(parse_float("right"), Some(Float::Right));
+        assert_eq!(parse_float("none"), None);
+        assert_eq!(parse_clear("both"), Some(Clear::Both));
+        assert_eq!(parse_clear("left"), Some(Clear::Left));
+        assert_eq!(parse_clear("none"), None);
+    }
 }
@@ -1,25 +1,79 @@
-use super::css::{AttributeOperator, Combinator, PseudoClass};
-use super::css::{CssParser, Rule, Selector, SimpleSelector};
-use super::dom::NodeRef;
+use super::css::{Attribute, AttributeOperator, Combinator, PseudoClass, PseudoElement};
+use super::css::{CssParser, FontFace, Rule, Selector, SimpleSelector};
+use super::css::{MediaFeature, MediaQuery, MediaQueryList};
+use super::dom::{NodeData, NodeRef};
+use super::parse::parse_length;
+use crate::unit::CENTIMETERS_PER_INCH;
 use fxhash::FxHashMap;
+use std::path::Path;
 
 pub type PropertyMap = FxHashMap<String, String>;
 
 #[derive(Debug, Clone)]
 pub struct StyleSheet {
     pub rules: Vec<Rule>,
+    pub font_faces: Vec<FontFace>,
+    pub media: Vec<MediaQueryList>,
+}
+
+// The characteristics of the output device tested by media queries.
+#[derive(Debug, Clone)]
+pub struct MediaContext {
+    // Viewport dimensions in pixels.
+    pub width: i32,
+    pub height: i32,
+    pub dpi: u16,
+    // Initial font size in points.
+    pub font_size: f32,
+    pub color: bool,
 }
 
 impl StyleSheet {
     pub fn new() -> Self {
-        StyleSheet { rules: Vec::new() }
+        StyleSheet {
+            rules: Vec::new(),
+            font_faces: Vec::new(),
+            media: Vec::new(),
+        }
     }
 
     pub fn append(&mut self, other: &mut Self, sort: bool) {
         if sort {
             other.sort();
         }
+        let media_offset = self.media.len();
+        for rule in &mut other.rules {
+            if let Some(index) = rule.media.as_mut() {
+                *index += media_offset;
+            }
+        }
         self.rules.append(&mut other.rules);
+        self.font_faces.append(&mut other.font_faces);
+        self.media.append(&mut other.media);
+    }
+
+    // Drops the rules of the `@media` blocks that don't apply to the given context.
+    pub fn evaluate_media(&mut self, context: &MediaContext) {
+        if self.media.is_empty() {
+            return;
+        }
+        let matches = self
+            .media
+            .iter()
+            .map(|queries| queries.iter().any(|query| query.matches(context)))
+            .collect::<Vec<bool>>();
+        self.rules
+            .retain(|rule| rule.media.is_none_or(|index| matches[index]));
+        for rule in &mut self.rules {
+            rule.media = None;
+        }
+        self.media.clear();
+    }
+
+    pub fn set_base_dir<P: AsRef<Path>>(&mut self, dir: P) {
+        for font_face in &mut self.font_faces {
+            font_face.base_dir = dir.as_ref().to_path_buf();
+        }
     }
 
     pub fn sort(&mut self) {
@@ -35,7 +89,7 @@ pub fn specified_values(node: NodeRef, stylesheet: &StyleSheet) -> PropertyMap {
     for rule in stylesheet
         .rules
         .iter()
-        .filter(|rule| rule.selector.matches(node))
+        .filter(|rule| rule.selector.pseudo_element.is_none() && rule.selector.matches(node))
     {
         for declaration in &rule.declarations {
             if declaration.important {
@@ -62,6 +116,34 @@ pub fn specified_values(node: NodeRef, stylesheet: &StyleSheet) -> PropertyMap {
     props
 }
 
+// The values specified for the given pseudo-element of a node.
+pub fn pseudo_element_values(
+    node: NodeRef,
+    stylesheet: &StyleSheet,
+    pseudo_element: PseudoElement,
+) -> PropertyMap {
+    let mut props = FxHashMap::default();
+    let mut important = Vec::new();
+
+    for rule in stylesheet.rules.iter().filter(|rule| {
+        rule.selector.pseudo_element == Some(pseudo_element) && rule.selector.matches(node)
+    }) {
+        for declaration in &rule.declarations {
+            if declaration.important {
+                important.push([&declaration.name, &declaration.value]);
+            } else {
+                expand_and_insert(&declaration.name, &declaration.value, &mut props);
+            }
+        }
+    }
+
+    for [name, value] in important {
+        expand_and_insert(name, value, &mut props);
+    }
+
+    props
+}
+
 impl Selector {
     fn matches(&self, node: NodeRef) -> bool {
         let index = self.simple_selectors.len().saturating_sub(1);
@@ -136,11 +218,11 @@ impl SimpleSelector {
             return false;
         }
 
-        if self.attributes.iter().any(|attr| {
-            node.attribute(&attr.name)
-                .map(|value| attr.operator.matches(value))
-                != Some(true)
-        }) {
+        if self
+            .attributes
+            .iter()
+            .any(|attr| node.attribute(&attr.name).map(|value| attr.matches(value)) != Some(true))
+        {
             return false;
         }
 
@@ -152,6 +234,16 @@ impl SimpleSelector {
     }
 }
 
+impl Attribute {
+    fn matches(&self, value: &str) -> bool {
+        if self.case_insensitive {
+            self.operator.matches(&value.to_lowercase())
+        } else {
+            self.operator.matches(value)
+        }
+    }
+}
+
 impl AttributeOperator {
     fn matches(&self, value: &str) -> bool {
         match self {
@@ -161,19 +253,159 @@ impl AttributeOperator {
             AttributeOperator::StartsWith(v) => {
                 v == value || (value.starts_with(v) && value[v.len()..].starts_with('-'))
             }
+            AttributeOperator::Prefix(v) => !v.is_empty() && value.starts_with(v.as_str()),
+            AttributeOperator::Suffix(v) => !v.is_empty() && value.ends_with(v.as_str()),
+            AttributeOperator::Substring(v) => !v.is_empty() && value.contains(v.as_str()),
         }
     }
 }
 
 impl PseudoClass {
     fn matches(&self, node: NodeRef) -> bool {
+        let same_type = |sib: &NodeRef| sib.tag_name() == node.tag_name();
         match self {
-            PseudoClass::FirstChild => node.previous_sibling_element().is_none(),
-            PseudoClass::LastChild => node.next_sibling_element().is_none(),
+            PseudoClass::FirstChild => previous_siblings(node).next().is_none(),
+            PseudoClass::LastChild => next_siblings(node).next().is_none(),
+            PseudoClass::OnlyChild => {
+                previous_siblings(node).next().is_none() && next_siblings(node).next().is_none()
+            }
+            PseudoClass::FirstOfType => !previous_siblings(node).any(|sib| same_type(&sib)),
+            PseudoClass::LastOfType => !next_siblings(node).any(|sib| same_type(&sib)),
+            PseudoClass::OnlyOfType => {
+                !previous_siblings(node).any(|sib| same_type(&sib))
+                    && !next_siblings(node).any(|sib| same_type(&sib))
+            }
+            PseudoClass::NthChild(a, b) => nth_matches(*a, *b, previous_siblings(node).count()),
+            PseudoClass::NthLastChild(a, b) => nth_matches(*a, *b, next_siblings(node).count()),
+            PseudoClass::NthOfType(a, b) => nth_matches(
+                *a,
+                *b,
+                previous_siblings(node).filter(|sib| same_type(sib)).count(),
+            ),
+            PseudoClass::NthLastOfType(a, b) => nth_matches(
+                *a,
+                *b,
+                next_siblings(node).filter(|sib| same_type(sib)).count(),
+            ),
+            PseudoClass::Empty => !node
+                .children()
+                .any(|child| child.is_element() || matches!(child.data(), NodeData::Text(..))),
+            PseudoClass::Root => node
+                .parent()
+                .is_some_and(|parent| matches!(parent.data(), NodeData::Root)),
+            PseudoClass::Not(selectors) => selectors.iter().all(|selec| !selec.matches(node)),
         }
     }
 }
 
+// The wrappers of inline content aren't part of the source document.
+fn previous_siblings<'a>(node: NodeRef<'a>) -> impl Iterator<Item = NodeRef<'a>> {
+    node.previous_sibling_elements()
+        .filter(|sib| !sib.is_wrapper())
+}
+
+fn next_siblings<'a>(node: NodeRef<'a>) -> impl Iterator<Item = NodeRef<'a>> {
+    node.next_sibling_elements().filter(|sib| !sib.is_wrapper())
+}
+
+// Whether the position of an element, given by the number of its preceding siblings,
+// is `an+b` for some non-negative integer `n`.
+fn nth_matches(a: i32, b: i32, count: usize) -> bool {
+    let delta = count as i32 + 1 - b;
+    if a == 0 {
+        delta == 0
+    } else {
+        delta % a == 0 && delta / a >= 0
+    }
+}
+
+impl MediaQuery {
+    fn matches(&self, context: &MediaContext) -> bool {
+        let type_matches = self
+            .media_type
+            .as_deref()
+            .is_none_or(|name| matches!(name, "all" | "screen" | "amzn-kf8"));
+        let result = type_matches && self.features.iter().all(|f| f.matches(context));
+        result != self.negated
+    }
+}
+
+impl MediaFeature {
+    fn matches(&self, context: &MediaContext) -> bool {
+        let (prefix, name) = if let Some(name) = self.name.strip_prefix("min-") {
+            (Some(true), name)
+        } else if let Some(name) = self.name.strip_prefix("max-") {
+            (Some(false), name)
+        } else {
+            (None, self.name.as_str())
+        };
+
+        let compare = |actual: f32, expected: f32| match prefix {
+            Some(true) => actual >= expected,
+            Some(false) => actual <= expected,
+            None => (actual - expected).abs() < f32::EPSILON,
+        };
+
+        let em = context.font_size;
+        let dpi = context.dpi;
+
+        match (name, self.value.as_deref()) {
+            ("width", Some(value)) => parse_length(value, em, em, dpi)
+                .is_some_and(|w| compare(context.width as f32, w as f32)),
+            ("height", Some(value)) => parse_length(value, em, em, dpi)
+                .is_some_and(|h| compare(context.height as f32, h as f32)),
+            ("width", None) => context.width > 0,
+            ("height", None) => context.height > 0,
+            ("orientation", Some(value)) => {
+                let portrait = context.height >= context.width;
+                match value {
+                    "portrait" => portrait,
+                    "landscape" => !portrait,
+                    _ => false,
+                }
+            }
+            ("aspect-ratio", Some(value)) => {
+                let mut terms = value.split('/').map(|v| v.trim().parse::<f32>().ok());
+                match (terms.next().flatten(), terms.next().flatten()) {
+                    (Some(a), Some(b)) if b > 0.0 && context.height > 0 => {
+                        compare(context.width as f32 / context.height as f32, a / b)
+                    }
+                    _ => false,
+                }
+            }
+            ("resolution", Some(value)) => {
+                let dpi = dpi as f32;
+                if let Some(v) = value.strip_suffix("dpi") {
+                    v.parse().is_ok_and(|v| compare(dpi, v))
+                } else if let Some(v) = value.strip_suffix("dpcm") {
+                    v.parse::<f32>()
+                        .is_ok_and(|v| compare(dpi, v * CENTIMETERS_PER_INCH))
+                } else if let Some(v) = value
+                    .strip_suffix("dppx")
+                    .or_else(|| value.strip_suffix('x'))
+                {
+                    v.parse::<f32>().is_ok_and(|v| compare(dpi, v * 96.0))
+                } else {
+                    false
+                }
+            }
+            ("color", None) => context.color,
+            ("monochrome", None) => !context.color,
+            ("color", Some(value)) => value
+                .parse::<f32>()
+                .is_ok_and(|v| compare(if context.color { COLOR_BITS } else { 0.0 }, v)),
+            ("monochrome", Some(value)) => value
+                .parse::<f32>()
+                .is_ok_and(|v| compare(if context.color { 0.0 } else { COLOR_BITS }, v)),
+            ("grid", value) => value.is_some_and(|v| v == "0"),
+            _ => false,
+        }
+    }
+}
+
+// The number of bits per color component of the frame buffer.
+const COLOR_BITS: f32 = 8.0;
+
 fn expand_and_insert(name: &str, value: &str, props: &mut PropertyMap) {
     match name {
         "margin" | "padding" => {
@@ -226,9 +458,9 @@ fn expand_and_insert(name: &str, value: &str, props: &mut PropertyMap) {
 
 #[cfg(test)]
 mod tests {
-    use super::super::css::CssParser;
+    use super::super::css::{CssParser, PseudoElement};
     use super::super::xml::XmlParser;
-    use super::specified_values;
+    use super::{pseudo_element_values, specified_values, MediaContext};
 
     #[test]
     fn simple_style() {
@@ -262,4 +494,108 @@ mod tests {
                 .collect()
         );
     }
+
+    #[test]
+    fn media_style() {
+        let xml = XmlParser::new("<p/>").parse();
+        let context = MediaContext {
+            width: 1072,
+            height: 1448,
+            dpi: 300,
+            font_size: 11.0,
+            color: false,
+        };
+        let mut css = CssParser::new(
+            "p { a: 1 }\
+             @media print { p { a: 2 } }\
+             @media amzn-kf8 { p { b: 1 } }\
+             @media amzn-mobi { p { b: 2 } }\
+             @media (max-width: 600px) { p { c: 1 } }\
+             @media screen and (min-width: 300px) and (orientation: portrait) { p { d: 1 } }\
+             @media not (color) { p { e: 1 } }\
+             @media (min-resolution: 2dppx), (color) { p { f: 1 } }",
+        )
+        .parse();
+        css.evaluate_media(&context);
+        let n = xml.root().first_child().unwrap();
+        let props = specified_values(n, &css);
+        assert_eq!(props.get("a").map(String::as_str), Some("1"));
+        assert_eq!(props.get("b").map(String::as_str), Some("1"));
+        assert_eq!(props.get("c").map(String::as_str), Some("1"));
+        assert_eq!(props.get("d").map(String::as_str), Some("1"));
+        assert_eq!(props.get("e").map(String::as_str), Some("1"));
+        assert_eq!(props.get("f").map(String::as_str), Some("1"));
+    }
+
+    #[test]
+    fn pseudo_element_style() {
+        let xml = XmlParser::new("<div><p class='a'/><p/></div>").parse();
+        let mut css = CssParser::new(
+            "p { a: 1 }\
+             p::first-letter { b: 1; c: 1 }\
+             p.a::first-letter { c: 2 }\
+             div > p:first-child::first-line { d: 1 }",
+        )
+        .parse();
+        css.sort();
+        let n1 = xml.root().find("p").unwrap();
+        let n2 = n1.next_sibling_element().unwrap();
+        let props = specified_values(n1, &css);
+        assert_eq!(props.len(), 1);
+        let props = pseudo_element_values(n1, &css, PseudoElement::FirstLetter);
+        assert_eq!(props.get("b").map(String::as_str), Some("1"));
+        assert_eq!(props.get("c").map(String::as_str), Some("2"));
+        let props = pseudo_element_values(n2, &css, PseudoElement::FirstLetter);
+        assert_eq!(props.get("c").map(String::as_str), Some("1"));
+        assert!(pseudo_element_values(n1, &css, PseudoElement::FirstLine).contains_key("d"));
+        assert!(pseudo_element_values(n2, &css, PseudoElement::FirstLine).is_empty());
+    }
+
+    #[test]
+    fn publisher_selectors_style() {
+        let xml = XmlParser::new(
+            "<html><body><section>\
+             <h2>Title</h2>\
+             <p class='first'>a</p>\
+             <p>b</p>\
+             <p class='noindent'>c</p>\
+             <p lang='fr-CA'>d</p>\
+             <div> </div>\
+             </section></body></html>",
+        )
+        .parse();
+        let mut css = CssParser::new(
+            "section > p:not(.first):not(.noindent, .centered) { indent: 1 }\
+             p:first-of-type { first: 1 }\
+             p:last-of-type { last: 1 }\
+             section > :nth-child(even) { even: 1 }\
+             p:nth-last-of-type(-n+2) { tail: 1 }\
+             [class^=no] { prefix: 1 }\
+             p[lang|=FR i] { lang: 1 }\
+             div:empty, p:empty { empty: 1 }\
+             :root { root: 1 }",
+        )
+        .parse();
+        css.sort();
+        let html = xml.root().find("html").unwrap();
+        let section = xml.root().find("section").unwrap();
+        let nodes = section
+            .children()
+            .filter(|n| n.is_element())
+            .collect::<Vec<_>>();
+        let keys = |n| {
+            let mut keys = specified_values(n, &css)
+                .into_keys()
+                .collect::<Vec<String>>();
+            keys.sort();
+            keys
+        };
+        assert_eq!(keys(html), vec!["root"]);
+        assert!(keys(nodes[0]).is_empty());
+        assert_eq!(keys(nodes[1]), vec!["even", "first"]);
+        assert_eq!(keys(nodes[2]), vec!["indent"]);
+        assert_eq!(keys(nodes[3]), vec!["even", "prefix", "tail"]);
+        assert_eq!(keys(nodes[4]), vec!["indent", "lang", "last", "tail"]);
+        assert_eq!(keys(nodes[5]), vec!["empty", "even"]);
+    }
 }
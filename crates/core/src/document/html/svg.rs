@@ -0,0 +1,218 @@
+use super::dom::{ElementData, NodeData, NodeRef, TextData};
+use super::engine::ResourceFetcher;
+use super::xml::XmlParser;
+use crate::helpers::{decode_entities, Normalize};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use percent_encoding::percent_decode_str;
+use std::path::Path;
+
+pub const SVG_MIME_TYPE: &str = "image/svg+xml";
+// Prefix of the paths of the inline SVG elements.
+const INLINE_SVG_PREFIX: &str = "inline-svg:";
+
+const SVG_NAMESPACE: &str = "http://www.w3.org/2000/svg";
+const XLINK_NAMESPACE: &str = "http://www.w3.org/1999/xlink";
+
+// Serializes an inline SVG element.
+// The references to external images are resolved relatively to the given directory.
+pub fn inline_svg_markup(node: NodeRef, spine_dir: &Path) -> String {
+    let mut markup = String::new();
+    serialize(
+        node,
+        &mut markup,
+        &mut |href| resolve_href(href, spine_dir),
+        true,
+    );
+    markup
+}
+
+// The image path under which the markup of an inline SVG element is registered.
+pub fn inline_svg_path(markup: &str) -> String {
+    format!("{}{:016x}", INLINE_SVG_PREFIX, fxhash::hash64(markup))
+}
+
+// Returns the markup of an SVG image with its external images embedded as data URIs:
+// the images of a document opened from memory can't be fetched otherwise.
+pub fn embed_images(
+    text: &str,
+    base_dir: &Path,
+    resource_fetcher: &mut dyn ResourceFetcher,
+) -> Option<String> {
+    let root = XmlParser::new(text).parse();
+    let svg = root.root().find("svg")?;
+    let mut markup = String::new();
+    serialize(
+        svg,
+        &mut markup,
+        &mut |href| {
+            let path = resolve_href(href, base_dir);
+            if let Ok(buf) = resource_fetcher.fetch(&path) {
+                format!("data:{};base64,{}", mime_type(&path), STANDARD.encode(buf))
+            } else {
+                path
+            }
+        },
+        true,
+    );
+    Some(markup)
+}
+
+// The intrinsic size, in CSS pixels, of an SVG element.
+pub fn intrinsic_size(node: NodeRef) -> Option<(f32, f32)> {
+    let view_box = node.attribute("viewBox").and_then(|value| {
+        let numbers: Vec<f32> = value
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter_map(|s| s.parse().ok())
+            .collect();
+        if numbers.len() == 4 && numbers[2] > 0.0 && numbers[3] > 0.0 {
+            Some((numbers[2], numbers[3]))
+        } else {
+            None
+        }
+    });
+
+    // Percentages and absolute units are ignored.
+    let length = |name: &str| {
+        node.attribute(name)
+            .and_then(|value| {
+                let value = value.trim();
+                value
+                    .strip_suffix("px")
+                    .unwrap_or(value)
+                    .parse::<f32>()
+                    .ok()
+            })
+            .filter(|value| *value > 0.0)
+    };
+
+    match (length("width"), length("height"), view_box) {
+        (Some(width), Some(height), _) => Some((width, height)),
+        (Some(width), None, Some((vw, vh))) => Some((width, width * vh / vw)),
+        (None, Some(height), Some((vw, vh))) => Some((height * vw / vh, height)),
+        (None, None, view_box) => view_box,
+        _ => None,
+    }
+}
+
+fn resolve_href(href: &str, base_dir: &Path) -> String {
+    if href.starts_with("data:") {
+        return href.to_string();
+    }
+    base_dir
+        .join(href)
+        .normalize()
+        .to_str()
+        .map(|uri| {
+            percent_decode_str(&decode_entities(uri))
+                .decode_utf8_lossy()
+                .into_owned()
+        })
+        .unwrap_or_else(|| href.to_string())
+}
+
+fn mime_type(path: &str) -> &'static str {
+    match Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("svg") => SVG_MIME_TYPE,
+        _ => "image/jpeg",
+    }
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+fn serialize(
+    node: NodeRef,
+    buf: &mut String,
+    map_href: &mut dyn FnMut(&str) -> String,
+    is_root: bool,
+) {
+    match node.data() {
+        NodeData::Element(ElementData {
+            name, attributes, ..
+        }) => {
+            buf.push('<');
+            buf.push_str(name);
+
+            if is_root {
+                if !attributes.contains_key("xmlns") {
+                    buf.push_str(&format!(" xmlns=\"{}\"", SVG_NAMESPACE));
+                }
+                if !attributes.contains_key("xmlns:xlink") {
+                    buf.push_str(&format!(" xmlns:xlink=\"{}\"", XLINK_NAMESPACE));
+                }
+            }
+
+            for (key, value) in attributes {
+                // The values of the attributes are stored as they appear in the source.
+                let value = if name == "image" && (key == "xlink:href" || key == "href") {
+                    escape_attribute(&map_href(&decode_entities(value)))
+                } else {
+                    value.replace('"', "&quot;")
+                };
+                buf.push_str(&format!(" {}=\"{}\"", key, value));
+            }
+
+            if node.has_children() {
+                buf.push('>');
+                for child in node.children() {
+                    serialize(child, buf, map_href, false);
+                }
+                buf.push_str(&format!("</{}>", name));
+            } else {
+                buf.push_str("/>");
+            }
+        }
+        NodeData::Text(TextData { text, .. }) | NodeData::Whitespace(TextData { text, .. }) => {
+            buf.push_str(text);
+        }
+        _ => {
+            for child in node.children() {
+                serialize(child, buf, map_href, false);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_svg_markup() {
+        let text = r#"<p><svg:svg viewBox="0 0 600 800"><svg:image width="600" height="800" xlink:href="../images/cover%20a.jpg"/><text x="1">a &amp; b</text></svg:svg></p>"#;
+        let root = XmlParser::new(text).parse();
+        let svg = root.root().find("svg").unwrap();
+        assert_eq!(intrinsic_size(svg), Some((600.0, 800.0)));
+        let markup = inline_svg_markup(svg, Path::new("OEBPS/text"));
+        assert!(markup.starts_with("<svg xmlns=\""));
+        assert!(markup.contains("xlink:href=\"OEBPS/images/cover a.jpg\""));
+        assert!(markup.ends_with("<text x=\"1\">a &amp; b</text></svg>"));
+        let path = inline_svg_path(&markup);
+        assert!(path.starts_with(INLINE_SVG_PREFIX));
+        assert_eq!(path, inline_svg_path(&markup));
+    }
+
+    #[test]
+    fn test_intrinsic_size() {
+        let text = r#"<svg width="100%" height="300px" viewBox="0,0,400,200"/>"#;
+        let root = XmlParser::new(text).parse();
+        let svg = root.root().find("svg").unwrap();
+        assert_eq!(intrinsic_size(svg), Some((600.0, 300.0)));
+        let text = r#"<svg width="100%" height="100%"/>"#;
+        let root = XmlParser::new(text).parse();
+        let svg = root.root().find("svg").unwrap();
+        assert_eq!(intrinsic_size(svg), None);
+    }
+}
@@ -209,4 +209,15 @@ mod tests {
         let xml = XmlParser::new(text).parse();
         assert_eq!(xml.root().text(), " ");
     }
+
+    #[test]
+    fn test_markup() {
+        let text = r#"<aside id='n1'><p>See <i>this</i> &amp; <br/>that.</p></aside>"#;
+        let xml = XmlParser::new(text).parse();
+        let n = xml.root().first_child().unwrap();
+        assert_eq!(
+            n.markup(),
+            r#"<aside id="n1"><p>See <i>this</i> &amp; <br/>that.</p></aside>"#
+        );
+    }
 }
@@ -0,0 +1,284 @@
+use anyhow::{bail, Error};
+use byteorder::{BigEndian, ByteOrder};
+use encoding_rs::WINDOWS_1252;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+// The size of the Palm database header, followed by the list of records.
+const PDB_HEADER_SIZE: usize = 78;
+const RECORD_ENTRY_SIZE: usize = 8;
+// The MOBI header follows the PalmDOC header within the first record.
+const MOBI_HEADER_OFFSET: usize = 16;
+const UTF8_ENCODING: u32 = 65001;
+// The first record is usually a few kilobytes long.
+const MAX_RECORD_SIZE: u64 = 1 << 20;
+const EXTH_FLAG: u32 = 0x40;
+
+const EXTH_AUTHOR: u32 = 100;
+const EXTH_PUBLISHER: u32 = 101;
+const EXTH_SUBJECT: u32 = 105;
+const EXTH_PUBLISHING_DATE: u32 = 106;
+const EXTH_UPDATED_TITLE: u32 = 503;
+const EXTH_LANGUAGE: u32 = 524;
+
+lazy_static! {
+    // There's no EXTH record for the series: the Kindle store appends it to the updated
+    // title, e.g. `The Eyre Affair (Thursday Next Book 1)`.
+    static ref SERIES_TITLE: Regex = Regex::new(
+        r"^(.+?)\s*\((.+?),?\s+(?:Book|Vol\.|Volume|#)\s*(\d+(?:\.\d+)?)\)$"
+    )
+    .unwrap();
+}
+
+// The metadata stored in the headers of MOBI and AZW3 files.
+pub struct MobiMetadata {
+    full_name: Option<String>,
+    exth: BTreeMap<u32, Vec<String>>,
+}
+
+impl MobiMetadata {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<MobiMetadata, Error> {
+        let file = File::open(path.as_ref())?;
+        let mut header = [0; PDB_HEADER_SIZE + 2 * RECORD_ENTRY_SIZE];
+        file.read_exact_at(&mut header, 0)?;
+
+        if &header[60..68] != b"BOOKMOBI" {
+            bail!("not a MOBI file");
+        }
+
+        let start = BigEndian::read_u32(&header[78..82]) as u64;
+        let end = (BigEndian::read_u32(&header[86..90]) as u64)
+            .min(file.metadata()?.len())
+            .min(start.saturating_add(MAX_RECORD_SIZE));
+
+        if end <= start {
+            bail!("invalid record list");
+        }
+
+        let mut record = vec![0; (end - start) as usize];
+        file.read_exact_at(&mut record, start)?;
+
+        MobiMetadata::from_record(&record)
+    }
+
+    // Parses the first record, which holds the MOBI and EXTH headers.
+    fn from_record(record: &[u8]) -> Result<MobiMetadata, Error> {
+        if record.len() < 132 || &record[MOBI_HEADER_OFFSET..MOBI_HEADER_OFFSET + 4] != b"MOBI" {
+            bail!("the MOBI header is missing");
+        }
+
+        let header_len = BigEndian::read_u32(&record[20..24]) as usize;
+        let utf8 = BigEndian::read_u32(&record[28..32]) == UTF8_ENCODING;
+        let name_offset = BigEndian::read_u32(&record[84..88]) as usize;
+        let name_len = BigEndian::read_u32(&record[88..92]) as usize;
+        let full_name = record
+            .get(name_offset..name_offset.saturating_add(name_len))
+            .map(|bytes| decode_text(bytes, utf8))
+            .filter(|name| !name.is_empty());
+
+        let mut exth = BTreeMap::new();
+        let flags = BigEndian::read_u32(&record[128..132]);
+        let mut offset = MOBI_HEADER_OFFSET.saturating_add(header_len);
+
+        if flags & EXTH_FLAG != 0
+            && record.get(offset..offset.saturating_add(4)) == Some(&b"EXTH"[..])
+        {
+            let count = record
+                .get(offset + 8..offset + 12)
+                .map_or(0, BigEndian::read_u32);
+            offset += 12;
+            for _ in 0..count {
+                if let Some(head) = record.get(offset..offset + 8) {
+                    let kind = BigEndian::read_u32(&head[..4]);
+                    let len = BigEndian::read_u32(&head[4..]) as usize;
+                    if let Some(data) = record.get(offset + 8..offset.saturating_add(len)) {
+                        exth.entry(kind)
+                            .or_insert_with(Vec::new)
+                            .push(decode_text(data, utf8));
+                        offset += len;
+                        continue;
+                    }
+                }
+                break;
+            }
+        }
+
+        Ok(MobiMetadata { full_name, exth })
+    }
+
+    fn values(&self, kind: u32) -> impl Iterator<Item = &str> {
+        self.exth
+            .get(&kind)
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .filter(|value| !value.is_empty())
+    }
+
+    fn value(&self, kind: u32) -> Option<String> {
+        self.values(kind).next().map(String::from)
+    }
+
+    fn full_title(&self) -> Option<String> {
+        self.value(EXTH_UPDATED_TITLE)
+            .or_else(|| self.full_name.clone())
+    }
+
+    pub fn title(&self) -> Option<String> {
+        self.full_title()
+            .map(|title| match SERIES_TITLE.captures(&title) {
+                Some(caps) => caps[1].to_string(),
+                None => title,
+            })
+    }
+
+    pub fn series(&self) -> Option<(String, String)> {
+        let title = self.full_title()?;
+        let caps = SERIES_TITLE.captures(&title)?;
+        Some((caps[2].to_string(), caps[3].to_string()))
+    }
+
+    pub fn author(&self) -> Option<String> {
+        let authors = self.values(EXTH_AUTHOR).collect::<Vec<&str>>();
+        if authors.is_empty() {
+            None
+        } else {
+            Some(authors.join(", "))
+        }
+    }
+
+    pub fn publisher(&self) -> Option<String> {
+        self.value(EXTH_PUBLISHER)
+    }
+
+    pub fn language(&self) -> Option<String> {
+        self.value(EXTH_LANGUAGE)
+    }
+
+    pub fn year(&self) -> Option<String> {
+        self.value(EXTH_PUBLISHING_DATE)
+            .map(|s| s.chars().take(4).collect())
+    }
+
+    pub fn categories(&self) -> BTreeSet<String> {
+        self.values(EXTH_SUBJECT)
+            .flat_map(|subject| subject.split(';'))
+            .map(|subject| subject.trim().to_string())
+            .filter(|subject| !subject.is_empty())
+            .collect()
+    }
+}
+
+fn decode_text(bytes: &[u8], utf8: bool) -> String {
+    let bytes = bytes.split(|&b| b == 0).next().unwrap_or_default();
+    let text = if utf8 {
+        String::from_utf8_lossy(bytes)
+    } else {
+        WINDOWS_1252.decode_without_bom_handling(bytes).0
+    };
+    text.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exth_record(kind: u32, data: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&kind.to_be_bytes());
+        buf.extend_from_slice(&(data.len() as u32 + 8).to_be_bytes());
+        buf.extend_from_slice(data);
+        buf
+    }
+
+    // Builds the first record of a MOBI file with the given encoding.
+    fn first_record(encoding: u32, full_name: &[u8], records: &[Vec<u8>]) -> Vec<u8> {
+        let header_len = 232;
+        let mut record = vec![0; MOBI_HEADER_OFFSET + header_len];
+        record[16..20].copy_from_slice(b"MOBI");
+        record[20..24].copy_from_slice(&(header_len as u32).to_be_bytes());
+        record[28..32].copy_from_slice(&encoding.to_be_bytes());
+        record[128..132].copy_from_slice(&EXTH_FLAG.to_be_bytes());
+        let exth = records.concat();
+        record.extend_from_slice(b"EXTH");
+        record.extend_from_slice(&(exth.len() as u32 + 12).to_be_bytes());
+        record.extend_from_slice(&(records.len() as u32).to_be_bytes());
+        record.extend_from_slice(&exth);
+        let name_offset = record.len() as u32;
+        record[84..88].copy_from_slice(&name_offset.to_be_bytes());
+        record[88..92].copy_from_slice(&(full_name.len() as u32).to_be_bytes());
+        record.extend_from_slice(full_name);
+        record.extend_from_slice(&[0, 0]);
+        record
+    }
+
+    #[test]
+    fn test_exth_metadata() {
+        let record = first_record(
+            UTF8_ENCODING,
+            b"Le Rouge et le Noir",
+            &[
+                exth_record(EXTH_AUTHOR, "Stendhal".as_bytes()),
+                exth_record(EXTH_AUTHOR, "Henri Beyle".as_bytes()),
+                exth_record(EXTH_PUBLISHER, "Levasseur".as_bytes()),
+                exth_record(EXTH_SUBJECT, "Fiction; Classics".as_bytes()),
+                exth_record(EXTH_PUBLISHING_DATE, "1830-11-13T00:00:00+00:00".as_bytes()),
+                exth_record(EXTH_LANGUAGE, "fr".as_bytes()),
+                exth_record(201, &[0, 0, 0, 1]),
+            ],
+        );
+        let md = MobiMetadata::from_record(&record).unwrap();
+        assert_eq!(md.title().as_deref(), Some("Le Rouge et le Noir"));
+        assert_eq!(md.series(), None);
+        assert_eq!(md.author().as_deref(), Some("Stendhal, Henri Beyle"));
+        assert_eq!(md.publisher().as_deref(), Some("Levasseur"));
+        assert_eq!(md.year().as_deref(), Some("1830"));
+        assert_eq!(md.language().as_deref(), Some("fr"));
+        assert_eq!(
+            md.categories().into_iter().collect::<Vec<String>>(),
+            vec!["Classics".to_string(), "Fiction".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_updated_title() {
+        let record = first_record(
+            1252,
+            b"Les Mis\xe9rables",
+            &[exth_record(
+                EXTH_UPDATED_TITLE,
+                b"Les Mis\xe9rables, tome I",
+            )],
+        );
+        let md = MobiMetadata::from_record(&record).unwrap();
+        assert_eq!(md.full_name.as_deref(), Some("Les Misérables"));
+        assert_eq!(md.title().as_deref(), Some("Les Misérables, tome I"));
+        assert!(MobiMetadata::from_record(&record[..100]).is_err());
+    }
+
+    #[test]
+    fn test_series() {
+        let titles = [
+            "The Eyre Affair (Thursday Next Book 1)",
+            "The Eyre Affair (Thursday Next, #1)",
+            "The Eyre Affair (Thursday Next Vol. 1)",
+        ];
+        for title in titles {
+            let record = first_record(
+                UTF8_ENCODING,
+                b"EYRE_AFFAIR",
+                &[exth_record(EXTH_UPDATED_TITLE, title.as_bytes())],
+            );
+            let md = MobiMetadata::from_record(&record).unwrap();
+            assert_eq!(md.title().as_deref(), Some("The Eyre Affair"));
+            assert_eq!(
+                md.series(),
+                Some(("Thursday Next".to_string(), "1".to_string()))
+            );
+        }
+    }
+}
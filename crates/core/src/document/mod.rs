@@ -1,15 +1,24 @@
+pub mod comic;
 pub mod djvu;
 pub mod epub;
+pub mod fb2;
 pub mod html;
+pub mod mobi;
 pub mod pdf;
+pub mod reflow;
+pub mod text;
+pub mod xps;
 
 mod djvulibre_sys;
 mod mupdf_sys;
 
+use self::comic::{is_image, ComicDocument};
 use self::djvu::DjvuOpener;
 use self::epub::EpubDocument;
+use self::fb2::Fb2Document;
 use self::html::HtmlDocument;
 use self::pdf::PdfOpener;
+use self::text::TextDocument;
 use crate::device::CURRENT_DEVICE;
 use crate::framebuffer::Pixmap;
 use crate::geom::{Boundary, CycleDir};
@@ -31,6 +40,7 @@ use std::path::Path;
 use std::process::Command;
 use unicode_normalization::char::is_combining_mark;
 use unicode_normalization::UnicodeNormalization;
+use zip::ZipArchive;
 
 pub const BYTES_PER_PAGE: f64 = 2048.0;
 
@@ -126,17 +136,49 @@ pub trait Document: Send + Sync {
         false
     }
 
+    // Whether the pages progress from right to left.
+    fn is_rtl(&self) -> bool {
+        false
+    }
+
+    // The note targeted by the given link, if it points to one, with the stylesheets
+    // and the images of the document.
+    fn footnote(&mut self, _loc: Location) -> Option<HtmlDocument> {
+        None
+    }
+
+    // Whether a password is required to read the document.
+    fn needs_password(&self) -> bool {
+        false
+    }
+
+    // Unlocks the document with the given password.
+    fn authenticate(&mut self, _password: &str) -> bool {
+        false
+    }
+
+    // Returns the highlights embedded in the document as annotations, and stops rendering them.
+    fn embedded_annotations(&mut self) -> Vec<Annotation> {
+        Vec::new()
+    }
+
+    // Adds, updates or removes the highlights embedded in the document saved at the given
+    // path, so that they match the given annotations.
+    fn save_annotations(&mut self, _annotations: &[Annotation], _path: &Path) -> Result<(), Error> {
+        Err(format_err!("this document can't hold annotations"))
+    }
+
+    // The text columns of the given page, in reading order.
+    fn columns(&mut self, _index: usize) -> Option<Vec<Boundary>> {
+        None
+    }
+
     fn save(&self, _path: &str) -> Result<(), Error> {
         Err(format_err!("this document can't be saved"))
     }
 
     fn preview_pixmap(&mut self, width: f32, height: f32, samples: usize) -> Option<Pixmap> {
-        self.dims(0)
-            .and_then(|dims| {
-                let scale = (width / dims.0).min(height / dims.1);
-                self.pixmap(Location::Exact(0), scale, samples)
-            })
-            .map(|(pixmap, _)| pixmap)
+        first_page_pixmap(self, width, height, samples)
     }
 
     fn resolve_location(&mut self, loc: Location) -> Option<usize> {
@@ -190,6 +232,19 @@ pub fn guess_kind<P: AsRef<Path>>(path: P) -> Result<&'static str, Error> {
         if &mime_type == b"mimetypeapplication/epub+zip" {
             return Ok("epub");
         }
+        // The other archives are XPS documents, when they hold a fixed document
+        // sequence, or comics, when they hold images.
+        let archive = ZipArchive::new(file)?;
+        if archive
+            .file_names()
+            .any(|name| name.to_lowercase().ends_with(".fdseq"))
+        {
+            return Ok("xps");
+        } else if archive.file_names().any(is_image) {
+            return Ok("cbz");
+        }
+    } else if &magic == b"7z\xBC\xAF" {
+        return Ok("cb7");
     } else if &magic == b"%PDF" {
         return Ok("pdf");
     } else if &magic == b"AT&T" {
@@ -234,20 +289,41 @@ pub fn asciify(name: &str) -> String {
 }
 
 pub fn open<P: AsRef<Path>>(path: P) -> Option<Box<dyn Document>> {
+    // Directories are read as comics made of the images they contain.
+    if path.as_ref().is_dir() {
+        return ComicDocument::new(&path)
+            .map_err(|e| eprintln!("{}: {:#}.", path.as_ref().display(), e))
+            .map(|d| Box::new(d) as Box<dyn Document>)
+            .ok();
+    }
+
     file_kind(path.as_ref()).and_then(|k| match k.as_ref() {
         "epub" => EpubDocument::new(&path)
             .map_err(|e| eprintln!("{}: {:#}.", path.as_ref().display(), e))
             .map(|d| Box::new(d) as Box<dyn Document>)
             .ok(),
+        "fb2" => Fb2Document::new(&path)
+            .map_err(|e| eprintln!("{}: {:#}.", path.as_ref().display(), e))
+            .map(|d| Box::new(d) as Box<dyn Document>)
+            .ok(),
         "html" | "htm" => HtmlDocument::new(&path)
             .map_err(|e| eprintln!("{}: {:#}.", path.as_ref().display(), e))
             .map(|d| Box::new(d) as Box<dyn Document>)
             .ok(),
+        "txt" | "md" | "markdown" => TextDocument::new(&path)
+            .map_err(|e| eprintln!("{}: {:#}.", path.as_ref().display(), e))
+            .map(|d| Box::new(d) as Box<dyn Document>)
+            .ok(),
+        "cbz" | "cb7" => ComicDocument::new(&path)
+            .map_err(|e| eprintln!("{}: {:#}.", path.as_ref().display(), e))
+            .map(|d| Box::new(d) as Box<dyn Document>)
+            .ok(),
         "djvu" | "djv" => {
             DjvuOpener::new().and_then(|o| o.open(path).map(|d| Box::new(d) as Box<dyn Document>))
         }
+        // MuPDF recognizes the MOBI, AZW and AZW3 files by their content.
         _ => PdfOpener::new().and_then(|mut o| {
-            if matches!(k.as_ref(), "mobi" | "fb2" | "xps" | "txt") {
+            if matches!(k.as_ref(), "mobi" | "azw" | "azw3" | "xps") {
                 o.load_user_stylesheet();
             }
             o.open(path).map(|d| Box::new(d) as Box<dyn Document>)
@@ -255,6 +331,35 @@ pub fn open<P: AsRef<Path>>(path: P) -> Option<Box<dyn Document>> {
     })
 }
 
+// Renders the first page of the given document, scaled to fit the given dimensions.
+fn first_page_pixmap<D: Document + ?Sized>(
+    doc: &mut D,
+    width: f32,
+    height: f32,
+    samples: usize,
+) -> Option<Pixmap> {
+    doc.dims(0)
+        .and_then(|dims| {
+            let scale = (width / dims.0).min(height / dims.1);
+            doc.pixmap(Location::Exact(0), scale, samples)
+        })
+        .map(|(pixmap, _)| pixmap)
+}
+
+// Renders the given cover image, if any, and falls back to the first page of the document.
+pub fn cover_pixmap<D: Document>(
+    doc: &mut D,
+    cover: Option<(String, Vec<u8>)>,
+    width: f32,
+    height: f32,
+    samples: usize,
+) -> Option<Pixmap> {
+    cover
+        .and_then(|(name, buf)| PdfOpener::new()?.open_memory(&name, &buf))
+        .and_then(|mut cover| first_page_pixmap(&mut cover, width, height, samples))
+        .or_else(|| first_page_pixmap(doc, width, height, samples))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum SimpleTocEntry {
@@ -34,6 +34,13 @@ pub const FZ_PAGE_BLOCK_STRUCT: libc::c_int = 2;
 pub const FZ_PAGE_BLOCK_VECTOR: libc::c_int = 3;
 pub const FZ_PAGE_BLOCK_GRID: libc::c_int = 4;
 
+pub const PDF_ANNOT_HIGHLIGHT: libc::c_int = 8;
+pub const PDF_ANNOT_UNDERLINE: libc::c_int = 9;
+pub const PDF_ANNOT_SQUIGGLY: libc::c_int = 10;
+pub const PDF_ANNOT_STRIKE_OUT: libc::c_int = 11;
+
+pub const PDF_ANNOT_IS_HIDDEN: libc::c_int = 1 << 1;
+
 pub const CACHE_SIZE: libc::size_t = 32 * 1024 * 1024;
 
 pub enum FzContext {}
@@ -58,6 +65,7 @@ pub enum FzGridPositions {}
 pub enum FzGridInfo {}
 pub enum FzPoolArray {}
 pub enum FzImage {}
+pub enum PdfAnnot {}
 
 #[link(name = "mupdf")]
 #[link(name = "mupdf_wrapper", kind = "static")]
@@ -100,6 +108,11 @@ extern "C" {
         size: libc::c_int,
     ) -> libc::c_int;
     pub fn fz_needs_password(ctx: *mut FzContext, doc: *mut FzDocument) -> libc::c_int;
+    pub fn mp_authenticate_password(
+        ctx: *mut FzContext,
+        doc: *mut FzDocument,
+        password: *const libc::c_char,
+    ) -> libc::c_int;
     pub fn fz_is_document_reflowable(ctx: *mut FzContext, doc: *mut FzDocument) -> libc::c_int;
     pub fn fz_layout_document(
         ctx: *mut FzContext,
@@ -160,6 +173,50 @@ extern "C" {
     pub fn fz_union_rect(a: FzRect, b: FzRect) -> FzRect;
     pub fn fz_rect_from_quad(q: FzQuad) -> FzRect;
     pub fn fz_runetochar(buf: *mut u8, rune: libc::c_int) -> libc::c_int;
+    pub fn mp_update_page(ctx: *mut FzContext, page: *mut FzPage) -> libc::c_int;
+    pub fn mp_first_annot(ctx: *mut FzContext, page: *mut FzPage) -> *mut PdfAnnot;
+    pub fn pdf_next_annot(ctx: *mut FzContext, annot: *mut PdfAnnot) -> *mut PdfAnnot;
+    pub fn pdf_drop_annot(ctx: *mut FzContext, annot: *mut PdfAnnot);
+    pub fn mp_annot_type(ctx: *mut FzContext, annot: *mut PdfAnnot) -> libc::c_int;
+    pub fn mp_annot_quad_point_count(ctx: *mut FzContext, annot: *mut PdfAnnot) -> libc::c_int;
+    pub fn mp_annot_quad_point(ctx: *mut FzContext, annot: *mut PdfAnnot, i: libc::c_int)
+        -> FzQuad;
+    pub fn mp_annot_contents(ctx: *mut FzContext, annot: *mut PdfAnnot) -> *const libc::c_char;
+    pub fn mp_annot_flags(ctx: *mut FzContext, annot: *mut PdfAnnot) -> libc::c_int;
+    pub fn mp_set_annot_flags(
+        ctx: *mut FzContext,
+        annot: *mut PdfAnnot,
+        flags: libc::c_int,
+    ) -> libc::c_int;
+    pub fn mp_delete_annot(
+        ctx: *mut FzContext,
+        page: *mut FzPage,
+        annot: *mut PdfAnnot,
+    ) -> libc::c_int;
+    pub fn mp_create_annot(
+        ctx: *mut FzContext,
+        page: *mut FzPage,
+        kind: libc::c_int,
+    ) -> *mut PdfAnnot;
+    pub fn mp_set_annot_quad_points(
+        ctx: *mut FzContext,
+        annot: *mut PdfAnnot,
+        n: libc::c_int,
+        qv: *const FzQuad,
+    ) -> libc::c_int;
+    pub fn mp_set_annot_contents(
+        ctx: *mut FzContext,
+        annot: *mut PdfAnnot,
+        text: *const libc::c_char,
+    ) -> libc::c_int;
+    pub fn mp_update_annot(ctx: *mut FzContext, annot: *mut PdfAnnot) -> libc::c_int;
+    pub fn mp_can_save_incrementally(ctx: *mut FzContext, doc: *mut FzDocument) -> libc::c_int;
+    pub fn mp_save_document(
+        ctx: *mut FzContext,
+        doc: *mut FzDocument,
+        path: *const libc::c_char,
+        incremental: libc::c_int,
+    ) -> libc::c_int;
     pub static fz_identity: FzMatrix;
 }
 
@@ -187,17 +244,17 @@ impl Default for FzRect {
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct FzPoint {
-    x: libc::c_float,
-    y: libc::c_float,
+    pub x: libc::c_float,
+    pub y: libc::c_float,
 }
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct FzQuad {
-    ul: FzPoint,
-    ur: FzPoint,
-    ll: FzPoint,
-    lr: FzPoint,
+    pub ul: FzPoint,
+    pub ur: FzPoint,
+    pub ll: FzPoint,
+    pub lr: FzPoint,
 }
 
 #[derive(Copy, Clone)]
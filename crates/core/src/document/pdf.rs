@@ -4,8 +4,11 @@ use super::{chapter, chapter_relative};
 use super::{BoundedText, Document, Location, TextLocation, TocEntry};
 use crate::framebuffer::Pixmap;
 use crate::geom::{Boundary, CycleDir};
-use crate::metadata::TextAlign;
+use crate::metadata::{Annotation, TextAlign};
 use crate::unit::pt_to_px;
+use anyhow::{format_err, Error};
+use chrono::Local;
+use fxhash::FxHashMap;
 use std::char;
 use std::ffi::{CStr, CString};
 use std::fs;
@@ -17,6 +20,31 @@ use std::rc::Rc;
 use std::slice;
 
 const USER_STYLESHEET: &str = "css/html-user.css";
+// The minimum number of lines on each side of the gutter of a two columns page.
+const MIN_COLUMN_LINES: usize = 4;
+
+impl From<Boundary> for FzQuad {
+    fn from(rect: Boundary) -> FzQuad {
+        FzQuad {
+            ul: FzPoint {
+                x: rect.min.x,
+                y: rect.min.y,
+            },
+            ur: FzPoint {
+                x: rect.max.x,
+                y: rect.min.y,
+            },
+            ll: FzPoint {
+                x: rect.min.x,
+                y: rect.max.y,
+            },
+            lr: FzPoint {
+                x: rect.max.x,
+                y: rect.max.y,
+            },
+        }
+    }
+}
 
 impl Into<Boundary> for FzRect {
     fn into(self) -> Boundary {
@@ -34,6 +62,19 @@ pub struct PdfOpener(Rc<PdfContext>);
 pub struct PdfDocument {
     ctx: Rc<PdfContext>,
     doc: *mut FzDocument,
+    // The password that unlocked the document.
+    password: Option<String>,
+    // The markup annotations embedded in the document, as they were last read or saved.
+    markups: Vec<Markup>,
+}
+
+// A markup annotation embedded in a document.
+struct Markup {
+    // The index of its page.
+    page: usize,
+    // Its rank among the annotations of its page.
+    rank: usize,
+    annotation: Annotation,
 }
 
 pub struct PdfPage<'a> {
@@ -65,10 +106,7 @@ impl PdfOpener {
             if doc.is_null() {
                 None
             } else {
-                Some(PdfDocument {
-                    ctx: self.0.clone(),
-                    doc,
-                })
+                Some(PdfDocument::new(self.0.clone(), doc))
             }
         }
     }
@@ -87,10 +125,7 @@ impl PdfOpener {
             if doc.is_null() {
                 None
             } else {
-                Some(PdfDocument {
-                    ctx: self.0.clone(),
-                    doc,
-                })
+                Some(PdfDocument::new(self.0.clone(), doc))
             }
         }
     }
@@ -113,6 +148,15 @@ unsafe impl Send for PdfDocument {}
 unsafe impl Sync for PdfDocument {}
 
 impl PdfDocument {
+    fn new(ctx: Rc<PdfContext>, doc: *mut FzDocument) -> PdfDocument {
+        PdfDocument {
+            ctx,
+            doc,
+            password: None,
+            markups: Vec::new(),
+        }
+    }
+
     pub fn page(&self, index: usize) -> Option<PdfPage<'_>> {
         unsafe {
             let page = mp_load_page(self.ctx.0, self.doc, index as libc::c_int);
@@ -170,6 +214,98 @@ impl PdfDocument {
     pub fn is_protected(&self) -> bool {
         unsafe { fz_needs_password(self.ctx.0, self.doc) == 1 }
     }
+
+    // Returns the markup annotations of the document, and hides them if requested.
+    fn read_markups(&self, hide: bool) -> Vec<Markup> {
+        let modified = Local::now().naive_local();
+        let mut markups = Vec::new();
+        for index in 0..self.pages_count() {
+            let page = if let Some(page) = self.page(index) {
+                page
+            } else {
+                continue;
+            };
+            let page_markups = page.markups(hide);
+            if page_markups.is_empty() {
+                continue;
+            }
+            let words = page.words().unwrap_or_default();
+            for (rank, rects, note) in page_markups {
+                let covered: Vec<&BoundedText> = words
+                    .iter()
+                    .filter(|word| {
+                        let x = (word.rect.min.x + word.rect.max.x) / 2.0;
+                        let y = (word.rect.min.y + word.rect.max.y) / 2.0;
+                        rects.iter().any(|rect| {
+                            x >= rect.min.x && x <= rect.max.x && y >= rect.min.y && y <= rect.max.y
+                        })
+                    })
+                    .collect();
+                if let (Some(first), Some(last)) = (covered.first(), covered.last()) {
+                    let text = covered
+                        .iter()
+                        .map(|word| word.text.as_str())
+                        .collect::<Vec<&str>>()
+                        .join(" ");
+                    markups.push(Markup {
+                        page: index,
+                        rank,
+                        annotation: Annotation {
+                            note,
+                            text,
+                            selection: [first.location, last.location],
+                            modified,
+                        },
+                    });
+                }
+            }
+        }
+        markups
+    }
+
+    // Opens a fresh instance of the document saved at the given path.
+    fn reopen(&self, path: &Path) -> Result<PdfDocument, Error> {
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        let doc = unsafe { mp_open_document(self.ctx.0, c_path.as_ptr()) };
+        if doc.is_null() {
+            return Err(format_err!("can't open {}", path.display()));
+        }
+        let mut doc = PdfDocument::new(self.ctx.clone(), doc);
+        if doc.is_protected() && !self.password.as_ref().is_some_and(|p| doc.authenticate(p)) {
+            return Err(format_err!("can't unlock {}", path.display()));
+        }
+        Ok(doc)
+    }
+
+    // Saves the document at the given path. The document is written to a temporary
+    // file first, since the original file is still read by the open documents.
+    fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut name = path
+            .file_name()
+            .ok_or_else(|| format_err!("invalid path {}", path.display()))?
+            .to_os_string();
+        name.push(".tmp");
+        let temp = path.with_file_name(name);
+        let incremental = unsafe { mp_can_save_incrementally(self.ctx.0, self.doc) == 1 };
+        if incremental {
+            fs::copy(path, &temp)?;
+        }
+        let c_temp = CString::new(temp.as_os_str().as_bytes())?;
+        let saved = unsafe {
+            mp_save_document(
+                self.ctx.0,
+                self.doc,
+                c_temp.as_ptr(),
+                incremental as libc::c_int,
+            ) == 0
+        };
+        if !saved {
+            fs::remove_file(&temp).ok();
+            return Err(format_err!("can't save {}", path.display()));
+        }
+        fs::rename(&temp, path)?;
+        Ok(())
+    }
 }
 
 impl Document for PdfDocument {
@@ -295,6 +431,129 @@ impl Document for PdfDocument {
             .map(|images| (images, index))
     }
 
+    fn needs_password(&self) -> bool {
+        self.is_protected()
+    }
+
+    fn authenticate(&mut self, password: &str) -> bool {
+        let c_password = CString::new(password).unwrap_or_default();
+        let authenticated =
+            unsafe { mp_authenticate_password(self.ctx.0, self.doc, c_password.as_ptr()) != 0 };
+        if authenticated {
+            self.password = Some(password.to_string());
+        }
+        authenticated
+    }
+
+    fn embedded_annotations(&mut self) -> Vec<Annotation> {
+        self.markups = self.read_markups(true);
+        self.markups
+            .iter()
+            .map(|markup| markup.annotation.clone())
+            .collect()
+    }
+
+    fn save_annotations(&mut self, annotations: &[Annotation], path: &Path) -> Result<(), Error> {
+        // The changes are applied to a fresh instance of the document, so that nothing
+        // else that was altered while reading it, such as the appearance of its form
+        // fields or the visibility of its markups, is saved.
+        let doc = self.reopen(path)?;
+        let mut kept = vec![false; self.markups.len()];
+        let mut notes: Vec<(usize, &str)> = Vec::new();
+        let mut added: Vec<(usize, Vec<Boundary>, &str)> = Vec::new();
+        let mut words: FxHashMap<usize, Vec<BoundedText>> = FxHashMap::default();
+
+        for annot in annotations {
+            let [start, end] = annot.selection;
+            for index in start.location()..=end.location() {
+                let covered: Vec<&BoundedText> = words
+                    .entry(index)
+                    .or_insert_with(|| {
+                        doc.page(index)
+                            .and_then(|page| page.words())
+                            .unwrap_or_default()
+                    })
+                    .iter()
+                    .filter(|word| word.location >= start && word.location <= end)
+                    .collect();
+                let (first, last) = match (covered.first(), covered.last()) {
+                    (Some(first), Some(last)) => (first, last),
+                    _ => continue,
+                };
+                let selection = [first.location, last.location];
+                let note = if index == start.location() {
+                    annot.note.as_str()
+                } else {
+                    ""
+                };
+                let existing = (0..self.markups.len()).find(|&i| {
+                    !kept[i]
+                        && self.markups[i].page == index
+                        && self.markups[i].annotation.selection == selection
+                });
+                if let Some(i) = existing {
+                    kept[i] = true;
+                    if self.markups[i].annotation.note != note {
+                        notes.push((i, note));
+                    }
+                } else {
+                    let rects: Vec<Boundary> = covered.iter().map(|word| word.rect).collect();
+                    added.push((index, line_rects(&rects), note));
+                }
+            }
+        }
+
+        if notes.is_empty() && added.is_empty() && kept.iter().all(|&k| k) {
+            return Ok(());
+        }
+
+        let mut pages: Vec<usize> = self
+            .markups
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| !kept[i])
+            .map(|(_, markup)| markup.page)
+            .chain(notes.iter().map(|&(i, _)| self.markups[i].page))
+            .chain(added.iter().map(|&(index, ..)| index))
+            .collect();
+        pages.sort_unstable();
+        pages.dedup();
+
+        for index in pages {
+            let page = doc
+                .page(index)
+                .ok_or_else(|| format_err!("can't load page {}", index))?;
+            let page_notes: Vec<(usize, &str)> = notes
+                .iter()
+                .filter(|&&(i, _)| self.markups[i].page == index)
+                .map(|&(i, note)| (self.markups[i].rank, note))
+                .collect();
+            let deleted: Vec<usize> = self
+                .markups
+                .iter()
+                .enumerate()
+                .filter(|&(i, markup)| !kept[i] && markup.page == index)
+                .map(|(_, markup)| markup.rank)
+                .collect();
+            if !page.edit_annotations(&page_notes, &deleted) {
+                return Err(format_err!("can't edit the annotations of page {}", index));
+            }
+            for (_, rects, note) in added.iter().filter(|&&(i, ..)| i == index) {
+                if !page.add_highlight(rects, note) {
+                    return Err(format_err!("can't annotate page {}", index));
+                }
+            }
+        }
+
+        doc.save(path)?;
+        self.markups = doc.read_markups(false);
+        Ok(())
+    }
+
+    fn columns(&mut self, index: usize) -> Option<Vec<Boundary>> {
+        self.page(index).and_then(|page| page.columns())
+    }
+
     fn links(&mut self, loc: Location) -> Option<(Vec<BoundedText>, usize)> {
         let index = self.resolve_location(loc)?;
         self.page(index)
@@ -509,6 +768,8 @@ impl<'a> PdfPage<'a> {
             } else {
                 fz_device_rgb(self.ctx.0)
             };
+            // Synthesizes the missing appearances of the form fields.
+            mp_update_page(self.ctx.0, self.page);
             let pixmap = mp_new_pixmap_from_page(self.ctx.0, self.page, mat, color_space, 0);
             if pixmap.is_null() {
                 return None;
@@ -551,6 +812,102 @@ impl<'a> PdfPage<'a> {
         }
     }
 
+    // Returns the ranks, areas and notes of the markup annotations of the page,
+    // and hides them if requested.
+    fn markups(&self, hide: bool) -> Vec<(usize, Vec<Boundary>, String)> {
+        unsafe {
+            let mut markups = Vec::new();
+            let mut annot = mp_first_annot(self.ctx.0, self.page);
+            let mut rank = 0;
+            while !annot.is_null() {
+                let kind = mp_annot_type(self.ctx.0, annot);
+                if (PDF_ANNOT_HIGHLIGHT..=PDF_ANNOT_STRIKE_OUT).contains(&kind) {
+                    let count = mp_annot_quad_point_count(self.ctx.0, annot);
+                    let rects = (0..count)
+                        .map(|i| {
+                            fz_rect_from_quad(mp_annot_quad_point(self.ctx.0, annot, i)).into()
+                        })
+                        .collect();
+                    let contents = mp_annot_contents(self.ctx.0, annot);
+                    let note = if contents.is_null() {
+                        String::new()
+                    } else {
+                        CStr::from_ptr(contents).to_string_lossy().into_owned()
+                    };
+                    if hide {
+                        let flags = mp_annot_flags(self.ctx.0, annot);
+                        mp_set_annot_flags(self.ctx.0, annot, flags | PDF_ANNOT_IS_HIDDEN);
+                    }
+                    markups.push((rank, rects, note));
+                }
+                annot = pdf_next_annot(self.ctx.0, annot);
+                rank += 1;
+            }
+            markups
+        }
+    }
+
+    // Sets the notes of, and then deletes, the annotations at the given ranks.
+    fn edit_annotations(&self, notes: &[(usize, &str)], deleted: &[usize]) -> bool {
+        unsafe {
+            let mut annots = Vec::new();
+            let mut annot = mp_first_annot(self.ctx.0, self.page);
+            while !annot.is_null() {
+                annots.push(annot);
+                annot = pdf_next_annot(self.ctx.0, annot);
+            }
+            let mut success = true;
+            for &(rank, note) in notes {
+                if let Some(&annot) = annots.get(rank) {
+                    let contents = CString::new(note.replace('\0', "")).unwrap_or_default();
+                    success &= mp_set_annot_contents(self.ctx.0, annot, contents.as_ptr()) == 0
+                        && mp_update_annot(self.ctx.0, annot) >= 0;
+                } else {
+                    success = false;
+                }
+            }
+            for &rank in deleted {
+                if let Some(&annot) = annots.get(rank) {
+                    success &= mp_delete_annot(self.ctx.0, self.page, annot) == 0;
+                } else {
+                    success = false;
+                }
+            }
+            success
+        }
+    }
+
+    // Adds a highlight, with the given note, that covers the given areas.
+    pub fn add_highlight(&self, rects: &[Boundary], note: &str) -> bool {
+        unsafe {
+            let annot = mp_create_annot(self.ctx.0, self.page, PDF_ANNOT_HIGHLIGHT);
+            if annot.is_null() {
+                return false;
+            }
+            let quads: Vec<FzQuad> = rects.iter().map(|rect| (*rect).into()).collect();
+            let mut success = mp_set_annot_quad_points(
+                self.ctx.0,
+                annot,
+                quads.len() as libc::c_int,
+                quads.as_ptr(),
+            ) == 0;
+            if success && !note.is_empty() {
+                let contents = CString::new(note.replace('\0', "")).unwrap_or_default();
+                success = mp_set_annot_contents(self.ctx.0, annot, contents.as_ptr()) == 0;
+            }
+            success = success && mp_update_annot(self.ctx.0, annot) >= 0;
+            pdf_drop_annot(self.ctx.0, annot);
+            success
+        }
+    }
+
+    // The text columns of the page, in reading order.
+    pub fn columns(&self) -> Option<Vec<Boundary>> {
+        let bbox = self.boundary_box()?;
+        let lines: Vec<Boundary> = self.lines()?.into_iter().map(|line| line.rect).collect();
+        Some(detect_columns(&lines, bbox))
+    }
+
     pub fn dims(&self) -> (f32, f32) {
         unsafe {
             let bounds = fz_bound_page(self.ctx.0, self.page);
@@ -572,6 +929,78 @@ impl<'a> PdfPage<'a> {
     }
 }
 
+// Merges the rectangles of consecutive words that sit on the same line.
+fn line_rects(words: &[Boundary]) -> Vec<Boundary> {
+    let mut lines: Vec<Boundary> = Vec::new();
+    for rect in words {
+        if let Some(last) = lines.last_mut() {
+            let overlap = rect.max.y.min(last.max.y) - rect.min.y.max(last.min.y);
+            if overlap > rect.height().min(last.height()) / 2.0 {
+                last.min.x = last.min.x.min(rect.min.x);
+                last.min.y = last.min.y.min(rect.min.y);
+                last.max.x = last.max.x.max(rect.max.x);
+                last.max.y = last.max.y.max(rect.max.y);
+                continue;
+            }
+        }
+        lines.push(*rect);
+    }
+    lines
+}
+
+// Splits the content of a page into two columns when its lines sit on either side of
+// a vertical gutter. Full width lines above or below the columns become a header or
+// a footer. The regions are returned in reading order.
+fn detect_columns(lines: &[Boundary], bbox: Boundary) -> Vec<Boundary> {
+    let middle = (bbox.min.x + bbox.max.x) / 2.0;
+    let (spanning, sides): (Vec<&Boundary>, Vec<&Boundary>) = lines
+        .iter()
+        .filter(|line| line.width() > 0.0 && line.height() > 0.0)
+        .partition(|line| line.min.x < middle && line.max.x > middle);
+    let (left, right): (Vec<&Boundary>, Vec<&Boundary>) =
+        sides.iter().partition(|line| line.max.x <= middle);
+
+    if left.len() < MIN_COLUMN_LINES || right.len() < MIN_COLUMN_LINES {
+        return vec![bbox];
+    }
+
+    let gutter_start = left.iter().map(|line| line.max.x).fold(f32::MIN, f32::max);
+    let gutter_end = right.iter().map(|line| line.min.x).fold(f32::MAX, f32::min);
+
+    if gutter_end <= gutter_start {
+        return vec![bbox];
+    }
+
+    let top = sides.iter().map(|line| line.min.y).fold(f32::MAX, f32::min);
+    let bottom = sides.iter().map(|line| line.max.y).fold(f32::MIN, f32::max);
+
+    // Too many lines cross the gutter for this to be a two columns layout.
+    let crossing = spanning
+        .iter()
+        .filter(|line| line.max.y > top && line.min.y < bottom)
+        .count();
+    if 10 * crossing > sides.len() {
+        return vec![bbox];
+    }
+
+    let has_header = spanning.iter().any(|line| line.max.y <= top);
+    let has_footer = spanning.iter().any(|line| line.min.y >= bottom);
+    let top = if has_header { top } else { bbox.min.y };
+    let bottom = if has_footer { bottom } else { bbox.max.y };
+    let gutter = (gutter_start + gutter_end) / 2.0;
+
+    let mut columns = Vec::new();
+    if has_header {
+        columns.push(bndr!(bbox.min.x, bbox.min.y, bbox.max.x, top));
+    }
+    columns.push(bndr!(bbox.min.x, top, gutter, bottom));
+    columns.push(bndr!(gutter, top, bbox.max.x, bottom));
+    if has_footer {
+        columns.push(bndr!(bbox.min.x, bottom, bbox.max.x, bbox.max.y));
+    }
+    columns
+}
+
 impl Drop for PdfContext {
     fn drop(&mut self) {
         unsafe {
@@ -595,3 +1024,59 @@ impl<'a> Drop for PdfPage<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geom::Rectangle;
+
+    fn column_lines(x0: f32, x1: f32, y0: f32, count: usize) -> Vec<Boundary> {
+        (0..count)
+            .map(|i| {
+                let y = y0 + 12.0 * i as f32;
+                bndr!(x0, y, x1, y + 10.0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn two_columns_with_header() {
+        let bbox = bndr!(50.0, 50.0, 550.0, 750.0);
+        let mut lines = vec![bndr!(150.0, 50.0, 450.0, 70.0)];
+        lines.extend(column_lines(50.0, 290.0, 100.0, 50));
+        lines.extend(column_lines(310.0, 550.0, 100.0, 40));
+        let columns: Vec<Rectangle> = detect_columns(&lines, bbox)
+            .iter()
+            .map(Boundary::to_rect)
+            .collect();
+        assert_eq!(
+            columns,
+            vec![
+                rect![50, 50, 550, 100],
+                rect![50, 100, 300, 750],
+                rect![300, 100, 550, 750],
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_words_into_lines() {
+        let words = vec![
+            bndr!(10.0, 10.0, 40.0, 20.0),
+            bndr!(45.0, 11.0, 80.0, 21.0),
+            bndr!(10.0, 24.0, 50.0, 34.0),
+        ];
+        let lines: Vec<Rectangle> = line_rects(&words).iter().map(Boundary::to_rect).collect();
+        assert_eq!(lines, vec![rect![10, 10, 80, 21], rect![10, 24, 50, 34]]);
+    }
+
+    #[test]
+    fn single_column() {
+        let bbox = bndr!(50.0, 50.0, 550.0, 750.0);
+        let mut lines = column_lines(50.0, 550.0, 50.0, 50);
+        lines.extend(column_lines(50.0, 200.0, 650.0, 5));
+        let columns = detect_columns(&lines, bbox);
+        assert_eq!(columns.len(), 1);
+        assert_eq!(columns[0].to_rect(), bbox.to_rect());
+    }
+}
@@ -0,0 +1,597 @@
+use super::html::HtmlDocument;
+use super::{chapter, chapter_relative};
+use crate::document::{BoundedText, Document, Location, TocEntry};
+use crate::framebuffer::Pixmap;
+use crate::geom::{Boundary, CycleDir, Rectangle};
+use crate::helpers::{load_json, save_json};
+use crate::metadata::TextAlign;
+use anyhow::Error;
+use std::fs;
+use std::path::Path;
+
+// The scale at which the images of the pages are rendered.
+const IMAGE_SCALE: f32 = 2.0;
+
+// The fraction of a page above which an image is considered a background.
+const BACKGROUND_RATIO: f32 = 0.9;
+
+// The files, within the cache directory, that hold the HTML and the offsets of the pages.
+const HTML_FILENAME: &str = "index.html";
+const PAGE_OFFSETS_FILENAME: &str = "page-offsets.json";
+
+// The characters that end a paragraph when they end a page.
+const TERMINAL_PUNCTUATION: [char; 8] = ['.', '!', '?', ':', '"', '”', '»', '…'];
+
+// A fixed layout document whose text is reconstructed into paragraphs,
+// reading order and images, and rendered as HTML.
+pub struct ReflowDocument {
+    html: HtmlDocument,
+    // The offset, within the HTML, of the content of each page of the original document.
+    page_offsets: Vec<usize>,
+    toc: Vec<TocEntry>,
+}
+
+// The result of reflowing a document.
+struct Reflow {
+    text: String,
+    // The rendered illustrations, indexed by name.
+    images: Vec<(String, Vec<u8>)>,
+    page_offsets: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct TextLine {
+    rect: Boundary,
+    text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Block {
+    Heading(String),
+    Paragraph(String),
+    Image(usize),
+}
+
+impl ReflowDocument {
+    // Reflows the given document, unless the result is found in the given cache directory,
+    // in which case it's loaded from there, since reflowing requires extracting the words
+    // of every page and rendering the illustrations.
+    pub fn new<P: AsRef<Path>>(doc: Box<dyn Document>, cache_dir: Option<P>) -> ReflowDocument {
+        ReflowDocument::with_progress(doc, cache_dir, |_| ())
+    }
+
+    // Like `new`, but calls the given function with the percentage of the pages reflowed so far.
+    pub fn with_progress<P, F>(
+        mut doc: Box<dyn Document>,
+        cache_dir: Option<P>,
+        on_progress: F,
+    ) -> ReflowDocument
+    where
+        P: AsRef<Path>,
+        F: FnMut(u8),
+    {
+        let cached = cache_dir.as_ref().and_then(|dir| {
+            let dir = dir.as_ref();
+            if !is_cached(dir) {
+                return None;
+            }
+            load_cache(dir)
+                .map_err(|e| eprintln!("Can't load the reflowed document: {:#}.", e))
+                .ok()
+        });
+
+        let (html, page_offsets) = if let Some(cached) = cached {
+            cached
+        } else {
+            let reflow = reflow(doc.as_mut(), on_progress);
+            if let Some(dir) = cache_dir.as_ref() {
+                if let Err(e) = save_cache(dir.as_ref(), &reflow) {
+                    eprintln!("Can't cache the reflowed document: {:#}.", e);
+                }
+            }
+            let mut html = HtmlDocument::new_from_memory(&reflow.text);
+            for (name, data) in reflow.images {
+                html.add_resource(&name, data);
+            }
+            (html, reflow.page_offsets)
+        };
+
+        let toc = doc
+            .toc()
+            .map(|toc| reflow_toc(&toc, &page_offsets))
+            .unwrap_or_default();
+
+        ReflowDocument {
+            html,
+            page_offsets,
+            toc,
+        }
+    }
+
+    pub fn page_offsets(&self) -> &[usize] {
+        &self.page_offsets
+    }
+}
+
+// Whether the reflowed document is found in the given cache directory.
+pub fn is_cached(cache_dir: &Path) -> bool {
+    cache_dir.join(PAGE_OFFSETS_FILENAME).exists()
+}
+
+// The index of the page of the original document that holds the given offset.
+pub fn page_from_offset(page_offsets: &[usize], offset: usize) -> usize {
+    page_offsets
+        .iter()
+        .rposition(|&page_offset| page_offset <= offset)
+        .unwrap_or(0)
+}
+
+// Returns the HTML of the given document, its images and the offset of each page within it.
+fn reflow<F: FnMut(u8)>(doc: &mut dyn Document, mut on_progress: F) -> Reflow {
+    let pages_count = doc.pages_count();
+    let mut pages = Vec::with_capacity(pages_count);
+    let mut images = Vec::new();
+    let mut progress = 0;
+
+    for index in 0..pages_count {
+        let words = doc
+            .words(Location::Exact(index))
+            .map(|(words, _)| words)
+            .unwrap_or_default();
+        let lines = group_lines(&words);
+        let page_rect = doc
+            .dims(index)
+            .map(|(width, height)| bndr![0.0, 0.0, width, height]);
+        let page_images: Vec<Boundary> = doc
+            .images(Location::Exact(index))
+            .map(|(images, _)| images)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|rect| is_illustration(rect, page_rect.as_ref(), &lines))
+            .collect();
+
+        let mut blocks = page_blocks(&lines, &page_images);
+        for block in &mut blocks {
+            if let Block::Image(k) = block {
+                images.push((index, page_images[*k]));
+                *k = images.len() - 1;
+            }
+        }
+        pages.push(blocks);
+
+        // The illustrations are rendered afterwards, hence the text only accounts for half of the
+        // progress.
+        let percent = (50 * (index + 1) / pages_count) as u8;
+        if percent > progress {
+            progress = percent;
+            on_progress(progress);
+        }
+    }
+
+    let title = doc.title().unwrap_or_default();
+    let (text, page_offsets) = blocks_to_html(&title, &pages);
+
+    let images_count = images.len();
+    let images = images
+        .into_iter()
+        .enumerate()
+        .filter_map(|(k, (index, rect))| {
+            let percent = (50 + 50 * (k + 1) / images_count) as u8;
+            if percent > progress {
+                progress = percent;
+                on_progress(progress);
+            }
+            doc.pixmap(Location::Exact(index), IMAGE_SCALE, 1)
+                .and_then(|(pixmap, _)| {
+                    let rect: Rectangle = (rect * IMAGE_SCALE).into();
+                    pixmap.crop(&rect)
+                })
+                .and_then(|pixmap| pixmap.to_png().ok())
+                .map(|data| (image_name(k), data))
+        })
+        .collect();
+
+    Reflow {
+        text,
+        images,
+        page_offsets,
+    }
+}
+
+fn load_cache(dir: &Path) -> Result<(HtmlDocument, Vec<usize>), Error> {
+    let page_offsets = load_json(dir.join(PAGE_OFFSETS_FILENAME))?;
+    let html = HtmlDocument::new(dir.join(HTML_FILENAME))?;
+    Ok((html, page_offsets))
+}
+
+// The page offsets are written last: their presence marks a complete cache.
+fn save_cache(dir: &Path, reflow: &Reflow) -> Result<(), Error> {
+    fs::create_dir_all(dir)?;
+    for (name, data) in &reflow.images {
+        fs::write(dir.join(name), data)?;
+    }
+    fs::write(dir.join(HTML_FILENAME), &reflow.text)?;
+    save_json(&reflow.page_offsets, dir.join(PAGE_OFFSETS_FILENAME))
+}
+
+fn image_name(index: usize) -> String {
+    format!("image-{}.png", index)
+}
+
+fn reflow_toc(toc: &[TocEntry], page_offsets: &[usize]) -> Vec<TocEntry> {
+    toc.iter()
+        .map(|entry| TocEntry {
+            title: entry.title.clone(),
+            location: match entry.location {
+                Location::Exact(index) => {
+                    Location::Exact(page_offsets.get(index).copied().unwrap_or_default())
+                }
+                ref location => location.clone(),
+            },
+            index: entry.index,
+            children: reflow_toc(&entry.children, page_offsets),
+        })
+        .collect()
+}
+
+// Whether the given image is worth keeping: tiny images are usually ornaments, and
+// images covering the page are the scans behind a text layer.
+fn is_illustration(rect: &Boundary, page_rect: Option<&Boundary>, lines: &[TextLine]) -> bool {
+    let size = median_height(lines);
+    if rect.width() < 2.0 * size || rect.height() < 2.0 * size {
+        return false;
+    }
+    !page_rect.is_some_and(|page_rect| {
+        !lines.is_empty()
+            && rect.width() * rect.height()
+                > BACKGROUND_RATIO * page_rect.width() * page_rect.height()
+    })
+}
+
+fn median_height(lines: &[TextLine]) -> f32 {
+    let mut heights: Vec<f32> = lines.iter().map(|line| line.rect.height()).collect();
+    heights.sort_by(|a, b| a.total_cmp(b));
+    heights.get(heights.len() / 2).copied().unwrap_or(10.0)
+}
+
+// Groups the words, given in reading order, into lines.
+fn group_lines(words: &[BoundedText]) -> Vec<TextLine> {
+    let mut lines: Vec<TextLine> = Vec::new();
+
+    for word in words {
+        let text = word.text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        let center = (word.rect.min.y + word.rect.max.y) / 2.0;
+        if let Some(line) = lines.last_mut() {
+            let height = line.rect.height();
+            if center > line.rect.min.y
+                && center < line.rect.max.y
+                && word.rect.min.x > line.rect.max.x - height / 2.0
+            {
+                line.text.push(' ');
+                line.text.push_str(text);
+                line.rect.min.x = line.rect.min.x.min(word.rect.min.x);
+                line.rect.min.y = line.rect.min.y.min(word.rect.min.y);
+                line.rect.max.x = line.rect.max.x.max(word.rect.max.x);
+                line.rect.max.y = line.rect.max.y.max(word.rect.max.y);
+                continue;
+            }
+        }
+        lines.push(TextLine {
+            rect: word.rect,
+            text: text.to_string(),
+        });
+    }
+
+    lines
+}
+
+// Splits the lines of a page into headings and paragraphs, and places the images
+// before the first line that follows them.
+fn page_blocks(lines: &[TextLine], images: &[Boundary]) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let size = median_height(lines);
+    let mut images: Vec<(usize, &Boundary)> = images.iter().enumerate().collect();
+    images.sort_by(|a, b| a.1.min.y.total_cmp(&b.1.min.y));
+    let mut images = images.into_iter().peekable();
+    let mut right = 0.0f32;
+    let mut previous: Option<&TextLine> = None;
+
+    for (i, line) in lines.iter().enumerate() {
+        // Page numbers and running titles made of digits.
+        if (i == 0 || i == lines.len() - 1) && line.text.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+
+        while let Some((k, _)) = images.next_if(|(_, rect)| rect.min.y < line.rect.min.y) {
+            blocks.push(Block::Image(k));
+            previous = None;
+        }
+
+        let is_heading = line.rect.height() > 1.3 * size;
+        let is_new = previous.is_none_or(|prev| {
+            line.rect.min.y - prev.rect.max.y > 0.7 * size
+                || line.rect.max.y < prev.rect.min.y
+                || line.rect.min.x > prev.rect.min.x + size
+                || prev.rect.max.x < right - 2.0 * size
+                || (prev.rect.height() > 1.3 * size) != is_heading
+        });
+
+        if is_new {
+            right = line.rect.max.x;
+            blocks.push(if is_heading {
+                Block::Heading(line.text.clone())
+            } else {
+                Block::Paragraph(line.text.clone())
+            });
+        } else {
+            right = right.max(line.rect.max.x);
+            if let Some(Block::Heading(text) | Block::Paragraph(text)) = blocks.last_mut() {
+                join_line(text, &line.text);
+            }
+        }
+
+        previous = Some(line);
+    }
+
+    blocks.extend(images.map(|(k, _)| Block::Image(k)));
+    blocks
+}
+
+// Appends a line to a paragraph, rejoining the words hyphenated across lines.
+fn join_line(text: &mut String, line: &str) {
+    let mut chars = text.chars().rev();
+    if chars.next() == Some('-')
+        && chars.next().is_some_and(char::is_alphabetic)
+        && line.chars().next().is_some_and(char::is_lowercase)
+    {
+        text.pop();
+    } else {
+        text.push(' ');
+    }
+    text.push_str(line);
+}
+
+// Whether the first paragraph of a page continues the last paragraph of the previous page.
+fn continues(previous: &str, text: &str) -> bool {
+    !previous.ends_with(TERMINAL_PUNCTUATION) && text.chars().next().is_some_and(char::is_lowercase)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// Returns the HTML of the given pages and the offset of each page within it.
+fn blocks_to_html(title: &str, pages: &[Vec<Block>]) -> (String, Vec<usize>) {
+    let mut html = format!(
+        "<html>\n<head>\n<title>{}</title>\n</head>\n<body>\n",
+        escape(title)
+    );
+    let mut page_offsets = Vec::with_capacity(pages.len());
+    // The last paragraph, which might continue on the next page.
+    let mut pending: Option<String> = None;
+
+    for blocks in pages {
+        let mut blocks = blocks.iter().peekable();
+
+        if let Some(text) = pending.as_mut() {
+            if let Some(Block::Paragraph(next)) = blocks.peek() {
+                if continues(text, next) {
+                    join_line(text, next);
+                    blocks.next();
+                }
+            }
+        }
+
+        page_offsets.push(html.len());
+
+        for block in blocks {
+            if let Some(text) = pending.take() {
+                html.push_str(&format!("<p>{}</p>\n", escape(&text)));
+            }
+            match block {
+                Block::Heading(text) => html.push_str(&format!("<h2>{}</h2>\n", escape(text))),
+                Block::Paragraph(text) => pending = Some(text.clone()),
+                Block::Image(k) => html.push_str(&format!(
+                    "<div class=\"image\"><img src=\"{}\"/></div>\n",
+                    image_name(*k)
+                )),
+            }
+        }
+    }
+
+    if let Some(text) = pending.take() {
+        html.push_str(&format!("<p>{}</p>\n", escape(&text)));
+    }
+
+    html.push_str("</body>\n</html>\n");
+    (html, page_offsets)
+}
+
+impl Document for ReflowDocument {
+    fn dims(&self, index: usize) -> Option<(f32, f32)> {
+        self.html.dims(index)
+    }
+
+    fn pages_count(&self) -> usize {
+        self.html.pages_count()
+    }
+
+    fn toc(&mut self) -> Option<Vec<TocEntry>> {
+        if self.toc.is_empty() {
+            None
+        } else {
+            Some(self.toc.clone())
+        }
+    }
+
+    fn chapter<'a>(&mut self, offset: usize, toc: &'a [TocEntry]) -> Option<(&'a TocEntry, f32)> {
+        chapter(offset, self.pages_count(), toc)
+    }
+
+    fn chapter_relative<'a>(
+        &mut self,
+        offset: usize,
+        dir: CycleDir,
+        toc: &'a [TocEntry],
+    ) -> Option<&'a TocEntry> {
+        chapter_relative(offset, dir, toc)
+    }
+
+    fn resolve_location(&mut self, loc: Location) -> Option<usize> {
+        self.html.resolve_location(loc)
+    }
+
+    fn words(&mut self, loc: Location) -> Option<(Vec<BoundedText>, usize)> {
+        self.html.words(loc)
+    }
+
+    fn lines(&mut self, loc: Location) -> Option<(Vec<BoundedText>, usize)> {
+        self.html.lines(loc)
+    }
+
+    fn links(&mut self, loc: Location) -> Option<(Vec<BoundedText>, usize)> {
+        self.html.links(loc)
+    }
+
+    fn images(&mut self, loc: Location) -> Option<(Vec<Boundary>, usize)> {
+        self.html.images(loc)
+    }
+
+    fn pixmap(&mut self, loc: Location, scale: f32, samples: usize) -> Option<(Pixmap, usize)> {
+        self.html.pixmap(loc, scale, samples)
+    }
+
+    fn layout(&mut self, width: u32, height: u32, font_size: f32, dpi: u16) {
+        self.html.layout(width, height, font_size, dpi);
+    }
+
+    fn set_font_family(&mut self, family_name: &str, search_path: &str) {
+        self.html.set_font_family(family_name, search_path);
+    }
+
+    fn set_margin_width(&mut self, width: i32) {
+        self.html.set_margin_width(width);
+    }
+
+    fn set_text_align(&mut self, text_align: TextAlign) {
+        self.html.set_text_align(text_align);
+    }
+
+    fn set_line_height(&mut self, line_height: f32) {
+        self.html.set_line_height(line_height);
+    }
+
+    fn set_hyphen_penalty(&mut self, hyphen_penalty: i32) {
+        self.html.set_hyphen_penalty(hyphen_penalty);
+    }
+
+    fn set_stretch_tolerance(&mut self, stretch_tolerance: f32) {
+        self.html.set_stretch_tolerance(stretch_tolerance);
+    }
+
+    fn set_ignore_document_css(&mut self, ignore: bool) {
+        self.html.set_ignore_document_css(ignore);
+    }
+
+    fn title(&self) -> Option<String> {
+        self.html.title()
+    }
+
+    fn author(&self) -> Option<String> {
+        None
+    }
+
+    fn metadata(&self, _key: &str) -> Option<String> {
+        None
+    }
+
+    fn is_reflowable(&self) -> bool {
+        true
+    }
+
+    fn has_synthetic_page_numbers(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::TextLocation;
+
+    fn word(text: &str, x: f32, y: f32, height: f32) -> BoundedText {
+        BoundedText {
+            text: text.to_string(),
+            rect: bndr![x, y, x + 6.0 * text.len() as f32, y + height],
+            location: TextLocation::Static(0, 0),
+        }
+    }
+
+    #[test]
+    fn test_paragraphs() {
+        let words = vec![
+            word("Walden", 50.0, 20.0, 20.0),
+            word("I", 70.0, 60.0, 10.0),
+            word("went", 80.0, 60.0, 10.0),
+            word("to", 110.0, 60.0, 10.0),
+            word("the", 50.0, 72.0, 10.0),
+            word("woods", 74.0, 72.0, 10.0),
+            word("be-", 200.0, 72.0, 10.0),
+            word("cause", 50.0, 84.0, 10.0),
+            word("I", 86.0, 84.0, 10.0),
+            word("It", 70.0, 96.0, 10.0),
+            word("12", 120.0, 200.0, 10.0),
+        ];
+        let lines = group_lines(&words);
+        assert_eq!(lines.len(), 6);
+        assert_eq!(
+            page_blocks(&lines, &[]),
+            vec![
+                Block::Heading("Walden".to_string()),
+                Block::Paragraph("I went to the woods because I".to_string()),
+                Block::Paragraph("It".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_continued_paragraph() {
+        let pages = vec![
+            vec![
+                Block::Paragraph("One.".to_string()),
+                Block::Paragraph("Two and".to_string()),
+            ],
+            vec![Block::Paragraph("three.".to_string()), Block::Image(0)],
+        ];
+        let (html, page_offsets) = blocks_to_html("A & B", &pages);
+        assert!(html.contains("<title>A &amp; B</title>"));
+        assert!(html.contains("<p>Two and three.</p>\n<div class=\"image\">"));
+        assert_eq!(&html[page_offsets[1]..page_offsets[1] + 3], "<p>");
+        assert_eq!(page_from_offset(&page_offsets, html.len()), 1);
+    }
+
+    #[test]
+    fn test_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("reflow");
+        let reflow = Reflow {
+            text: "<html><body><p>One.</p><p>Two.</p></body></html>".to_string(),
+            images: vec![(image_name(0), vec![1, 2, 3])],
+            page_offsets: vec![0, 25],
+        };
+        assert!(!is_cached(&cache_dir));
+        save_cache(&cache_dir, &reflow).unwrap();
+        assert!(is_cached(&cache_dir));
+        assert_eq!(
+            fs::read(cache_dir.join(image_name(0))).unwrap(),
+            vec![1, 2, 3]
+        );
+        let (_, page_offsets) = load_cache(&cache_dir).unwrap();
+        assert_eq!(page_offsets, vec![0, 25]);
+    }
+}
@@ -0,0 +1,631 @@
+use super::html::HtmlDocument;
+use super::{chapter, chapter_relative};
+use crate::document::{BoundedText, Document, Location, TocEntry};
+use crate::framebuffer::Pixmap;
+use crate::geom::{Boundary, CycleDir};
+use crate::metadata::TextAlign;
+use anyhow::Error;
+use chardetng::EncodingDetector;
+use encoding_rs::Encoding;
+use std::fs;
+use std::path::Path;
+
+// A plain text or Markdown document, converted to HTML.
+pub struct TextDocument {
+    html: HtmlDocument,
+    toc: Vec<TocEntry>,
+}
+
+#[derive(Debug)]
+struct Heading {
+    level: usize,
+    title: String,
+    offset: usize,
+}
+
+impl TextDocument {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<TextDocument, Error> {
+        let buf = fs::read(path.as_ref())?;
+        let text = decode_text(&buf);
+        let is_markdown = path
+            .as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "md" | "markdown"));
+        let mut doc = TextDocument::new_from_memory(&text, is_markdown);
+        if let Some(parent) = path.as_ref().parent() {
+            doc.html.set_parent(parent);
+        }
+        Ok(doc)
+    }
+
+    pub fn new_from_memory(text: &str, is_markdown: bool) -> TextDocument {
+        let mut headings = Vec::new();
+        let markup = if is_markdown {
+            markdown_to_html(text, &mut headings)
+        } else {
+            text_to_html(text)
+        };
+        let toc = toc_entries(&headings, &mut 0, 1);
+
+        TextDocument {
+            html: HtmlDocument::new_from_memory(&markup),
+            toc,
+        }
+    }
+}
+
+impl Document for TextDocument {
+    fn dims(&self, index: usize) -> Option<(f32, f32)> {
+        self.html.dims(index)
+    }
+
+    fn pages_count(&self) -> usize {
+        self.html.pages_count()
+    }
+
+    fn toc(&mut self) -> Option<Vec<TocEntry>> {
+        if self.toc.is_empty() {
+            None
+        } else {
+            Some(self.toc.clone())
+        }
+    }
+
+    fn chapter<'a>(&mut self, offset: usize, toc: &'a [TocEntry]) -> Option<(&'a TocEntry, f32)> {
+        chapter(offset, self.pages_count(), toc)
+    }
+
+    fn chapter_relative<'a>(
+        &mut self,
+        offset: usize,
+        dir: CycleDir,
+        toc: &'a [TocEntry],
+    ) -> Option<&'a TocEntry> {
+        chapter_relative(offset, dir, toc)
+    }
+
+    fn resolve_location(&mut self, loc: Location) -> Option<usize> {
+        self.html.resolve_location(loc)
+    }
+
+    fn words(&mut self, loc: Location) -> Option<(Vec<BoundedText>, usize)> {
+        self.html.words(loc)
+    }
+
+    fn lines(&mut self, loc: Location) -> Option<(Vec<BoundedText>, usize)> {
+        self.html.lines(loc)
+    }
+
+    fn links(&mut self, loc: Location) -> Option<(Vec<BoundedText>, usize)> {
+        self.html.links(loc)
+    }
+
+    fn images(&mut self, loc: Location) -> Option<(Vec<Boundary>, usize)> {
+        self.html.images(loc)
+    }
+
+    fn pixmap(&mut self, loc: Location, scale: f32, samples: usize) -> Option<(Pixmap, usize)> {
+        self.html.pixmap(loc, scale, samples)
+    }
+
+    fn layout(&mut self, width: u32, height: u32, font_size: f32, dpi: u16) {
+        self.html.layout(width, height, font_size, dpi);
+    }
+
+    fn set_font_family(&mut self, family_name: &str, search_path: &str) {
+        self.html.set_font_family(family_name, search_path);
+    }
+
+    fn set_margin_width(&mut self, width: i32) {
+        self.html.set_margin_width(width);
+    }
+
+    fn set_text_align(&mut self, text_align: TextAlign) {
+        self.html.set_text_align(text_align);
+    }
+
+    fn set_line_height(&mut self, line_height: f32) {
+        self.html.set_line_height(line_height);
+    }
+
+    fn set_hyphen_penalty(&mut self, hyphen_penalty: i32) {
+        self.html.set_hyphen_penalty(hyphen_penalty);
+    }
+
+    fn set_stretch_tolerance(&mut self, stretch_tolerance: f32) {
+        self.html.set_stretch_tolerance(stretch_tolerance);
+    }
+
+    fn set_ignore_document_css(&mut self, ignore: bool) {
+        self.html.set_ignore_document_css(ignore);
+    }
+
+    fn title(&self) -> Option<String> {
+        self.html.title()
+    }
+
+    fn author(&self) -> Option<String> {
+        None
+    }
+
+    fn metadata(&self, _key: &str) -> Option<String> {
+        None
+    }
+
+    fn is_reflowable(&self) -> bool {
+        true
+    }
+
+    fn has_synthetic_page_numbers(&self) -> bool {
+        true
+    }
+}
+
+// Decodes the text according to its byte order mark, to the encoding given by its XML
+// declaration, or to the most likely encoding.
+pub fn decode_text(buf: &[u8]) -> String {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(buf) {
+        return encoding
+            .decode_without_bom_handling(&buf[bom_length..])
+            .0
+            .into_owned();
+    }
+
+    if let Some(encoding) = declared_encoding(buf) {
+        return encoding.decode_without_bom_handling(buf).0.into_owned();
+    }
+
+    if let Ok(text) = std::str::from_utf8(buf) {
+        return text.to_string();
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(buf, true);
+    detector.guess(None, true).decode(buf).0.into_owned()
+}
+
+fn declared_encoding(buf: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(&buf[..buf.len().min(128)]);
+    if !head.trim_start().starts_with("<?xml") {
+        return None;
+    }
+    let index = head.find("encoding=")?;
+    let rest = &head[index + 9..];
+    let quote = rest.chars().next()?;
+    rest[1..]
+        .split(quote)
+        .next()
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+}
+
+fn toc_entries(headings: &[Heading], index: &mut usize, level: usize) -> Vec<TocEntry> {
+    let mut entries = Vec::new();
+
+    while let Some(heading) = headings.get(*index) {
+        if heading.level < level {
+            break;
+        }
+        let current_index = *index;
+        *index += 1;
+        let children = toc_entries(headings, index, heading.level + 1);
+        entries.push(TocEntry {
+            title: heading.title.clone(),
+            location: Location::Exact(heading.offset),
+            index: current_index,
+            children,
+        });
+    }
+
+    entries
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html_document(title: Option<&str>, body: &str) -> String {
+    format!(
+        "<html><head><title>{}</title></head><body>{}</body></html>",
+        title.map(escape_html).unwrap_or_default(),
+        body
+    )
+}
+
+// Paragraphs are separated by blank lines, unless there are none: each line is then a paragraph.
+fn text_to_html(text: &str) -> String {
+    let has_blank_lines = text.lines().any(|line| line.trim().is_empty());
+    let mut body = String::new();
+    let mut paragraph = Vec::new();
+
+    for line in text.lines() {
+        let line = line.trim();
+        if (line.is_empty() || !has_blank_lines) && !paragraph.is_empty() {
+            body.push_str(&format!("<p>{}</p>", escape_html(&paragraph.join(" "))));
+            paragraph.clear();
+        }
+        if !line.is_empty() {
+            paragraph.push(line);
+        }
+    }
+
+    if !paragraph.is_empty() {
+        body.push_str(&format!("<p>{}</p>", escape_html(&paragraph.join(" "))));
+    }
+
+    html_document(None, &body)
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum ListKind {
+    Unordered,
+    Ordered,
+}
+
+impl ListKind {
+    fn tag(self) -> &'static str {
+        match self {
+            ListKind::Unordered => "ul",
+            ListKind::Ordered => "ol",
+        }
+    }
+}
+
+// The kind and the content of a list item.
+fn list_item(line: &str) -> Option<(ListKind, &str)> {
+    let line = line.trim_start();
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(rest) = line.strip_prefix(marker) {
+            return Some((ListKind::Unordered, rest));
+        }
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0 && digits < 10 {
+        let rest = &line[digits..];
+        if let Some(rest) = rest.strip_prefix(". ").or_else(|| rest.strip_prefix(") ")) {
+            return Some((ListKind::Ordered, rest));
+        }
+    }
+    None
+}
+
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    if (1..=6).contains(&level) {
+        let rest = &line[level..];
+        if rest.is_empty() || rest.starts_with(' ') {
+            return Some((level, rest.trim().trim_end_matches('#').trim_end()));
+        }
+    }
+    None
+}
+
+fn is_thematic_break(line: &str) -> bool {
+    let line: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    line.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|&m| line.chars().all(|c| c == m))
+}
+
+fn markdown_to_html(text: &str, headings: &mut Vec<Heading>) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let title = lines
+        .iter()
+        .find_map(|line| heading(line).filter(|(level, _)| *level == 1))
+        .map(|(_, title)| strip_inline_markup(title));
+    // The offsets of the headings are relative to the final document.
+    let mut buf = html_document(title.as_deref(), "");
+    let footer = buf.split_off(buf.len() - "</body></html>".len());
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut list: Option<ListKind> = None;
+    let mut index = 0;
+
+    while index < lines.len() {
+        let line = lines[index];
+        let trimmed = line.trim();
+
+        // Setext headings underline their text.
+        if !paragraph.is_empty()
+            && !trimmed.is_empty()
+            && (trimmed.chars().all(|c| c == '=') || trimmed.chars().all(|c| c == '-'))
+        {
+            let level = if trimmed.starts_with('=') { 1 } else { 2 };
+            let title = paragraph.join(" ");
+            paragraph.clear();
+            push_heading(&mut buf, level, &title, headings);
+            index += 1;
+            continue;
+        }
+
+        let is_special = trimmed.is_empty()
+            || heading(trimmed).is_some()
+            || trimmed.starts_with("```")
+            || trimmed.starts_with("~~~")
+            || trimmed.starts_with('>')
+            || is_thematic_break(trimmed)
+            || list_item(line).is_some()
+            || (paragraph.is_empty() && list.is_none() && line.starts_with("    "));
+
+        if is_special && !paragraph.is_empty() {
+            let tag = if list.is_some() { "li" } else { "p" };
+            buf.push_str(&format!(
+                "<{}>{}</{}>",
+                tag,
+                inline_markup(&paragraph.join(" ")),
+                tag
+            ));
+            paragraph.clear();
+        }
+
+        if list.is_some() && list_item(line).is_none() && is_special && !trimmed.is_empty() {
+            if let Some(kind) = list.take() {
+                buf.push_str(&format!("</{}>", kind.tag()));
+            }
+        }
+
+        if trimmed.is_empty() {
+            // A blank line ends a list unless another item follows.
+            if let Some(kind) = list {
+                let next_item = lines[index + 1..]
+                    .iter()
+                    .find(|line| !line.trim().is_empty())
+                    .and_then(|line| list_item(line));
+                if next_item.map(|(k, _)| k) != Some(kind) {
+                    buf.push_str(&format!("</{}>", kind.tag()));
+                    list = None;
+                }
+            }
+        } else if let Some((level, title)) = heading(trimmed) {
+            push_heading(&mut buf, level, title, headings);
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            let fence = &trimmed[..3];
+            let mut code = Vec::new();
+            index += 1;
+            while index < lines.len() && !lines[index].trim_start().starts_with(fence) {
+                code.push(escape_html(lines[index]));
+                index += 1;
+            }
+            buf.push_str(&format!("<pre><code>{}</code></pre>", code.join("\n")));
+        } else if paragraph.is_empty() && list.is_none() && line.starts_with("    ") {
+            let mut code = Vec::new();
+            while index < lines.len()
+                && (lines[index].starts_with("    ") || lines[index].trim().is_empty())
+            {
+                code.push(escape_html(lines[index].get(4..).unwrap_or_default()));
+                index += 1;
+            }
+            while code.last().is_some_and(|line| line.is_empty()) {
+                code.pop();
+            }
+            buf.push_str(&format!("<pre><code>{}</code></pre>", code.join("\n")));
+            continue;
+        } else if trimmed.starts_with('>') {
+            let mut quote = Vec::new();
+            while index < lines.len() && lines[index].trim_start().starts_with('>') {
+                quote.push(lines[index].trim_start()[1..].trim());
+                index += 1;
+            }
+            buf.push_str(&format!(
+                "<blockquote><p>{}</p></blockquote>",
+                inline_markup(&quote.join(" "))
+            ));
+            continue;
+        } else if is_thematic_break(trimmed) {
+            buf.push_str("<hr/>");
+        } else if let Some((kind, content)) = list_item(line) {
+            if list != Some(kind) {
+                if let Some(previous) = list {
+                    buf.push_str(&format!("</{}>", previous.tag()));
+                }
+                buf.push_str(&format!("<{}>", kind.tag()));
+                list = Some(kind);
+            }
+            paragraph.push(content.trim());
+        } else {
+            paragraph.push(trimmed);
+        }
+
+        index += 1;
+    }
+
+    if !paragraph.is_empty() {
+        let tag = if list.is_some() { "li" } else { "p" };
+        buf.push_str(&format!(
+            "<{}>{}</{}>",
+            tag,
+            inline_markup(&paragraph.join(" ")),
+            tag
+        ));
+    }
+
+    if let Some(kind) = list {
+        buf.push_str(&format!("</{}>", kind.tag()));
+    }
+
+    buf.push_str(&footer);
+    buf
+}
+
+fn push_heading(buf: &mut String, level: usize, title: &str, headings: &mut Vec<Heading>) {
+    headings.push(Heading {
+        level,
+        title: strip_inline_markup(title),
+        offset: buf.len(),
+    });
+    buf.push_str(&format!(
+        "<h{}>{}</h{}>",
+        level,
+        inline_markup(title),
+        level
+    ));
+}
+
+// The text of the given inline markup, without its formatting.
+fn strip_inline_markup(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' | '_' | '`' => (),
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    result.push(next);
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+// Converts code spans, emphasis, images and links.
+fn inline_markup(text: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    let mut open_tags: Vec<&str> = Vec::new();
+
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '\\' if rest.len() > 1 => {
+                let next = rest[1..].chars().next().unwrap();
+                result.push_str(&escape_html(&next.to_string()));
+                rest = &rest[1 + next.len_utf8()..];
+            }
+            '`' => {
+                if let Some(end) = rest[1..].find('`') {
+                    result.push_str(&format!("<code>{}</code>", escape_html(&rest[1..end + 1])));
+                    rest = &rest[end + 2..];
+                } else {
+                    result.push('`');
+                    rest = &rest[1..];
+                }
+            }
+            '*' | '_' => {
+                let strong = rest.starts_with("**") || rest.starts_with("__");
+                let (tag, length) = if strong { ("strong", 2) } else { ("em", 1) };
+                if open_tags.last() == Some(&tag) {
+                    open_tags.pop();
+                    result.push_str(&format!("</{}>", tag));
+                } else if rest[length..]
+                    .chars()
+                    .next()
+                    .is_some_and(|c| !c.is_whitespace())
+                    && rest[length..].contains(&rest[..length])
+                {
+                    open_tags.push(tag);
+                    result.push_str(&format!("<{}>", tag));
+                } else {
+                    result.push_str(&rest[..length]);
+                }
+                rest = &rest[length..];
+            }
+            '!' | '[' => {
+                let is_image = c == '!';
+                let start = if is_image { 2 } else { 1 };
+                let link = if !is_image || rest[1..].starts_with('[') {
+                    rest[start..].find("](").and_then(|i| {
+                        rest[start + i + 2..]
+                            .find(')')
+                            .map(|j| (i, start + i + 2, start + i + 2 + j))
+                    })
+                } else {
+                    None
+                };
+                if let Some((i, url_start, url_end)) = link {
+                    let label = &rest[start..start + i];
+                    let url = escape_html(&rest[url_start..url_end]);
+                    if is_image {
+                        result.push_str(&format!(
+                            "<img src=\"{}\" alt=\"{}\"/>",
+                            url,
+                            escape_html(label)
+                        ));
+                    } else {
+                        result.push_str(&format!(
+                            "<a href=\"{}\">{}</a>",
+                            url,
+                            inline_markup(label)
+                        ));
+                    }
+                    rest = &rest[url_end + 1..];
+                } else {
+                    result.push(c);
+                    rest = &rest[1..];
+                }
+            }
+            _ => {
+                result.push_str(&escape_html(&c.to_string()));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    while let Some(tag) = open_tags.pop() {
+        result.push_str(&format!("</{}>", tag));
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_text() {
+        assert_eq!(decode_text(b"\xef\xbb\xbfcaf\xc3\xa9"), "café");
+        assert_eq!(decode_text("naïve".as_bytes()), "naïve");
+        assert_eq!(
+            decode_text(b"C'\xe9tait l'\xe9t\xe9, \xe0 la campagne."),
+            "C'était l'été, à la campagne."
+        );
+        let buf = b"<?xml version=\"1.0\" encoding=\"windows-1251\"?><p>\xcf\xf0\xe8</p>";
+        assert!(decode_text(buf).ends_with("<p>\u{41f}\u{440}\u{438}</p>"));
+    }
+
+    #[test]
+    fn test_text_to_html() {
+        assert_eq!(
+            text_to_html("First\nline.\n\nSecond & last.\n"),
+            "<html><head><title></title></head><body>\
+             <p>First line.</p><p>Second &amp; last.</p></body></html>"
+        );
+        assert!(text_to_html("One.\nTwo.").contains("<p>One.</p><p>Two.</p>"));
+    }
+
+    #[test]
+    fn test_inline_markup() {
+        assert_eq!(
+            inline_markup("Some **bold**, *italic* and `a < b`."),
+            "Some <strong>bold</strong>, <em>italic</em> and <code>a &lt; b</code>."
+        );
+        assert_eq!(
+            inline_markup("A [link](http://x.org) and 2 * 3."),
+            "A <a href=\"http://x.org\">link</a> and 2 * 3."
+        );
+        assert_eq!(inline_markup("snake_case"), "snake_case");
+    }
+
+    #[test]
+    fn test_markdown_to_html() {
+        let text = "# Title\n\nIntro\ntext.\n\n## Part\n\n- one\n- two\n\n```\nlet x = 1;\n```\n\n\
+                    Sub\n---\n\n1. first\n2. second\n";
+        let mut headings = Vec::new();
+        let html = markdown_to_html(text, &mut headings);
+        assert!(html.starts_with("<html><head><title>Title</title></head><body><h1>Title</h1>"));
+        assert!(html.contains("<p>Intro text.</p><h2>Part</h2><ul><li>one</li><li>two</li></ul>"));
+        assert!(html.contains("<pre><code>let x = 1;</code></pre><h2>Sub</h2>"));
+        assert!(html.ends_with("<ol><li>first</li><li>second</li></ol></body></html>"));
+        assert_eq!(headings.len(), 3);
+        assert!(html[headings[1].offset..].starts_with("<h2>Part</h2>"));
+
+        let toc = toc_entries(&headings, &mut 0, 1);
+        assert_eq!(toc.len(), 1);
+        assert_eq!(toc[0].children.len(), 2);
+        assert_eq!(toc[0].children[1].title, "Sub");
+    }
+}
@@ -0,0 +1,145 @@
+use super::html::dom::XmlTree;
+use super::html::xml::XmlParser;
+use crate::helpers::decode_entities;
+use anyhow::Error;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use zip::ZipArchive;
+
+const PACKAGE_RELATIONSHIPS: &str = "_rels/.rels";
+const CORE_PROPERTIES_TYPE: &str = "/metadata/core-properties";
+const DEFAULT_CORE_PROPERTIES: &str = "docProps/core.xml";
+
+// The core properties of XPS and OpenXPS documents.
+pub struct XpsProperties {
+    info: XmlTree,
+}
+
+impl XpsProperties {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<XpsProperties, Error> {
+        let file = File::open(path)?;
+        let mut archive = ZipArchive::new(file)?;
+
+        // The package relationships give the location of the core properties.
+        let core_path = {
+            let mut text = String::new();
+            if let Ok(mut zf) = archive.by_name(PACKAGE_RELATIONSHIPS) {
+                zf.read_to_string(&mut text)?;
+            }
+            core_properties_path(&text)
+        };
+
+        let mut zf = archive.by_name(&core_path)?;
+        let mut text = String::new();
+        zf.read_to_string(&mut text)?;
+
+        Ok(XpsProperties::from_text(&text))
+    }
+
+    fn from_text(text: &str) -> XpsProperties {
+        XpsProperties {
+            info: XmlParser::new(text).parse(),
+        }
+    }
+
+    fn metadata(&self, key: &str) -> Option<String> {
+        self.info
+            .root()
+            .find(key)
+            .map(|node| decode_entities(node.text().trim()).into_owned())
+            .filter(|text| !text.is_empty())
+    }
+
+    pub fn title(&self) -> Option<String> {
+        self.metadata("title")
+    }
+
+    pub fn author(&self) -> Option<String> {
+        self.metadata("creator")
+    }
+
+    // The core properties have no publisher, but some producers add the Dublin Core one.
+    pub fn publisher(&self) -> Option<String> {
+        self.metadata("publisher")
+    }
+
+    pub fn language(&self) -> Option<String> {
+        self.metadata("language")
+    }
+
+    pub fn year(&self) -> Option<String> {
+        self.metadata("created")
+            .map(|s| s.chars().take(4).collect())
+    }
+
+    pub fn categories(&self) -> BTreeSet<String> {
+        ["category", "keywords"]
+            .iter()
+            .filter_map(|key| self.metadata(key))
+            .flat_map(|value| {
+                value
+                    .split([',', ';'])
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<String>>()
+            })
+            .collect()
+    }
+}
+
+fn core_properties_path(relationships: &str) -> String {
+    let info = XmlParser::new(relationships).parse();
+    info.root()
+        .descendants()
+        .filter(|node| node.tag_name() == Some("Relationship"))
+        .find(|node| {
+            node.attribute("Type")
+                .is_some_and(|kind| kind.ends_with(CORE_PROPERTIES_TYPE))
+        })
+        .and_then(|node| node.attribute("Target"))
+        .map(|target| target.trim_start_matches('/').to_string())
+        .unwrap_or_else(|| DEFAULT_CORE_PROPERTIES.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_core_properties() {
+        let rels = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+  <Relationship Id="R0" Type="http://schemas.microsoft.com/xps/2005/06/fixedrepresentation" Target="/FixedDocSeq.fdseq"/>
+  <Relationship Id="R1" Type="http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties" Target="/Metadata/Core.xml"/>
+</Relationships>"#;
+        assert_eq!(core_properties_path(rels), "Metadata/Core.xml");
+        assert_eq!(core_properties_path(""), DEFAULT_CORE_PROPERTIES);
+
+        let core = r#"<?xml version="1.0" encoding="UTF-8"?>
+<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/">
+  <dc:title>Annual Report &amp; Accounts</dc:title>
+  <dc:creator>Jane Doe</dc:creator>
+  <dc:language>en-GB</dc:language>
+  <dc:publisher>Acme</dc:publisher>
+  <cp:category>Finance</cp:category>
+  <cp:keywords>report; 2019</cp:keywords>
+  <dcterms:created>2019-04-02T10:00:00Z</dcterms:created>
+</cp:coreProperties>"#;
+        let props = XpsProperties::from_text(core);
+        assert_eq!(props.title().as_deref(), Some("Annual Report & Accounts"));
+        assert_eq!(props.author().as_deref(), Some("Jane Doe"));
+        assert_eq!(props.language().as_deref(), Some("en-GB"));
+        assert_eq!(props.publisher().as_deref(), Some("Acme"));
+        assert_eq!(props.year().as_deref(), Some("2019"));
+        assert_eq!(
+            props.categories().into_iter().collect::<Vec<String>>(),
+            vec![
+                "2019".to_string(),
+                "Finance".to_string(),
+                "report".to_string()
+            ]
+        );
+    }
+}
@@ -0,0 +1,346 @@
+use crate::document::{Document, TextLocation, BYTES_PER_PAGE};
+use crate::helpers::datetime_format;
+use crate::metadata::{Info, ReaderInfo};
+use anyhow::{format_err, Error};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Version of the JSON schema, bumped on incompatible changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::Html => "HTML",
+            ExportFormat::Json => "JSON",
+        }
+    }
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "html" => Ok(ExportFormat::Html),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format_err!("unknown export format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedBook {
+    pub version: u32,
+    pub title: String,
+    pub author: String,
+    pub file: PathBuf,
+    pub annotations: Vec<ExportedAnnotation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedAnnotation {
+    pub chapter: Option<String>,
+    pub page: String,
+    pub text: String,
+    pub note: String,
+    #[serde(with = "datetime_format")]
+    pub modified: NaiveDateTime,
+}
+
+// The label of the page holding the given location, as shown by the reader.
+pub fn page_label(loc: TextLocation, reader: &ReaderInfo, synthetic: bool) -> String {
+    let index = loc.location();
+
+    if synthetic {
+        return format!("{:.1}", index as f64 / BYTES_PER_PAGE);
+    }
+
+    if let Some(name) = reader.page_names.get(&index) {
+        return name.clone();
+    }
+
+    // Numeric page names apply to the pages that follow them.
+    reader
+        .page_names
+        .range(..index)
+        .next_back()
+        .and_then(|(i, name)| name.parse::<usize>().ok().map(|n| n + index - i))
+        .unwrap_or(index + 1)
+        .to_string()
+}
+
+pub fn exported_book(doc: &mut dyn Document, info: &Info) -> ExportedBook {
+    let toc = doc.toc().unwrap_or_default();
+    let synthetic = doc.has_synthetic_page_numbers();
+    let mut annotations = Vec::new();
+
+    if let Some(reader) = info.reader.as_ref() {
+        let mut sorted = reader.annotations.clone();
+        sorted.sort_by(|a, b| a.selection[0].cmp(&b.selection[0]));
+        for annot in sorted {
+            let start = annot.selection[0];
+            let chapter = doc
+                .chapter(start.location(), &toc)
+                .map(|(entry, _)| entry.title.clone());
+            annotations.push(ExportedAnnotation {
+                chapter,
+                page: page_label(start, reader, synthetic),
+                text: annot.text,
+                note: annot.note,
+                modified: annot.modified,
+            });
+        }
+    }
+
+    // Entries read from the file system carry no metadata.
+    let title = if info.title.is_empty() {
+        doc.title()
+            .filter(|t| !t.is_empty())
+            .unwrap_or_else(|| info.file_stem())
+    } else {
+        info.title.clone()
+    };
+    let author = if info.author.is_empty() {
+        doc.author().unwrap_or_default()
+    } else {
+        info.author.clone()
+    };
+
+    ExportedBook {
+        version: SCHEMA_VERSION,
+        title,
+        author,
+        file: info.file.path.clone(),
+        annotations,
+    }
+}
+
+pub fn book_as_markdown(book: &ExportedBook) -> String {
+    let mut buf = format!("# {}\n", book.title);
+    if !book.author.is_empty() {
+        buf.push_str(&format!("\n*{}*\n", book.author));
+    }
+
+    let mut chapter = None;
+    for annot in &book.annotations {
+        if annot.chapter.is_some() && annot.chapter != chapter {
+            chapter = annot.chapter.clone();
+            buf.push_str(&format!(
+                "\n## {}\n",
+                chapter.as_deref().unwrap_or_default()
+            ));
+        }
+        buf.push('\n');
+        for line in annot.text.lines() {
+            buf.push_str(&format!("> {}\n", line));
+        }
+        buf.push_str(&format!(">\n> — p. {}\n", annot.page));
+        if !annot.note.is_empty() {
+            buf.push_str(&format!("\n{}\n", annot.note));
+        }
+    }
+
+    buf
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+pub fn book_as_html(book: &ExportedBook) -> String {
+    let title = escape_html(&book.title);
+    let mut buf = format!(
+        "<!DOCTYPE html>\n<html>\n\t<head>\n\t\t<meta charset=\"utf-8\"/>\n\t\t\
+         <title>{}</title>\n\t\t<style>\n\t\t\t\
+         blockquote {{ margin: 1em 0; padding-left: 1em; border-left: 3px solid #888; }}\n\t\t\t\
+         .page {{ color: #666; font-size: smaller; }}\n\t\t\t\
+         .note {{ font-style: italic; }}\n\t\t</style>\n\t</head>\n\t<body>\n\t\t<h1>{}</h1>\n",
+        title, title
+    );
+    if !book.author.is_empty() {
+        buf.push_str(&format!(
+            "\t\t<p class=\"author\">{}</p>\n",
+            escape_html(&book.author)
+        ));
+    }
+
+    let mut chapter = None;
+    for annot in &book.annotations {
+        if annot.chapter.is_some() && annot.chapter != chapter {
+            chapter = annot.chapter.clone();
+            buf.push_str(&format!(
+                "\t\t<h2>{}</h2>\n",
+                escape_html(chapter.as_deref().unwrap_or_default())
+            ));
+        }
+        buf.push_str(&format!(
+            "\t\t<blockquote>{}</blockquote>\n\t\t<p class=\"page\">p. {}</p>\n",
+            escape_html(&annot.text),
+            escape_html(&annot.page)
+        ));
+        if !annot.note.is_empty() {
+            buf.push_str(&format!(
+                "\t\t<p class=\"note\">{}</p>\n",
+                escape_html(&annot.note)
+            ));
+        }
+    }
+
+    buf.push_str("\t</body>\n</html>\n");
+    buf
+}
+
+// The path of the export of the given book: the book's relative path is mirrored within the
+// export directory, so that books sharing a name don't overwrite each other's exports.
+pub fn export_path(info: &Info, format: ExportFormat, dir: &Path) -> Result<PathBuf, Error> {
+    let name = info
+        .file
+        .path
+        .file_name()
+        .ok_or_else(|| format_err!("invalid file name: {}", info.file.path.display()))?;
+    let mut path = dir.join(&info.file.path);
+    path.set_file_name(format!("{}.{}", name.to_string_lossy(), format.extension()));
+    Ok(path)
+}
+
+// Writes the annotations of the given book in the given directory and returns the path of the
+// resulting file.
+pub fn export_annotations(
+    doc: &mut dyn Document,
+    info: &Info,
+    format: ExportFormat,
+    dir: &Path,
+) -> Result<PathBuf, Error> {
+    let book = exported_book(doc, info);
+    if book.annotations.is_empty() {
+        return Err(format_err!("no annotations to export"));
+    }
+
+    let content = match format {
+        ExportFormat::Markdown => book_as_markdown(&book),
+        ExportFormat::Html => book_as_html(&book),
+        ExportFormat::Json => serde_json::to_string_pretty(&book)?,
+    };
+
+    let path = export_path(info, format, dir)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = File::create(&path)?;
+    file.write_all(content.as_bytes())?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn book() -> ExportedBook {
+        let modified =
+            NaiveDateTime::parse_from_str("2024-03-01 10:00:00", datetime_format::FORMAT).unwrap();
+        ExportedBook {
+            version: SCHEMA_VERSION,
+            title: "Walden".to_string(),
+            author: "Henry David Thoreau".to_string(),
+            file: PathBuf::from("Thoreau/Walden.epub"),
+            annotations: vec![
+                ExportedAnnotation {
+                    chapter: Some("Economy".to_string()),
+                    page: "4.2".to_string(),
+                    text: "The mass of men lead lives of quiet desperation.".to_string(),
+                    note: String::new(),
+                    modified,
+                },
+                ExportedAnnotation {
+                    chapter: Some("Economy".to_string()),
+                    page: "7.0".to_string(),
+                    text: "Simplify, simplify.".to_string(),
+                    note: "See <Where I Lived>.".to_string(),
+                    modified,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_page_label() {
+        let mut reader = ReaderInfo::default();
+        assert_eq!(
+            page_label(TextLocation::Dynamic(4096), &reader, true),
+            "2.0"
+        );
+        assert_eq!(page_label(TextLocation::Static(4, 0), &reader, false), "5");
+        reader.page_names = BTreeMap::from([(2, "i".to_string()), (6, "1".to_string())]);
+        assert_eq!(page_label(TextLocation::Static(2, 0), &reader, false), "i");
+        assert_eq!(page_label(TextLocation::Static(9, 3), &reader, false), "4");
+    }
+
+    #[test]
+    fn test_export_path() {
+        let dir = Path::new("/mnt/onboard/Annotations");
+        let mut info = Info::default();
+        info.file.path = PathBuf::from("Thoreau/Walden.epub");
+        assert_eq!(
+            export_path(&info, ExportFormat::Markdown, dir).unwrap(),
+            dir.join("Thoreau/Walden.epub.md")
+        );
+        info.file.path = PathBuf::from("Walden.pdf");
+        assert_eq!(
+            export_path(&info, ExportFormat::Json, dir).unwrap(),
+            dir.join("Walden.pdf.json")
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        let markdown = book_as_markdown(&book());
+        assert!(markdown.starts_with("# Walden\n\n*Henry David Thoreau*\n\n## Economy\n\n> The"));
+        assert_eq!(markdown.matches("## Economy").count(), 1);
+        assert!(
+            markdown.ends_with("> Simplify, simplify.\n>\n> — p. 7.0\n\nSee <Where I Lived>.\n")
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        let html = book_as_html(&book());
+        assert!(html.contains("<h2>Economy</h2>"));
+        assert!(html.contains("<p class=\"note\">See &lt;Where I Lived&gt;.</p>"));
+    }
+
+    #[test]
+    fn test_json() {
+        let json = serde_json::to_value(book()).unwrap();
+        assert_eq!(json["version"], SCHEMA_VERSION);
+        assert_eq!(json["annotations"][1]["page"], "7.0");
+        assert_eq!(json["annotations"][1]["modified"], "2024-03-01 10:00:00");
+    }
+}
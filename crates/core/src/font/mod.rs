@@ -1805,18 +1805,32 @@ impl Font {
         render_plan: &mut RenderPlan,
         missing_glyphs: Vec<(usize, usize)>,
         buf: *mut HbBuffer,
+        direction: HbDirection,
     ) {
         let mut drift = 0;
         for (mut start, mut end) in missing_glyphs.into_iter() {
             start = (start as i32 + drift).max(0) as usize;
             end = (end as i32 + drift).max(0) as usize;
             hb_buffer_clear_contents(buf);
-            let start_index = render_plan.glyphs[start].cluster;
-            let end_index = render_plan
-                .glyphs
-                .get(end)
-                .map(|g| g.cluster)
-                .unwrap_or_else(|| txt.len());
+            // In RTL plans, the glyphs are in visual order and the clusters decrease.
+            let (start_index, end_index) = if direction == HB_DIRECTION_RTL {
+                (
+                    render_plan.glyphs[end - 1].cluster,
+                    start
+                        .checked_sub(1)
+                        .map(|i| render_plan.glyphs[i].cluster)
+                        .unwrap_or_else(|| txt.len()),
+                )
+            } else {
+                (
+                    render_plan.glyphs[start].cluster,
+                    render_plan
+                        .glyphs
+                        .get(end)
+                        .map(|g| g.cluster)
+                        .unwrap_or_else(|| txt.len()),
+                )
+            };
             let chunk = &txt[start_index..end_index];
             hb_buffer_add_utf8(
                 buf,
@@ -1825,6 +1839,9 @@ impl Font {
                 0,
                 -1,
             );
+            if direction == HB_DIRECTION_TTB {
+                hb_buffer_set_direction(buf, direction);
+            }
             hb_buffer_guess_segment_properties(buf);
             let mut script = hb_buffer_get_script(buf);
             if script == HB_SCRIPT_INVALID || script == HB_SCRIPT_UNKNOWN {
@@ -1852,11 +1869,11 @@ impl Font {
             for i in 0..len {
                 let pos_i = &*pos.add(i);
                 let info_i = &*info.add(i);
-                render_plan.width += pos_i.x_advance >> 6;
+                render_plan.width += (pos_i.x_advance - pos_i.y_advance) >> 6;
                 glyphs.push(GlyphPlan {
                     codepoint: info_i.codepoint,
                     cluster: start_index + info_i.cluster as usize,
-                    advance: pt!(pos_i.x_advance >> 6, pos_i.y_advance >> 6),
+                    advance: pt!(pos_i.x_advance >> 6, -pos_i.y_advance >> 6),
                     offset: pt!(pos_i.x_offset >> 6, -pos_i.y_offset >> 6),
                 });
                 render_plan.scripts.insert(start + i, script);
@@ -1875,6 +1892,36 @@ impl Font {
         text: S,
         max_width: Option<i32>,
         features: Option<&[String]>,
+    ) -> RenderPlan {
+        self.shape(text, max_width, features, HB_DIRECTION_LTR)
+    }
+
+    // The glyphs of the resulting plan are given in visual order.
+    pub fn plan_rtl<S: AsRef<str>>(
+        &mut self,
+        text: S,
+        max_width: Option<i32>,
+        features: Option<&[String]>,
+    ) -> RenderPlan {
+        self.shape(text, max_width, features, HB_DIRECTION_RTL)
+    }
+
+    // The width of the resulting plan is its vertical advance.
+    pub fn plan_vertical<S: AsRef<str>>(
+        &mut self,
+        text: S,
+        max_width: Option<i32>,
+        features: Option<&[String]>,
+    ) -> RenderPlan {
+        self.shape(text, max_width, features, HB_DIRECTION_TTB)
+    }
+
+    fn shape<S: AsRef<str>>(
+        &mut self,
+        text: S,
+        max_width: Option<i32>,
+        features: Option<&[String]>,
+        direction: HbDirection,
     ) -> RenderPlan {
         unsafe {
             let buf = hb_buffer_create();
@@ -1887,7 +1934,7 @@ impl Font {
             );
 
             // If the direction is RTL, the clusters are given in reverse order.
-            hb_buffer_set_direction(buf, HB_DIRECTION_LTR);
+            hb_buffer_set_direction(buf, direction);
             hb_buffer_guess_segment_properties(buf);
 
             let features_vec: Vec<HbFeature> = features
@@ -1938,12 +1985,13 @@ impl Font {
                         missing_glyphs.push((i, i + 1));
                     }
                 } else {
-                    render_plan.width += pos_i.x_advance >> 6;
+                    // Vertical advances point upward.
+                    render_plan.width += (pos_i.x_advance - pos_i.y_advance) >> 6;
                 }
                 let glyph = GlyphPlan {
                     codepoint: info_i.codepoint,
                     cluster: info_i.cluster as usize,
-                    advance: pt!(pos_i.x_advance >> 6, pos_i.y_advance >> 6),
+                    advance: pt!(pos_i.x_advance >> 6, -pos_i.y_advance >> 6),
                     offset: pt!(pos_i.x_offset >> 6, -pos_i.y_offset >> 6),
                 };
                 render_plan.glyphs.push(glyph);
@@ -1955,6 +2003,7 @@ impl Font {
                 &mut render_plan,
                 missing_glyphs,
                 buf,
+                direction,
             );
 
             hb_buffer_destroy(buf);
@@ -2126,6 +2175,28 @@ impl Font {
         color: Color,
         render_plan: &RenderPlan,
         origin: Point,
+    ) {
+        self.draw(fb, color, render_plan, origin, false);
+    }
+
+    // Renders the plan rotated by a quarter turn clockwise around its origin.
+    pub fn render_sideways(
+        &mut self,
+        fb: &mut dyn Framebuffer,
+        color: Color,
+        render_plan: &RenderPlan,
+        origin: Point,
+    ) {
+        self.draw(fb, color, render_plan, origin, true);
+    }
+
+    fn draw(
+        &mut self,
+        fb: &mut dyn Framebuffer,
+        color: Color,
+        render_plan: &RenderPlan,
+        origin: Point,
+        sideways: bool,
     ) {
         unsafe {
             let mut pos = origin;
@@ -2165,7 +2236,10 @@ impl Font {
                     for x in 0..bitmap.width {
                         let blackness = *bitmap.buffer.offset((bitmap.pitch * y + x) as isize);
                         let alpha = blackness as f32 / 255.0;
-                        let pt = top_left + pt!(x, y);
+                        let mut pt = top_left + pt!(x, y);
+                        if sideways {
+                            pt = origin + pt!(origin.y - pt.y, pt.x - origin.x);
+                        }
                         fb.set_blended_pixel(pt.x as u32, pt.y as u32, color, alpha);
                     }
                 }
@@ -2257,7 +2331,11 @@ impl RenderPlan {
         if let Some((_, start)) = self.glyphs.split_last_mut() {
             let len = start.len() as i32;
             for glyph in start {
-                glyph.advance.x += letter_spacing;
+                if glyph.advance.y != 0 {
+                    glyph.advance.y += letter_spacing;
+                } else {
+                    glyph.advance.x += letter_spacing;
+                }
             }
             self.width += len * letter_spacing;
         }
@@ -2307,13 +2385,19 @@ impl RenderPlan {
         self.width += other.width;
     }
 
+    // One of the coordinates of each advance is null: the plans are either horizontal or vertical.
     pub fn total_advance(&self, index: usize) -> i32 {
-        self.glyphs.iter().take(index).map(|g| g.advance.x).sum()
+        self.glyphs
+            .iter()
+            .take(index)
+            .map(|g| g.advance.x + g.advance.y)
+            .sum()
     }
 
     #[inline]
     pub fn glyph_advance(&self, index: usize) -> i32 {
-        self.glyphs[index].advance.x
+        let advance = self.glyphs[index].advance;
+        advance.x + advance.y
     }
 }
 
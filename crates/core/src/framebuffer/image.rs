@@ -65,6 +65,41 @@ impl Pixmap {
         Ok(pixmap)
     }
 
+    // Encodes the pixmap as a PNG image.
+    pub fn to_png(&self) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+        let mut encoder = png::Encoder::new(&mut buf, self.width, self.height);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_color(if self.samples == 3 {
+            png::ColorType::Rgb
+        } else {
+            png::ColorType::Grayscale
+        });
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.data)?;
+        writer.finish()?;
+        Ok(buf)
+    }
+
+    // Returns the part of the pixmap within the given rectangle.
+    pub fn crop(&self, rect: &Rectangle) -> Option<Pixmap> {
+        let rect = rect.intersection(&rect![0, 0, self.width as i32, self.height as i32])?;
+        let (x0, x1) = (rect.min.x as usize, rect.max.x as usize);
+        let mut pixmap = Pixmap::empty(rect.width(), rect.height(), self.samples);
+        let row_len = self.samples * self.width as usize;
+        for row in self
+            .data
+            .chunks(row_len)
+            .skip(rect.min.y as usize)
+            .take(rect.height() as usize)
+        {
+            pixmap
+                .data
+                .extend_from_slice(&row[self.samples * x0..self.samples * x1]);
+        }
+        Some(pixmap)
+    }
+
     #[inline]
     pub fn get_pixel(&self, x: u32, y: u32) -> Color {
         if self.data.is_empty() {
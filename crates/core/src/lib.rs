@@ -7,6 +7,7 @@ pub mod context;
 pub mod device;
 mod dictionary;
 pub mod document;
+pub mod export;
 pub mod font;
 pub mod framebuffer;
 pub mod frontlight;
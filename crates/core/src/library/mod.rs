@@ -1,24 +1,34 @@
+mod sqlite;
+pub mod thumbnails;
+
+use self::sqlite::Store;
+use self::thumbnails::{generate_thumbnail, rank_by_recency, rank_thumbnails};
 use crate::document::file_kind;
 use crate::helpers::{load_json, save_json, Fingerprint, Fp, IsHidden};
-use crate::metadata::{extract_metadata_from_document, sort, sorter};
+use crate::metadata::{extract_metadata_from_document, sort, sort_series, sorter};
 use crate::metadata::{BookQuery, FileInfo, Info, ReaderInfo, SimpleStatus, SortMethod};
 use crate::settings::{ImportSettings, LibraryMode};
 use anyhow::{bail, format_err, Error};
 use chrono::{DateTime, Local};
 use fxhash::{FxBuildHasher, FxHashMap, FxHashSet};
 use indexmap::IndexMap;
+use std::cmp::Ordering;
 use std::collections::BTreeSet;
 use std::fs::{self, File};
 use std::io::{Error as IoError, ErrorKind};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, SystemTime};
 use walkdir::WalkDir;
 
 pub const METADATA_FILENAME: &str = ".metadata.json";
+pub const SQLITE_FILENAME: &str = ".metadata.sqlite";
 pub const FAT32_EPOCH_FILENAME: &str = ".fat32-epoch";
 pub const READING_STATES_DIRNAME: &str = ".reading-states";
 pub const THUMBNAIL_PREVIEWS_DIRNAME: &str = ".thumbnail-previews";
+pub const REFLOW_CACHE_DIRNAME: &str = ".reflow-cache";
+pub const PASSWORDS_FILENAME: &str = ".passwords.json";
 
 pub struct Library {
     pub home: PathBuf,
@@ -26,12 +36,19 @@ pub struct Library {
     pub db: IndexMap<Fp, Info, FxBuildHasher>,
     pub paths: FxHashMap<PathBuf, Fp>,
     pub reading_states: FxHashMap<Fp, ReaderInfo>,
+    // The remembered passwords, kept apart from the reading states, which are exported
+    // and synchronized.
+    passwords: FxHashMap<Fp, String>,
+    has_passwords_changed: bool,
     pub modified_reading_states: FxHashSet<Fp>,
+    pub modified_infos: FxHashSet<Fp>,
     pub has_db_changed: bool,
     pub fat32_epoch: SystemTime,
     pub sort_method: SortMethod,
     pub reverse_order: bool,
     pub show_hidden: bool,
+    store: Option<Store>,
+    thumbnail_worker: Option<JoinHandle<()>>,
 }
 
 impl Library {
@@ -42,9 +59,18 @@ impl Library {
             }
         }
 
+        let mut store = None;
+        // A new SQLite database is initialized from the JSON layout.
+        let mut from_json = mode == LibraryMode::Database;
+        if mode == LibraryMode::Sqlite {
+            let s = Store::open(home.as_ref().join(SQLITE_FILENAME))?;
+            from_json = !s.is_migrated()?;
+            store = Some(s);
+        }
+
         let path = home.as_ref().join(METADATA_FILENAME);
         let mut db;
-        if mode == LibraryMode::Database {
+        if from_json {
             match load_json::<IndexMap<Fp, Info, FxBuildHasher>, _>(&path) {
                 Err(e) => {
                     if e.downcast_ref::<IoError>().map(|e| e.kind()) != Some(ErrorKind::NotFound) {
@@ -55,6 +81,8 @@ impl Library {
                 }
                 Ok(v) => db = v,
             }
+        } else if let Some(store) = store.as_ref() {
+            db = store.load()?;
         } else {
             db = IndexMap::with_capacity_and_hasher(0, FxBuildHasher::default());
         }
@@ -68,36 +96,49 @@ impl Library {
             }
         }
 
-        for entry in fs::read_dir(&path)? {
-            let entry = entry?;
-            let path = entry.path();
-            if let Some(fp) = path
-                .file_stem()
-                .and_then(|v| v.to_str())
-                .and_then(|v| Fp::from_str(v).ok())
-            {
-                if let Ok(reader_info) =
-                    load_json(path).map_err(|e| eprintln!("Can't load reading state: {:#}.", e))
+        if from_json || mode == LibraryMode::Filesystem {
+            for entry in fs::read_dir(&path)? {
+                let entry = entry?;
+                let path = entry.path();
+                if let Some(fp) = path
+                    .file_stem()
+                    .and_then(|v| v.to_str())
+                    .and_then(|v| Fp::from_str(v).ok())
                 {
-                    if mode == LibraryMode::Database {
-                        if let Some(info) = db.get_mut(&fp) {
-                            info.reader = Some(reader_info);
+                    if let Ok(reader_info) =
+                        load_json(path).map_err(|e| eprintln!("Can't load reading state: {:#}.", e))
+                    {
+                        if mode != LibraryMode::Filesystem {
+                            if let Some(info) = db.get_mut(&fp) {
+                                info.reader = Some(reader_info);
+                            } else {
+                                eprintln!("Unknown fingerprint: {}.", fp);
+                            }
                         } else {
-                            eprintln!("Unknown fingerprint: {}.", fp);
+                            reading_states.insert(fp, reader_info);
                         }
-                    } else {
-                        reading_states.insert(fp, reader_info);
                     }
                 }
             }
         }
 
+        if let Some(store) = store.as_mut() {
+            if from_json {
+                if !db.is_empty() {
+                    let fps = db.keys().cloned().collect();
+                    store.update(&db, &fps, &fps)?;
+                    println!("Migrated {} entries to {}.", db.len(), SQLITE_FILENAME);
+                }
+                store.set_migrated()?;
+            }
+        }
+
         let path = home.as_ref().join(THUMBNAIL_PREVIEWS_DIRNAME);
         if !path.exists() {
             fs::create_dir(&path).ok();
         }
 
-        let paths = if mode == LibraryMode::Database {
+        let paths = if mode != LibraryMode::Filesystem {
             db.iter()
                 .map(|(fp, info)| (info.file.path.clone(), *fp))
                 .collect()
@@ -113,6 +154,14 @@ impl Library {
 
         let fat32_epoch = path.metadata()?.modified()?;
 
+        let passwords = load_json(home.as_ref().join(PASSWORDS_FILENAME))
+            .map_err(|e| {
+                if e.downcast_ref::<IoError>().map(|e| e.kind()) != Some(ErrorKind::NotFound) {
+                    eprintln!("Can't load passwords: {:#}.", e);
+                }
+            })
+            .unwrap_or_default();
+
         let sort_method = SortMethod::Opened;
 
         Ok(Library {
@@ -121,12 +170,17 @@ impl Library {
             db,
             paths,
             reading_states,
+            passwords,
+            has_passwords_changed: false,
             modified_reading_states: FxHashSet::default(),
+            modified_infos: FxHashSet::default(),
             has_db_changed: false,
             fat32_epoch,
             sort_method,
             reverse_order: sort_method.reverse_order(),
             show_hidden: false,
+            store,
+            thumbnail_worker: None,
         })
     }
 
@@ -140,7 +194,7 @@ impl Library {
         let mut files = Vec::new();
 
         match self.mode {
-            LibraryMode::Database => {
+            LibraryMode::Database | LibraryMode::Sqlite => {
                 let relat_prefix = prefix
                     .as_ref()
                     .strip_prefix(&self.home)
@@ -264,7 +318,7 @@ impl Library {
                     self.paths.remove(&self.db[&fp].file.path);
                     self.paths.insert(relat.to_path_buf(), fp);
                     self.db[&fp].file.path = relat.to_path_buf();
-                    self.has_db_changed = true;
+                    self.touch(fp);
                 }
             // The path is known: update the fp.
             } else if let Some(fp2) = self.paths.get(relat).cloned() {
@@ -288,7 +342,9 @@ impl Library {
                 if tpp.exists() {
                     fs::remove_file(tpp).ok();
                 }
-                self.has_db_changed = true;
+                self.modified_reading_states.insert(fp);
+                self.touch(fp2);
+                self.touch(fp);
             } else {
                 let fp1 = self
                     .fat32_epoch
@@ -328,6 +384,8 @@ impl Library {
                     let tp1 = self.thumbnail_preview_path(nfp);
                     let tp2 = self.thumbnail_preview_path(fp);
                     fs::rename(tp1, tp2).ok();
+                    self.modified_reading_states.insert(fp);
+                    self.touch(nfp);
                     if relat != self.db[&fp].file.path {
                         println!(
                             "Update path for {}: {} → {}.",
@@ -339,6 +397,7 @@ impl Library {
                         self.paths.insert(relat.to_path_buf(), fp);
                         self.db[&fp].file.path = relat.to_path_buf();
                     }
+                    self.touch(fp);
                 // We found a new file: add it to the db.
                 } else {
                     let kind = file_kind(&path).unwrap_or_default();
@@ -361,14 +420,13 @@ impl Library {
                     }
                     self.db.insert(fp, info);
                     self.paths.insert(relat.to_path_buf(), fp);
+                    self.touch(fp);
                 }
-
-                self.has_db_changed = true;
             }
         }
 
         let home = &self.home;
-        let len = self.db.len();
+        let mut removed = Vec::new();
 
         self.db.retain(|fp, info| {
             let path = home.join(&info.file.path);
@@ -376,12 +434,14 @@ impl Library {
                 true
             } else {
                 println!("Remove entry: {}, {}.", fp, info.file.path.display());
+                removed.push(*fp);
                 false
             }
         });
 
-        if self.db.len() != len {
+        if !removed.is_empty() {
             self.has_db_changed = true;
+            self.modified_infos.extend(removed);
             let db = &self.db;
             self.paths.retain(|_, fp| db.contains_key(fp));
             self.modified_reading_states
@@ -408,6 +468,97 @@ impl Library {
                     }
                 }
             }
+
+            let db = &self.db;
+            remove_orphan_reflow_caches(home, |fp| db.contains_key(fp));
+        }
+
+        self.evict_thumbnails(settings);
+    }
+
+    // Evicts the thumbnail previews beyond the cache limit. In the filesystem mode, the
+    // thumbnails of the books that were never opened are ranked by creation date.
+    fn evict_thumbnails(&mut self, settings: &ImportSettings) {
+        let (_, evicted) = match self.mode {
+            LibraryMode::Database | LibraryMode::Sqlite => {
+                rank_thumbnails(&self.db, settings.max_thumbnails)
+            }
+            LibraryMode::Filesystem => {
+                let entries = fs::read_dir(self.home.join(THUMBNAIL_PREVIEWS_DIRNAME))
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|entry| {
+                        let entry = entry.ok()?;
+                        let fp = entry
+                            .path()
+                            .file_stem()
+                            .and_then(|v| v.to_str())
+                            .and_then(|v| Fp::from_str(v).ok())?;
+                        let used = match self.reading_states.get(&fp) {
+                            Some(reader) => reader.opened,
+                            None => {
+                                DateTime::<Local>::from(entry.metadata().ok()?.modified().ok()?)
+                                    .naive_local()
+                            }
+                        };
+                        Some((fp, used))
+                    })
+                    .collect();
+                rank_by_recency(entries, settings.max_thumbnails)
+            }
+        };
+
+        for fp in evicted {
+            let tpp = self.thumbnail_preview_path(fp);
+            if tpp.exists() {
+                fs::remove_file(tpp).ok();
+            }
+        }
+    }
+
+    // Renders, in the background, the missing thumbnail previews, of the given size, of the
+    // entries kept in the cache, if asked to. Nothing is started while the previous
+    // rendering is still running: its remaining previews are rendered when first displayed.
+    pub fn pregenerate_thumbnails(&mut self, settings: &ImportSettings, width: f32, height: f32) {
+        if !settings.pregenerate_thumbnails {
+            return;
+        }
+
+        if let Some(worker) = self.thumbnail_worker.take() {
+            if !worker.is_finished() {
+                self.thumbnail_worker = Some(worker);
+                return;
+            }
+            worker.join().ok();
+        }
+
+        let (kept, _) = rank_thumbnails(&self.db, settings.max_thumbnails);
+        let jobs: Vec<(PathBuf, PathBuf)> = kept
+            .into_iter()
+            .map(|fp| {
+                (
+                    self.home.join(&self.db[&fp].file.path),
+                    self.thumbnail_preview_path(fp),
+                )
+            })
+            .filter(|(_, tpp)| !tpp.exists())
+            .collect();
+
+        if jobs.is_empty() {
+            return;
+        }
+
+        self.thumbnail_worker = Some(thread::spawn(move || {
+            for (path, tpp) in jobs {
+                generate_thumbnail(&path, &tpp, width, height);
+            }
+        }));
+    }
+
+    // Waits for the thumbnail previews rendered after the last import.
+    pub fn wait_for_thumbnails(&mut self) {
+        if let Some(worker) = self.thumbnail_worker.take() {
+            worker.join().ok();
         }
     }
 
@@ -420,10 +571,10 @@ impl Library {
             self.modified_reading_states.insert(fp);
         }
 
-        if self.mode == LibraryMode::Database {
+        if self.mode != LibraryMode::Filesystem {
             self.paths.insert(info.file.path.clone(), fp);
             self.db.insert(fp, info);
-            self.has_db_changed = true;
+            self.touch(fp);
         } else {
             if let Some(reader_info) = info.reader {
                 self.reading_states.insert(fp, reader_info);
@@ -448,12 +599,12 @@ impl Library {
         dest.set_file_name(file_name);
         fs::rename(&src, &dest)?;
 
-        if self.mode == LibraryMode::Database {
+        if self.mode != LibraryMode::Filesystem {
             let new_path = dest.strip_prefix(&self.home)?;
             self.paths.insert(new_path.to_path_buf(), fp);
             if let Some(info) = self.db.get_mut(&fp) {
                 info.file.path = new_path.to_path_buf();
-                self.has_db_changed = true;
+                self.touch(fp);
             }
         }
 
@@ -495,16 +646,22 @@ impl Library {
             fs::remove_file(tpp)?;
         }
 
-        if self.mode == LibraryMode::Database {
+        let rcp = self.reflow_cache_path(fp);
+        if rcp.exists() {
+            fs::remove_dir_all(rcp)?;
+        }
+
+        if self.mode != LibraryMode::Filesystem {
             self.paths.remove(path.as_ref());
             if self.db.shift_remove(&fp).is_some() {
-                self.has_db_changed = true;
+                self.touch(fp);
             }
         } else {
             self.reading_states.remove(&fp);
         }
 
         self.modified_reading_states.remove(&fp);
+        self.has_passwords_changed |= self.passwords.remove(&fp).is_some();
 
         Ok(())
     }
@@ -560,7 +717,7 @@ impl Library {
             fs::copy(&tpp_src, &tpp_dest)?;
         }
 
-        if other.mode == LibraryMode::Database {
+        if other.mode != LibraryMode::Filesystem {
             let info = self.db.get(&fp).cloned().or_else(|| {
                 self.reading_states
                     .get(&fp)
@@ -580,7 +737,7 @@ impl Library {
                 info.file.path = dest_path.to_path_buf();
                 other.db.insert(fp, info);
                 other.paths.insert(dest_path.to_path_buf(), fp);
-                other.has_db_changed = true;
+                other.touch(fp);
             }
         } else {
             let reader_info = self
@@ -646,7 +803,7 @@ impl Library {
             fs::rename(&tpp_src, &tpp_dest)?;
         }
 
-        if other.mode == LibraryMode::Database {
+        if other.mode != LibraryMode::Filesystem {
             let info = self.db.shift_remove(&fp).or_else(|| {
                 self.reading_states.remove(&fp).map(|reader_info| Info {
                     file: FileInfo {
@@ -664,8 +821,8 @@ impl Library {
                 other.db.insert(fp, info);
                 self.paths.remove(path.as_ref());
                 other.paths.insert(dest_path.to_path_buf(), fp);
-                self.has_db_changed = true;
-                other.has_db_changed = true;
+                self.touch(fp);
+                other.touch(fp);
             }
         } else {
             let reader_info = self
@@ -684,8 +841,8 @@ impl Library {
         Ok(())
     }
 
-    pub fn clean_up(&mut self) {
-        if self.mode == LibraryMode::Database {
+    pub fn clean_up(&mut self, settings: &ImportSettings) {
+        if self.mode != LibraryMode::Filesystem {
             return;
         }
 
@@ -739,6 +896,9 @@ impl Library {
                 }
             }
         }
+
+        remove_orphan_reflow_caches(&self.home, |fp| fps.contains(fp));
+        self.evict_thumbnails(settings);
     }
 
     pub fn sort(&mut self, sort_method: SortMethod, reverse_order: bool) {
@@ -770,9 +930,86 @@ impl Library {
             f(&self.home, info);
         }
 
+        self.modified_infos.extend(self.db.keys().cloned());
         self.has_db_changed = true;
     }
 
+    // Returns the entries that have a reading state, without modifying the library.
+    pub fn read_entries(&self) -> Vec<Info> {
+        if self.mode != LibraryMode::Filesystem {
+            return self
+                .db
+                .values()
+                .filter(|info| info.reader.is_some())
+                .cloned()
+                .collect();
+        }
+
+        WalkDir::new(&self.home)
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|e| !e.is_hidden())
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| !entry.file_type().is_dir())
+            .filter_map(|entry| {
+                let md = entry.metadata().ok()?;
+                let fp = md.fingerprint(self.fat32_epoch).ok()?;
+                let reader = self.reading_states.get(&fp)?;
+                let path = entry.path();
+                let secs = (*fp >> 32) as i64;
+                let nsecs = ((*fp & ((1 << 32) - 1)) % 1_000_000_000) as u32;
+                let added = DateTime::from_timestamp(secs, nsecs)?.naive_utc();
+                Some(Info {
+                    file: FileInfo {
+                        path: path.strip_prefix(&self.home).unwrap_or(path).to_path_buf(),
+                        kind: file_kind(path).unwrap_or_default(),
+                        size: md.len(),
+                    },
+                    added,
+                    reader: Some(reader.clone()),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    // Returns the volume that follows the given book within its series.
+    pub fn next_in_series(&self, info: &Info) -> Option<Info> {
+        if self.mode == LibraryMode::Filesystem || info.series.is_empty() {
+            return None;
+        }
+
+        self.db
+            .values()
+            .filter(|other| {
+                other.series == info.series && sort_series(other, info) == Ordering::Greater
+            })
+            .min_by(|a, b| sort_series(a, b))
+            .cloned()
+    }
+
+    // The categories are only stored by the database modes.
+    pub fn edit_categories<P, F>(&mut self, path: P, f: F) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&mut BTreeSet<String>),
+    {
+        if self.mode == LibraryMode::Filesystem {
+            bail!("categories aren't stored in filesystem mode");
+        }
+
+        let fp =
+            self.paths.get(path.as_ref()).cloned().ok_or_else(|| {
+                format_err!("can't get fingerprint of {}", path.as_ref().display())
+            })?;
+        if let Some(info) = self.db.get_mut(&fp) {
+            f(&mut info.categories);
+            self.touch(fp);
+        }
+
+        Ok(())
+    }
+
     pub fn sync_reader_info<P: AsRef<Path>>(&mut self, path: P, reader: &ReaderInfo) {
         let fp = self.paths.get(path.as_ref()).cloned().unwrap_or_else(|| {
             self.home
@@ -784,7 +1021,7 @@ impl Library {
         });
         self.modified_reading_states.insert(fp);
         match self.mode {
-            LibraryMode::Database => {
+            LibraryMode::Database | LibraryMode::Sqlite => {
                 if let Some(info) = self.db.get_mut(&fp) {
                     info.reader = Some(reader.clone());
                 }
@@ -795,6 +1032,79 @@ impl Library {
         }
     }
 
+    // The fingerprint of the given document.
+    pub fn fingerprint<P: AsRef<Path>>(&self, path: P) -> Option<Fp> {
+        self.paths.get(path.as_ref()).cloned().or_else(|| {
+            self.home
+                .join(path.as_ref())
+                .metadata()
+                .ok()?
+                .fingerprint(self.fat32_epoch)
+                .ok()
+        })
+    }
+
+    // Moves the data of the given document from its previous fingerprint to its current one,
+    // after its content was modified.
+    pub fn update_fingerprint<P: AsRef<Path>>(&mut self, path: P, fp: Fp) {
+        let md = match self.home.join(path.as_ref()).metadata() {
+            Ok(md) => md,
+            Err(e) => {
+                eprintln!(
+                    "Can't get metadata of {}: {:#}.",
+                    path.as_ref().display(),
+                    e
+                );
+                return;
+            }
+        };
+        let nfp = match md.fingerprint(self.fat32_epoch) {
+            Ok(nfp) if nfp != fp => nfp,
+            _ => return,
+        };
+
+        println!(
+            "Update fingerprint for {}: {} → {}.",
+            path.as_ref().display(),
+            fp,
+            nfp
+        );
+
+        match self.mode {
+            LibraryMode::Database | LibraryMode::Sqlite => {
+                if let Some(mut info) = self.db.swap_remove(&fp) {
+                    info.file.size = md.len();
+                    self.db.insert(nfp, info);
+                    self.paths.insert(path.as_ref().to_path_buf(), nfp);
+                    self.modified_reading_states.insert(nfp);
+                    self.touch(fp);
+                    self.touch(nfp);
+                }
+            }
+            LibraryMode::Filesystem => {
+                if let Some(reader_info) = self.reading_states.remove(&fp) {
+                    self.reading_states.insert(nfp, reader_info);
+                    self.modified_reading_states.insert(nfp);
+                }
+            }
+        }
+
+        self.modified_reading_states.remove(&fp);
+
+        if let Some(password) = self.passwords.remove(&fp) {
+            self.passwords.insert(nfp, password);
+            self.has_passwords_changed = true;
+        }
+
+        fs::rename(self.reading_state_path(fp), self.reading_state_path(nfp)).ok();
+        fs::rename(
+            self.thumbnail_preview_path(fp),
+            self.thumbnail_preview_path(nfp),
+        )
+        .ok();
+        fs::rename(self.reflow_cache_path(fp), self.reflow_cache_path(nfp)).ok();
+    }
+
     pub fn thumbnail_preview<P: AsRef<Path>>(&self, path: P) -> PathBuf {
         if path.as_ref().starts_with(THUMBNAIL_PREVIEWS_DIRNAME) {
             self.home.join(path.as_ref())
@@ -811,6 +1121,39 @@ impl Library {
         }
     }
 
+    // The remembered password of the given document.
+    pub fn password<P: AsRef<Path>>(&self, path: P) -> Option<String> {
+        let fp = self.fingerprint(path)?;
+        self.passwords.get(&fp).cloned()
+    }
+
+    // Remembers, or forgets, the password of the given document.
+    pub fn set_password<P: AsRef<Path>>(&mut self, path: P, password: Option<&str>) {
+        let fp = match self.fingerprint(path) {
+            Some(fp) => fp,
+            None => return,
+        };
+        let changed = if let Some(password) = password {
+            self.passwords.insert(fp, password.to_string()).as_deref() != Some(password)
+        } else {
+            self.passwords.remove(&fp).is_some()
+        };
+        self.has_passwords_changed |= changed;
+    }
+
+    // The directory in which the reflowed text of the given document is cached.
+    pub fn reflow_cache<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf> {
+        let fp = self.paths.get(path.as_ref()).cloned().or_else(|| {
+            self.home
+                .join(path.as_ref())
+                .metadata()
+                .ok()?
+                .fingerprint(self.fat32_epoch)
+                .ok()
+        })?;
+        Some(self.reflow_cache_path(fp))
+    }
+
     pub fn set_status<P: AsRef<Path>>(&mut self, path: P, status: SimpleStatus) {
         let fp = self.paths.get(path.as_ref()).cloned().unwrap_or_else(|| {
             self.home
@@ -820,14 +1163,15 @@ impl Library {
                 .fingerprint(self.fat32_epoch)
                 .unwrap()
         });
-        if self.mode == LibraryMode::Database {
+        if self.mode != LibraryMode::Filesystem {
             match status {
                 SimpleStatus::New => {
                     if let Some(info) = self.db.get_mut(&fp) {
                         info.reader = None;
                     }
                     fs::remove_file(self.reading_state_path(fp)).ok();
-                    self.modified_reading_states.remove(&fp);
+                    // Lets the SQLite database drop the reading state.
+                    self.modified_reading_states.insert(fp);
                 }
                 SimpleStatus::Reading | SimpleStatus::Finished => {
                     if let Some(info) = self.db.get_mut(&fp) {
@@ -857,7 +1201,18 @@ impl Library {
     }
 
     pub fn reload(&mut self) {
-        if self.mode == LibraryMode::Database {
+        if let Some(store) = self.store.as_ref() {
+            match store.load() {
+                Err(e) => {
+                    eprintln!("Can't reload database: {:#}.", e);
+                    return;
+                }
+                Ok(v) => {
+                    self.db = v;
+                    self.has_db_changed = false;
+                }
+            }
+        } else if self.mode == LibraryMode::Database {
             let path = self.home.join(METADATA_FILENAME);
 
             match load_json(&path) {
@@ -875,35 +1230,38 @@ impl Library {
         let path = self.home.join(READING_STATES_DIRNAME);
 
         self.modified_reading_states.clear();
+        self.modified_infos.clear();
         if self.mode == LibraryMode::Filesystem {
             self.reading_states.clear();
         }
 
-        for entry in fs::read_dir(&path).unwrap() {
-            let entry = entry.unwrap();
-            let path = entry.path();
-            if let Some(fp) = path
-                .file_stem()
-                .and_then(|v| v.to_str())
-                .and_then(|v| Fp::from_str(v).ok())
-            {
-                if let Ok(reader_info) =
-                    load_json(path).map_err(|e| eprintln!("Can't load reading state: {:#}.", e))
+        if self.store.is_none() {
+            for entry in fs::read_dir(&path).unwrap() {
+                let entry = entry.unwrap();
+                let path = entry.path();
+                if let Some(fp) = path
+                    .file_stem()
+                    .and_then(|v| v.to_str())
+                    .and_then(|v| Fp::from_str(v).ok())
                 {
-                    if self.mode == LibraryMode::Database {
-                        if let Some(info) = self.db.get_mut(&fp) {
-                            info.reader = Some(reader_info);
+                    if let Ok(reader_info) =
+                        load_json(path).map_err(|e| eprintln!("Can't load reading state: {:#}.", e))
+                    {
+                        if self.mode == LibraryMode::Database {
+                            if let Some(info) = self.db.get_mut(&fp) {
+                                info.reader = Some(reader_info);
+                            } else {
+                                eprintln!("Unknown fingerprint: {}.", fp);
+                            }
                         } else {
-                            eprintln!("Unknown fingerprint: {}.", fp);
+                            self.reading_states.insert(fp, reader_info);
                         }
-                    } else {
-                        self.reading_states.insert(fp, reader_info);
                     }
                 }
             }
         }
 
-        if self.mode == LibraryMode::Database {
+        if self.mode != LibraryMode::Filesystem {
             self.paths = self
                 .db
                 .iter()
@@ -913,8 +1271,32 @@ impl Library {
     }
 
     pub fn flush(&mut self) {
+        if self.has_passwords_changed {
+            save_json(&self.passwords, self.home.join(PASSWORDS_FILENAME))
+                .map_err(|e| eprintln!("Can't save passwords: {:#}.", e))
+                .ok();
+            self.has_passwords_changed = false;
+        }
+
+        // Only the modified entries are written to the SQLite database.
+        if let Some(store) = self.store.as_mut() {
+            match store.update(
+                &self.db,
+                &self.modified_infos,
+                &self.modified_reading_states,
+            ) {
+                Err(e) => eprintln!("Can't update database: {:#}.", e),
+                Ok(()) => {
+                    self.modified_infos.clear();
+                    self.modified_reading_states.clear();
+                    self.has_db_changed = false;
+                }
+            }
+            return;
+        }
+
         for fp in &self.modified_reading_states {
-            let reader_info = if self.mode == LibraryMode::Database {
+            let reader_info = if self.mode != LibraryMode::Filesystem {
                 self.db.get(fp).and_then(|info| info.reader.as_ref())
             } else {
                 self.reading_states.get(fp)
@@ -927,6 +1309,7 @@ impl Library {
         }
 
         self.modified_reading_states.clear();
+        self.modified_infos.clear();
 
         if self.has_db_changed {
             save_json(&self.db, self.home.join(METADATA_FILENAME))
@@ -936,14 +1319,50 @@ impl Library {
         }
     }
 
+    // Writes the entries and their reading states in the JSON layout.
+    pub fn export_json(&self) -> Result<(), Error> {
+        if self.mode == LibraryMode::Filesystem {
+            bail!("the filesystem mode has no database");
+        }
+
+        save_json(&self.db, self.home.join(METADATA_FILENAME))?;
+
+        for (fp, info) in &self.db {
+            if let Some(reader_info) = info.reader.as_ref() {
+                save_json(reader_info, self.reading_state_path(*fp))?;
+            }
+        }
+
+        // Remove the stale reading states of an older export.
+        for entry in fs::read_dir(self.home.join(READING_STATES_DIRNAME))? {
+            let path = entry?.path();
+            if let Some(fp) = path
+                .file_stem()
+                .and_then(|v| v.to_str())
+                .and_then(|v| Fp::from_str(v).ok())
+            {
+                if self.db.get(&fp).is_none_or(|info| info.reader.is_none()) {
+                    fs::remove_file(path).ok();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn is_empty(&self) -> Option<bool> {
-        if self.mode == LibraryMode::Database {
+        if self.mode != LibraryMode::Filesystem {
             Some(self.db.is_empty())
         } else {
             None
         }
     }
 
+    fn touch(&mut self, fp: Fp) {
+        self.modified_infos.insert(fp);
+        self.has_db_changed = true;
+    }
+
     fn reading_state_path(&self, fp: Fp) -> PathBuf {
         self.home
             .join(READING_STATES_DIRNAME)
@@ -955,4 +1374,165 @@ impl Library {
             .join(THUMBNAIL_PREVIEWS_DIRNAME)
             .join(format!("{}.png", fp))
     }
+
+    fn reflow_cache_path(&self, fp: Fp) -> PathBuf {
+        self.home.join(REFLOW_CACHE_DIRNAME).join(fp.to_string())
+    }
+}
+
+// Removes the cached reflowed texts of the documents that aren't kept.
+fn remove_orphan_reflow_caches<F: Fn(&Fp) -> bool>(home: &Path, keep: F) {
+    if let Ok(entries) = fs::read_dir(home.join(REFLOW_CACHE_DIRNAME)) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            if let Some(fp) = entry
+                .file_name()
+                .to_str()
+                .and_then(|v| Fp::from_str(v).ok())
+            {
+                if !keep(&fp) {
+                    fs::remove_dir_all(entry.path()).ok();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    // Imports a document, gives it a reading state, a thumbnail and a reflow cache, then
+    // modifies it, and returns its previous fingerprint.
+    fn modified_document(library: &mut Library) -> Fp {
+        let path = Path::new("walden.txt");
+        fs::write(library.home.join(path), "Walden").unwrap();
+        library.import(&ImportSettings::default());
+        if library.mode == LibraryMode::Filesystem {
+            library.add_document(Info {
+                file: FileInfo {
+                    path: path.to_path_buf(),
+                    ..Default::default()
+                },
+                reader: Some(ReaderInfo::default()),
+                ..Default::default()
+            });
+        }
+        let fp = library.fingerprint(path).unwrap();
+        library.sync_reader_info(
+            path,
+            &ReaderInfo {
+                current_page: 7,
+                ..Default::default()
+            },
+        );
+        library.flush();
+        fs::create_dir_all(library.home.join(THUMBNAIL_PREVIEWS_DIRNAME)).unwrap();
+        fs::write(library.thumbnail_preview_path(fp), b"png").unwrap();
+        fs::create_dir_all(library.reflow_cache_path(fp)).unwrap();
+
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(library.home.join(path))
+            .unwrap();
+        file.write_all(b", or Life in the Woods").unwrap();
+        fp
+    }
+
+    fn assert_moved(library: &Library, fp: Fp, nfp: Fp) {
+        assert_ne!(fp, nfp);
+        assert!(!library.thumbnail_preview_path(fp).exists());
+        assert!(library.thumbnail_preview_path(nfp).exists());
+        assert!(!library.reflow_cache_path(fp).exists());
+        assert!(library.reflow_cache_path(nfp).exists());
+        assert!(!library.reading_state_path(fp).exists());
+    }
+
+    #[test]
+    fn update_fingerprint_database() {
+        for mode in [LibraryMode::Database, LibraryMode::Sqlite] {
+            let dir = tempfile::tempdir().unwrap();
+            let mut library = Library::new(dir.path(), mode).unwrap();
+            let path = Path::new("walden.txt");
+            let fp = modified_document(&mut library);
+
+            library.update_fingerprint(path, fp);
+            library.flush();
+            let nfp = library.fingerprint(path).unwrap();
+            assert_eq!(library.db[&nfp].file.size, 28);
+            assert_moved(&library, fp, nfp);
+
+            let library = Library::new(dir.path(), mode).unwrap();
+            assert!(!library.db.contains_key(&fp));
+            assert_eq!(library.db[&nfp].reader.as_ref().unwrap().current_page, 7);
+        }
+    }
+
+    #[test]
+    fn update_fingerprint_filesystem() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut library = Library::new(dir.path(), LibraryMode::Filesystem).unwrap();
+        let path = Path::new("walden.txt");
+        let fp = modified_document(&mut library);
+
+        library.update_fingerprint(path, fp);
+        library.flush();
+        let nfp = library.fingerprint(path).unwrap();
+        assert!(!library.reading_states.contains_key(&fp));
+        assert_eq!(library.reading_states[&nfp].current_page, 7);
+        assert_moved(&library, fp, nfp);
+        assert!(library.reading_state_path(nfp).exists());
+
+        // The reading state is found under the new fingerprint once the library is reloaded.
+        let library = Library::new(dir.path(), LibraryMode::Filesystem).unwrap();
+        assert_eq!(library.reading_states[&nfp].current_page, 7);
+    }
+
+    #[test]
+    fn evict_thumbnails_filesystem() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut library = Library::new(dir.path(), LibraryMode::Filesystem).unwrap();
+        let (walden, civil) = (Path::new("walden.txt"), Path::new("civil.txt"));
+        fs::write(library.home.join(walden), "Walden").unwrap();
+        fs::write(library.home.join(civil), "Civil Disobedience").unwrap();
+        library.sync_reader_info(walden, &ReaderInfo::default());
+        let walden_fp = library.fingerprint(walden).unwrap();
+        let civil_fp = library.fingerprint(civil).unwrap();
+        fs::create_dir_all(library.home.join(THUMBNAIL_PREVIEWS_DIRNAME)).unwrap();
+        fs::write(library.thumbnail_preview_path(walden_fp), b"png").unwrap();
+        fs::write(library.thumbnail_preview_path(civil_fp), b"png").unwrap();
+        // The thumbnail of the book that was never opened is ranked by its creation date.
+        File::options()
+            .write(true)
+            .open(library.thumbnail_preview_path(civil_fp))
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH)
+            .unwrap();
+
+        library.clean_up(&ImportSettings {
+            max_thumbnails: 1,
+            ..Default::default()
+        });
+        assert!(library.thumbnail_preview_path(walden_fp).exists());
+        assert!(!library.thumbnail_preview_path(civil_fp).exists());
+    }
+
+    #[test]
+    fn passwords() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut library = Library::new(dir.path(), LibraryMode::Database).unwrap();
+        let path = Path::new("walden.txt");
+        let fp = modified_document(&mut library);
+        library.set_password(path, Some("thoreau"));
+        library.update_fingerprint(path, fp);
+        library.flush();
+
+        let mut library = Library::new(dir.path(), LibraryMode::Database).unwrap();
+        assert_eq!(library.password(path).as_deref(), Some("thoreau"));
+        library.set_password(path, None);
+        library.flush();
+
+        let library = Library::new(dir.path(), LibraryMode::Database).unwrap();
+        assert_eq!(library.password(path), None);
+    }
 }
@@ -0,0 +1,229 @@
+use crate::helpers::Fp;
+use crate::metadata::Info;
+use anyhow::{Context, Error};
+use fxhash::{FxBuildHasher, FxHashSet};
+use indexmap::IndexMap;
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::str::FromStr;
+
+// The entries and the reading states are stored as JSON, which keeps the conversions
+// from and to the JSON layout lossless. The books are filtered and sorted in memory,
+// hence the absence of indexes.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS books (
+        fp TEXT PRIMARY KEY,
+        info TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS reading_states (
+        fp TEXT PRIMARY KEY,
+        state TEXT NOT NULL
+    );
+";
+
+// An embedded SQLite database holding the entries of a library and their reading states.
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Store, Error> {
+        let conn = Connection::open(path.as_ref())
+            .with_context(|| format!("can't open database {}", path.as_ref().display()))?;
+        conn.execute_batch(SCHEMA)
+            .with_context(|| format!("can't create tables in {}", path.as_ref().display()))?;
+        Ok(Store { conn })
+    }
+
+    pub fn is_empty(&self) -> Result<bool, Error> {
+        let count: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM books", [], |row| row.get(0))?;
+        Ok(count == 0)
+    }
+
+    // Whether the entries of the JSON layout were imported. The databases created before
+    // this was recorded are considered migrated as soon as they hold entries.
+    pub fn is_migrated(&self) -> Result<bool, Error> {
+        let version: i64 = self
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        Ok(version > 0 || !self.is_empty()?)
+    }
+
+    pub fn set_migrated(&self) -> Result<(), Error> {
+        self.conn
+            .execute_batch("PRAGMA user_version = 1")
+            .map_err(Into::into)
+    }
+
+    pub fn load(&self) -> Result<IndexMap<Fp, Info, FxBuildHasher>, Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT books.fp, books.info, reading_states.state FROM books \
+             LEFT JOIN reading_states ON reading_states.fp = books.fp \
+             ORDER BY books.rowid",
+        )?;
+        let mut rows = stmt.query([])?;
+        let mut db = IndexMap::with_capacity_and_hasher(0, FxBuildHasher::default());
+
+        while let Some(row) = rows.next()? {
+            let fp = Fp::from_str(&row.get::<_, String>(0)?)?;
+            let mut info: Info = serde_json::from_str(&row.get::<_, String>(1)?)
+                .with_context(|| format!("can't parse entry {}", fp))?;
+            if let Some(state) = row.get::<_, Option<String>>(2)? {
+                info.reader = serde_json::from_str(&state)
+                    .map_err(|e| eprintln!("Can't load reading state: {:#}.", e))
+                    .ok();
+            }
+            db.insert(fp, info);
+        }
+
+        Ok(db)
+    }
+
+    // Writes the given entries and reading states within a single transaction.
+    // The ones that aren't in `db` anymore are deleted. The rows are written in the
+    // order of `db` and updated in place, so that they're loaded in the same order.
+    pub fn update(
+        &mut self,
+        db: &IndexMap<Fp, Info, FxBuildHasher>,
+        infos: &FxHashSet<Fp>,
+        reading_states: &FxHashSet<Fp>,
+    ) -> Result<(), Error> {
+        let tx = self.conn.transaction()?;
+
+        for fp in infos.iter().filter(|fp| !db.contains_key(*fp)) {
+            tx.execute("DELETE FROM books WHERE fp = ?1", [fp.to_string()])?;
+            tx.execute("DELETE FROM reading_states WHERE fp = ?1", [fp.to_string()])?;
+        }
+
+        for (fp, info) in db.iter().filter(|(fp, _)| infos.contains(*fp)) {
+            tx.execute(
+                "INSERT INTO books (fp, info) VALUES (?1, ?2) \
+                 ON CONFLICT (fp) DO UPDATE SET info = excluded.info",
+                params![fp.to_string(), serde_json::to_string(info)?],
+            )?;
+        }
+
+        for fp in reading_states {
+            if let Some(reader_info) = db.get(fp).and_then(|info| info.reader.as_ref()) {
+                tx.execute(
+                    "INSERT INTO reading_states (fp, state) VALUES (?1, ?2) \
+                     ON CONFLICT (fp) DO UPDATE SET state = excluded.state",
+                    params![fp.to_string(), serde_json::to_string(reader_info)?],
+                )?;
+            } else {
+                tx.execute("DELETE FROM reading_states WHERE fp = ?1", [fp.to_string()])?;
+            }
+        }
+
+        tx.commit().map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::save_json;
+    use crate::library::{Library, METADATA_FILENAME, READING_STATES_DIRNAME};
+    use crate::metadata::ReaderInfo;
+    use crate::settings::LibraryMode;
+
+    fn sample_db() -> IndexMap<Fp, Info, FxBuildHasher> {
+        let mut db = IndexMap::with_capacity_and_hasher(2, FxBuildHasher::default());
+        let mut first = Info {
+            title: "The Eyre Affair".to_string(),
+            series: "Thursday Next".to_string(),
+            number: "1".to_string(),
+            ..Default::default()
+        };
+        first.file.path = "fforde/eyre.epub".into();
+        first.categories.insert("Fiction.Fantasy".to_string());
+        first.reader = Some(ReaderInfo {
+            current_page: 12,
+            pages_count: 300,
+            ..Default::default()
+        });
+        let mut second = Info {
+            title: "Lost in a Good Book".to_string(),
+            ..Default::default()
+        };
+        second.file.path = "fforde/lost.epub".into();
+        db.insert(Fp::from_str("0123456789ABCDEF").unwrap(), first);
+        db.insert(Fp::from_str("FEDCBA9876543210").unwrap(), second);
+        db
+    }
+
+    fn to_json(db: &IndexMap<Fp, Info, FxBuildHasher>) -> Vec<(String, String, String)> {
+        db.iter()
+            .map(|(fp, info)| {
+                (
+                    fp.to_string(),
+                    serde_json::to_string(info).unwrap(),
+                    serde_json::to_string(&info.reader).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn incremental_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::open(dir.path().join("library.sqlite")).unwrap();
+        assert!(store.is_empty().unwrap());
+
+        let mut db = sample_db();
+        let fps: FxHashSet<Fp> = db.keys().cloned().collect();
+        store.update(&db, &fps, &fps).unwrap();
+        assert_eq!(to_json(&store.load().unwrap()), to_json(&db));
+
+        let (first, second) = (*db.get_index(0).unwrap().0, *db.get_index(1).unwrap().0);
+        db.shift_remove(&second);
+        db[&first].reader = None;
+        let changed: FxHashSet<Fp> = [second].into_iter().collect();
+        let states: FxHashSet<Fp> = [first].into_iter().collect();
+        store.update(&db, &changed, &states).unwrap();
+        assert_eq!(to_json(&store.load().unwrap()), to_json(&db));
+    }
+
+    #[test]
+    fn json_migration() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = sample_db();
+        let fp = *db.get_index(0).unwrap().0;
+        save_json(&db, dir.path().join(METADATA_FILENAME)).unwrap();
+        std::fs::create_dir(dir.path().join(READING_STATES_DIRNAME)).unwrap();
+        save_json(
+            &db[&fp].reader,
+            dir.path()
+                .join(READING_STATES_DIRNAME)
+                .join(format!("{}.json", fp)),
+        )
+        .unwrap();
+
+        let library = Library::new(dir.path(), LibraryMode::Sqlite).unwrap();
+        assert_eq!(to_json(&library.db), to_json(&db));
+        std::fs::remove_file(dir.path().join(METADATA_FILENAME)).unwrap();
+
+        let library = Library::new(dir.path(), LibraryMode::Sqlite).unwrap();
+        assert_eq!(to_json(&library.db), to_json(&db));
+        library.export_json().unwrap();
+
+        let library = Library::new(dir.path(), LibraryMode::Database).unwrap();
+        assert_eq!(to_json(&library.db), to_json(&db));
+    }
+
+    #[test]
+    fn no_migration_after_removal() {
+        let dir = tempfile::tempdir().unwrap();
+        save_json(&sample_db(), dir.path().join(METADATA_FILENAME)).unwrap();
+
+        let mut library = Library::new(dir.path(), LibraryMode::Sqlite).unwrap();
+        library.modified_infos = library.db.keys().cloned().collect();
+        library.db.clear();
+        library.flush();
+
+        let library = Library::new(dir.path(), LibraryMode::Sqlite).unwrap();
+        assert!(library.db.is_empty());
+    }
+}
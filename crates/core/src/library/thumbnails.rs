@@ -0,0 +1,100 @@
+use crate::device::CURRENT_DEVICE;
+use crate::document::open;
+use crate::framebuffer::Framebuffer;
+use crate::helpers::Fp;
+use crate::metadata::Info;
+use chrono::NaiveDateTime;
+use fxhash::FxBuildHasher;
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
+use std::cmp::Reverse;
+use std::path::Path;
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref EXCLUSIVE_ACCESS: Mutex<u8> = Mutex::new(0);
+}
+
+// Renders the preview of the given document. Returns whether the thumbnail exists.
+pub fn generate_thumbnail(doc_path: &Path, thumb_path: &Path, width: f32, height: f32) -> bool {
+    // This is a hack to circumvent a segfault (EXC_BAD_ACCESS)
+    // triggered by loading multiple jp2 pixmaps in parallel.
+    let _guard = EXCLUSIVE_ACCESS.lock().unwrap();
+
+    // It might have been generated while we were waiting.
+    if thumb_path.exists() {
+        return true;
+    }
+
+    open(doc_path)
+        .and_then(|mut doc| doc.preview_pixmap(width, height, CURRENT_DEVICE.color_samples()))
+        .is_some_and(|pixmap| pixmap.save(&thumb_path.to_string_lossy()).is_ok())
+}
+
+// Splits the entries between the ones whose thumbnails are kept, at most `max_count`,
+// and the ones whose thumbnails are evicted. The books opened or added most recently
+// come first.
+pub fn rank_thumbnails(
+    db: &IndexMap<Fp, Info, FxBuildHasher>,
+    max_count: usize,
+) -> (Vec<Fp>, Vec<Fp>) {
+    let entries = db
+        .iter()
+        .map(|(fp, info)| {
+            let used = info
+                .reader
+                .as_ref()
+                .map_or(info.added, |r| r.opened.max(info.added));
+            (*fp, used)
+        })
+        .collect();
+    rank_by_recency(entries, max_count)
+}
+
+// Splits the given entries, paired with the date they were last used, the same way.
+pub fn rank_by_recency(
+    mut entries: Vec<(Fp, NaiveDateTime)>,
+    max_count: usize,
+) -> (Vec<Fp>, Vec<Fp>) {
+    entries.sort_by_key(|(_, used)| Reverse(*used));
+    let mut fps: Vec<Fp> = entries.into_iter().map(|(fp, _)| fp).collect();
+    let evicted = fps.split_off(max_count.min(fps.len()));
+    (fps, evicted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::ReaderInfo;
+    use chrono::NaiveDate;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_rank_thumbnails() {
+        let date = |day| {
+            NaiveDate::from_ymd_opt(2024, 1, day)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        };
+        let mut db = IndexMap::with_capacity_and_hasher(3, FxBuildHasher::default());
+        for (fp, added, opened) in [("01", 1, Some(20)), ("02", 10, None), ("03", 5, None)] {
+            let info = Info {
+                added: date(added),
+                reader: opened.map(|day| ReaderInfo {
+                    opened: date(day),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            db.insert(Fp::from_str(fp).unwrap(), info);
+        }
+        let (kept, evicted) = rank_thumbnails(&db, 2);
+        assert_eq!(
+            kept,
+            vec![Fp::from_str("01").unwrap(), Fp::from_str("02").unwrap()]
+        );
+        assert_eq!(evicted, vec![Fp::from_str("03").unwrap()]);
+        assert_eq!(rank_thumbnails(&db, 8).0.len(), 3);
+    }
+}
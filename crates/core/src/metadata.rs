@@ -1,13 +1,17 @@
 use crate::document::asciify;
+use crate::document::comic::ComicDocument;
 use crate::document::djvu::DjvuOpener;
 use crate::document::epub::EpubDocument;
+use crate::document::fb2::Fb2Document;
 use crate::document::html::HtmlDocument;
+use crate::document::mobi::MobiMetadata;
 use crate::document::pdf::PdfOpener;
+use crate::document::xps::XpsProperties;
 use crate::document::{Document, SimpleTocEntry, TextLocation};
 use crate::geom::Point;
 use crate::helpers::datetime_format;
 use chrono::{Local, NaiveDateTime};
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHashSet};
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
@@ -188,6 +192,14 @@ impl TextAlign {
             TextAlign::Center => "align-center",
         }
     }
+
+    pub fn mirrored(self) -> TextAlign {
+        match self {
+            TextAlign::Left => TextAlign::Right,
+            TextAlign::Right => TextAlign::Left,
+            _ => self,
+        }
+    }
 }
 
 impl fmt::Display for TextAlign {
@@ -205,6 +217,7 @@ pub struct ReaderInfo {
     pub pages_count: usize,
     pub finished: bool,
     pub dithered: bool,
+    pub reflow: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub zoom_mode: Option<ZoomMode>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -237,6 +250,12 @@ pub struct ReaderInfo {
     pub bookmarks: BTreeSet<usize>,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub annotations: Vec<Annotation>,
+    // The page names and annotations of the other layout: the original pages when the
+    // text is reflowed, and the reflowed text otherwise.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub other_page_names: BTreeMap<usize, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub other_annotations: Vec<Annotation>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
@@ -250,6 +269,7 @@ pub enum ZoomMode {
 pub enum ScrollMode {
     Screen,
     Page,
+    Columns,
 }
 
 impl PartialEq for ZoomMode {
@@ -279,6 +299,7 @@ impl Default for ReaderInfo {
             pages_count: 1,
             finished: false,
             dithered: false,
+            reflow: false,
             zoom_mode: None,
             scroll_mode: None,
             page_offset: None,
@@ -295,6 +316,8 @@ impl Default for ReaderInfo {
             page_names: BTreeMap::new(),
             bookmarks: BTreeSet::new(),
             annotations: Vec::new(),
+            other_page_names: BTreeMap::new(),
+            other_annotations: Vec::new(),
         }
     }
 }
@@ -445,6 +468,114 @@ impl Info {
             self.title()
         }
     }
+
+    // Returns whether the book belongs to the given category or to one of its subcategories.
+    pub fn has_category(&self, category: &str) -> bool {
+        self.categories.iter().any(|c| {
+            c.strip_prefix(category)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(CATEGORY_SEPARATOR))
+        })
+    }
+}
+
+// The volumes of a series, shown as a single entry of the shelf.
+#[derive(Debug, Clone)]
+pub struct SeriesGroup {
+    pub name: String,
+    pub count: usize,
+    pub finished: usize,
+    pub size: u64,
+    pub expanded: bool,
+}
+
+// Replaces the volumes of each series by a single entry, placed where the first
+// listed volume was. The entry stands for the series, and borrows the file of its first
+// volume for the cover. It's followed by all the volumes, in reading order, when the
+// series is expanded.
+pub fn group_series(
+    books: &[Info],
+    expanded: &FxHashSet<String>,
+) -> (Metadata, Vec<Option<SeriesGroup>>) {
+    let mut volumes: FxHashMap<&str, Vec<&Info>> = FxHashMap::default();
+    for info in books.iter().filter(|info| !info.series.is_empty()) {
+        volumes.entry(info.series.as_str()).or_default().push(info);
+    }
+
+    let mut grouped = Vec::with_capacity(books.len());
+    let mut groups = Vec::with_capacity(books.len());
+    let mut seen = FxHashSet::default();
+
+    for info in books {
+        match volumes.get_mut(info.series.as_str()) {
+            Some(series) if series.len() > 1 => {
+                if !seen.insert(info.series.as_str()) {
+                    continue;
+                }
+                series.sort_by(|a, b| sort_series(a, b));
+                let is_expanded = expanded.contains(&info.series);
+                grouped.push(Info {
+                    title: info.series.clone(),
+                    series: info.series.clone(),
+                    file: series[0].file.clone(),
+                    ..Default::default()
+                });
+                groups.push(Some(SeriesGroup {
+                    name: info.series.clone(),
+                    count: series.len(),
+                    finished: series
+                        .iter()
+                        .filter(|info| info.simple_status() == SimpleStatus::Finished)
+                        .count(),
+                    size: series.iter().map(|info| info.file.size).sum(),
+                    expanded: is_expanded,
+                }));
+                if is_expanded {
+                    for volume in series.iter() {
+                        grouped.push((*volume).clone());
+                        groups.push(None);
+                    }
+                }
+            }
+            _ => {
+                grouped.push(info.clone());
+                groups.push(None);
+            }
+        }
+    }
+
+    (grouped, groups)
+}
+
+// Separates the levels of hierarchical categories, e.g. `Fiction.Fantasy`.
+pub const CATEGORY_SEPARATOR: char = '.';
+
+// Returns the direct subcategories of the given category (or the top-level categories),
+// with the number of books that belong to each of them.
+pub fn category_counts(books: &[Info], parent: Option<&str>) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for info in books {
+        let children: BTreeSet<&str> = info
+            .categories
+            .iter()
+            .filter_map(|category| {
+                let (start, rest) = match parent {
+                    Some(parent) => {
+                        let rest = category
+                            .strip_prefix(parent)
+                            .and_then(|rest| rest.strip_prefix(CATEGORY_SEPARATOR))?;
+                        (parent.len() + 1, rest)
+                    }
+                    None => (0, category.as_str()),
+                };
+                let len = rest.find(CATEGORY_SEPARATOR).unwrap_or(rest.len());
+                (len > 0).then(|| &category[..start + len])
+            })
+            .collect();
+        for child in children {
+            *counts.entry(child.to_string()).or_insert(0) += 1;
+        }
+    }
+    counts
 }
 
 pub fn make_query(text: &str) -> Option<Regex> {
@@ -481,6 +612,7 @@ pub struct BookQuery {
     pub edition: Option<Regex>,
     pub volume: Option<Regex>,
     pub number: Option<Regex>,
+    pub category: Option<Regex>,
     pub reading: Option<bool>,
     pub new: Option<bool>,
     pub finished: Option<bool>,
@@ -554,6 +686,11 @@ impl BookQuery {
                             query.number = make_query(&buf.join(" "));
                             buf.clear();
                         }
+                        Some('c') => {
+                            buf.reverse();
+                            query.category = make_query(&buf.join(" "));
+                            buf.clear();
+                        }
                         Some('R') => query.reading = Some(!invert),
                         Some('N') => query.new = Some(!invert),
                         Some('F') => query.finished = Some(!invert),
@@ -599,6 +736,7 @@ impl BookQuery {
             && query.edition.is_none()
             && query.volume.is_none()
             && query.number.is_none()
+            && query.category.is_none()
             && query.reading.is_none()
             && query.new.is_none()
             && query.finished.is_none()
@@ -636,6 +774,11 @@ impl BookQuery {
             && self.edition.as_ref().map(|re| re.is_match(&info.edition)) != Some(false)
             && self.volume.as_ref().map(|re| re.is_match(&info.volume)) != Some(false)
             && self.number.as_ref().map(|re| re.is_match(&info.number)) != Some(false)
+            && self
+                .category
+                .as_ref()
+                .map(|re| info.categories.iter().any(|c| re.is_match(c)))
+                != Some(false)
             && self
                 .reading
                 .as_ref()
@@ -888,6 +1031,62 @@ pub fn extract_metadata_from_document(prefix: &Path, info: &mut Info) {
             }
             Err(e) => eprintln!("Can't open {}: {:#}.", info.file.path.display(), e),
         },
+        "fb2" => match Fb2Document::new(&path) {
+            Ok(doc) => {
+                info.title = doc.title().unwrap_or_default();
+                info.author = doc.author().unwrap_or_default();
+                info.year = doc.year().unwrap_or_default();
+                info.publisher = doc.publisher().unwrap_or_default();
+                if let Some((title, index)) = doc.series() {
+                    info.series = title;
+                    info.number = index;
+                }
+                info.language = doc.language().unwrap_or_default();
+                info.categories.append(&mut doc.categories());
+            }
+            Err(e) => eprintln!("Can't open {}: {:#}.", info.file.path.display(), e),
+        },
+        "cbz" | "cb7" => match ComicDocument::new(&path) {
+            Ok(doc) => {
+                info.title = doc.title().unwrap_or_default();
+                info.author = doc.author().unwrap_or_default();
+                info.year = doc.year().unwrap_or_default();
+                info.publisher = doc.publisher().unwrap_or_default();
+                if let Some((title, index)) = doc.series() {
+                    info.series = title;
+                    info.number = index;
+                }
+                info.language = doc.language().unwrap_or_default();
+                info.categories.append(&mut doc.categories());
+            }
+            Err(e) => eprintln!("Can't open {}: {:#}.", info.file.path.display(), e),
+        },
+        "mobi" | "azw3" | "azw" => match MobiMetadata::new(&path) {
+            Ok(md) => {
+                info.title = md.title().unwrap_or_default();
+                info.author = md.author().unwrap_or_default();
+                info.year = md.year().unwrap_or_default();
+                info.publisher = md.publisher().unwrap_or_default();
+                if let Some((title, index)) = md.series() {
+                    info.series = title;
+                    info.number = index;
+                }
+                info.language = md.language().unwrap_or_default();
+                info.categories.append(&mut md.categories());
+            }
+            Err(e) => eprintln!("Can't open {}: {:#}.", info.file.path.display(), e),
+        },
+        "xps" | "oxps" => match XpsProperties::new(&path) {
+            Ok(props) => {
+                info.title = props.title().unwrap_or_default();
+                info.author = props.author().unwrap_or_default();
+                info.year = props.year().unwrap_or_default();
+                info.publisher = props.publisher().unwrap_or_default();
+                info.language = props.language().unwrap_or_default();
+                info.categories.append(&mut props.categories());
+            }
+            Err(e) => eprintln!("Can't open {}: {:#}.", info.file.path.display(), e),
+        },
         "html" | "htm" => match HtmlDocument::new(&path) {
             Ok(doc) => {
                 info.title = doc.title().unwrap_or_default();
@@ -1049,3 +1248,72 @@ pub fn file_name_from_info(info: &Info) -> String {
         .replace('!', "")
         .replace(':', "")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(categories: &[&str]) -> Info {
+        Info {
+            categories: categories.iter().map(|c| c.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn hierarchical_categories() {
+        let books = vec![
+            book(&["Fiction.Fantasy", "Fiction.Fantasy.Epic"]),
+            book(&["Fiction.Crime", "History"]),
+            book(&["Fictional"]),
+        ];
+        let counts = category_counts(&books, None);
+        assert_eq!(counts.get("Fiction"), Some(&2));
+        assert_eq!(counts.get("Fictional"), Some(&1));
+        assert_eq!(counts.get("History"), Some(&1));
+        let counts = category_counts(&books, Some("Fiction"));
+        assert_eq!(
+            counts.keys().collect::<Vec<_>>(),
+            ["Fiction.Crime", "Fiction.Fantasy"]
+        );
+        assert_eq!(counts.get("Fiction.Fantasy"), Some(&1));
+        assert!(books[0].has_category("Fiction"));
+        assert!(!books[2].has_category("Fiction"));
+    }
+
+    #[test]
+    fn series_groups() {
+        let volume = |title: &str, series: &str, number: &str| Info {
+            title: title.to_string(),
+            series: series.to_string(),
+            number: number.to_string(),
+            ..Default::default()
+        };
+        let books = vec![
+            volume("B", "Saga", "10"),
+            volume("Standalone", "", ""),
+            volume("A", "Saga", "2"),
+            volume("C", "Trilogy", "1"),
+        ];
+        let (grouped, groups) = group_series(&books, &FxHashSet::default());
+        let titles: Vec<&str> = grouped.iter().map(|info| info.title.as_str()).collect();
+        assert_eq!(titles, ["Saga", "Standalone", "C"]);
+        assert_eq!(groups[0].as_ref().map(|group| group.count), Some(2));
+        assert!(groups[1].is_none() && groups[2].is_none());
+
+        let expanded = ["Saga".to_string()].into_iter().collect();
+        let (grouped, groups) = group_series(&books, &expanded);
+        let titles: Vec<&str> = grouped.iter().map(|info| info.title.as_str()).collect();
+        assert_eq!(titles, ["Saga", "A", "B", "Standalone", "C"]);
+        assert_eq!(groups.len(), grouped.len());
+        assert!(groups[0].as_ref().is_some_and(|group| group.expanded));
+        assert!(groups[1..].iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn category_query() {
+        let query = BookQuery::new("'c fantasy").unwrap();
+        assert!(query.is_match(&book(&["Fiction.Fantasy"])));
+        assert!(!query.is_match(&book(&["History"])));
+    }
+}
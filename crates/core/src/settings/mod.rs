@@ -165,6 +165,7 @@ pub struct Settings {
     pub import: ImportSettings,
     pub dictionary: DictionarySettings,
     pub sketch: SketchSettings,
+    pub export: ExportSettings,
     pub calculator: CalculatorSettings,
     pub battery: BatterySettings,
     pub frontlight_levels: LightLevels,
@@ -176,6 +177,14 @@ pub struct Settings {
 pub enum LibraryMode {
     Database,
     Filesystem,
+    Sqlite,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NavigationMode {
+    Directories,
+    Categories,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -188,6 +197,7 @@ pub struct LibrarySettings {
     pub first_column: FirstColumn,
     pub second_column: SecondColumn,
     pub thumbnail_previews: bool,
+    pub group_series: bool,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub hooks: Vec<Hook>,
 }
@@ -204,6 +214,7 @@ impl Default for LibrarySettings {
             first_column: FirstColumn::TitleAndAuthor,
             second_column: SecondColumn::Progress,
             thumbnail_previews: true,
+            group_series: false,
             hooks: Vec::new(),
         }
     }
@@ -217,6 +228,8 @@ pub struct ImportSettings {
     pub sync_metadata: bool,
     pub metadata_kinds: FxHashSet<String>,
     pub allowed_kinds: FxHashSet<String>,
+    pub pregenerate_thumbnails: bool,
+    pub max_thumbnails: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -246,6 +259,12 @@ pub struct SketchSettings {
     pub pen: Pen,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ExportSettings {
+    pub path: PathBuf,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct CalculatorSettings {
@@ -288,6 +307,14 @@ impl Default for SketchSettings {
     }
 }
 
+impl Default for ExportSettings {
+    fn default() -> Self {
+        ExportSettings {
+            path: PathBuf::from("Annotations"),
+        }
+    }
+}
+
 impl Default for CalculatorSettings {
     fn default() -> Self {
         CalculatorSettings {
@@ -346,6 +373,7 @@ impl Default for Hook {
 pub struct HomeSettings {
     pub address_bar: bool,
     pub navigation_bar: bool,
+    pub navigation_mode: NavigationMode,
     pub max_levels: usize,
     pub max_trash_size: u64,
 }
@@ -389,6 +417,8 @@ pub struct ReaderSettings {
     pub line_height: f32,
     pub continuous_fit_to_width: bool,
     pub ignore_document_css: bool,
+    pub sync_pdf_annotations: bool,
+    pub remember_passwords: bool,
     pub dithered_kinds: FxHashSet<String>,
     pub paragraph_breaker: ParagraphBreakerSettings,
     pub refresh_rate: RefreshRateSettings,
@@ -509,6 +539,7 @@ impl Default for HomeSettings {
         HomeSettings {
             address_bar: false,
             navigation_bar: true,
+            navigation_mode: NavigationMode::Directories,
             max_levels: 3,
             max_trash_size: 32 * (1 << 20),
         }
@@ -547,7 +578,9 @@ impl Default for ReaderSettings {
             line_height: DEFAULT_LINE_HEIGHT,
             continuous_fit_to_width: true,
             ignore_document_css: false,
-            dithered_kinds: ["cbz", "png", "jpg", "jpeg"]
+            sync_pdf_annotations: false,
+            remember_passwords: false,
+            dithered_kinds: ["cbz", "cb7", "png", "jpg", "jpeg"]
                 .iter()
                 .map(|k| k.to_string())
                 .collect(),
@@ -563,16 +596,21 @@ impl Default for ImportSettings {
             unshare_trigger: true,
             startup_trigger: true,
             sync_metadata: true,
-            metadata_kinds: ["epub", "pdf", "djvu"]
-                .iter()
-                .map(|k| k.to_string())
-                .collect(),
+            metadata_kinds: [
+                "epub", "fb2", "pdf", "djvu", "cbz", "cb7", "mobi", "azw", "azw3", "xps", "oxps",
+            ]
+            .iter()
+            .map(|k| k.to_string())
+            .collect(),
             allowed_kinds: [
-                "pdf", "djvu", "epub", "fb2", "txt", "xps", "oxps", "mobi", "cbz",
+                "pdf", "djvu", "epub", "fb2", "txt", "md", "xps", "oxps", "mobi", "azw", "azw3",
+                "cbz", "cb7",
             ]
             .iter()
             .map(|k| k.to_string())
             .collect(),
+            pregenerate_thumbnails: false,
+            max_thumbnails: 4096,
         }
     }
 }
@@ -642,6 +680,7 @@ impl Default for Settings {
             import: ImportSettings::default(),
             dictionary: DictionarySettings::default(),
             sketch: SketchSettings::default(),
+            export: ExportSettings::default(),
             calculator: CalculatorSettings::default(),
             battery: BatterySettings::default(),
             frontlight_levels: LightLevels::default(),
@@ -0,0 +1,221 @@
+use super::filler::Filler;
+use super::image::Image;
+use super::THICKNESS_MEDIUM;
+use super::{Bus, Event, Hub, Id, RenderData, RenderQueue, View, ViewId, ID_FEEDER};
+use crate::color::BLACK;
+use crate::context::Context;
+use crate::device::CURRENT_DEVICE;
+use crate::document::html::HtmlDocument;
+use crate::document::{Document, Location};
+use crate::font::Fonts;
+use crate::framebuffer::{Framebuffer, Pixmap, UpdateMode};
+use crate::geom::{CycleDir, Dir, Rectangle};
+use crate::gesture::GestureEvent;
+use crate::input::{ButtonCode, ButtonStatus, DeviceEvent};
+use crate::unit::scale_by_dpi;
+
+// The fraction of the screen's height covered by the popup.
+const HEIGHT_RATIO: f32 = 0.4;
+
+pub struct Footnote {
+    id: Id,
+    rect: Rectangle,
+    children: Vec<Box<dyn View>>,
+    doc: HtmlDocument,
+    location: usize,
+    font_size: f32,
+}
+
+fn popup_rects(rect: Rectangle) -> (Rectangle, Rectangle) {
+    let thickness = scale_by_dpi(THICKNESS_MEDIUM, CURRENT_DEVICE.dpi) as i32;
+    let height = (HEIGHT_RATIO * rect.height() as f32) as i32;
+    let popup_rect = rect![rect.min.x, rect.max.y - height, rect.max.x, rect.max.y];
+    let separator_rect = rect![
+        popup_rect.min.x,
+        popup_rect.min.y,
+        popup_rect.max.x,
+        popup_rect.min.y + thickness
+    ];
+    (popup_rect, separator_rect)
+}
+
+impl Footnote {
+    pub fn new(
+        rect: Rectangle,
+        mut doc: HtmlDocument,
+        font_size: f32,
+        margin_width: i32,
+        rq: &mut RenderQueue,
+    ) -> Footnote {
+        let id = ID_FEEDER.next();
+        let mut children = Vec::new();
+        let (rect, separator_rect) = popup_rects(rect);
+
+        let separator = Filler::new(separator_rect, BLACK);
+        children.push(Box::new(separator) as Box<dyn View>);
+
+        let image_rect = rect![
+            separator_rect.min.x,
+            separator_rect.max.y,
+            rect.max.x,
+            rect.max.y
+        ];
+        let mut image = Image::new(image_rect, Pixmap::new(1, 1, 1));
+
+        doc.layout(
+            image_rect.width(),
+            image_rect.height(),
+            font_size,
+            CURRENT_DEVICE.dpi,
+        );
+        doc.set_margin_width(margin_width);
+
+        let mut location = 0;
+        if let Some((pixmap, loc)) =
+            doc.pixmap(Location::Exact(0), 1.0, CURRENT_DEVICE.color_samples())
+        {
+            image.update(pixmap, &mut RenderQueue::new());
+            location = loc;
+        }
+        children.push(Box::new(image) as Box<dyn View>);
+
+        rq.add(RenderData::new(id, rect, UpdateMode::Gui));
+
+        Footnote {
+            id,
+            rect,
+            children,
+            doc,
+            location,
+            font_size,
+        }
+    }
+
+    // Returns whether there was a page in the given direction.
+    fn go_to_neighbor(&mut self, dir: CycleDir, rq: &mut RenderQueue) -> bool {
+        let location = match dir {
+            CycleDir::Previous => Location::Previous(self.location),
+            CycleDir::Next => Location::Next(self.location),
+        };
+        if let Some((pixmap, loc)) = self
+            .doc
+            .pixmap(location, 1.0, CURRENT_DEVICE.color_samples())
+        {
+            if let Some(image) = self.children[1].downcast_mut::<Image>() {
+                image.update(pixmap, rq);
+            }
+            self.location = loc;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl View for Footnote {
+    fn handle_event(
+        &mut self,
+        evt: &Event,
+        hub: &Hub,
+        _bus: &mut Bus,
+        rq: &mut RenderQueue,
+        _context: &mut Context,
+    ) -> bool {
+        match *evt {
+            Event::Gesture(GestureEvent::Tap(center)) => {
+                // Tapping the note shows its next page, if any.
+                if !self.rect.includes(center) || !self.go_to_neighbor(CycleDir::Next, rq) {
+                    hub.send(Event::Close(ViewId::Footnote)).ok();
+                }
+                true
+            }
+            Event::Gesture(GestureEvent::Swipe { dir, start, .. }) if self.rect.includes(start) => {
+                match dir {
+                    Dir::West => {
+                        self.go_to_neighbor(CycleDir::Next, rq);
+                    }
+                    Dir::East => {
+                        self.go_to_neighbor(CycleDir::Previous, rq);
+                    }
+                    _ => (),
+                }
+                true
+            }
+            Event::Gesture(..) => true,
+            Event::Device(DeviceEvent::Button {
+                code,
+                status: ButtonStatus::Released,
+                ..
+            }) => {
+                match code {
+                    ButtonCode::Backward => {
+                        self.go_to_neighbor(CycleDir::Previous, rq);
+                    }
+                    ButtonCode::Forward => {
+                        if !self.go_to_neighbor(CycleDir::Next, rq) {
+                            hub.send(Event::Close(ViewId::Footnote)).ok();
+                        }
+                    }
+                    _ => return false,
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn render(&self, _fb: &mut dyn Framebuffer, _rect: Rectangle, _fonts: &mut Fonts) {}
+
+    fn resize(&mut self, rect: Rectangle, hub: &Hub, rq: &mut RenderQueue, context: &mut Context) {
+        let (rect, separator_rect) = popup_rects(rect);
+        self.children[0].resize(separator_rect, hub, rq, context);
+        let image_rect = rect![
+            separator_rect.min.x,
+            separator_rect.max.y,
+            rect.max.x,
+            rect.max.y
+        ];
+        self.doc.layout(
+            image_rect.width(),
+            image_rect.height(),
+            self.font_size,
+            CURRENT_DEVICE.dpi,
+        );
+        if let Some(image) = self.children[1].downcast_mut::<Image>() {
+            if let Some((pixmap, loc)) = self.doc.pixmap(
+                Location::Exact(self.location),
+                1.0,
+                CURRENT_DEVICE.color_samples(),
+            ) {
+                image.update(pixmap, &mut RenderQueue::new());
+                self.location = loc;
+            }
+        }
+        self.children[1].resize(image_rect, hub, rq, context);
+        self.rect = rect;
+    }
+
+    fn rect(&self) -> &Rectangle {
+        &self.rect
+    }
+
+    fn rect_mut(&mut self) -> &mut Rectangle {
+        &mut self.rect
+    }
+
+    fn children(&self) -> &Vec<Box<dyn View>> {
+        &self.children
+    }
+
+    fn children_mut(&mut self) -> &mut Vec<Box<dyn View>> {
+        &mut self.children
+    }
+
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn view_id(&self) -> Option<ViewId> {
+        Some(ViewId::Footnote)
+    }
+}
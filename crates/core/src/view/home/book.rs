@@ -9,7 +9,7 @@ use crate::font::{MD_AUTHOR, MD_KIND, MD_SIZE, MD_TITLE, MD_YEAR};
 use crate::framebuffer::{Framebuffer, UpdateMode};
 use crate::geom::{halves, BorderSpec, CornerSpec, Rectangle};
 use crate::gesture::GestureEvent;
-use crate::metadata::{Info, Status};
+use crate::metadata::{Info, SeriesGroup, Status};
 use crate::settings::{FirstColumn, SecondColumn};
 use crate::unit::scale_by_dpi;
 use crate::view::{Bus, Event, Hub, Id, RenderData, RenderQueue, View, ID_FEEDER, THICKNESS_SMALL};
@@ -26,6 +26,8 @@ pub struct Book {
     first_column: FirstColumn,
     second_column: SecondColumn,
     preview_path: Option<PathBuf>,
+    // Set when the entry stands for all the volumes of a series.
+    series: Option<SeriesGroup>,
     active: bool,
 }
 
@@ -37,6 +39,7 @@ impl Book {
         first_column: FirstColumn,
         second_column: SecondColumn,
         preview_path: Option<PathBuf>,
+        series: Option<SeriesGroup>,
     ) -> Book {
         Book {
             id: ID_FEEDER.next(),
@@ -47,6 +50,7 @@ impl Book {
             first_column,
             second_column,
             preview_path,
+            series,
             active: false,
         }
     }
@@ -63,13 +67,17 @@ impl View for Book {
     ) -> bool {
         match *evt {
             Event::Gesture(GestureEvent::Tap(center)) if self.rect.includes(center) => {
+                if let Some(group) = self.series.as_ref() {
+                    bus.push_back(Event::ToggleExpandSeries(group.name.clone()));
+                    return true;
+                }
                 self.active = true;
                 rq.add(RenderData::new(self.id, self.rect, UpdateMode::Gui));
                 hub.send(Event::Open(Box::new(self.info.clone()))).ok();
                 true
             }
             Event::Gesture(GestureEvent::HoldFingerShort(center, ..))
-                if self.rect.includes(center) =>
+                if self.rect.includes(center) && self.series.is_none() =>
             {
                 let pt = pt!(center.x, self.rect.center().y);
                 bus.push_back(Event::ToggleBookMenu(Rectangle::from_point(pt), self.index));
@@ -108,8 +116,17 @@ impl View for Book {
 
         fb.draw_rectangle(&self.rect, scheme[0]);
 
-        let (title, author) = if self.first_column == FirstColumn::TitleAndAuthor {
-            (self.info.title(), self.info.author.as_str())
+        let (title, author) = if let Some(group) = self.series.as_ref() {
+            (
+                group.name.clone(),
+                format!(
+                    "{} volumes · {} unread",
+                    group.count,
+                    group.count - group.finished
+                ),
+            )
+        } else if self.first_column == FirstColumn::TitleAndAuthor {
+            (self.info.title(), self.info.author.clone())
         } else {
             let filename = self
                 .info
@@ -118,7 +135,7 @@ impl View for Book {
                 .file_stem()
                 .map(|v| v.to_string_lossy().into_owned())
                 .unwrap_or_default();
-            (filename, "")
+            (filename, String::new())
         };
 
         let year = &self.info.year;
@@ -177,7 +194,7 @@ impl View for Book {
         // Author
         let author_width = {
             let font = font_from_style(fonts, &MD_AUTHOR, dpi);
-            let plan = font.plan(author, Some(width), None);
+            let plan = font.plan(&author, Some(width), None);
             let pt = pt!(start_x, self.rect.max.y - baseline);
             font.render(fb, scheme[1], &plan, pt);
             plan.width
@@ -223,20 +240,9 @@ impl View for Book {
             font.render(fb, scheme[1], &plan, pt);
         }
 
-        // Year or Progress
+        // Year, Progress or read volumes
         match self.second_column {
-            SecondColumn::Year => {
-                let font = font_from_style(fonts, &MD_YEAR, dpi);
-                let plan = font.plan(year, None, None);
-                let dx = (second_width - padding - plan.width) / 2;
-                let dy = (self.rect.height() as i32 - font.x_heights.1 as i32) / 2;
-                let pt = pt!(
-                    self.rect.min.x + first_width + big_half_padding + dx,
-                    self.rect.max.y - dy
-                );
-                font.render(fb, scheme[1], &plan, pt);
-            }
-            SecondColumn::Progress => {
+            SecondColumn::Progress if self.series.is_none() => {
                 let progress_height = scale_by_dpi(PROGRESS_HEIGHT, dpi) as i32;
                 let thickness = scale_by_dpi(THICKNESS_SMALL, dpi) as u16;
                 let (small_radius, big_radius) = halves(progress_height);
@@ -290,11 +296,30 @@ impl View for Book {
                     }
                 }
             }
+            _ => {
+                let text = self.series.as_ref().map_or_else(
+                    || year.clone(),
+                    |group| format!("{}/{}", group.finished, group.count),
+                );
+                let font = font_from_style(fonts, &MD_YEAR, dpi);
+                let plan = font.plan(&text, None, None);
+                let dx = (second_width - padding - plan.width) / 2;
+                let dy = (self.rect.height() as i32 - font.x_heights.1 as i32) / 2;
+                let pt = pt!(
+                    self.rect.min.x + first_width + big_half_padding + dx,
+                    self.rect.max.y - dy
+                );
+                font.render(fb, scheme[1], &plan, pt);
+            }
         }
 
         // File kind
         {
-            let kind = file_info.kind.to_uppercase();
+            let kind = if self.series.is_some() {
+                "SERIES".to_string()
+            } else {
+                file_info.kind.to_uppercase()
+            };
             let font = font_from_style(fonts, &MD_KIND, dpi);
             let mut plan = font.plan(&kind, None, None);
             let letter_spacing = scale_by_dpi(3.0, dpi) as i32;
@@ -308,7 +333,11 @@ impl View for Book {
 
         // File size
         {
-            let size = file_info.size.human_size();
+            let size = self
+                .series
+                .as_ref()
+                .map_or(file_info.size, |group| group.size)
+                .human_size();
             let font = font_from_style(fonts, &MD_SIZE, dpi);
             let plan = font.plan(&size, None, None);
             let pt = pt!(
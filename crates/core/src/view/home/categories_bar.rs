@@ -0,0 +1,222 @@
+use super::category::Category;
+use crate::color::TEXT_BUMP_SMALL;
+use crate::context::Context;
+use crate::device::CURRENT_DEVICE;
+use crate::font::{font_from_style, Fonts, NORMAL_STYLE};
+use crate::framebuffer::{Framebuffer, UpdateMode};
+use crate::geom::{divide, CycleDir, Dir, Point, Rectangle};
+use crate::gesture::GestureEvent;
+use crate::metadata::CATEGORY_SEPARATOR;
+use crate::unit::scale_by_dpi;
+use crate::view::{Align, Bus, Event, Hub, Id, RenderData, RenderQueue, View, ID_FEEDER};
+use crate::view::{SMALL_BAR_HEIGHT, THICKNESS_MEDIUM};
+use std::collections::BTreeMap;
+
+// Lists the subcategories of the selected category, along with their number of books.
+#[derive(Debug)]
+pub struct CategoriesBar {
+    id: Id,
+    pub rect: Rectangle,
+    selected: Option<String>,
+    counts: BTreeMap<String, usize>,
+    pages: Vec<Vec<Box<dyn View>>>,
+    current_page: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Item {
+    name: String,
+    label: String,
+    selected: bool,
+    width: i32,
+    max_width: Option<i32>,
+}
+
+impl CategoriesBar {
+    pub fn new(rect: Rectangle) -> CategoriesBar {
+        CategoriesBar {
+            id: ID_FEEDER.next(),
+            rect,
+            selected: None,
+            counts: BTreeMap::new(),
+            pages: vec![Vec::new()],
+            current_page: 0,
+        }
+    }
+
+    pub fn shift(&mut self, delta: Point) {
+        for children in &mut self.pages {
+            for child in children {
+                *child.rect_mut() += delta;
+            }
+        }
+        self.rect += delta;
+    }
+
+    pub fn set_current_page(&mut self, dir: CycleDir) {
+        match dir {
+            CycleDir::Next if self.current_page < self.pages.len() - 1 => {
+                self.current_page += 1;
+            }
+            CycleDir::Previous if self.current_page > 0 => {
+                self.current_page -= 1;
+            }
+            _ => (),
+        }
+    }
+
+    pub fn set_content(
+        &mut self,
+        selected: Option<&str>,
+        counts: BTreeMap<String, usize>,
+        fonts: &mut Fonts,
+    ) {
+        self.selected = selected.map(String::from);
+        self.counts = counts;
+        self.current_page = 0;
+        self.update_content(fonts);
+    }
+
+    pub fn update_content(&mut self, fonts: &mut Fonts) {
+        let dpi = CURRENT_DEVICE.dpi;
+        let thickness = scale_by_dpi(THICKNESS_MEDIUM, dpi) as i32;
+        let min_height = scale_by_dpi(SMALL_BAR_HEIGHT, dpi) as i32 - thickness;
+        let font = font_from_style(fonts, &NORMAL_STYLE, dpi);
+        let x_height = font.x_heights.0 as i32;
+        let padding = font.em() as i32;
+        let vertical_padding = min_height - x_height;
+        let max_line_width = self.rect.width() as i32 - 2 * padding;
+        let max_lines = (((self.rect.height() as i32 - vertical_padding / 2)
+            / (x_height + vertical_padding / 2)) as usize)
+            .max(1);
+
+        // The selected category comes first, followed by its subcategories.
+        let mut items = Vec::new();
+        if let Some(selected) = self.selected.as_ref() {
+            items.push((selected.clone(), selected.clone(), true));
+        }
+        for (name, count) in &self.counts {
+            let base = name
+                .rsplit_once(CATEGORY_SEPARATOR)
+                .map_or(name.as_str(), |(_, base)| base);
+            items.push((name.clone(), format!("{} ({})", base, count), false));
+        }
+
+        // Break the items into lines.
+        let mut lines: Vec<Vec<Item>> = Vec::new();
+        let mut line_width = 0;
+        for (name, label, selected) in items {
+            let mut width = font.plan(&label, None, None).width;
+            let mut max_width = None;
+            if width > max_line_width {
+                width = max_line_width;
+                max_width = Some(max_line_width);
+            }
+            let item = Item {
+                name,
+                label,
+                selected,
+                width,
+                max_width,
+            };
+            match lines.last_mut() {
+                Some(line) if line_width + padding + width <= max_line_width => {
+                    line_width += padding + width;
+                    line.push(item);
+                }
+                _ => {
+                    line_width = width;
+                    lines.push(vec![item]);
+                }
+            }
+        }
+
+        let heights = divide(self.rect.height() as i32, max_lines as i32);
+        self.pages = lines
+            .chunks(max_lines)
+            .map(|page| {
+                let mut children = Vec::new();
+                let mut y = self.rect.min.y;
+                for (line, height) in page.iter().zip(heights.iter()) {
+                    let mut x = self.rect.min.x + padding / 2;
+                    for item in line {
+                        let rect = rect![x, y, x + item.width + padding, y + height];
+                        let category = Category::new(
+                            rect,
+                            item.name.clone(),
+                            item.label.clone(),
+                            item.selected,
+                            Align::Left(padding / 2),
+                            item.max_width,
+                        );
+                        children.push(Box::new(category) as Box<dyn View>);
+                        x += item.width + padding;
+                    }
+                    y += height;
+                }
+                children
+            })
+            .collect();
+
+        if self.pages.is_empty() {
+            self.pages.push(Vec::new());
+        }
+
+        self.current_page = self.current_page.min(self.pages.len() - 1);
+    }
+}
+
+impl View for CategoriesBar {
+    fn handle_event(
+        &mut self,
+        evt: &Event,
+        _hub: &Hub,
+        _bus: &mut Bus,
+        rq: &mut RenderQueue,
+        _context: &mut Context,
+    ) -> bool {
+        match *evt {
+            Event::Gesture(GestureEvent::Swipe { dir, start, .. }) if self.rect.includes(start) => {
+                let current_page = self.current_page;
+                match dir {
+                    Dir::West => self.set_current_page(CycleDir::Next),
+                    Dir::East => self.set_current_page(CycleDir::Previous),
+                    _ => return false,
+                }
+                if self.current_page != current_page {
+                    rq.add(RenderData::new(self.id, self.rect, UpdateMode::Gui));
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn render(&self, fb: &mut dyn Framebuffer, _rect: Rectangle, _fonts: &mut Fonts) {
+        fb.draw_rectangle(&self.rect, TEXT_BUMP_SMALL[0]);
+    }
+
+    fn is_background(&self) -> bool {
+        true
+    }
+
+    fn rect(&self) -> &Rectangle {
+        &self.rect
+    }
+
+    fn rect_mut(&mut self) -> &mut Rectangle {
+        &mut self.rect
+    }
+
+    fn children(&self) -> &Vec<Box<dyn View>> {
+        &self.pages[self.current_page]
+    }
+
+    fn children_mut(&mut self) -> &mut Vec<Box<dyn View>> {
+        &mut self.pages[self.current_page]
+    }
+
+    fn id(&self) -> Id {
+        self.id
+    }
+}
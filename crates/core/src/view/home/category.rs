@@ -0,0 +1,115 @@
+use crate::color::{BLACK, TEXT_BUMP_SMALL, WHITE};
+use crate::context::Context;
+use crate::device::CURRENT_DEVICE;
+use crate::font::{font_from_style, Fonts, NORMAL_STYLE};
+use crate::framebuffer::Framebuffer;
+use crate::geom::{BorderSpec, CornerSpec, Rectangle};
+use crate::gesture::GestureEvent;
+use crate::unit::scale_by_dpi;
+use crate::view::{Align, Bus, Event, Hub, Id, RenderQueue, View, ID_FEEDER};
+use crate::view::{BORDER_RADIUS_SMALL, THICKNESS_SMALL};
+
+pub struct Category {
+    id: Id,
+    rect: Rectangle,
+    children: Vec<Box<dyn View>>,
+    pub name: String,
+    label: String,
+    selected: bool,
+    align: Align,
+    max_width: Option<i32>,
+}
+
+impl Category {
+    pub fn new(
+        rect: Rectangle,
+        name: String,
+        label: String,
+        selected: bool,
+        align: Align,
+        max_width: Option<i32>,
+    ) -> Category {
+        Category {
+            id: ID_FEEDER.next(),
+            rect,
+            children: Vec::new(),
+            name,
+            label,
+            selected,
+            align,
+            max_width,
+        }
+    }
+}
+
+impl View for Category {
+    fn handle_event(
+        &mut self,
+        evt: &Event,
+        _hub: &Hub,
+        bus: &mut Bus,
+        _rq: &mut RenderQueue,
+        _context: &mut Context,
+    ) -> bool {
+        match *evt {
+            Event::Gesture(GestureEvent::Tap(center)) if self.rect.includes(center) => {
+                bus.push_back(Event::ToggleSelectCategory(self.name.clone()));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn render(&self, fb: &mut dyn Framebuffer, _rect: Rectangle, fonts: &mut Fonts) {
+        let dpi = CURRENT_DEVICE.dpi;
+        fb.draw_rectangle(&self.rect, TEXT_BUMP_SMALL[0]);
+        let font = font_from_style(fonts, &NORMAL_STYLE, dpi);
+        let x_height = font.x_heights.0 as i32;
+        let plan = font.plan(&self.label, self.max_width, None);
+
+        let dx = self.align.offset(plan.width, self.rect.width() as i32);
+        let dy = (self.rect.height() as i32 - x_height) / 2;
+
+        if self.selected {
+            let padding = font.em() as i32 / 2 - scale_by_dpi(3.0, dpi) as i32;
+            let bg_width = plan.width + 2 * padding;
+            let bg_height = 3 * x_height;
+            let pt = self.rect.min + pt!(dx - padding, dy - x_height);
+            let bg_rect = rect![pt, pt + pt!(bg_width, bg_height)];
+            let border_radius = scale_by_dpi(BORDER_RADIUS_SMALL, dpi) as i32;
+            let border_thickness = scale_by_dpi(THICKNESS_SMALL, dpi) as u16;
+            fb.draw_rounded_rectangle_with_border(
+                &bg_rect,
+                &CornerSpec::Uniform(border_radius),
+                &BorderSpec {
+                    thickness: border_thickness,
+                    color: BLACK,
+                },
+                &WHITE,
+            );
+        }
+
+        let pt = pt!(self.rect.min.x + dx, self.rect.max.y - dy);
+        font.render(fb, TEXT_BUMP_SMALL[1], &plan, pt);
+    }
+
+    fn rect(&self) -> &Rectangle {
+        &self.rect
+    }
+
+    fn rect_mut(&mut self) -> &mut Rectangle {
+        &mut self.rect
+    }
+
+    fn children(&self) -> &Vec<Box<dyn View>> {
+        &self.children
+    }
+
+    fn children_mut(&mut self) -> &mut Vec<Box<dyn View>> {
+        &mut self.children
+    }
+
+    fn id(&self) -> Id {
+        self.id
+    }
+}
@@ -1,6 +1,8 @@
 mod address_bar;
 mod book;
 mod bottom_bar;
+mod categories_bar;
+mod category;
 mod directories_bar;
 mod directory;
 mod library_label;
@@ -21,8 +23,9 @@ use crate::geom::{halves, CycleDir, DiagDir, Dir, Rectangle};
 use crate::gesture::GestureEvent;
 use crate::input::{ButtonCode, ButtonStatus, DeviceEvent};
 use crate::library::Library;
-use crate::metadata::{sort, BookQuery, Info, Metadata, SimpleStatus, SortMethod};
-use crate::settings::{FirstColumn, Hook, LibraryMode, SecondColumn};
+use crate::metadata::{category_counts, group_series, sort, BookQuery, Info, Metadata};
+use crate::metadata::{SeriesGroup, SimpleStatus, SortMethod, CATEGORY_SEPARATOR};
+use crate::settings::{FirstColumn, Hook, LibraryMode, NavigationMode, SecondColumn};
 use crate::unit::scale_by_dpi;
 use crate::view::common::{locate, locate_by_id, rlocate};
 use crate::view::common::{toggle_battery_menu, toggle_clock_menu, toggle_main_menu};
@@ -34,12 +37,13 @@ use crate::view::named_input::NamedInput;
 use crate::view::notification::Notification;
 use crate::view::search_bar::SearchBar;
 use crate::view::{Bus, Event, Hub, RenderData, RenderQueue, View};
-use crate::view::{EntryId, EntryKind, Id, ViewId, ID_FEEDER};
+use crate::view::{EntryId, EntryKind, Id, NotificationEvent, ViewId, ID_FEEDER};
 use crate::view::{BIG_BAR_HEIGHT, SMALL_BAR_HEIGHT, THICKNESS_MEDIUM};
 use anyhow::{format_err, Error};
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHashSet};
 use rand_core::RngCore;
 use serde_json::{json, Value as JsonValue};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::io::Write;
 use std::io::{BufRead, BufReader};
@@ -63,7 +67,12 @@ pub struct Home {
     sort_method: SortMethod,
     reverse_order: bool,
     visible_books: Metadata,
+    // The series entries of the visible books, empty unless the series are grouped.
+    visible_groups: Vec<Option<SeriesGroup>>,
+    expanded_series: FxHashSet<String>,
     current_directory: PathBuf,
+    selected_category: Option<String>,
+    category_counts: BTreeMap<String, usize>,
     target_document: Option<PathBuf>,
     background_fetchers: FxHashMap<u32, Fetcher>,
 }
@@ -105,7 +114,15 @@ impl Home {
 
         context.library.sort(sort_method, reverse_order);
 
-        let (visible_books, dirs) = context.library.list(&current_directory, None, false);
+        let (books, dirs) = context.library.list(&current_directory, None, false);
+        let category_counts = if context.settings.home.navigation_mode == NavigationMode::Categories
+        {
+            category_counts(&books, None)
+        } else {
+            BTreeMap::new()
+        };
+        let (visible_books, visible_groups) =
+            group_visible_series(books, &FxHashSet::default(), context);
         let count = visible_books.len();
         let current_page = 0;
         let mut shelf_index = 2;
@@ -171,7 +188,15 @@ impl Home {
                 context.settings.home.max_levels,
             );
 
-            nav_bar.set_path(&current_directory, &dirs, &mut RenderQueue::new(), context);
+            populate_navigation_bar(
+                &mut nav_bar,
+                &current_directory,
+                &dirs,
+                None,
+                &category_counts,
+                &mut RenderQueue::new(),
+                context,
+            );
             y_start = nav_bar.rect().max.y;
 
             children.push(Box::new(nav_bar) as Box<dyn View>);
@@ -207,6 +232,7 @@ impl Home {
 
         shelf.update(
             &visible_books[index_lower..index_upper],
+            visible_groups.get(index_lower..index_upper).unwrap_or(&[]),
             hub,
             &mut RenderQueue::new(),
             context,
@@ -254,7 +280,11 @@ impl Home {
             sort_method,
             reverse_order,
             visible_books,
+            visible_groups,
+            expanded_series: FxHashSet::default(),
             current_directory,
+            selected_category: None,
+            category_counts,
             target_document: None,
             background_fetchers: FxHashMap::default(),
         })
@@ -285,10 +315,10 @@ impl Home {
             context
                 .library
                 .list(&self.current_directory, self.query.as_ref(), false);
-        self.visible_books = files;
+        self.set_visible_books(files, context);
         self.current_page = 0;
 
-        let mut index = 2;
+        let index = 2;
 
         if context.settings.home.address_bar {
             let addr_bar = self.children[index]
@@ -296,26 +326,142 @@ impl Home {
                 .downcast_mut::<AddressBar>()
                 .unwrap();
             addr_bar.set_text(self.current_directory.to_string_lossy(), rq, context);
-            index += 2;
         }
 
         if context.settings.home.navigation_bar {
-            let nav_bar = self.children[index]
-                .as_mut()
-                .downcast_mut::<NavigationBar>()
-                .unwrap();
-            nav_bar.set_path(&self.current_directory, &dirs, rq, context);
-            self.adjust_shelf_top_edge();
-            rq.add(RenderData::new(
-                self.child(index + 1).id(),
-                *self.child(index + 1).rect(),
-                UpdateMode::Partial,
-            ));
-            rq.add(RenderData::new(
-                self.child(index).id(),
-                *self.child(index).rect(),
-                UpdateMode::Partial,
-            ));
+            self.update_navigation_bar(&dirs, rq, context);
+        }
+
+        self.update_shelf(true, hub, rq, context);
+        self.update_bottom_bar(rq, context);
+    }
+
+    fn update_navigation_bar(
+        &mut self,
+        dirs: &BTreeSet<PathBuf>,
+        rq: &mut RenderQueue,
+        context: &mut Context,
+    ) {
+        let index = self.shelf_index - 2;
+        let nav_bar = self.children[index]
+            .as_mut()
+            .downcast_mut::<NavigationBar>()
+            .unwrap();
+        populate_navigation_bar(
+            nav_bar,
+            &self.current_directory,
+            dirs,
+            self.selected_category.as_deref(),
+            &self.category_counts,
+            rq,
+            context,
+        );
+        self.adjust_shelf_top_edge();
+        rq.add(RenderData::new(
+            self.child(index + 1).id(),
+            *self.child(index + 1).rect(),
+            UpdateMode::Partial,
+        ));
+        rq.add(RenderData::new(
+            self.child(index).id(),
+            *self.child(index).rect(),
+            UpdateMode::Partial,
+        ));
+    }
+
+    // Filters the listed books by category and groups the volumes of each series.
+    fn set_visible_books(&mut self, mut books: Metadata, context: &Context) {
+        if let Some(category) = self.selected_category.as_ref() {
+            books.retain(|info| info.has_category(category));
+        }
+        self.category_counts =
+            if context.settings.home.navigation_mode == NavigationMode::Categories {
+                category_counts(&books, self.selected_category.as_deref())
+            } else {
+                BTreeMap::new()
+            };
+        let (visible_books, visible_groups) =
+            group_visible_series(books, &self.expanded_series, context);
+        self.visible_books = visible_books;
+        self.visible_groups = visible_groups;
+    }
+
+    fn toggle_expand_series(
+        &mut self,
+        name: &str,
+        hub: &Hub,
+        rq: &mut RenderQueue,
+        context: &mut Context,
+    ) {
+        if !self.expanded_series.remove(name) {
+            self.expanded_series.insert(name.to_string());
+        }
+        self.refresh_visibles(true, false, hub, rq, context);
+    }
+
+    fn select_category(
+        &mut self,
+        category: Option<String>,
+        hub: &Hub,
+        rq: &mut RenderQueue,
+        context: &mut Context,
+    ) {
+        if self.selected_category == category {
+            return;
+        }
+
+        self.selected_category = category;
+        self.refresh_visibles(true, true, hub, rq, context);
+    }
+
+    fn toggle_select_category(
+        &mut self,
+        category: &str,
+        hub: &Hub,
+        rq: &mut RenderQueue,
+        context: &mut Context,
+    ) {
+        if self.selected_category.as_deref() == Some(category) {
+            let parent = category
+                .rsplit_once(CATEGORY_SEPARATOR)
+                .map(|(parent, _)| parent.to_string());
+            self.select_category(parent, hub, rq, context);
+        } else {
+            self.select_category(Some(category.to_string()), hub, rq, context);
+        }
+    }
+
+    fn set_navigation_mode(
+        &mut self,
+        mode: NavigationMode,
+        hub: &Hub,
+        rq: &mut RenderQueue,
+        context: &mut Context,
+    ) {
+        if context.settings.home.navigation_mode == mode {
+            return;
+        }
+
+        // The categories are stored in the database of the library.
+        if mode == NavigationMode::Categories && context.library.mode == LibraryMode::Filesystem {
+            let msg = "Categories aren't available in filesystem mode.".to_string();
+            hub.send(Event::Notification(NotificationEvent::Show(msg)))
+                .ok();
+            return;
+        }
+
+        context.settings.home.navigation_mode = mode;
+        self.selected_category = None;
+        self.refresh_visibles(false, true, hub, rq, context);
+
+        if context.settings.home.navigation_bar {
+            if let Some(nav_bar) =
+                self.children[self.shelf_index - 2].downcast_mut::<NavigationBar>()
+            {
+                nav_bar.clear();
+            }
+            let (_, dirs) = context.library.list(&self.current_directory, None, true);
+            self.update_navigation_bar(&dirs, rq, context);
         }
 
         self.update_shelf(true, hub, rq, context);
@@ -430,7 +576,7 @@ impl Home {
         let (files, _) = context
             .library
             .list(&self.current_directory, self.query.as_ref(), false);
-        self.visible_books = files;
+        self.set_visible_books(files, context);
 
         let max_lines = {
             let shelf = self
@@ -449,6 +595,21 @@ impl Home {
         }
 
         if update {
+            // The counts of the categories might have changed.
+            if context.settings.home.navigation_bar
+                && context.settings.home.navigation_mode == NavigationMode::Categories
+            {
+                if let Some(nav_bar) =
+                    self.children[self.shelf_index - 2].downcast_mut::<NavigationBar>()
+                {
+                    nav_bar.set_categories(
+                        self.selected_category.as_deref(),
+                        self.category_counts.clone(),
+                        rq,
+                        context,
+                    );
+                }
+            }
             self.update_shelf(false, hub, rq, context);
             self.update_bottom_bar(rq, context);
         }
@@ -531,6 +692,9 @@ impl Home {
 
         shelf.update(
             &self.visible_books[index_lower..index_upper],
+            self.visible_groups
+                .get(index_lower..index_upper)
+                .unwrap_or(&[]),
             hub,
             rq,
             context,
@@ -555,7 +719,9 @@ impl Home {
                 .as_mut()
                 .downcast_mut::<BottomBar>()
                 .unwrap();
-            let filter = self.query.is_some() || self.current_directory != context.library.home;
+            let filter = self.query.is_some()
+                || self.selected_category.is_some()
+                || self.current_directory != context.library.home;
             let selected_library = context.settings.selected_library;
             let library_settings = &context.settings.libraries[selected_library];
             bottom_bar.update_library_label(
@@ -861,7 +1027,15 @@ impl Home {
                 context.settings.home.max_levels,
             );
             let (_, dirs) = context.library.list(&self.current_directory, None, true);
-            nav_bar.set_path(&self.current_directory, &dirs, rq, context);
+            populate_navigation_bar(
+                &mut nav_bar,
+                &self.current_directory,
+                &dirs,
+                self.selected_category.as_deref(),
+                &self.category_counts,
+                rq,
+                context,
+            );
             self.children
                 .insert(sep_index + 1, Box::new(nav_bar) as Box<dyn View>);
 
@@ -1099,6 +1273,56 @@ impl Home {
         }
     }
 
+    fn toggle_add_categories(
+        &mut self,
+        enable: Option<bool>,
+        hub: &Hub,
+        rq: &mut RenderQueue,
+        context: &mut Context,
+    ) {
+        if let Some(index) = locate_by_id(self, ViewId::AddCategories) {
+            if let Some(true) = enable {
+                return;
+            }
+            self.target_document = None;
+            rq.add(RenderData::expose(
+                *self.child(index).rect(),
+                UpdateMode::Gui,
+            ));
+            self.children.remove(index);
+            if let Some(ViewId::AddCategoriesInput) = self.focus {
+                self.toggle_keyboard(
+                    false,
+                    true,
+                    Some(ViewId::AddCategoriesInput),
+                    hub,
+                    rq,
+                    context,
+                );
+            }
+        } else {
+            if let Some(false) = enable {
+                return;
+            }
+            let add_categories = NamedInput::new(
+                "Add categories".to_string(),
+                ViewId::AddCategories,
+                ViewId::AddCategoriesInput,
+                21,
+                context,
+            );
+            rq.add(RenderData::new(
+                add_categories.id(),
+                *add_categories.rect(),
+                UpdateMode::Gui,
+            ));
+            hub.send(Event::Focus(Some(ViewId::AddCategoriesInput)))
+                .ok();
+            self.children
+                .push(Box::new(add_categories) as Box<dyn View>);
+        }
+    }
+
     fn toggle_go_to_page(
         &mut self,
         enable: Option<bool>,
@@ -1307,6 +1531,28 @@ impl Home {
                 .map(|s| EntryKind::Command(s.to_string(), EntryId::SetStatus(path.clone(), *s)))
                 .collect();
             entries.push(EntryKind::SubMenu("Mark As".to_string(), submenu));
+
+            if context.library.mode != LibraryMode::Filesystem {
+                let mut categories = vec![EntryKind::Command(
+                    "Add".to_string(),
+                    EntryId::AddCategories(path.clone()),
+                )];
+                if !info.categories.is_empty() {
+                    let remove = info
+                        .categories
+                        .iter()
+                        .map(|c| {
+                            EntryKind::Command(
+                                c.clone(),
+                                EntryId::RemoveCategory(path.clone(), c.clone()),
+                            )
+                        })
+                        .collect();
+                    categories.push(EntryKind::SubMenu("Remove".to_string(), remove));
+                }
+                entries.push(EntryKind::SubMenu("Categories".to_string(), categories));
+            }
+
             entries.push(EntryKind::Separator);
 
             let selected_library = context.settings.selected_library;
@@ -1399,7 +1645,7 @@ impl Home {
                 })
                 .collect();
 
-            let database = if library_settings.mode == LibraryMode::Database {
+            let mut database = if library_settings.mode != LibraryMode::Filesystem {
                 vec![
                     EntryKind::Command("Import".to_string(), EntryId::Import),
                     EntryKind::Command("Flush".to_string(), EntryId::Flush),
@@ -1408,6 +1654,13 @@ impl Home {
                 Vec::new()
             };
 
+            if library_settings.mode == LibraryMode::Sqlite {
+                database.push(EntryKind::Command(
+                    "Export to JSON".to_string(),
+                    EntryId::ExportJson,
+                ));
+            }
+
             let filesystem = if library_settings.mode == LibraryMode::Filesystem {
                 vec![
                     EntryKind::CheckBox(
@@ -1490,6 +1743,29 @@ impl Home {
                 library_settings.thumbnail_previews,
             ));
 
+            entries.push(EntryKind::CheckBox(
+                "Group Series".to_string(),
+                EntryId::GroupSeries,
+                library_settings.group_series,
+            ));
+
+            let navigation_mode = context.settings.home.navigation_mode;
+            entries.push(EntryKind::SubMenu(
+                "Navigation".to_string(),
+                vec![
+                    EntryKind::RadioButton(
+                        "Directories".to_string(),
+                        EntryId::SetNavigationMode(NavigationMode::Directories),
+                        navigation_mode == NavigationMode::Directories,
+                    ),
+                    EntryKind::RadioButton(
+                        "Categories".to_string(),
+                        EntryId::SetNavigationMode(NavigationMode::Categories),
+                        navigation_mode == NavigationMode::Categories,
+                    ),
+                ],
+            ));
+
             let trash_path = context.library.home.join(TRASH_DIRNAME);
             if let Ok(trash) = Library::new(trash_path, LibraryMode::Database)
                 .map_err(|e| eprintln!("Can't inspect trash: {:#?}.", e))
@@ -1589,6 +1865,50 @@ impl Home {
         Ok(())
     }
 
+    fn add_categories(
+        &mut self,
+        path: &Path,
+        text: &str,
+        hub: &Hub,
+        rq: &mut RenderQueue,
+        context: &mut Context,
+    ) {
+        let names = text
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(String::from);
+        if let Err(e) = context
+            .library
+            .edit_categories(path, |categories| categories.extend(names))
+        {
+            let msg = format!("Can't add categories: {:#}.", e);
+            hub.send(Event::Notification(NotificationEvent::Show(msg)))
+                .ok();
+            return;
+        }
+        self.refresh_visibles(true, false, hub, rq, context);
+    }
+
+    fn remove_category(
+        &mut self,
+        path: &Path,
+        name: &str,
+        hub: &Hub,
+        rq: &mut RenderQueue,
+        context: &mut Context,
+    ) {
+        if let Err(e) = context.library.edit_categories(path, |categories| {
+            categories.remove(name);
+        }) {
+            let msg = format!("Can't remove category: {:#}.", e);
+            hub.send(Event::Notification(NotificationEvent::Show(msg)))
+                .ok();
+            return;
+        }
+        self.refresh_visibles(true, false, hub, rq, context);
+    }
+
     fn remove(
         &mut self,
         path: &Path,
@@ -1687,11 +2007,16 @@ impl Home {
 
     fn sort(&mut self, update: bool, hub: &Hub, rq: &mut RenderQueue, context: &mut Context) {
         context.library.sort(self.sort_method, self.reverse_order);
-        sort(
-            &mut self.visible_books,
-            self.sort_method,
-            self.reverse_order,
-        );
+        if self.visible_groups.is_empty() {
+            sort(
+                &mut self.visible_books,
+                self.sort_method,
+                self.reverse_order,
+            );
+        } else {
+            // The series entries have to be rebuilt.
+            self.refresh_visibles(false, false, hub, rq, context);
+        }
 
         if update {
             self.update_shelf(false, hub, rq, context);
@@ -1759,18 +2084,24 @@ impl Home {
             shelf.set_thumbnail_previews(library_settings.thumbnail_previews);
         }
 
+        self.selected_category = None;
+        self.expanded_series.clear();
         let home = context.library.home.clone();
         self.select_directory(&home, hub, rq, context);
     }
 
     fn import(&mut self, hub: &Hub, rq: &mut RenderQueue, context: &mut Context) {
         context.library.import(&context.settings.import);
+        let (width, height) = thumbnail_size(CURRENT_DEVICE.dpi);
+        context
+            .library
+            .pregenerate_thumbnails(&context.settings.import, width, height);
         context.library.sort(self.sort_method, self.reverse_order);
         self.refresh_visibles(true, false, hub, rq, context);
     }
 
     fn clean_up(&mut self, hub: &Hub, rq: &mut RenderQueue, context: &mut Context) {
-        context.library.clean_up();
+        context.library.clean_up(&context.settings.import);
         self.refresh_visibles(true, false, hub, rq, context);
     }
 
@@ -1778,6 +2109,15 @@ impl Home {
         context.library.flush();
     }
 
+    fn export_json(&mut self, hub: &Hub, context: &mut Context) {
+        let msg = match context.library.export_json() {
+            Ok(()) => "Database exported to JSON.".to_string(),
+            Err(e) => format!("Can't export database: {:#}.", e),
+        };
+        hub.send(Event::Notification(NotificationEvent::Show(msg)))
+            .ok();
+    }
+
     fn terminate_fetchers(&mut self, path: &Path, update: bool, hub: &Hub, context: &mut Context) {
         self.background_fetchers.retain(|id, fetcher| {
             if fetcher.full_path == path {
@@ -2113,6 +2453,10 @@ impl View for Home {
                 self.toggle_rename_document(Some(false), hub, rq, context);
                 true
             }
+            Event::Close(ViewId::AddCategories) => {
+                self.toggle_add_categories(Some(false), hub, rq, context);
+                true
+            }
             Event::Select(EntryId::Sort(sort_method)) => {
                 let selected_library = context.settings.selected_library;
                 context.settings.libraries[selected_library].sort_method = sort_method;
@@ -2140,6 +2484,10 @@ impl View for Home {
                 self.flush(context);
                 true
             }
+            Event::Select(EntryId::ExportJson) => {
+                self.export_json(hub, context);
+                true
+            }
             Event::FetcherAddDocument(_, ref info) => {
                 self.add_document(*info.clone(), hub, rq, context);
                 true
@@ -2160,6 +2508,18 @@ impl View for Home {
                 self.update_second_column(hub, rq, context);
                 true
             }
+            Event::Select(EntryId::GroupSeries) => {
+                let selected_library = context.settings.selected_library;
+                context.settings.libraries[selected_library].group_series =
+                    !context.settings.libraries[selected_library].group_series;
+                self.expanded_series.clear();
+                self.refresh_visibles(true, true, hub, rq, context);
+                true
+            }
+            Event::ToggleExpandSeries(ref name) => {
+                self.toggle_expand_series(name, hub, rq, context);
+                true
+            }
             Event::Select(EntryId::ThumbnailPreviews) => {
                 let selected_library = context.settings.selected_library;
                 context.settings.libraries[selected_library].thumbnail_previews =
@@ -2219,6 +2579,12 @@ impl View for Home {
                 }
                 true
             }
+            Event::Submit(ViewId::AddCategoriesInput, ref text) => {
+                if let Some(ref path) = self.target_document.take() {
+                    self.add_categories(path, text, hub, rq, context);
+                }
+                true
+            }
             Event::NavigationBarResized(_) => {
                 self.adjust_shelf_top_edge();
                 self.update_shelf(true, hub, rq, context);
@@ -2241,6 +2607,15 @@ impl View for Home {
                 self.toggle_rename_document(Some(true), hub, rq, context);
                 true
             }
+            Event::Select(EntryId::AddCategories(ref path)) => {
+                self.target_document = Some(path.clone());
+                self.toggle_add_categories(Some(true), hub, rq, context);
+                true
+            }
+            Event::Select(EntryId::RemoveCategory(ref path, ref name)) => {
+                self.remove_category(path, name, hub, rq, context);
+                true
+            }
             Event::Select(EntryId::Remove(ref path))
             | Event::FetcherRemoveDocument(_, ref path) => {
                 self.remove(path, hub, rq, context)
@@ -2275,6 +2650,14 @@ impl View for Home {
                 self.toggle_select_directory(path, hub, rq, context);
                 true
             }
+            Event::ToggleSelectCategory(ref category) => {
+                self.toggle_select_category(category, hub, rq, context);
+                true
+            }
+            Event::Select(EntryId::SetNavigationMode(mode)) => {
+                self.set_navigation_mode(mode, hub, rq, context);
+                true
+            }
             Event::Select(EntryId::SearchAuthor(ref author)) => {
                 let text = format!("'a {}", author);
                 let query = BookQuery::new(&text);
@@ -2487,9 +2870,12 @@ impl View for Home {
             );
             nav_bar.vertical_limit =
                 rect.max.y - count * small_height - big_height - small_thickness;
-            nav_bar.set_path(
+            populate_navigation_bar(
+                nav_bar,
                 &self.current_directory,
                 &dirs,
+                self.selected_category.as_deref(),
+                &self.category_counts,
                 &mut RenderQueue::new(),
                 context,
             );
@@ -2602,3 +2988,41 @@ impl View for Home {
         self.id
     }
 }
+
+// Lists either the directories or the categories, depending on the navigation mode.
+// The dimensions of the thumbnail previews displayed in the shelf.
+pub fn thumbnail_size(dpi: u16) -> (f32, f32) {
+    let height = scale_by_dpi(BIG_BAR_HEIGHT, dpi) as i32;
+    ((3 * height / 4) as f32, height as f32)
+}
+
+fn populate_navigation_bar(
+    nav_bar: &mut NavigationBar,
+    current_directory: &Path,
+    dirs: &BTreeSet<PathBuf>,
+    category: Option<&str>,
+    counts: &BTreeMap<String, usize>,
+    rq: &mut RenderQueue,
+    context: &mut Context,
+) {
+    match context.settings.home.navigation_mode {
+        NavigationMode::Directories => nav_bar.set_path(current_directory, dirs, rq, context),
+        NavigationMode::Categories => {
+            nav_bar.set_categories(category, counts.clone(), rq, context);
+        }
+    }
+}
+
+// Groups the volumes of each series, if it's enabled for the current library.
+fn group_visible_series(
+    books: Metadata,
+    expanded: &FxHashSet<String>,
+    context: &Context,
+) -> (Metadata, Vec<Option<SeriesGroup>>) {
+    let selected_library = context.settings.selected_library;
+    if context.settings.libraries[selected_library].group_series {
+        group_series(&books, expanded)
+    } else {
+        (books, Vec::new())
+    }
+}
@@ -1,3 +1,4 @@
+use super::categories_bar::CategoriesBar;
 use super::directories_bar::DirectoriesBar;
 use crate::color::SEPARATOR_NORMAL;
 use crate::context::Context;
@@ -11,7 +12,7 @@ use crate::view::filler::Filler;
 use crate::view::{Bus, Event, Hub, Id, RenderData, RenderQueue, View, ID_FEEDER};
 use crate::view::{SMALL_BAR_HEIGHT, THICKNESS_MEDIUM};
 use fxhash::FxHashMap;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
@@ -235,10 +236,55 @@ impl NavigationBar {
         self.path = path.as_ref().to_path_buf();
     }
 
+    // Replaces the directories with the subcategories of the selected category.
+    pub fn set_categories(
+        &mut self,
+        selected: Option<&str>,
+        counts: BTreeMap<String, usize>,
+        rq: &mut RenderQueue,
+        context: &mut Context,
+    ) {
+        let dpi = CURRENT_DEVICE.dpi;
+        let thickness = scale_by_dpi(THICKNESS_MEDIUM, dpi) as i32;
+        let min_height = scale_by_dpi(SMALL_BAR_HEIGHT, dpi) as i32 - thickness;
+        let font = font_from_style(&mut context.fonts, &NORMAL_STYLE, dpi);
+        let x_height = font.x_heights.0 as i32;
+        let padding = min_height - x_height;
+
+        // Keep the height of the existing bar, it might have been resized by the user.
+        let existing = self
+            .children
+            .first()
+            .filter(|child| child.is::<CategoriesBar>())
+            .map(|child| *child.rect());
+        let rect = existing.unwrap_or_else(|| {
+            let count = guess_categories_bar_size(&counts) as i32;
+            let height = (count * x_height + (count + 1) * padding / 2)
+                .min(self.vertical_limit - self.rect.min.y)
+                .max(min_height);
+            rect![
+                self.rect.min.x,
+                self.rect.min.y,
+                self.rect.max.x,
+                self.rect.min.y + height
+            ]
+        });
+
+        let mut categories_bar = CategoriesBar::new(rect);
+        categories_bar.set_content(selected, counts, &mut context.fonts);
+        self.children.clear();
+        self.children.push(Box::new(categories_bar));
+
+        self.rect.max.y = rect.max.y;
+        rq.add(RenderData::new(self.id, self.rect, UpdateMode::Partial));
+    }
+
     pub fn shift(&mut self, delta: Point) {
         for child in &mut self.children {
             if let Some(dirs_bar) = child.downcast_mut::<DirectoriesBar>() {
                 dirs_bar.shift(delta);
+            } else if let Some(categories_bar) = child.downcast_mut::<CategoriesBar>() {
+                categories_bar.shift(delta);
             } else {
                 *child.rect_mut() += delta;
             }
@@ -315,11 +361,12 @@ impl NavigationBar {
         self.children[index].rect_mut().max.y = y_max;
         let y_shift = y_max - rect.max.y;
 
-        let dirs_bar = self.children[index]
-            .downcast_mut::<DirectoriesBar>()
-            .unwrap();
-        let dirs = dirs_bar.dirs();
-        dirs_bar.update_content(&dirs, self.path.as_ref(), fonts);
+        if let Some(dirs_bar) = self.children[index].downcast_mut::<DirectoriesBar>() {
+            let dirs = dirs_bar.dirs();
+            dirs_bar.update_content(&dirs, self.path.as_ref(), fonts);
+        } else if let Some(categories_bar) = self.children[index].downcast_mut::<CategoriesBar>() {
+            categories_bar.update_content(fonts);
+        }
 
         // Shift all the children after index.
         for i in index + 1..self.children.len() {
@@ -342,6 +389,11 @@ fn guess_bar_size(dirs: &BTreeSet<PathBuf>) -> usize {
     (dirs.iter().map(|dir| dir.as_os_str().len()).sum::<usize>() / 300).clamp(1, 4)
 }
 
+#[inline]
+fn guess_categories_bar_size(counts: &BTreeMap<String, usize>) -> usize {
+    (counts.keys().map(|name| name.len() + 4).sum::<usize>() / 150).clamp(1, 4)
+}
+
 impl View for NavigationBar {
     fn handle_event(
         &mut self,
@@ -359,7 +411,8 @@ impl View for NavigationBar {
                     Dir::North | Dir::South => {
                         let pt = if dir == Dir::North { end } else { start };
                         if let Some(index) = self.children.iter().position(|child| {
-                            child.is::<DirectoriesBar>() && child.rect().includes(pt)
+                            (child.is::<DirectoriesBar>() || child.is::<CategoriesBar>())
+                                && child.rect().includes(pt)
                         }) {
                             // Move the bottom edge of the child by end.y - start.y.
                             // Shift all the children after the child.
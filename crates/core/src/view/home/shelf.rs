@@ -1,28 +1,23 @@
 use super::book::Book;
+use super::thumbnail_size;
 use crate::color::{SEPARATOR_NORMAL, WHITE};
 use crate::context::Context;
 use crate::device::CURRENT_DEVICE;
-use crate::document::open;
 use crate::font::Fonts;
 use crate::framebuffer::{Framebuffer, UpdateMode};
 use crate::geom::divide;
 use crate::geom::{halves, CycleDir, Dir, Rectangle};
 use crate::gesture::GestureEvent;
-use crate::metadata::Info;
+use crate::library::thumbnails::generate_thumbnail;
+use crate::metadata::{Info, SeriesGroup};
 use crate::settings::{FirstColumn, SecondColumn};
 use crate::unit::scale_by_dpi;
 use crate::view::filler::Filler;
 use crate::view::{Bus, Event, Hub, Id, RenderData, RenderQueue, View, ID_FEEDER};
 use crate::view::{BIG_BAR_HEIGHT, THICKNESS_MEDIUM};
-use lazy_static::lazy_static;
 use std::path::PathBuf;
-use std::sync::Mutex;
 use std::thread;
 
-lazy_static! {
-    static ref EXCLUSIVE_ACCESS: Mutex<u8> = Mutex::new(0);
-}
-
 pub struct Shelf {
     id: Id,
     pub rect: Rectangle,
@@ -70,6 +65,7 @@ impl Shelf {
     pub fn update(
         &mut self,
         metadata: &[Info],
+        groups: &[Option<SeriesGroup>],
         hub: &Hub,
         rq: &mut RenderQueue,
         context: &Context,
@@ -82,8 +78,8 @@ impl Shelf {
         let max_lines = ((self.rect.height() as i32 + thickness) / big_height) as usize;
         let book_heights = divide(self.rect.height() as i32, max_lines as i32);
         let mut y_pos = self.rect.min.y;
-        let th = big_height;
-        let tw = 3 * th / 4;
+        let (tw, th) = thumbnail_size(dpi);
+        let (tw, th) = (tw as i32, th as i32);
 
         for (index, info) in metadata.iter().enumerate() {
             let y_min = y_pos + if index > 0 { big_thickness } else { 0 };
@@ -98,30 +94,13 @@ impl Shelf {
                 let thumb_path = context.library.thumbnail_preview(&info.file.path);
                 if !thumb_path.exists() {
                     let hub2 = hub.clone();
-                    let thumb_path2 = thumb_path.to_string_lossy().into_owned();
                     let path = info.file.path.clone();
                     let full_path = context.library.home.join(&info.file.path);
                     thread::spawn(move || {
-                        // This is a hack to circumvent a segfault (EXC_BAD_ACCESS)
-                        // triggered by loading multiple jp2 pixmaps in parallel.
-                        let _guard = EXCLUSIVE_ACCESS.lock().unwrap();
-                        open(full_path)
-                            .and_then(|mut doc| {
-                                doc.preview_pixmap(
-                                    tw as f32,
-                                    th as f32,
-                                    CURRENT_DEVICE.color_samples(),
-                                )
-                            })
-                            .map(|pixmap| {
-                                if pixmap.save(&thumb_path2).is_ok() {
-                                    hub2.send(Event::RefreshBookPreview(
-                                        path,
-                                        Some(PathBuf::from(thumb_path2)),
-                                    ))
-                                    .ok();
-                                }
-                            })
+                        if generate_thumbnail(&full_path, &thumb_path, tw as f32, th as f32) {
+                            hub2.send(Event::RefreshBookPreview(path, Some(thumb_path)))
+                                .ok();
+                        }
                     });
                     Some(PathBuf::default())
                 } else {
@@ -138,6 +117,7 @@ impl Shelf {
                 self.first_column,
                 self.second_column,
                 preview_path,
+                groups.get(index).cloned().flatten(),
             );
             self.children.push(Box::new(book) as Box<dyn View>);
 
@@ -17,6 +17,7 @@ pub mod common;
 pub mod dialog;
 pub mod dictionary;
 pub mod filler;
+pub mod footnote;
 pub mod frontlight;
 pub mod home;
 pub mod icon;
@@ -52,6 +53,7 @@ use self::key::KeyKind;
 use crate::color::Color;
 use crate::context::Context;
 use crate::document::{Location, TextLocation};
+use crate::export::ExportFormat;
 use crate::font::Fonts;
 use crate::framebuffer::{Framebuffer, UpdateMode};
 use crate::geom::{Boundary, CycleDir, LinearDir, Rectangle};
@@ -60,7 +62,7 @@ use crate::input::{DeviceEvent, FingerStatus};
 use crate::metadata::{
     Info, Margin, PageScheme, ScrollMode, SimpleStatus, SortMethod, TextAlign, ZoomMode,
 };
-use crate::settings::{ButtonScheme, FirstColumn, RotationLock, SecondColumn};
+use crate::settings::{ButtonScheme, FirstColumn, NavigationMode, RotationLock, SecondColumn};
 use downcast_rs::{impl_downcast, Downcast};
 use fxhash::FxHashMap;
 use std::collections::VecDeque;
@@ -378,6 +380,8 @@ pub enum Event {
     Chapter(CycleDir),
     SelectDirectory(PathBuf),
     ToggleSelectDirectory(PathBuf),
+    ToggleSelectCategory(String),
+    ToggleExpandSeries(String),
     NavigationBarResized(i32),
     Focus(Option<ViewId>),
     Select(EntryId),
@@ -410,6 +414,7 @@ pub enum Event {
     CheckFetcher(u32),
     EndOfSearch,
     Finished,
+    Reflowed,
     ClockTick,
     BatteryTick,
     ToggleFrontlight,
@@ -458,6 +463,7 @@ pub enum ViewId {
     KeyboardLayoutMenu,
     Frontlight,
     Dictionary,
+    Footnote,
     FontSizeMenu,
     TextAlignMenu,
     FontFamilyMenu,
@@ -475,6 +481,8 @@ pub enum ViewId {
     SketchMenu,
     RenameDocument,
     RenameDocumentInput,
+    AddCategories,
+    AddCategoriesInput,
     GoToPage,
     GoToPageInput,
     GoToResultsPage,
@@ -483,6 +491,8 @@ pub enum ViewId {
     NamePageInput,
     EditNote,
     EditNoteInput,
+    PasswordPrompt,
+    PasswordPromptInput,
     EditLanguages,
     EditLanguagesInput,
     HomeSearchInput,
@@ -495,6 +505,7 @@ pub enum ViewId {
     Keyboard,
     AboutDialog,
     ShareDialog,
+    NextVolumeDialog,
     MarginCropper,
     TopBottomBars,
     TableOfContents,
@@ -579,6 +590,7 @@ pub enum EntryId {
     Flush,
     Save,
     Import,
+    ExportJson,
     CleanUp,
     Sort(SortMethod),
     ReverseOrder,
@@ -590,12 +602,17 @@ pub enum EntryId {
     AddDirectory(PathBuf),
     SelectDirectory(PathBuf),
     ToggleSelectDirectory(PathBuf),
+    SetNavigationMode(NavigationMode),
+    AddCategories(PathBuf),
+    RemoveCategory(PathBuf, String),
     SetStatus(PathBuf, SimpleStatus),
     SearchAuthor(String),
     RemovePreset(usize),
     FirstColumn(FirstColumn),
     SecondColumn(SecondColumn),
     ThumbnailPreviews,
+    GroupSeries,
+    OpenNextVolume,
     ApplyCroppings(usize, PageScheme),
     RemoveCroppings,
     SetZoomMode(ZoomMode),
@@ -608,6 +625,7 @@ pub enum EntryId {
     SearchForSelection,
     AdjustSelection,
     Annotations,
+    ExportAnnotations(ExportFormat),
     Bookmarks,
     RemoveAnnotation([TextLocation; 2]),
     EditAnnotationNote([TextLocation; 2]),
@@ -631,6 +649,7 @@ pub enum EntryId {
     ToggleFuzzy,
     ToggleInverted,
     ToggleDithered,
+    ToggleReflow,
     ToggleWifi,
     Rotate(i8),
     Launch(AppCmd),
@@ -14,12 +14,14 @@ use crate::color::{BLACK, WHITE};
 use crate::context::Context;
 use crate::device::CURRENT_DEVICE;
 use crate::document::html::HtmlDocument;
+use crate::document::reflow::{is_cached, page_from_offset, ReflowDocument};
 use crate::document::{
     annotations_as_html, bookmarks_as_html, toc_as_html, SimpleTocEntry, TocEntry, TocLocation,
 };
 use crate::document::{
     open, BoundedText, Document, Location, Neighbors, TextLocation, BYTES_PER_PAGE,
 };
+use crate::export::{export_annotations, ExportFormat};
 use crate::font::family_names;
 use crate::font::Fonts;
 use crate::framebuffer::{Framebuffer, Pixmap, UpdateMode};
@@ -45,14 +47,16 @@ use crate::settings::{HYPHEN_PENALTY, STRETCH_TOLERANCE};
 use crate::unit::{mm_to_px, scale_by_dpi};
 use crate::view::common::{locate, locate_by_id, rlocate};
 use crate::view::common::{toggle_battery_menu, toggle_clock_menu, toggle_main_menu};
+use crate::view::dialog::Dialog;
 use crate::view::filler::Filler;
+use crate::view::footnote::Footnote;
 use crate::view::keyboard::Keyboard;
 use crate::view::menu::{Menu, MenuKind};
 use crate::view::menu_entry::MenuEntry;
 use crate::view::named_input::NamedInput;
 use crate::view::notification::Notification;
 use crate::view::search_bar::SearchBar;
-use crate::view::{AppCmd, Bus, Event, Hub, RenderData, RenderQueue, View};
+use crate::view::{AppCmd, Bus, Event, Hub, NotificationEvent, RenderData, RenderQueue, View};
 use crate::view::{EntryId, EntryKind, Id, SliderId, ViewId, ID_FEEDER};
 use crate::view::{BIG_BAR_HEIGHT, SMALL_BAR_HEIGHT, THICKNESS_MEDIUM};
 use chrono::Local;
@@ -64,6 +68,7 @@ use septem::{Digit, Roman};
 use std::collections::{BTreeMap, VecDeque};
 use std::fs::OpenOptions;
 use std::io::prelude::*;
+use std::mem;
 use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering as AtomicOrdering;
@@ -82,6 +87,7 @@ pub struct Reader {
     children: Vec<Box<dyn View>>,
     doc: Arc<Mutex<Box<dyn Document>>>,
     cache: BTreeMap<usize, Resource>,         // Cached page pixmaps.
+    columns: FxHashMap<usize, Vec<Boundary>>, // Cached column layouts.
     chunks: Vec<RenderChunk>,                 // Chunks of pages being rendered.
     text: FxHashMap<usize, Vec<BoundedText>>, // Text of the current chunks.
     annotations: FxHashMap<usize, Vec<Annotation>>, // Annotations for the current chunks.
@@ -100,8 +106,19 @@ pub struct Reader {
     view_port: ViewPort,
     contrast: Contrast,
     synthetic: bool,
+    rtl: bool,
     page_turns: usize,
     reflowable: bool,
+    // The offsets of the pages of the original document, when its text is reflowed.
+    page_offsets: Option<Vec<usize>>,
+    // The reflowed document being built in the background.
+    pending_reflow: Option<Arc<Mutex<Option<ReflowDocument>>>>,
+    // Whether the annotations are synchronized with the highlights embedded in the document.
+    sync_annotations: bool,
+    // The password that unlocked the document.
+    password: Option<String>,
+    // Whether the document is waiting for its password.
+    locked: bool,
     ephemeral: bool,
     finished: bool,
 }
@@ -217,6 +234,27 @@ fn scaling_factor(
     }
 }
 
+// Returns the column layout of the given page, detecting it on first use.
+fn page_columns<'a>(
+    columns: &'a mut FxHashMap<usize, Vec<Boundary>>,
+    doc: &mut dyn Document,
+    location: usize,
+) -> &'a [Boundary] {
+    columns
+        .entry(location)
+        .or_insert_with(|| doc.columns(location).unwrap_or_default())
+}
+
+// Returns the index of the column that holds the given point, and the point's
+// ordinate. Falls back to the top of the first column.
+fn locate_column(columns: &[Rectangle], point: Point) -> (usize, i32) {
+    columns
+        .iter()
+        .position(|c| c.min.x == point.x && point.y >= c.min.y && point.y < c.max.y)
+        .map(|index| (index, point.y))
+        .unwrap_or((0, columns[0].min.y))
+}
+
 fn build_pixmap(rect: &Rectangle, doc: &mut dyn Document, location: usize) -> (Pixmap, usize) {
     let scale = scaling_factor(
         rect,
@@ -277,16 +315,60 @@ fn word_separator(lang: &str) -> &'static str {
 
 impl Reader {
     pub fn new(
+        rect: Rectangle,
+        info: Info,
+        hub: &Hub,
+        rq: &mut RenderQueue,
+        context: &mut Context,
+    ) -> Option<Reader> {
+        Reader::with_document(rect, info, None, None, hub, rq, context)
+    }
+
+    // Opens the document, unless it's given, and unlocks it if it's protected, with the
+    // given password or the one remembered by the library.
+    fn with_document(
         rect: Rectangle,
         mut info: Info,
+        doc: Option<Box<dyn Document>>,
+        password: Option<String>,
         hub: &Hub,
+        rq: &mut RenderQueue,
         context: &mut Context,
     ) -> Option<Reader> {
         let id = ID_FEEDER.next();
-        let settings = &context.settings;
         let path = context.library.home.join(&info.file.path);
+        let reflow = info.reader.as_ref().is_some_and(|r| r.reflow);
+        let mut page_offsets = None;
+        let mut password = password;
+
+        let mut doc = doc.or_else(|| open(&path));
+        let locked = doc.as_mut().is_some_and(|doc| {
+            if !doc.needs_password() {
+                return false;
+            }
+            password = password
+                .take()
+                .or_else(|| context.library.password(&info.file.path));
+            !password.as_ref().is_some_and(|p| doc.authenticate(p))
+        });
+
+        if locked {
+            return doc.map(|doc| Reader::locked(rect, doc, info, hub, rq, context));
+        }
+
+        let settings = &context.settings;
 
-        open(&path).and_then(|mut doc| {
+        let doc = doc.map(|doc| {
+            if reflow && !doc.is_reflowable() {
+                let doc = ReflowDocument::new(doc, context.library.reflow_cache(&info.file.path));
+                page_offsets = Some(doc.page_offsets().to_vec());
+                Box::new(doc) as Box<dyn Document>
+            } else {
+                doc
+            }
+        });
+
+        doc.and_then(|mut doc| {
             let (width, height) = context.display.dims;
             let font_size = info
                 .reader
@@ -415,8 +497,28 @@ impl Reader {
                 });
             }
 
+            // The embedded highlights aren't rendered while the document is open, since
+            // they're drawn as annotations, and are updated when it's closed.
+            let sync_annotations = settings.reader.sync_pdf_annotations
+                && info.file.kind == "pdf"
+                && !doc.is_reflowable();
+            if sync_annotations {
+                let embedded = doc.embedded_annotations();
+                if let Some(ref mut r) = info.reader {
+                    for annot in embedded {
+                        if !r.annotations.iter().any(|a| {
+                            a.selection[0] <= annot.selection[0]
+                                && a.selection[1] >= annot.selection[1]
+                        }) {
+                            r.annotations.push(annot);
+                        }
+                    }
+                }
+            }
+
             let synthetic = doc.has_synthetic_page_numbers();
             let reflowable = doc.is_reflowable();
+            let rtl = doc.is_rtl();
 
             println!("{}", info.file.path.display());
 
@@ -428,6 +530,7 @@ impl Reader {
                 children: Vec::new(),
                 doc: Arc::new(Mutex::new(doc)),
                 cache: BTreeMap::new(),
+                columns: FxHashMap::default(),
                 chunks: Vec::new(),
                 text: FxHashMap::default(),
                 annotations: FxHashMap::default(),
@@ -445,10 +548,16 @@ impl Reader {
                 pages_count,
                 view_port,
                 synthetic,
+                rtl,
                 page_turns: 0,
                 contrast,
                 ephemeral: false,
                 reflowable,
+                page_offsets,
+                pending_reflow: None,
+                sync_annotations,
+                password,
+                locked: false,
                 finished: false,
             })
         })
@@ -499,6 +608,7 @@ impl Reader {
             children: Vec::new(),
             doc: Arc::new(Mutex::new(Box::new(doc))),
             cache: BTreeMap::new(),
+            columns: FxHashMap::default(),
             chunks: Vec::new(),
             text: FxHashMap::default(),
             annotations: FxHashMap::default(),
@@ -516,14 +626,71 @@ impl Reader {
             pages_count,
             view_port: ViewPort::default(),
             synthetic: true,
+            rtl: false,
             page_turns: 0,
             contrast: Contrast::default(),
             ephemeral: true,
             reflowable: true,
+            page_offsets: None,
+            pending_reflow: None,
+            sync_annotations: false,
+            password: None,
+            locked: false,
             finished: false,
         }
     }
 
+    // A reader that asks for the password of a protected document.
+    fn locked(
+        rect: Rectangle,
+        doc: Box<dyn Document>,
+        info: Info,
+        hub: &Hub,
+        rq: &mut RenderQueue,
+        context: &mut Context,
+    ) -> Reader {
+        let id = ID_FEEDER.next();
+        let mut reader = Reader {
+            id,
+            rect,
+            children: Vec::new(),
+            doc: Arc::new(Mutex::new(doc)),
+            cache: BTreeMap::new(),
+            columns: FxHashMap::default(),
+            chunks: Vec::new(),
+            text: FxHashMap::default(),
+            annotations: FxHashMap::default(),
+            noninverted_regions: FxHashMap::default(),
+            focus: None,
+            search: None,
+            search_direction: LinearDir::Forward,
+            held_buttons: FxHashSet::default(),
+            selection: None,
+            target_annotation: None,
+            history: VecDeque::new(),
+            state: State::Idle,
+            info,
+            current_page: 0,
+            pages_count: 0,
+            view_port: ViewPort::default(),
+            synthetic: false,
+            rtl: false,
+            page_turns: 0,
+            contrast: Contrast::default(),
+            ephemeral: false,
+            reflowable: false,
+            page_offsets: None,
+            pending_reflow: None,
+            sync_annotations: false,
+            password: None,
+            locked: true,
+            finished: false,
+        };
+        hub.send(Event::Update(UpdateMode::Partial)).ok();
+        reader.toggle_password_prompt(hub, rq, context);
+        reader
+    }
+
     fn load_pixmap(&mut self, location: usize) {
         if self.cache.contains_key(&location) {
             return;
@@ -534,18 +701,30 @@ impl Reader {
             .info
             .reader
             .as_ref()
+            .filter(|_| !self.reflowable)
             .and_then(|r| r.cropping_margins.as_ref().map(|c| c.margin(location)))
             .cloned()
             .unwrap_or_default();
         let dims = doc.dims(location).unwrap_or((3.0, 4.0));
         let screen_margin_width = self.view_port.margin_width;
-        let scale = scaling_factor(
+        let mut scale = scaling_factor(
             &self.rect,
             &cropping_margin,
             screen_margin_width,
             dims,
             self.view_port.zoom_mode,
         );
+        if self.view_port.zoom_mode == ZoomMode::FitToWidth
+            && self.view_port.scroll_mode == ScrollMode::Columns
+        {
+            let width = page_columns(&mut self.columns, doc.as_mut(), location)
+                .iter()
+                .map(|column| column.width())
+                .fold(0.0, f32::max);
+            if width > 0.0 {
+                scale = (self.rect.width() as i32 - 2 * screen_margin_width) as f32 / width;
+            }
+        }
         if let Some((pixmap, _)) = doc.pixmap(
             Location::Exact(location),
             scale,
@@ -581,6 +760,22 @@ impl Reader {
         }
     }
 
+    // The frames of the text columns of the given page, within its pixmap.
+    fn column_frames(&mut self, location: usize) -> Vec<Rectangle> {
+        self.load_pixmap(location);
+        let Resource { frame, scale, .. } = self.cache[&location];
+        let mut doc = self.doc.lock().unwrap();
+        let frames: Vec<Rectangle> = page_columns(&mut self.columns, doc.as_mut(), location)
+            .iter()
+            .filter_map(|column| (*column * scale).to_rect().intersection(&frame))
+            .collect();
+        if frames.is_empty() {
+            vec![frame]
+        } else {
+            frames
+        }
+    }
+
     fn load_text(&mut self, location: usize) {
         if self.text.contains_key(&location) {
             return;
@@ -817,6 +1012,16 @@ impl Reader {
                     next_top_offset = self.view_port.page_offset.y;
                 }
             }
+            ScrollMode::Columns => {
+                let columns = self.column_frames(location);
+                let frame = self.cache[&location].frame;
+                let available_height = self.rect.height() as i32 - 2 * self.view_port.margin_width;
+                let (index, top) = locate_column(&columns, frame.min + self.view_port.page_offset);
+                let column = columns[index];
+                let max_top = (column.max.y - available_height).max(column.min.y);
+                next_top_offset = (top + delta_y).clamp(column.min.y, max_top) - frame.min.y;
+                self.view_port.page_offset.x = column.min.x - frame.min.x;
+            }
         }
 
         let location_changed = location != self.current_page;
@@ -862,6 +1067,33 @@ impl Reader {
         }
     }
 
+    // Horizontal page turns are mirrored when the pages progress from right to left.
+    fn page_turn_dir(&self, dir: Dir) -> Dir {
+        match dir {
+            Dir::West | Dir::East if self.rtl => dir.opposite(),
+            _ => dir,
+        }
+    }
+
+    fn run_finished_action(&mut self, hub: &Hub, rq: &mut RenderQueue, context: &mut Context) {
+        let action = if self.ephemeral {
+            FinishedAction::Notify
+        } else {
+            context.settings.reader.finished
+        };
+        match action {
+            FinishedAction::Notify => {
+                let notif =
+                    Notification::new(None, "No next page.".to_string(), false, hub, rq, context);
+                self.children.push(Box::new(notif) as Box<dyn View>);
+            }
+            FinishedAction::Close => {
+                self.quit(context);
+                hub.send(Event::Back).ok();
+            }
+        }
+    }
+
     fn go_to_neighbor(
         &mut self,
         dir: CycleDir,
@@ -952,6 +1184,39 @@ impl Reader {
                                 Location::Previous(current_page)
                             }
                         }
+                        ScrollMode::Columns => {
+                            let available_height =
+                                self.rect.height() as i32 - 2 * self.view_port.margin_width;
+                            let columns = self.column_frames(current_page);
+                            let frame = self.cache[&current_page].frame;
+                            let (index, top) =
+                                locate_column(&columns, frame.min + self.view_port.page_offset);
+                            let column = columns[index];
+                            if top > column.min.y {
+                                let top = (top - available_height).max(column.min.y);
+                                self.view_port.page_offset = pt!(column.min.x, top) - frame.min;
+                                Location::Exact(current_page)
+                            } else if index > 0 {
+                                let column = columns[index - 1];
+                                let top = (column.max.y - available_height).max(column.min.y);
+                                self.view_port.page_offset = pt!(column.min.x, top) - frame.min;
+                                Location::Exact(current_page)
+                            } else {
+                                let previous_location = self
+                                    .doc
+                                    .lock()
+                                    .unwrap()
+                                    .resolve_location(Location::Previous(current_page));
+                                if let Some(location) = previous_location {
+                                    let columns = self.column_frames(location);
+                                    let frame = self.cache[&location].frame;
+                                    let column = columns[columns.len() - 1];
+                                    let top = (column.max.y - available_height).max(column.min.y);
+                                    self.view_port.page_offset = pt!(column.min.x, top) - frame.min;
+                                }
+                                Location::Previous(current_page)
+                            }
+                        }
                     },
                     ZoomMode::Custom(_) => {
                         self.view_port.page_offset = pt!(0);
@@ -991,6 +1256,27 @@ impl Reader {
                                 Location::Exact(current_page)
                             }
                         }
+                        ScrollMode::Columns => {
+                            let available_height =
+                                self.rect.height() as i32 - 2 * self.view_port.margin_width;
+                            let columns = self.column_frames(current_page);
+                            let frame = self.cache[&current_page].frame;
+                            let (index, top) =
+                                locate_column(&columns, frame.min + self.view_port.page_offset);
+                            let column = columns[index];
+                            if top + available_height < column.max.y {
+                                let top =
+                                    (top + available_height).min(column.max.y - available_height);
+                                self.view_port.page_offset = pt!(column.min.x, top) - frame.min;
+                                Location::Exact(current_page)
+                            } else if let Some(column) = columns.get(index + 1) {
+                                self.view_port.page_offset = column.min - frame.min;
+                                Location::Exact(current_page)
+                            } else {
+                                self.view_port.page_offset = pt!(0);
+                                Location::Next(current_page)
+                            }
+                        }
                     },
                     ZoomMode::Custom(_) => {
                         self.view_port.page_offset = pt!(0);
@@ -1022,28 +1308,30 @@ impl Reader {
             _ => match dir {
                 CycleDir::Next => {
                     self.finished = true;
-                    let action = if self.ephemeral {
-                        FinishedAction::Notify
+                    if locate_by_id(self, ViewId::NextVolumeDialog).is_some() {
+                        return;
+                    }
+                    let next_volume = if self.ephemeral {
+                        None
                     } else {
-                        context.settings.reader.finished
+                        context.library.next_in_series(&self.info)
                     };
-                    match action {
-                        FinishedAction::Notify => {
-                            let notif = Notification::new(
-                                None,
-                                "No next page.".to_string(),
-                                false,
-                                hub,
-                                rq,
-                                context,
-                            );
-                            self.children.push(Box::new(notif) as Box<dyn View>);
-                        }
-                        FinishedAction::Close => {
-                            self.quit(context);
-                            hub.send(Event::Back).ok();
-                        }
+                    if let Some(info) = next_volume {
+                        let dialog = Dialog::new(
+                            ViewId::NextVolumeDialog,
+                            Some(Event::Select(EntryId::OpenNextVolume)),
+                            format!("Open the next volume?\n{}", info.title()),
+                            context,
+                        );
+                        rq.add(RenderData::new(
+                            dialog.id(),
+                            *dialog.rect(),
+                            UpdateMode::Gui,
+                        ));
+                        self.children.push(Box::new(dialog) as Box<dyn View>);
+                        return;
                     }
+                    self.run_finished_action(hub, rq, context);
                 }
                 CycleDir::Previous => {
                     let notif = Notification::new(
@@ -1386,6 +1674,28 @@ impl Reader {
                         scale,
                     });
                 }
+                ScrollMode::Columns => {
+                    let columns = self.column_frames(location);
+                    self.load_text(location);
+                    let available_width = self.rect.width() as i32 - 2 * smw;
+                    let available_height = self.rect.height() as i32 - 2 * smw;
+                    let Resource { frame, scale, .. } = self.cache[&location];
+                    let (index, top) =
+                        locate_column(&columns, frame.min + self.view_port.page_offset);
+                    let mut frame = columns[index];
+                    frame.min.y = top;
+                    frame.max.y = (top + available_height).min(frame.max.y);
+                    let position = pt!(
+                        smw + (available_width - frame.width() as i32) / 2,
+                        smw + (available_height - frame.height() as i32) / 2
+                    );
+                    self.chunks.push(RenderChunk {
+                        frame,
+                        location,
+                        position,
+                        scale,
+                    });
+                }
             },
             ZoomMode::Custom(_) => {
                 self.load_pixmap(location);
@@ -2208,6 +2518,110 @@ impl Reader {
         }
     }
 
+    fn toggle_password_prompt(&mut self, hub: &Hub, rq: &mut RenderQueue, context: &mut Context) {
+        if locate_by_id(self, ViewId::PasswordPrompt).is_some() {
+            return;
+        }
+
+        let prompt = NamedInput::new(
+            "Password".to_string(),
+            ViewId::PasswordPrompt,
+            ViewId::PasswordPromptInput,
+            16,
+            context,
+        );
+        rq.add(RenderData::new(
+            prompt.id(),
+            *prompt.rect(),
+            UpdateMode::Gui,
+        ));
+        hub.send(Event::Focus(Some(ViewId::PasswordPromptInput)))
+            .ok();
+
+        self.children.push(Box::new(prompt) as Box<dyn View>);
+    }
+
+    // Unlocks the document and replaces the prompt with the actual reader.
+    fn unlock(&mut self, password: &str, hub: &Hub, rq: &mut RenderQueue, context: &mut Context) {
+        let authenticated = self.doc.lock().unwrap().authenticate(password);
+        if !authenticated {
+            let notif = Notification::new(
+                None,
+                "Incorrect password. Tap to try again.".to_string(),
+                false,
+                hub,
+                rq,
+                context,
+            );
+            self.children.push(Box::new(notif) as Box<dyn View>);
+            return;
+        }
+
+        self.toggle_keyboard(false, None, hub, rq, context);
+        let info = self.info.clone();
+        // The authenticated document is handed over to the actual reader.
+        let doc = mem::replace(
+            &mut *self.doc.lock().unwrap(),
+            Box::new(HtmlDocument::new_from_memory("")),
+        );
+        if let Some(reader) = Reader::with_document(
+            self.rect,
+            info,
+            Some(doc),
+            Some(password.to_string()),
+            hub,
+            rq,
+            context,
+        ) {
+            *self = reader;
+        } else {
+            hub.send(Event::Back).ok();
+            hub.send(Event::Invalid(self.info.file.path.clone())).ok();
+        }
+    }
+
+    // Handles the events received while the document is waiting for its password.
+    fn handle_locked_event(
+        &mut self,
+        evt: &Event,
+        hub: &Hub,
+        rq: &mut RenderQueue,
+        context: &mut Context,
+    ) -> bool {
+        match *evt {
+            Event::Submit(ViewId::PasswordPromptInput, ref text) => {
+                self.unlock(text, hub, rq, context);
+                true
+            }
+            Event::Close(ViewId::PasswordPrompt) => {
+                self.toggle_keyboard(false, None, hub, rq, context);
+                false
+            }
+            Event::Gesture(GestureEvent::Tap(center)) if self.rect.includes(center) => {
+                self.toggle_password_prompt(hub, rq, context);
+                true
+            }
+            Event::Gesture(GestureEvent::Cross(_)) => {
+                hub.send(Event::Back).ok();
+                true
+            }
+            Event::Update(mode) => {
+                rq.add(RenderData::new(self.id, self.rect, mode));
+                true
+            }
+            Event::Focus(v) => {
+                if self.focus != v {
+                    self.focus = v;
+                    if v.is_some() {
+                        self.toggle_keyboard(true, v, hub, rq, context);
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
     fn toggle_go_to_page(
         &mut self,
         enable: Option<bool>,
@@ -2479,9 +2893,25 @@ impl Reader {
                         EntryId::SetScrollMode(ScrollMode::Page),
                         scroll_mode == ScrollMode::Page,
                     ),
+                    EntryKind::RadioButton(
+                        "Columns".to_string(),
+                        EntryId::SetScrollMode(ScrollMode::Columns),
+                        scroll_mode == ScrollMode::Columns,
+                    ),
                 ],
             ));
 
+            if !self.ephemeral
+                && (self.page_offsets.is_some()
+                    || matches!(self.info.file.kind.as_str(), "pdf" | "djvu" | "djv"))
+            {
+                entries.push(EntryKind::CheckBox(
+                    "Reflow Text".to_string(),
+                    EntryId::ToggleReflow,
+                    self.page_offsets.is_some(),
+                ));
+            }
+
             if self.ephemeral {
                 entries.push(EntryKind::Command("Save".to_string(), EntryId::Save));
             }
@@ -2496,6 +2926,22 @@ impl Reader {
                     "Annotations".to_string(),
                     EntryId::Annotations,
                 ));
+                entries.push(EntryKind::SubMenu(
+                    "Export Annotations".to_string(),
+                    [
+                        ExportFormat::Markdown,
+                        ExportFormat::Html,
+                        ExportFormat::Json,
+                    ]
+                    .iter()
+                    .map(|format| {
+                        EntryKind::Command(
+                            format.label().to_string(),
+                            EntryId::ExportAnnotations(*format),
+                        )
+                    })
+                    .collect(),
+                ));
             }
 
             if self
@@ -3427,6 +3873,10 @@ impl Reader {
         {
             return;
         }
+        // The pages are scaled to the width of their columns in this mode.
+        if scroll_mode == ScrollMode::Columns || self.view_port.scroll_mode == ScrollMode::Columns {
+            self.cache.clear();
+        }
         self.view_port.scroll_mode = scroll_mode;
         self.view_port.page_offset = pt!(0, 0);
         self.update(None, hub, rq, context);
@@ -3676,7 +4126,7 @@ impl Reader {
             s.running.store(false, AtomicOrdering::Relaxed);
         }
 
-        if self.ephemeral {
+        if self.ephemeral || self.locked {
             return;
         }
 
@@ -3714,10 +4164,168 @@ impl Reader {
                 r.contrast_gray = None;
             }
 
+            if self.sync_annotations {
+                let path = context.library.home.join(&self.info.file.path);
+                let fp = context.library.fingerprint(&self.info.file.path);
+                match self
+                    .doc
+                    .lock()
+                    .unwrap()
+                    .save_annotations(&r.annotations, &path)
+                {
+                    Err(e) => {
+                        eprintln!("Can't save annotations to {}: {:#}.", path.display(), e)
+                    }
+                    // Saving the document changes its fingerprint.
+                    Ok(()) => {
+                        if let Some(fp) = fp {
+                            context.library.update_fingerprint(&self.info.file.path, fp);
+                        }
+                    }
+                }
+            }
+
+            if let Some(password) = self.password.as_ref() {
+                let password =
+                    Some(password.as_str()).filter(|_| context.settings.reader.remember_passwords);
+                context.library.set_password(&self.info.file.path, password);
+            }
+
             context.library.sync_reader_info(&self.info.file.path, r);
         }
     }
 
+    // Reopens the document with, or without, its text reflowed. Unless it's cached, the
+    // reflowed document is built in the background, and swapped in once it's ready.
+    fn toggle_reflow(&mut self, hub: &Hub, rq: &mut RenderQueue, context: &mut Context) {
+        if self.pending_reflow.is_some() {
+            return;
+        }
+
+        if self.page_offsets.is_none() {
+            let cache_dir = context.library.reflow_cache(&self.info.file.path);
+            if !cache_dir.as_deref().is_some_and(is_cached) {
+                self.start_reflow(cache_dir, hub, context);
+                return;
+            }
+        }
+
+        self.reopen(None, hub, rq, context);
+    }
+
+    fn start_reflow(&mut self, cache_dir: Option<PathBuf>, hub: &Hub, context: &Context) {
+        let path = context.library.home.join(&self.info.file.path);
+        let password = self.password.clone();
+        let reflowed = Arc::new(Mutex::new(None));
+        let reflowed2 = Arc::clone(&reflowed);
+        let hub2 = hub.clone();
+        let notif_id = ViewId::MessageNotif(ID_FEEDER.next());
+
+        hub.send(Event::Notification(NotificationEvent::ShowPinned(
+            notif_id,
+            "Reflowing the text.".to_string(),
+        )))
+        .ok();
+        hub.send(Event::Notification(NotificationEvent::UpdateProgress(
+            notif_id, 0,
+        )))
+        .ok();
+
+        thread::spawn(move || {
+            let doc = open(&path).and_then(|mut doc| {
+                let unlocked =
+                    !doc.needs_password() || password.as_ref().is_some_and(|p| doc.authenticate(p));
+                Some(doc).filter(|_| unlocked)
+            });
+            if let Some(doc) = doc {
+                let doc = ReflowDocument::with_progress(doc, cache_dir, |progress| {
+                    hub2.send(Event::Notification(NotificationEvent::UpdateProgress(
+                        notif_id, progress,
+                    )))
+                    .ok();
+                });
+                *reflowed2.lock().unwrap() = Some(doc);
+            }
+            hub2.send(Event::Close(notif_id)).ok();
+            hub2.send(Event::Reflowed).ok();
+        });
+
+        self.pending_reflow = Some(reflowed);
+    }
+
+    // Replaces the reader by one that reads the same document with, or without, its text
+    // reflowed. The given document, if any, is already reflowed.
+    fn reopen(
+        &mut self,
+        doc: Option<ReflowDocument>,
+        hub: &Hub,
+        rq: &mut RenderQueue,
+        context: &mut Context,
+    ) {
+        let current_page = self.current_page;
+        self.quit(context);
+
+        let mut info = self.info.clone();
+        if let Some(ref mut r) = info.reader {
+            r.reflow = self.page_offsets.is_none();
+            r.zoom_mode = None;
+            r.scroll_mode = None;
+            r.page_offset = None;
+            // The locations of the annotations don't translate between both layouts.
+            mem::swap(&mut r.annotations, &mut r.other_annotations);
+            mem::swap(&mut r.page_names, &mut r.other_page_names);
+            if let Some(page_offsets) = self.page_offsets.as_ref() {
+                r.current_page = page_from_offset(page_offsets, current_page);
+                r.bookmarks = r
+                    .bookmarks
+                    .iter()
+                    .map(|&offset| page_from_offset(page_offsets, offset))
+                    .collect();
+            }
+        }
+
+        let page_offsets = doc.as_ref().map(|doc| doc.page_offsets().to_vec());
+        let doc = doc.map(|doc| Box::new(doc) as Box<dyn Document>);
+        if let Some(mut reader) = Reader::with_document(
+            self.rect,
+            info,
+            doc,
+            self.password.clone(),
+            hub,
+            rq,
+            context,
+        ) {
+            if page_offsets.is_some() {
+                reader.page_offsets = page_offsets;
+            }
+            if self.page_offsets.is_none() {
+                if let Some(page_offsets) = reader.page_offsets.clone() {
+                    let offset = page_offsets.get(current_page).copied().unwrap_or_default();
+                    if let Some(location) = reader
+                        .doc
+                        .lock()
+                        .unwrap()
+                        .resolve_location(Location::Exact(offset))
+                    {
+                        reader.current_page = location;
+                    }
+                    if let Some(ref mut r) = reader.info.reader {
+                        r.current_page = reader.current_page;
+                        r.bookmarks = r
+                            .bookmarks
+                            .iter()
+                            .filter_map(|&index| page_offsets.get(index).copied())
+                            .collect();
+                    }
+                }
+            }
+            if let Some(ref r) = reader.info.reader {
+                context.library.sync_reader_info(&reader.info.file.path, r);
+            }
+            *self = reader;
+        }
+    }
+
     fn scale_page(
         &mut self,
         center: Point,
@@ -3770,6 +4378,10 @@ impl View for Reader {
         rq: &mut RenderQueue,
         context: &mut Context,
     ) -> bool {
+        if self.locked {
+            return self.handle_locked_event(evt, hub, rq, context);
+        }
+
         match *evt {
             Event::Gesture(GestureEvent::Rotate { quarter_turns, .. }) if quarter_turns != 0 => {
                 let (_, dir) = CURRENT_DEVICE.mirroring_scheme();
@@ -3782,7 +4394,7 @@ impl View for Reader {
             {
                 match self.view_port.zoom_mode {
                     ZoomMode::FitToPage | ZoomMode::FitToWidth => {
-                        match dir {
+                        match self.page_turn_dir(dir) {
                             Dir::West => self.go_to_neighbor(CycleDir::Next, hub, rq, context),
                             Dir::East => self.go_to_neighbor(CycleDir::Previous, hub, rq, context),
                             Dir::South | Dir::North => {
@@ -4276,6 +4888,30 @@ impl View for Reader {
                         }
                     } else {
                         let mut doc = self.doc.lock().unwrap();
+                        let offset = match link.location {
+                            TextLocation::Dynamic(offset) => offset,
+                            TextLocation::Static(..) => self.current_page,
+                        };
+                        if let Some(note) =
+                            doc.footnote(Location::LocalUri(offset, link.text.clone()))
+                        {
+                            let font_size = self
+                                .info
+                                .reader
+                                .as_ref()
+                                .and_then(|r| r.font_size)
+                                .unwrap_or(context.settings.reader.font_size);
+                            let margin_width = self
+                                .info
+                                .reader
+                                .as_ref()
+                                .and_then(|r| r.margin_width)
+                                .unwrap_or(context.settings.reader.margin_width);
+                            let footnote =
+                                Footnote::new(self.rect, note, font_size, margin_width, rq);
+                            self.children.push(Box::new(footnote) as Box<dyn View>);
+                            return true;
+                        }
                         let loc = Location::LocalUri(self.current_page, link.text.clone());
                         if let Some(location) = doc.resolve_location(loc) {
                             hub.send(Event::GoTo(location)).ok();
@@ -4376,7 +5012,7 @@ impl View for Reader {
                             }
                         }
                     },
-                    Region::Strip(dir) => match dir {
+                    Region::Strip(dir) => match self.page_turn_dir(dir) {
                         Dir::West => {
                             if self.search.is_none() {
                                 match context.settings.reader.west_strip {
@@ -4787,6 +5423,13 @@ impl View for Reader {
                 self.toggle_keyboard(false, None, hub, rq, context);
                 false
             }
+            // The next volume wasn't opened: fall back to the configured action.
+            Event::Close(ViewId::NextVolumeDialog) => {
+                if locate_by_id(self, ViewId::NextVolumeDialog).is_some() {
+                    self.run_finished_action(hub, rq, context);
+                }
+                false
+            }
             Event::Show(ViewId::TableOfContents) => {
                 {
                     self.toggle_bars(Some(false), hub, rq, context);
@@ -4853,6 +5496,16 @@ impl View for Reader {
                 self.toggle_margin_cropper(true, hub, rq, context);
                 true
             }
+            Event::Close(ViewId::Footnote) => {
+                if let Some(index) = locate_by_id(self, ViewId::Footnote) {
+                    rq.add(RenderData::expose(
+                        *self.child(index).rect(),
+                        UpdateMode::Gui,
+                    ));
+                    self.children.remove(index);
+                }
+                true
+            }
             Event::Close(ViewId::MarginCropper) => {
                 self.toggle_margin_cropper(false, hub, rq, context);
                 true
@@ -5031,6 +5684,14 @@ impl View for Reader {
                 }
                 true
             }
+            Event::Select(EntryId::OpenNextVolume) => {
+                if let Some(info) = context.library.next_in_series(&self.info) {
+                    self.quit(context);
+                    hub.send(Event::Back).ok();
+                    hub.send(Event::Open(Box::new(info))).ok();
+                }
+                true
+            }
             Event::Select(EntryId::SetZoomMode(zoom_mode)) => {
                 self.set_zoom_mode(zoom_mode, true, hub, rq, context);
                 true
@@ -5055,6 +5716,22 @@ impl View for Reader {
                 self.children.push(Box::new(notif) as Box<dyn View>);
                 true
             }
+            Event::Select(EntryId::ExportAnnotations(format)) => {
+                let dir = context.library.home.join(&context.settings.export.path);
+                let mut doc = self.doc.lock().unwrap();
+                let msg = match export_annotations(doc.as_mut(), &self.info, format, &dir) {
+                    Err(e) => format!("Couldn't export annotations: {}.", e),
+                    Ok(path) => format!(
+                        "Exported {}.",
+                        path.strip_prefix(&context.library.home)
+                            .unwrap_or(&path)
+                            .display()
+                    ),
+                };
+                let notif = Notification::new(None, msg, false, hub, rq, context);
+                self.children.push(Box::new(notif) as Box<dyn View>);
+                true
+            }
             Event::Select(EntryId::ApplyCroppings(index, scheme)) => {
                 self.info.reader.as_mut().map(|r| {
                     if r.cropping_margins.is_none() {
@@ -5124,6 +5801,31 @@ impl View for Reader {
                 }
                 true
             }
+            Event::Select(EntryId::ToggleReflow) => {
+                self.toggle_reflow(hub, rq, context);
+                true
+            }
+            Event::Reflowed => {
+                let doc = self
+                    .pending_reflow
+                    .take()
+                    .and_then(|reflowed| reflowed.lock().unwrap().take());
+                if let Some(doc) = doc {
+                    self.reopen(Some(doc), hub, rq, context);
+                    rq.add(RenderData::new(self.id, self.rect, UpdateMode::Full));
+                } else {
+                    let notif = Notification::new(
+                        None,
+                        "Couldn't reflow the text.".to_string(),
+                        false,
+                        hub,
+                        rq,
+                        context,
+                    );
+                    self.children.push(Box::new(notif) as Box<dyn View>);
+                }
+                true
+            }
             Event::Select(EntryId::ToggleInverted) => {
                 self.update_noninverted_regions(!context.fb.inverted());
                 false
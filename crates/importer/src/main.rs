@@ -1,13 +1,17 @@
 use cadmus_core::anyhow::{format_err, Context, Error};
 use cadmus_core::chrono::NaiveDateTime;
-use cadmus_core::helpers::datetime_format;
+use cadmus_core::device::CURRENT_DEVICE;
+use cadmus_core::document::open;
+use cadmus_core::export::{export_annotations, ExportFormat};
+use cadmus_core::helpers::{datetime_format, load_toml};
 use cadmus_core::library::Library;
 use cadmus_core::metadata::{consolidate, rename_from_info};
 use cadmus_core::metadata::{extract_metadata_from_document, extract_metadata_from_filename};
-use cadmus_core::settings::{ImportSettings, LibraryMode};
+use cadmus_core::settings::{ImportSettings, LibraryMode, Settings, SETTINGS_PATH};
+use cadmus_core::view::home::thumbnail_size;
 use getopts::Options;
 use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 fn main() -> Result<(), Error> {
     let args: Vec<String> = env::args().skip(1).collect();
@@ -16,11 +20,17 @@ fn main() -> Result<(), Error> {
 
     opts.optflag("h", "help", "Print this help message.");
     opts.optflag("I", "import", "Import new files or update existing files.");
+    opts.optflag(
+        "T",
+        "thumbnails",
+        "Render the missing thumbnail previews during the import.",
+    );
     opts.optflag(
         "C",
         "clean-up",
         "Remove reading states with unknown fingerprints.",
     );
+    opts.optflag("J", "export-json", "Write the database in the JSON layout.");
     opts.optflag(
         "E",
         "extract-metadata-document",
@@ -41,6 +51,18 @@ fn main() -> Result<(), Error> {
         "rename-from-info",
         "Rename files based on their information.",
     );
+    opts.optopt(
+        "X",
+        "export-annotations",
+        "Export the annotations in the given format (`markdown`, `html` or `json`).",
+        "EXPORT_FORMAT",
+    );
+    opts.optopt(
+        "o",
+        "export-path",
+        "The directory where the annotations are exported (defaults to the export path of the settings).",
+        "EXPORT_PATH",
+    );
     opts.optopt(
         "k",
         "allowed-kinds",
@@ -62,7 +84,7 @@ fn main() -> Result<(), Error> {
     opts.optopt(
         "m",
         "library-mode",
-        "The library mode (`database` or `filesystem`).",
+        "The library mode (`database`, `filesystem` or `sqlite`).",
         "LIBRARY_MODE",
     );
 
@@ -71,7 +93,7 @@ fn main() -> Result<(), Error> {
         .context("failed to parse the command line arguments")?;
 
     if matches.opt_present("h") {
-        println!("{}", opts.usage("Usage: cadmus-import -h|-I|-C|-EFSN [-k ALLOWED_KINDS] [-e METADATA_KINDS] [-a ADDED_DATETIME] [-m LIBRARY_MODE] LIBRARY_PATH"));
+        println!("{}", opts.usage("Usage: cadmus-import -h|-I [-T]|-C|-J|-EFSN|-X EXPORT_FORMAT [-o EXPORT_PATH] [-k ALLOWED_KINDS] [-e METADATA_KINDS] [-a ADDED_DATETIME] [-m LIBRARY_MODE] LIBRARY_PATH"));
         return Ok(());
     }
 
@@ -82,7 +104,12 @@ fn main() -> Result<(), Error> {
     let library_path = Path::new(&matches.free[0]);
 
     let mut import_settings = ImportSettings {
-        metadata_kinds: ["epub"].iter().map(|k| k.to_string()).collect(),
+        metadata_kinds: [
+            "epub", "fb2", "mobi", "azw", "azw3", "cbz", "cb7", "xps", "oxps",
+        ]
+        .iter()
+        .map(|k| k.to_string())
+        .collect(),
         ..Default::default()
     };
 
@@ -111,6 +138,7 @@ fn main() -> Result<(), Error> {
         .and_then(|v| match v.as_ref() {
             "database" => Some(LibraryMode::Database),
             "filesystem" => Some(LibraryMode::Filesystem),
+            "sqlite" => Some(LibraryMode::Sqlite),
             _ => None,
         })
         .unwrap_or(LibraryMode::Database);
@@ -118,9 +146,58 @@ fn main() -> Result<(), Error> {
     let mut library = Library::new(&library_path, mode)?;
 
     if matches.opt_present("I") {
+        import_settings.pregenerate_thumbnails = matches.opt_present("T");
         library.import(&import_settings);
+        let (width, height) = thumbnail_size(CURRENT_DEVICE.dpi);
+        library.pregenerate_thumbnails(&import_settings, width, height);
+        library.wait_for_thumbnails();
     } else if matches.opt_present("C") {
-        library.clean_up();
+        library.clean_up(&import_settings);
+    } else if matches.opt_present("J") {
+        library.export_json()?;
+    } else if let Some(format) = matches.opt_str("X") {
+        let format = format.parse::<ExportFormat>()?;
+        let export_path = match matches.opt_str("o") {
+            Some(path) => PathBuf::from(path),
+            None if Path::new(SETTINGS_PATH).exists() => {
+                load_toml::<Settings, _>(SETTINGS_PATH)
+                    .context("can't load settings")?
+                    .export
+                    .path
+            }
+            None => Settings::default().export.path,
+        };
+
+        let mut count = 0;
+        for info in library.read_entries() {
+            let has_annotations = info
+                .reader
+                .as_ref()
+                .is_some_and(|r| !r.annotations.is_empty());
+            if !has_annotations || added_after.is_some_and(|added| info.added < added) {
+                continue;
+            }
+
+            let doc_path = library.home.join(&info.file.path);
+            if let Some(mut doc) = open(&doc_path) {
+                match export_annotations(
+                    doc.as_mut(),
+                    &info,
+                    format,
+                    &library.home.join(&export_path),
+                ) {
+                    Ok(export) => {
+                        println!("{}", export.display());
+                        count += 1;
+                    }
+                    Err(e) => eprintln!("{}: {:#}.", doc_path.display(), e),
+                }
+            }
+        }
+
+        if count == 0 {
+            println!("No annotations were exported.");
+        }
     } else {
         let opt_extract_metadata_document = matches.opt_present("E");
         let opt_extract_metadata_filename = matches.opt_present("F");
@@ -1,4 +1,5 @@
 #include <mupdf/fitz.h>
+#include <mupdf/pdf.h>
 
 #define WRAP(name, ret_type, failure_val, call, ...) \
     ret_type mp_##name(fz_context *ctx, ##__VA_ARGS__) { \
@@ -17,3 +18,73 @@ WRAP(count_pages, int, -1, fz_count_pages(ctx, doc), fz_document *doc)
 WRAP(page_number_from_location, int, -1, fz_page_number_from_location(ctx, doc, loc), fz_document *doc, fz_location loc)
 WRAP(new_pixmap_from_page, fz_pixmap*, NULL, fz_new_pixmap_from_page(ctx, page, mat, cs, alpha), fz_page *page, fz_matrix mat, fz_colorspace *cs, int alpha)
 WRAP(new_stext_page_from_page, fz_stext_page*, NULL, fz_new_stext_page_from_page(ctx, page, options), fz_page *page, fz_stext_options *options)
+
+static pdf_page *as_pdf_page(fz_context *ctx, fz_page *page) {
+    pdf_page *result = pdf_page_from_fz_page(ctx, page);
+    if (!result) {
+        fz_throw(ctx, FZ_ERROR_ARGUMENT, "not a PDF page");
+    }
+    return result;
+}
+
+WRAP(authenticate_password, int, 0, fz_authenticate_password(ctx, doc, password), fz_document *doc, const char *password)
+WRAP(first_annot, pdf_annot*, NULL, pdf_first_annot(ctx, as_pdf_page(ctx, page)), fz_page *page)
+WRAP(annot_type, int, -1, pdf_annot_type(ctx, annot), pdf_annot *annot)
+WRAP(annot_quad_point_count, int, 0, pdf_annot_quad_point_count(ctx, annot), pdf_annot *annot)
+WRAP(annot_quad_point, fz_quad, fz_quad_from_rect(fz_empty_rect), pdf_annot_quad_point(ctx, annot, i), pdf_annot *annot, int i)
+WRAP(annot_contents, const char*, NULL, pdf_annot_contents(ctx, annot), pdf_annot *annot)
+WRAP(annot_flags, int, 0, pdf_annot_flags(ctx, annot), pdf_annot *annot)
+WRAP(set_annot_flags, int, -1, (pdf_set_annot_flags(ctx, annot, flags), 0), pdf_annot *annot, int flags)
+WRAP(delete_annot, int, -1, (pdf_delete_annot(ctx, as_pdf_page(ctx, page), annot), 0), fz_page *page, pdf_annot *annot)
+WRAP(create_annot, pdf_annot*, NULL, pdf_create_annot(ctx, as_pdf_page(ctx, page), kind), fz_page *page, int kind)
+WRAP(set_annot_quad_points, int, -1, (pdf_set_annot_quad_points(ctx, annot, n, qv), 0), pdf_annot *annot, int n, const fz_quad *qv)
+WRAP(set_annot_contents, int, -1, (pdf_set_annot_contents(ctx, annot, text), 0), pdf_annot *annot, const char *text)
+WRAP(update_annot, int, -1, pdf_update_annot(ctx, annot), pdf_annot *annot)
+
+int mp_update_page(fz_context *ctx, fz_page *page) {
+    pdf_page *result = pdf_page_from_fz_page(ctx, page);
+    int ret = 0;
+    if (!result) {
+        return 0;
+    }
+    fz_try (ctx) {
+        ret = pdf_update_page(ctx, result);
+    }
+    fz_catch (ctx) {
+        ret = -1;
+    }
+    return ret;
+}
+
+int mp_can_save_incrementally(fz_context *ctx, fz_document *doc) {
+    pdf_document *pdf = pdf_specifics(ctx, doc);
+    int ret = 0;
+    if (!pdf) {
+        return 0;
+    }
+    fz_try (ctx) {
+        ret = pdf_can_be_saved_incrementally(ctx, pdf);
+    }
+    fz_catch (ctx) {
+        ret = 0;
+    }
+    return ret;
+}
+
+// An incremental save appends the changes to the file at the given path,
+// which must therefore be a copy of the file the document was opened from.
+int mp_save_document(fz_context *ctx, fz_document *doc, const char *path, int incremental) {
+    pdf_document *pdf = pdf_specifics(ctx, doc);
+    pdf_write_options opts = pdf_default_write_options;
+    if (!pdf) {
+        return -1;
+    }
+    fz_try (ctx) {
+        opts.do_incremental = incremental;
+        pdf_save_document(ctx, pdf, path, &opts);
+    }
+    fz_catch (ctx) {
+        return -1;
+    }
+    return 0;
+}